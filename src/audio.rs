@@ -0,0 +1,217 @@
+//! 受信した音声（32bit浮動小数点のプレーナ形式）のチャンネル選択とダウンミックス
+//!
+//! NDIの音声は `FLTP`（チャンネルごとに `channel_stride` サンプルずつ並ぶ）で届く。
+//! 16ch のエンベデッド音声のように必要以上のチャンネルを持つソースが多いので、
+//! VUメーター・再生・WAV録音に渡す前にここで絞り込む。
+
+/// 音声チャンネルの扱い（`--audio-channels` / `--downmix`）
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ChannelMix {
+    /// すべてのチャンネルをそのまま渡す
+    #[default]
+    All,
+    /// 指定したチャンネル（0始まり）だけをこの順で渡す
+    Select(Vec<usize>),
+    /// ステレオにダウンミックスする
+    ///
+    /// モノラルは両側に複製し、3ch以上は偶数番目を左、奇数番目を右として平均する
+    /// （エンベデッド音声はL/Rのペアで並ぶことが多いため）。
+    Stereo,
+}
+
+impl ChannelMix {
+    /// `--audio-channels` の "0,1" 形式をパースする
+    pub fn parse_channels(s: &str) -> Option<Self> {
+        let channels: Vec<usize> = s
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        Some(Self::Select(channels))
+    }
+
+    /// `--downmix` の値をパースする（今のところ "stereo" だけ）
+    pub fn parse_downmix(name: &str) -> Option<Self> {
+        match name {
+            "stereo" => Some(Self::Stereo),
+            _ => None,
+        }
+    }
+
+    /// 出力のチャンネル数
+    pub fn output_channels(&self, input_channels: usize) -> usize {
+        match self {
+            Self::All => input_channels,
+            Self::Select(channels) => channels.len(),
+            Self::Stereo => 2,
+        }
+    }
+
+    /// プレーナの `data`（チャンネルごとに `stride` サンプル）を変換して `out` に書き込む
+    ///
+    /// `out` は `samples` サンプルずつ詰めたプレーナ形式になる。戻り値は出力のチャンネル数。
+    /// 存在しないチャンネルを選んだ場合は、チャンネル数が変わらないよう無音で埋める。
+    pub fn apply(
+        &self,
+        data: &[f32],
+        channels: usize,
+        samples: usize,
+        stride: usize,
+        out: &mut Vec<f32>,
+    ) -> usize {
+        let channel = |c: usize| data.get(c * stride..c * stride + samples);
+        let output_channels = self.output_channels(channels);
+        out.clear();
+        out.reserve(output_channels * samples);
+
+        match self {
+            Self::All => {
+                for c in 0..channels {
+                    extend_or_silence(out, channel(c), samples);
+                }
+            }
+            Self::Select(selected) => {
+                for &c in selected {
+                    extend_or_silence(out, (c < channels).then(|| channel(c)).flatten(), samples);
+                }
+            }
+            Self::Stereo if channels <= 2 => {
+                // モノラルは両側に同じものを出す
+                extend_or_silence(out, channel(0), samples);
+                extend_or_silence(out, channel(channels.saturating_sub(1)), samples);
+            }
+            Self::Stereo => {
+                for side in 0..2 {
+                    let sources: Vec<&[f32]> =
+                        (side..channels).step_by(2).filter_map(channel).collect();
+                    let gain = 1.0 / sources.len().max(1) as f32;
+                    out.extend(
+                        (0..samples)
+                            .map(|i| sources.iter().map(|source| source[i]).sum::<f32>() * gain),
+                    );
+                }
+            }
+        }
+        output_channels
+    }
+}
+
+// 1チャンネル分を追加する（データが足りなければ無音）
+fn extend_or_silence(out: &mut Vec<f32>, channel: Option<&[f32]>, samples: usize) {
+    match channel {
+        Some(channel) => out.extend_from_slice(channel),
+        None => out.resize(out.len() + samples, 0.0),
+    }
+}
+
+/// プレーナの `planar`（チャンネルごとに詰めたもの）をインターリーブ（L R L R …）に並べ替えて `out` に書き込む
+///
+/// NDIとこのクレートの音声はプレーナだが、WAVや多くの再生APIはインターリーブを受け取る。
+/// `planar.len()` が `channels` の倍数でなければ、端数のサンプルは捨てる。
+pub fn interleave(planar: &[f32], channels: usize, out: &mut Vec<f32>) {
+    out.clear();
+    if channels == 0 {
+        return;
+    }
+    let samples = planar.len() / channels;
+    out.reserve(samples * channels);
+    for i in 0..samples {
+        out.extend((0..channels).map(|c| planar[c * samples + i]));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // チャンネル c のサンプル i は c*10+i、各チャンネルの後ろに `padding` サンプルの余白（-1）
+    fn planar(channels: usize, samples: usize, padding: usize) -> Vec<f32> {
+        (0..channels)
+            .flat_map(|c| {
+                let channel = (0..samples).map(move |i| (c * 10 + i) as f32);
+                channel.chain(std::iter::repeat_n(-1.0, padding))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn select_reorders_and_fills_missing_channels_with_silence() {
+        let data = planar(3, 2, 0);
+        let mut out = Vec::new();
+        let mix = ChannelMix::Select(vec![2, 0, 7]);
+        assert_eq!(mix.apply(&data, 3, 2, 2, &mut out), 3);
+        assert_eq!(out, [20.0, 21.0, 0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn a_channel_stride_longer_than_the_samples_skips_the_padding() {
+        let data = planar(2, 3, 2);
+        let mut out = Vec::new();
+        assert_eq!(ChannelMix::All.apply(&data, 2, 3, 5, &mut out), 2);
+        assert_eq!(out, [0.0, 1.0, 2.0, 10.0, 11.0, 12.0]);
+
+        let mix = ChannelMix::Select(vec![1]);
+        assert_eq!(mix.apply(&data, 2, 3, 5, &mut out), 1);
+        assert_eq!(out, [10.0, 11.0, 12.0]);
+
+        // 最後のチャンネルの余白がなくても読める
+        let short = &data[..data.len() - 2];
+        assert_eq!(ChannelMix::Stereo.apply(short, 2, 3, 5, &mut out), 2);
+        assert_eq!(out, [0.0, 1.0, 2.0, 10.0, 11.0, 12.0]);
+    }
+
+    #[test]
+    fn stereo_duplicates_mono() {
+        let data = planar(1, 3, 0);
+        let mut out = vec![9.0; 10];
+        assert_eq!(ChannelMix::Stereo.apply(&data, 1, 3, 3, &mut out), 2);
+        assert_eq!(out, [0.0, 1.0, 2.0, 0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn stereo_averages_the_even_and_odd_channels() {
+        let data = planar(4, 2, 1);
+        let mut out = Vec::new();
+        assert_eq!(ChannelMix::Stereo.apply(&data, 4, 2, 3, &mut out), 2);
+        // 左は 0 と 2、右は 1 と 3 の平均
+        assert_eq!(out, [10.0, 11.0, 20.0, 21.0]);
+    }
+
+    #[test]
+    fn missing_data_is_silence() {
+        let data = planar(2, 2, 0);
+        let mut out = Vec::new();
+        // 4ch と言いながら2ch分しかない
+        assert_eq!(ChannelMix::All.apply(&data, 4, 2, 2, &mut out), 4);
+        assert_eq!(out, [0.0, 1.0, 10.0, 11.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn interleave_drops_the_trailing_partial_samples() {
+        let mut out = Vec::new();
+        interleave(&[0.0, 1.0, 2.0, 10.0, 11.0, 12.0], 2, &mut out);
+        assert_eq!(out, [0.0, 10.0, 1.0, 11.0, 2.0, 12.0]);
+
+        // 7サンプルを2chとして読むと3サンプルずつ、最後の1つは捨てる
+        interleave(&[0.0, 1.0, 2.0, 10.0, 11.0, 12.0, 99.0], 2, &mut out);
+        assert_eq!(out, [0.0, 10.0, 1.0, 11.0, 2.0, 12.0]);
+
+        interleave(&[1.0, 2.0], 0, &mut out);
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn parse_channels_and_downmix() {
+        assert_eq!(
+            ChannelMix::parse_channels("0, 1,3"),
+            Some(ChannelMix::Select(vec![0, 1, 3]))
+        );
+        for invalid in ["", "0,x", "0,,1", "-1"] {
+            assert_eq!(ChannelMix::parse_channels(invalid), None, "{invalid:?}");
+        }
+        assert_eq!(
+            ChannelMix::parse_downmix("stereo"),
+            Some(ChannelMix::Stereo)
+        );
+        assert_eq!(ChannelMix::parse_downmix("5.1"), None);
+    }
+}
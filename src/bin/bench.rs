@@ -0,0 +1,178 @@
+//! 受信の遅延と処理時間を測るヘッドレスのベンチマーク（結果は1つのJSONオブジェクト）
+//!
+//! ソースに接続して `--frames` 枚受信し、フレームごとに次を測って最小・最大・平均・p99を出す。
+//!
+//! - `processing_ms`: コールバックで受信バッファから所有フレームにコピーする時間
+//!   （ビューアが受信スレッドで毎フレーム行う処理に相当）
+//! - `interval_ms`: コールバックが呼ばれる間隔（実時間）
+//! - `timecode_gap_ms`: 続くフレームのタイムコードの差
+//! - `drift_ms`: 実時間の間隔からタイムコードの差を引いたもの（受信側の遅れ・詰まり）
+//! - `latency_ms`: 送信側のタイムスタンプから受信までの時間（送受信の時計が合っていないと意味がない）
+//!
+//! JSONは実行ごとに保存しておけば差分で比べられる。受信の設定は他のビューアと同じオプションで変えられる。
+
+use anyhow::Result;
+use rust_ndi_viewer::{
+    exit_if_given_up, init_tracing, json, CliArgs, NdiReceiver, TransformControl,
+    TARGET_SOURCE_NAME,
+};
+use std::env;
+use std::ops::ControlFlow;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const DEFAULT_FRAMES: usize = 600;
+
+// NDIのタイムスタンプ・タイムコードの単位（100ns）をミリ秒に
+const TICKS_PER_MS: f64 = 10_000.0;
+
+/// ベンチマーク専用のオプション（残りは `CliArgs` に渡す）
+struct BenchArgs {
+    frames: usize,
+    rest: Vec<String>,
+}
+
+impl BenchArgs {
+    fn parse() -> Self {
+        let mut parsed = Self {
+            frames: DEFAULT_FRAMES,
+            rest: Vec::new(),
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if arg != "--frames" {
+                parsed.rest.push(arg);
+                continue;
+            }
+            let Some(value) = args.next() else {
+                warn!("--frames requires a value");
+                break;
+            };
+            match value.parse() {
+                Ok(frames) if frames > 0 => parsed.frames = frames,
+                _ => warn!("Invalid frame count \"{value}\", ignoring."),
+            }
+        }
+        parsed
+    }
+}
+
+/// フレームごとの計測値（ミリ秒）
+#[derive(Default)]
+struct Samples {
+    processing: Vec<f64>,
+    interval: Vec<f64>,
+    timecode_gap: Vec<f64>,
+    drift: Vec<f64>,
+    latency: Vec<f64>,
+}
+
+/// 1項目の最小・最大・平均・p99（値がなければ `null`）
+fn summary_json(values: &[f64]) -> String {
+    if values.is_empty() {
+        return "null".to_string();
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let count = sorted.len();
+    let mean = sorted.iter().sum::<f64>() / count as f64;
+    let p99 = sorted[(count * 99 / 100).min(count - 1)];
+    format!(
+        "{{\"count\": {count}, \"min\": {:.3}, \"max\": {:.3}, \"mean\": {mean:.3}, \"p99\": {p99:.3}}}",
+        sorted[0],
+        sorted[count - 1]
+    )
+}
+
+// 今の時刻をNDIのタイムスタンプと同じ単位（UNIXエポックからの100ns）で
+fn now_ticks() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| (d.as_nanos() / 100) as i64)
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let bench = BenchArgs::parse();
+    let args = CliArgs::parse_from(bench.rest);
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+
+    let source_name = (!TARGET_SOURCE_NAME.is_empty()).then_some(TARGET_SOURCE_NAME);
+    let mut receiver = match NdiReceiver::connect_to(source_name, &args) {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            exit_if_given_up(&e);
+            return Err(e);
+        }
+    };
+    let source = receiver.source_label();
+    let frame_stats = receiver.frame_stats();
+
+    info!("Measuring {} frames from {source} ...", bench.frames);
+    let mut samples = Samples::default();
+    let mut previous: Option<(Instant, i64)> = None;
+    let mut size = (0, 0);
+    let started = Instant::now();
+    let result = receiver.run_loop_until(|frame| {
+        let arrived = Instant::now();
+        let arrived_ticks = now_ticks();
+
+        let copy_start = Instant::now();
+        let owned = frame.to_owned();
+        samples
+            .processing
+            .push(copy_start.elapsed().as_secs_f64() * 1000.0);
+        size = (owned.width, owned.height);
+
+        if let Some((last_arrived, last_timecode)) = previous {
+            let interval = arrived.duration_since(last_arrived).as_secs_f64() * 1000.0;
+            let gap = (frame.timecode - last_timecode) as f64 / TICKS_PER_MS;
+            samples.interval.push(interval);
+            samples.timecode_gap.push(gap);
+            samples.drift.push(interval - gap);
+        }
+        previous = Some((arrived, frame.timecode));
+
+        // 送信側がタイムスタンプを付けていなければ（0や未定義の最大値）数えない
+        if frame.timestamp > 0 && frame.timestamp != i64::MAX {
+            let latency = (arrived_ticks - frame.timestamp) as f64 / TICKS_PER_MS;
+            samples.latency.push(latency);
+        }
+
+        if samples.processing.len() >= bench.frames {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+    if let Err(e) = &result {
+        exit_if_given_up(e);
+    }
+    result?;
+    let elapsed = started.elapsed();
+
+    println!(
+        "{{\"source\": \"{}\", \"frames\": {}, \"width\": {}, \"height\": {}, \
+         \"elapsed_s\": {:.3}, \"fps\": {:.3}, \"dropped\": {}, \"bytes_per_sec\": {:.0}, \
+         \"processing_ms\": {}, \"interval_ms\": {}, \"timecode_gap_ms\": {}, \
+         \"drift_ms\": {}, \"latency_ms\": {}}}",
+        json::escape(&source),
+        samples.processing.len(),
+        size.0,
+        size.1,
+        elapsed.as_secs_f64(),
+        frame_stats.fps(),
+        frame_stats.dropped(),
+        frame_stats.bytes_per_sec(),
+        summary_json(&samples.processing),
+        summary_json(&samples.interval),
+        summary_json(&samples.timecode_gap),
+        summary_json(&samples.drift),
+        summary_json(&samples.latency)
+    );
+    Ok(())
+}
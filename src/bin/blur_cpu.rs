@@ -1,117 +1,126 @@
 use anyhow::Result;
 use arc_swap::ArcSwap;
 use eframe::egui;
-use rust_ndi_viewer::{create_native_options, NdiReceiver, TARGET_SOURCE_NAME};
+use rust_ndi_viewer::filter::GaussianBlur;
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, CliArgs, FrameTexture, HelpOverlay, InfoOverlay, ParamHandle, Pipeline,
+    ReceiverHandle, SourceOverlay, StreamStats, TransformControl,
+};
 use std::sync::Arc;
-use std::thread;
+use tracing::trace;
+
+const APP_TITLE: &str = "NDI Blur Viewer (CPU)";
 
 struct NdiApp {
     // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
     frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
 
     // egui用のテクスチャハンドル
-    texture: Option<egui::TextureHandle>,
+    texture: FrameTexture,
+
+    // ぼかしの強さ（スライダーで変え、受信スレッドのフィルタが次のフレームから使う）
+    sigma: ParamHandle<f32>,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
         let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
         let frame_buffer_clone = frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
 
         // egui::Contextをクローンしてスレッドで使用
         let ctx = cc.egui_ctx.clone();
 
-        // NDI receiver thread
-        thread::spawn(move || {
-            let receiver = match NdiReceiver::connect() {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("Failed to connect to NDI: {e}");
-                    return;
-                }
-            };
-
-            let _ = receiver.run_loop(|frame| {
-                // CPUでガウシアンブラー処理（5x5カーネル）
-                let width = frame.width as usize;
-                let height = frame.height as usize;
-                let mut blurred_data = vec![0u8; frame.data.len()];
-
-                // ガウシアンカーネル 5x5 (正規化済み)
-                // 1   4   6   4   1
-                // 4  16  24  16   4
-                // 6  24  36  24   6
-                // 4  16  24  16   4
-                // 1   4   6   4   1
-                // 合計 = 256
-                let kernel = [
-                    1, 4, 6, 4, 1,
-                    4, 16, 24, 16, 4,
-                    6, 24, 36, 24, 6,
-                    4, 16, 24, 16, 4,
-                    1, 4, 6, 4, 1,
-                ];
-                let kernel_sum = 256;
-
-                for y in 0..height {
-                    for x in 0..width {
-                        let mut r_sum = 0u32;
-                        let mut g_sum = 0u32;
-                        let mut b_sum = 0u32;
-                        let mut a_sum = 0u32;
-
-                        // 5x5カーネルを適用
-                        for ky in 0..5 {
-                            for kx in 0..5 {
-                                // 境界処理: クランプ
-                                let py = (y as i32 + ky - 2).clamp(0, height as i32 - 1) as usize;
-                                let px = (x as i32 + kx - 2).clamp(0, width as i32 - 1) as usize;
-                                let idx = (py * width + px) * 4;
-
-                                let weight = kernel[ky as usize * 5 + kx as usize];
-                                r_sum += frame.data[idx] as u32 * weight;
-                                g_sum += frame.data[idx + 1] as u32 * weight;
-                                b_sum += frame.data[idx + 2] as u32 * weight;
-                                a_sum += frame.data[idx + 3] as u32 * weight;
-                            }
-                        }
-
-                        let out_idx = (y * width + x) * 4;
-                        blurred_data[out_idx] = (r_sum / kernel_sum) as u8;
-                        blurred_data[out_idx + 1] = (g_sum / kernel_sum) as u8;
-                        blurred_data[out_idx + 2] = (b_sum / kernel_sum) as u8;
-                        blurred_data[out_idx + 3] = (a_sum / kernel_sum) as u8;
-                    }
-                }
-
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        // CPUフィルタはパイプライン側で適用される（ガウシアンブラー、横→縦の2パス）
+        let blur = GaussianBlur::default();
+        let sigma = blur.sigma();
+        let receiver = Pipeline::builder()
+            .args(args.clone())
+            .filter(blur)
+            .stats(stats_clone)
+            .on_frame(move |frame| {
                 // Convert to egui::ColorImage
-                let image = egui::ColorImage::from_rgba_unmultiplied(
-                    [frame.width as usize, frame.height as usize],
-                    &blurred_data,
-                );
-
-                // Store using ArcSwap (lock-free)
-                frame_buffer_clone.store(Arc::new(Some(image)));
+                let image = frame.to_color_image();
 
-                // これをしないとマウスカーソルを動かさないと再描画されない
-                ctx.request_repaint();
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&frame_buffer_clone, image, &ctx);
 
-                println!(
-                    "Frame received (blur CPU): {}x{}, timecode={}",
-                    frame.width, frame.height, frame.timecode
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (blur CPU)"
                 );
-            });
-        });
+            })
+            .spawn();
 
         Self {
             frame_buffer,
-            texture: None,
+            texture: FrameTexture::from_args(&args),
+            sigma,
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            receiver,
+            args,
         }
     }
 }
 
+impl NdiApp {
+    // 左下のぼかし調整（半径はsigmaから決まる）
+    fn sigma_slider(&self, ctx: &egui::Context) {
+        egui::Window::new("Blur")
+            .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut sigma = self.sigma.get();
+                let slider = egui::Slider::new(
+                    &mut sigma,
+                    GaussianBlur::MIN_SIGMA..=GaussianBlur::MAX_SIGMA,
+                )
+                .text("sigma");
+                if ui.add(slider).changed() {
+                    self.sigma.store(sigma);
+                }
+                ui.label(format!("radius {} px", GaussianBlur::radius_for(sigma)));
+            });
+    }
+}
+
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+        self.sigma_slider(ctx);
+
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
         egui::CentralPanel::default()
@@ -123,45 +132,40 @@ impl eframe::App for NdiApp {
 
                 // 新しい画像が来ていればテクスチャを更新
                 if let Some(image) = new_image {
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR,
-                    ));
+                    self.texture.update(ctx, image);
                 }
 
                 // テクスチャがあれば描画
-                if let Some(texture) = &self.texture {
-                    let size = ui.available_size();
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
 
                     // Note: NDIスレッドがrequest_repaintを呼ぶため、
                     // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
                 } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
                 }
             });
     }
 }
 
 fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
 
     eframe::run_native(
-        "NDI Blur Viewer (CPU)",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
@@ -1,108 +1,122 @@
 use anyhow::Result;
 use arc_swap::ArcSwap;
 use eframe::egui;
-use rust_ndi_viewer::{create_native_options, NdiReceiver, TARGET_SOURCE_NAME};
+use rust_ndi_viewer::gpu::{self, TilePlanner};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, CliArgs, FrameTexture, HelpOverlay, InfoOverlay, NdiReceiver, OwnedFrame,
+    Playback, ReceiverHandle, SourceOverlay, StreamStats, TransformControl,
+};
 use std::sync::Arc;
-use std::thread;
+use tracing::{error, trace};
 use wgpu::util::DeviceExt;
 
+const APP_TITLE: &str = "NDI Blur Viewer (WGPU)";
+
 // コンピュートシェーダーのワークグループサイズ
 const WORKGROUP_SIZE_X: u32 = 16;
 const WORKGROUP_SIZE_Y: u32 = 16;
 
-// 生のRGBAフレームデータ（wgpuで処理する前）
-#[derive(Clone)]
-struct RawFrame {
-    width: i32,
-    height: i32,
-    data: Vec<u8>,
+// ガウシアンブラーのコンピュートパイプライン
+struct BlurCompute {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
 }
 
 struct NdiApp {
     // スレッド間で共有する生フレームバッファ（ArcSwapでロックフリー）
-    raw_frame_buffer: Arc<ArcSwap<Option<RawFrame>>>,
+    raw_frame_buffer: Arc<ArcSwap<Option<OwnedFrame>>>,
 
     // wgpuリソース
-    device: Arc<wgpu::Device>,
-    queue: Arc<wgpu::Queue>,
-    pipeline: wgpu::ComputePipeline,
-    bind_group_layout: wgpu::BindGroupLayout,
+    compute: BlurCompute,
+
+    // 上限を超える大きなフレームを横帯に分けて処理する
+    tiles: TilePlanner,
 
     // egui用のテクスチャハンドル
-    texture: Option<egui::TextureHandle>,
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
         let raw_frame_buffer = Arc::new(ArcSwap::from_pointee(None));
         let raw_frame_buffer_clone = raw_frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
 
         // egui::Contextをクローンしてスレッドで使用
         let ctx = cc.egui_ctx.clone();
 
-        // NDI receiver thread
-        thread::spawn(move || {
-            let receiver = match NdiReceiver::connect() {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("Failed to connect to NDI: {e}");
-                    return;
-                }
-            };
-
-            let _ = receiver.run_loop(|frame| {
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        let connect_args = args.clone();
+        let receiver = ReceiverHandle::spawn(
+            move |stop| {
+                Ok(NdiReceiver::connect_with_stop(&connect_args, stop)?.with_stats(stats_clone))
+            },
+            move |frame| {
                 // copy frame
-                let data = frame.data.to_vec();
-                let raw = RawFrame {
-                    width: frame.width,
-                    height: frame.height,
-                    data,
-                };
-
-                // Store using ArcSwap (lock-free)
-                raw_frame_buffer_clone.store(Arc::new(Some(raw)));
-
-                // これをしないとマウスカーソルを動かさないと再描画されない
-                ctx.request_repaint();
-
-                println!(
-                    "Frame received (for wgpu blur): {}x{}, timecode={}",
-                    frame.width, frame.height, frame.timecode
-                );
-            });
-        });
+                let raw = frame.to_owned();
 
-        // wgpuインスタンスを手動で作成
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
-        });
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&raw_frame_buffer_clone, raw, &ctx);
 
-        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: None,
-            force_fallback_adapter: false,
-        }))
-        .expect("Failed to find an appropriate adapter");
-
-        // Raspi4 (Mobile/Downlevel向け) の制限設定
-        let mut limits = wgpu::Limits::downlevel_defaults();
-        limits.max_color_attachments = 4;
-
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: Some("Device"),
-                required_features: wgpu::Features::empty(),
-                required_limits: limits,
-                memory_hints: Default::default(),
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (for wgpu blur)"
+                );
             },
-            None,
-        ))
-        .expect("Failed to create device");
+        );
+
+        let backend_order = gpu::backend_order(&args);
+        let compute = gpu::init_with_fallback(&backend_order, args.adapter, |device, queue| {
+            let compute = BlurCompute::new(device, queue);
+            // 実行時に失敗するバックエンドもあるので、試しに1回ディスパッチしておく
+            let (width, height) = (WORKGROUP_SIZE_X, WORKGROUP_SIZE_Y);
+            compute.process(width, height, &vec![0u8; (width * height * 4) as usize])?;
+            Ok(compute)
+        })
+        .expect("Failed to initialize wgpu");
 
-        let device = Arc::new(device);
-        let queue = Arc::new(queue);
+        Self {
+            raw_frame_buffer,
+            compute,
+            // 5x5カーネルなので上下2行ずつののりしろが要る
+            tiles: TilePlanner::new(2),
+            texture: FrameTexture::from_args(&args),
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            receiver,
+            args,
+        }
+    }
+}
 
+impl BlurCompute {
+    fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
         // コンピュートシェーダーのコンパイル
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Blur Compute Shader"),
@@ -165,34 +179,44 @@ impl NdiApp {
         });
 
         Self {
-            raw_frame_buffer,
             device,
             queue,
             pipeline,
             bind_group_layout,
-            texture: None,
         }
     }
 
-    fn process_frame_with_wgpu(&self, raw: &RawFrame) -> Vec<u8> {
-        let width = raw.width as u32;
-        let height = raw.height as u32;
-        let pixel_count = width * height;
-        let byte_size = (pixel_count * 4) as usize;
+    // 1回のディスパッチで処理できる最大行数（バインドサイズとワークグループ数の上限から）
+    fn max_rows(&self, width: u32) -> u32 {
+        let limits = self.device.limits();
+        if width.div_ceil(WORKGROUP_SIZE_X) > limits.max_compute_workgroups_per_dimension {
+            return 0;
+        }
+        let row_bytes = width as u64 * 4;
+        let max_bytes = limits
+            .max_buffer_size
+            .min(limits.max_storage_buffer_binding_size as u64);
+        let max_rows = limits.max_compute_workgroups_per_dimension as u64 * WORKGROUP_SIZE_Y as u64;
+        (max_bytes / row_bytes).min(max_rows).min(u32::MAX as u64) as u32
+    }
+
+    fn process(&self, width: u32, height: u32, input: &[u8]) -> Result<Vec<u8>> {
+        // u32のまま掛けると大きなフレームで桁あふれするので、u64で求める
+        let byte_size = width as u64 * height as u64 * 4;
 
         // 入力バッファを作成してデータをアップロード
         let input_buffer = self
             .device
             .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                 label: Some("Input Buffer"),
-                contents: &raw.data,
+                contents: input,
                 usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             });
 
         // 出力バッファを作成
         let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Output Buffer"),
-            size: byte_size as u64,
+            size: byte_size,
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
             mapped_at_creation: false,
         });
@@ -210,7 +234,7 @@ impl NdiApp {
         // CPUに読み戻すためのステージングバッファ
         let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("Staging Buffer"),
-            size: byte_size as u64,
+            size: byte_size,
             usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -251,13 +275,13 @@ impl NdiApp {
             compute_pass.set_bind_group(0, &bind_group, &[]);
 
             // ワークグループ数を計算（切り上げ除算）
-            let workgroup_count_x = (width + WORKGROUP_SIZE_X - 1) / WORKGROUP_SIZE_X;
-            let workgroup_count_y = (height + WORKGROUP_SIZE_Y - 1) / WORKGROUP_SIZE_Y;
+            let workgroup_count_x = width.div_ceil(WORKGROUP_SIZE_X);
+            let workgroup_count_y = height.div_ceil(WORKGROUP_SIZE_Y);
             compute_pass.dispatch_workgroups(workgroup_count_x, workgroup_count_y, 1);
         }
 
         // 出力バッファからステージングバッファにコピー
-        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, byte_size as u64);
+        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, byte_size);
 
         // コマンドを送信
         self.queue.submit(Some(encoder.finish()));
@@ -270,8 +294,8 @@ impl NdiApp {
         });
 
         // デバイスをポーリングしてマップ完了を待つ
-        self.device.poll(wgpu::Maintain::Wait);
-        receiver.recv().unwrap().unwrap();
+        self.device.poll(wgpu::PollType::wait_indefinitely())?;
+        receiver.recv()??;
 
         // データを取得
         let data = buffer_slice.get_mapped_range();
@@ -279,12 +303,20 @@ impl NdiApp {
         drop(data);
         staging_buffer.unmap();
 
-        result
+        Ok(result)
     }
 }
 
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
         egui::CentralPanel::default()
@@ -296,53 +328,62 @@ impl eframe::App for NdiApp {
                     Arc::try_unwrap(new_raw_frame).unwrap_or_else(|arc| (*arc).clone());
 
                 // 新しいフレームが来ていればwgpuで処理
-                if let Some(raw) = new_raw_frame {
-                    let blurred_data = self.process_frame_with_wgpu(&raw);
-
-                    let image = egui::ColorImage::from_rgba_unmultiplied(
-                        [raw.width as usize, raw.height as usize],
-                        &blurred_data,
+                // 一時停止中は取り出したフレームを捨て、GPUの処理も飛ばす
+                if let Some(raw) = new_raw_frame
+                    && !Playback::is_paused(ctx)
+                {
+                    let result = self.tiles.run(
+                        raw.width as u32,
+                        raw.height as u32,
+                        &raw.data,
+                        |width| self.compute.max_rows(width),
+                        |width, height, input| self.compute.process(width, height, input),
                     );
-
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR,
-                    ));
+                    match result {
+                        Ok((blurred_data, width, height)) => {
+                            let mut image = egui::ColorImage::from_rgba_unmultiplied(
+                                [width as usize, height as usize],
+                                &blurred_data,
+                            );
+                            image.source_size = raw.display_size();
+
+                            self.texture.update(ctx, image);
+                        }
+                        Err(e) => error!("wgpu processing failed: {e}"),
+                    }
                 }
 
                 // テクスチャがあれば描画
-                if let Some(texture) = &self.texture {
-                    let size = ui.available_size();
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
 
                     // Note: NDIスレッドがrequest_repaintを呼ぶため、
                     // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
                 } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
                 }
             });
     }
 }
 
 fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
 
     eframe::run_native(
-        "NDI Blur Viewer (WGPU)",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
@@ -1,144 +1,438 @@
 use anyhow::Result;
+use arc_swap::ArcSwap;
 use eframe::egui;
-use rust_ndi_viewer::{create_native_options, NdiReceiver, TARGET_SOURCE_NAME};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    CliArgs, FrameTexture, HelpOverlay, InfoOverlay, NdiReceiver, Playback, ReceiverHandle,
+    Shortcut, SourceOverlay, StreamStats, Toast, TransformControl,
+};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, trace, warn};
 
-// バッファに保持する最大フレーム数（オーバーフロー防止）
-const MAX_BUFFER_SIZE: usize = 180;
+const APP_TITLE: &str = "NDI Buffered Viewer";
 
-// 表示遅延フレーム数（この数だけフレームがバッファに溜まってから表示開始）
-const BUFFER_DELAY_FRAMES: usize = 60;
+// バッファに保持する最大フレーム数の既定値（`--buffer-frames`、オーバーフロー防止）
+const DEFAULT_MAX_BUFFER_SIZE: usize = 180;
+
+// 到着から表示までの目標遅延の既定値（`--buffer-latency`）
+const DEFAULT_TARGET_LATENCY: Duration = Duration::from_millis(1000);
+
+// [ / ] で目標遅延を変える幅と範囲
+const LATENCY_STEP: Duration = Duration::from_millis(100);
+const MAX_TARGET_LATENCY: Duration = Duration::from_secs(10);
+
+// 表示したフレームの遅延がこれ以上目標からずれ続けたら基準を少しずつ動かす
+const DRIFT_TOLERANCE: Duration = Duration::from_millis(20);
+// 1フレーム表示するごとに基準を動かす量（60fpsなら1秒に60ms、再生速度の約6%）
+const DRIFT_STEP: Duration = Duration::from_millis(1);
+
+// タイムコードから求めた表示時刻が到着時刻からの予定とこれ以上ずれたら、基準を取り直す
+// （ソースの再起動やタイムコードの飛び）
+const RESYNC_THRESHOLD: Duration = Duration::from_secs(1);
+
+// NDIのタイムコードの単位（100ns）
+const TIMECODE_TICK_NANOS: i64 = 100;
+
+// 占有率ゲージの大きさ
+const GAUGE_WIDTH: f32 = 160.0;
+
+const SHORTCUT_LATENCY_DOWN: Shortcut = Shortcut {
+    keys: &[egui::Key::OpenBracket],
+    description: "Shorten the target latency by 100 ms",
+};
+
+const SHORTCUT_LATENCY_UP: Shortcut = Shortcut {
+    keys: &[egui::Key::CloseBracket],
+    description: "Lengthen the target latency by 100 ms",
+};
+
+const BUFFERED_VIEWER_SHORTCUTS: &[Shortcut] = &[SHORTCUT_LATENCY_DOWN, SHORTCUT_LATENCY_UP];
+
+/// バッファに溜めた1フレーム
+struct BufferedFrame {
+    image: egui::ColorImage,
+    timecode: i64,
+    // 受信スレッドがバッファに入れた時刻
+    arrived: Instant,
+}
+
+/// 溜めたフレームをタイムコードの間隔どおりに、目標の遅延で出す
+///
+/// 最初のフレームの「到着時刻 + 目標遅延」を基準に、以降はタイムコードの差だけずらした時刻に出す。
+/// 到着のばらつき（ネットワークのジッタ）は目標遅延の分だけ吸収される。送信側と受信側の時計の
+/// ずれで表示したフレームの遅延が目標から外れ続けたら、基準を [`DRIFT_STEP`] ずつ動かして戻す
+/// （溜まりすぎなら早め、足りなければ遅らせる）。
+struct Playout {
+    target: Duration,
+    max_frames: usize,
+    // タイムコードが .1 のフレームを .0 に出す
+    anchor: Option<(Instant, i64)>,
+    // 表示したフレームの到着から表示までの遅延（移動平均、秒）
+    latency: f64,
+    // 一時停止した時刻（再開したら止めていた分だけ基準を遅らせ、止めたフレームの続きから出す）
+    paused_since: Option<Instant>,
+    // 目標遅延にバッファが足りないと警告したか
+    too_small_warned: bool,
+}
+
+impl Playout {
+    fn new(target: Duration, max_frames: usize) -> Self {
+        Self {
+            target,
+            max_frames,
+            anchor: None,
+            latency: 0.0,
+            paused_since: None,
+            too_small_warned: false,
+        }
+    }
+
+    /// 目標遅延を変える（基準も同じだけずらすので、すぐに反映される）
+    fn set_target(&mut self, target: Duration) {
+        if let Some((instant, _)) = &mut self.anchor {
+            if target > self.target {
+                *instant += target - self.target;
+            } else {
+                *instant -= self.target - target;
+            }
+        }
+        self.target = target;
+    }
+
+    /// 一時停止の開始・終了を反映する
+    fn set_paused(&mut self, paused: bool, now: Instant) {
+        match (paused, self.paused_since) {
+            (true, None) => self.paused_since = Some(now),
+            (false, Some(since)) => {
+                self.paused_since = None;
+                if let Some((instant, _)) = &mut self.anchor {
+                    *instant += now.duration_since(since);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // タイムコードから求めたフレームの表示時刻
+    fn due(&self, frame: &BufferedFrame) -> Instant {
+        let Some((instant, timecode)) = self.anchor else {
+            return frame.arrived + self.target;
+        };
+        let nanos = (frame.timecode - timecode).saturating_mul(TIMECODE_TICK_NANOS);
+        let offset = Duration::from_nanos(nanos.unsigned_abs());
+        if nanos >= 0 {
+            instant + offset
+        } else {
+            instant.checked_sub(offset).unwrap_or(instant)
+        }
+    }
+
+    /// 今表示すべきフレーム（あれば）と、次のフレームを出すまでの時間を返す
+    ///
+    /// 表示時刻を過ぎたフレームが複数あれば最後の1枚だけ出し、残りは捨てる（遅れを引きずらない）。
+    fn next(
+        &mut self,
+        frames: &mut VecDeque<BufferedFrame>,
+        now: Instant,
+    ) -> (Option<egui::ColorImage>, Option<Duration>) {
+        if self.paused_since.is_some() {
+            return (None, None);
+        }
+        let mut shown = None;
+        while let Some(frame) = frames.front() {
+            let expected = frame.arrived + self.target;
+            let due = self.due(frame);
+            if self.anchor.is_none() || due.max(expected) - due.min(expected) > RESYNC_THRESHOLD {
+                if self.anchor.is_some() {
+                    debug!("Timecode jumped; resynchronizing the playout clock");
+                }
+                self.anchor = Some((expected, frame.timecode));
+            }
+            if self.due(frame) > now {
+                break;
+            }
+            shown = frames.pop_front();
+        }
+        let wait = frames
+            .front()
+            .map(|frame| self.due(frame).saturating_duration_since(now));
+
+        let Some(frame) = shown else {
+            return (None, wait);
+        };
+        self.correct_drift(now.duration_since(frame.arrived), frames.len());
+        (Some(frame.image), wait)
+    }
+
+    // 表示したフレームの遅延が目標から外れ続けていれば、基準を少し動かす
+    fn correct_drift(&mut self, latency: Duration, buffered: usize) {
+        self.latency += (latency.as_secs_f64() - self.latency) / 16.0;
+        let Some((instant, _)) = &mut self.anchor else {
+            return;
+        };
+        let target = self.target.as_secs_f64();
+        let tolerance = DRIFT_TOLERANCE.as_secs_f64();
+        if self.latency > target + tolerance {
+            *instant = instant.checked_sub(DRIFT_STEP).unwrap_or(*instant);
+        } else if self.latency < target - tolerance {
+            // バッファが満杯で遅延を伸ばせないなら、基準を遅らせ続けても止まるだけ
+            if buffered + 1 >= self.max_frames {
+                if !self.too_small_warned {
+                    warn!(
+                        "The buffer ({} frames) can't hold {} ms of video; \
+                         raise --buffer-frames or lower --buffer-latency",
+                        self.max_frames,
+                        self.target.as_millis()
+                    );
+                    self.too_small_warned = true;
+                }
+                return;
+            }
+            *instant += DRIFT_STEP;
+        }
+    }
+
+    /// 左下にバッファの占有率と、表示したフレームの遅延を出す
+    fn gauge(&self, ctx: &egui::Context, buffered: usize) {
+        egui::Area::new(egui::Id::new("buffer-gauge"))
+            .anchor(egui::Align2::LEFT_BOTTOM, [12.0, -12.0])
+            .show(ctx, |ui| {
+                egui::Frame::new()
+                    .fill(egui::Color32::from_black_alpha(160))
+                    .corner_radius(4.0)
+                    .inner_margin(6.0)
+                    .show(ui, |ui| {
+                        ui.set_width(GAUGE_WIDTH);
+                        let fill = buffered as f32 / self.max_frames as f32;
+                        let (rect, _) = ui.allocate_exact_size(
+                            egui::vec2(GAUGE_WIDTH, 6.0),
+                            egui::Sense::hover(),
+                        );
+                        let painter = ui.painter();
+                        painter.rect_filled(rect, 2.0, egui::Color32::from_gray(60));
+                        let mut filled = rect;
+                        filled.set_width(rect.width() * fill.clamp(0.0, 1.0));
+                        let color = if fill >= 0.9 {
+                            egui::Color32::from_rgb(0xE0, 0x50, 0x50)
+                        } else {
+                            egui::Color32::from_rgb(0x50, 0xC0, 0x70)
+                        };
+                        painter.rect_filled(filled, 2.0, color);
+                        ui.label(
+                            egui::RichText::new(format!(
+                                "Buffer {buffered}/{}, {:.0} ms (target {} ms)",
+                                self.max_frames,
+                                self.latency * 1000.0,
+                                self.target.as_millis()
+                            ))
+                            .color(egui::Color32::WHITE)
+                            .size(12.0),
+                        );
+                    });
+            });
+    }
+}
 
 struct NdiApp {
-    // スレッド間で共有するフレームバッファ（ColorImage + timecode）
+    // スレッド間で共有するフレームバッファ（ColorImage + timecode + 到着時刻）
     // Note: VecDequeは頻繁にpush/popするため、ArcSwapよりMutexが適切
-    frame_buffer: Arc<Mutex<VecDeque<(egui::ColorImage, i64)>>>,
+    frame_buffer: Arc<Mutex<VecDeque<BufferedFrame>>>,
+
+    // 溜めたフレームを出す時刻の管理
+    playout: Playout,
 
     // egui用のテクスチャハンドル
-    texture: Option<egui::TextureHandle>,
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+    // 変えた目標遅延の表示
+    toast: Toast,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
+        let max_frames = args.buffer_frames.unwrap_or(DEFAULT_MAX_BUFFER_SIZE);
+        let target = args
+            .buffer_latency
+            .unwrap_or(DEFAULT_TARGET_LATENCY)
+            .min(MAX_TARGET_LATENCY);
+
         let frame_buffer = Arc::new(Mutex::new(VecDeque::new()));
         let frame_buffer_clone = frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
 
         // egui::Contextをクローンしてスレッドで使用
         let ctx = cc.egui_ctx.clone();
 
-        // NDI receiver thread
-        thread::spawn(move || {
-            let receiver = match NdiReceiver::connect() {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("Failed to connect to NDI: {e}");
-                    return;
-                }
-            };
-
-            let _ = receiver.run_loop(|frame| {
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        let connect_args = args.clone();
+        let receiver = ReceiverHandle::spawn(
+            move |stop| {
+                Ok(NdiReceiver::connect_with_stop(&connect_args, stop)?.with_stats(stats_clone))
+            },
+            move |frame| {
                 // Convert NDI frame into egui::ColorImage
-                let image = egui::ColorImage::from_rgba_unmultiplied(
-                    [frame.width as usize, frame.height as usize],
-                    &frame.data,
-                );
+                let image = frame.to_color_image();
 
                 // try_lockでブロッキングを回避（ロックが取れなければフレームをドロップ）
                 if let Ok(mut buf) = frame_buffer_clone.try_lock() {
-                    buf.push_back((image, frame.timecode));
+                    buf.push_back(BufferedFrame {
+                        image,
+                        timecode: frame.timecode,
+                        arrived: Instant::now(),
+                    });
 
                     // バッファが上限を超えたら古いフレームを破棄
-                    while buf.len() > MAX_BUFFER_SIZE {
+                    // （一時停止中は取り出さないので溢れて当然、警告しない）
+                    let paused = Playback::is_paused(&ctx);
+                    while buf.len() > max_frames {
                         buf.pop_front();
-                        eprintln!("Warning: Frame buffer overflow, dropping oldest frame");
+                        if !paused {
+                            warn!("Frame buffer overflow, dropping oldest frame");
+                        }
                     }
 
-                    // これをしないとマウスカーソルを動かさないと再描画されない
-                    ctx.request_repaint();
+                    // 空だったバッファに入ったときだけ再描画を頼む
+                    // （溜まっている間はGUIが次のフレームの表示時刻に描き直す）
+                    if buf.len() == 1 {
+                        ctx.request_repaint();
+                    }
 
-                    println!(
-                        "Frame received: {}x{}, timecode={}",
-                        frame.width, frame.height, frame.timecode
+                    trace!(
+                        width = frame.width,
+                        height = frame.height,
+                        timecode = frame.timecode,
+                        "Frame received"
                     );
                 } else {
                     // ロックが取れなかった場合はフレームをドロップ（パフォーマンス優先）
-                    eprintln!("Frame dropped: lock contention");
+                    warn!("Frame dropped: lock contention");
                 }
-            });
-        });
+            },
+        );
 
         Self {
             frame_buffer,
-            texture: None,
+            playout: Playout::new(target, max_frames),
+            texture: FrameTexture::from_args(&args),
+            help: HelpOverlay::default().with_extra(BUFFERED_VIEWER_SHORTCUTS),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            toast: Toast::default(),
+            stats,
+            receiver,
+            args,
         }
     }
+
+    // [ / ] で目標遅延を変える
+    fn handle_latency_keys(&mut self, ctx: &egui::Context) {
+        let target = self.playout.target;
+        let target = if SHORTCUT_LATENCY_DOWN.pressed(ctx) {
+            target.saturating_sub(LATENCY_STEP)
+        } else if SHORTCUT_LATENCY_UP.pressed(ctx) {
+            (target + LATENCY_STEP).min(MAX_TARGET_LATENCY)
+        } else {
+            return;
+        };
+        self.playout.set_target(target);
+        self.toast
+            .post(format!("Target latency: {} ms", target.as_millis()));
+    }
 }
 
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.handle_latency_keys(ctx);
+        self.toast.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+
+        // バッファからタイムコードどおりの時刻でフレームを取得
+        // try_lockでブロッキングを回避してパフォーマンス向上
+        // 一時停止中は取り出さずに溜めておき、再開したら止めたフレームの続きから出す
+        // （止めていた分だけ伸びた遅延は、ドリフト補正で少しずつ目標に戻る）
+        let now = Instant::now();
+        self.playout.set_paused(Playback::is_paused(ctx), now);
+        let mut display_image = None;
+        if let Ok(mut buf) = self.frame_buffer.try_lock() {
+            let (image, wait) = self.playout.next(&mut buf, now);
+            display_image = image;
+            // 次のフレームの表示時刻に描き直す（受信スレッドの再描画依頼は到着時なので）
+            if let Some(wait) = wait {
+                ctx.request_repaint_after(wait);
+            }
+            if self.texture.handle().is_some() {
+                self.playout.gauge(ctx, buf.len());
+            }
+            trace!(buffer_size = buf.len(), "Playout");
+        } else {
+            // ロックが取れなかった場合はスキップ（すぐ描き直して再試行）
+            ctx.request_repaint();
+        }
+
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
         egui::CentralPanel::default()
             .frame(panel_frame)
             .show(ctx, |ui| {
-                // バッファから固定遅延でフレームを取得
-                // try_lockでブロッキングを回避してパフォーマンス向上
-                let mut display_image = None;
-                if let Ok(mut buf) = self.frame_buffer.try_lock() {
-                    // バッファに BUFFER_DELAY_FRAMES + 1 以上のフレームが溜まったら表示開始
-                    if buf.len() > BUFFER_DELAY_FRAMES {
-                        if let Some((image, timecode)) = buf.pop_front() {
-                            display_image = Some(image);
-                            println!(
-                                "Displaying frame: timecode={}, buffer_size={}",
-                                timecode,
-                                buf.len()
-                            );
-                        }
-                    }
-                }
-                // ロックが取れなかった場合はスキップ（次のフレームで再試行）
-
                 // 新しい画像が来ていればテクスチャを更新
                 if let Some(image) = display_image {
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR,
-                    ));
+                    self.texture.update(ctx, image);
                 }
 
                 // テクスチャがあれば描画
-                if let Some(texture) = &self.texture {
-                    let size = ui.available_size();
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
                 } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
                 }
             });
     }
 }
 
 fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
 
     eframe::run_native(
-        "NDI Buffered Viewer",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
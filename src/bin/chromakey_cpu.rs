@@ -0,0 +1,201 @@
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use eframe::egui;
+use rust_ndi_viewer::filter::{ChromaKey, ChromaKeySettings};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, AlphaBackground, CliArgs, FrameTexture, HelpOverlay, InfoOverlay, ParamHandle,
+    Pipeline, PixelFormat, ReceiverHandle, SourceOverlay, StreamStats, TransformControl,
+    ValidatedFrame,
+};
+use std::sync::Arc;
+use tracing::trace;
+
+const APP_TITLE: &str = "NDI Chroma Key Viewer (CPU)";
+
+// よく使う抜き色（ボタンで選べる）
+const KEY_PRESETS: [(&str, [u8; 3]); 2] = [("Green", [0, 255, 0]), ("Blue", [0, 0, 255])];
+
+struct NdiApp {
+    // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
+    frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
+
+    // egui用のテクスチャハンドル
+    texture: FrameTexture,
+
+    // 抜く色・許容幅・ソフトネス（スライダーで変え、受信スレッドのフィルタが次のフレームから使う）
+    settings: ParamHandle<ChromaKeySettings>,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
+}
+
+impl NdiApp {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
+        let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
+        let frame_buffer_clone = frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+
+        // 抜けたところが黒と見分けられるよう、最初から市松模様を敷く（C で黒に戻せる）
+        AlphaBackground::set(&cc.egui_ctx, AlphaBackground::Checkerboard);
+
+        // egui::Contextをクローンしてスレッドで使用
+        let ctx = cc.egui_ctx.clone();
+
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        // CPUフィルタはパイプライン側で適用される（画素ごとにHSVで色相の差を見る）
+        let chroma_key = ChromaKey::default();
+        let settings = chroma_key.settings();
+        let receiver = Pipeline::builder()
+            .args(args.clone())
+            .filter(chroma_key)
+            .stats(stats_clone)
+            .on_frame(move |frame| {
+                // キーで抜いたアルファを見せるため、RGBXのソースもRGBAとして扱う
+                let frame = ValidatedFrame {
+                    format: PixelFormat::RGBA,
+                    ..frame
+                };
+                let image = frame.to_color_image();
+
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&frame_buffer_clone, image, &ctx);
+
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (chroma key CPU)"
+                );
+            })
+            .spawn();
+
+        Self {
+            frame_buffer,
+            texture: FrameTexture::from_args(&args),
+            settings,
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            receiver,
+            args,
+        }
+    }
+
+    // 左下の抜き色・許容幅・ソフトネスの調整
+    fn key_window(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Chroma Key")
+            .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut settings = self.settings.get();
+                let mut changed = false;
+
+                ui.horizontal(|ui| {
+                    ui.label("Key color");
+                    changed |= ui.color_edit_button_srgb(&mut settings.key_color).changed();
+                    for (name, color) in KEY_PRESETS {
+                        if ui.button(name).clicked() {
+                            settings.key_color = color;
+                            changed = true;
+                        }
+                    }
+                });
+                let tolerance =
+                    egui::Slider::new(&mut settings.tolerance, ChromaKeySettings::TOLERANCE_RANGE)
+                        .text("hue tolerance")
+                        .suffix("°");
+                changed |= ui.add(tolerance).changed();
+                let softness =
+                    egui::Slider::new(&mut settings.softness, ChromaKeySettings::SOFTNESS_RANGE)
+                        .text("softness")
+                        .suffix("°");
+                changed |= ui.add(softness).changed();
+
+                if ui.button("Reset").clicked() {
+                    settings = ChromaKeySettings::default();
+                    changed = true;
+                }
+                if changed {
+                    self.settings.store(settings);
+                }
+            });
+    }
+}
+
+impl eframe::App for NdiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+        self.key_window(ctx);
+
+        let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
+
+        egui::CentralPanel::default()
+            .frame(panel_frame)
+            .show(ctx, |ui| {
+                // 最新フレームがあるかチェック（ArcSwapでロックフリー読み取り）
+                let new_image = self.frame_buffer.swap(Arc::new(None));
+                let new_image = Arc::try_unwrap(new_image).unwrap_or_else(|arc| (*arc).clone());
+
+                // 新しい画像が来ていればテクスチャを更新
+                if let Some(image) = new_image {
+                    self.texture.update(ctx, image);
+                }
+
+                // テクスチャがあれば描画（透明な部分の後ろに市松模様）
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
+
+                    // Note: NDIスレッドがrequest_repaintを呼ぶため、
+                    // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
+                } else {
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
+                }
+            });
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
+
+    eframe::run_native(
+        APP_TITLE,
+        options,
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
+    )
+    .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
+}
@@ -0,0 +1,252 @@
+//! 受け渡し方式ごとの遅延とドロップを比べるヘッドレスのベンチマーク
+//!
+//! 同じソースから方式ごとに `--frames` 枚ずつ受信し、`--display-fps` で取り出す表示側を
+//! 模擬して、受信してから表示側が取り出すまでの遅延と、表示されずに捨てた数を表にする。
+//! `--synthetic <fps>` を付けるとNDIを使わず、その間隔で生成したフレームで測る。
+
+use anyhow::Result;
+use rust_ndi_viewer::delivery::{Delivery, DeliveryStrategy};
+use rust_ndi_viewer::{
+    exit_if_given_up, init_tracing, CliArgs, NdiReceiver, TransformControl, TARGET_SOURCE_NAME,
+};
+use std::env;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// 比べる方式（buffered は buffered-viewer と同じ設定）
+const STRATEGIES: &[DeliveryStrategy] = &[
+    DeliveryStrategy::Latest,
+    DeliveryStrategy::Bounded(4),
+    DeliveryStrategy::Buffered {
+        capacity: 180,
+        delay: 60,
+    },
+];
+
+const DEFAULT_FRAMES: usize = 600;
+const DEFAULT_DISPLAY_FPS: f64 = 60.0;
+
+/// ベンチマーク専用のオプション（残りは `CliArgs` に渡す）
+struct BenchArgs {
+    frames: usize,
+    display_interval: Duration,
+    synthetic_interval: Option<Duration>,
+    rest: Vec<String>,
+}
+
+impl BenchArgs {
+    fn parse() -> Self {
+        let mut parsed = Self {
+            frames: DEFAULT_FRAMES,
+            display_interval: Duration::from_secs_f64(1.0 / DEFAULT_DISPLAY_FPS),
+            synthetic_interval: None,
+            rest: Vec::new(),
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let name = arg.as_str();
+            if !matches!(name, "--frames" | "--display-fps" | "--synthetic") {
+                parsed.rest.push(arg);
+                continue;
+            }
+            let Some(value) = args.next() else {
+                warn!("{name} requires a value");
+                break;
+            };
+            match name {
+                "--frames" => match value.parse() {
+                    Ok(frames) if frames > 0 => parsed.frames = frames,
+                    _ => warn!("Invalid frame count \"{value}\", ignoring."),
+                },
+                _ => match value.parse::<f64>() {
+                    Ok(fps) if fps > 0.0 && fps.is_finite() => {
+                        let interval = Duration::from_secs_f64(1.0 / fps);
+                        if name == "--synthetic" {
+                            parsed.synthetic_interval = Some(interval);
+                        } else {
+                            parsed.display_interval = interval;
+                        }
+                    }
+                    _ => warn!("Invalid frame rate \"{value}\" for {name}, ignoring."),
+                },
+            }
+        }
+        parsed
+    }
+}
+
+/// 計測用のフレーム供給元
+enum FrameSource {
+    Ndi(Box<NdiReceiver>),
+    Synthetic(Duration),
+}
+
+impl FrameSource {
+    /// `frames` 枚受け取るまで `on_frame` を呼ぶ。戻り値はその間にNDIが落としたフレーム数
+    fn run(&mut self, frames: usize, mut on_frame: impl FnMut()) -> Result<u64> {
+        match self {
+            Self::Ndi(receiver) => {
+                let stats = receiver.stats();
+                let dropped_before = stats.load().dropped;
+                let mut received = 0;
+                receiver.run_loop_until(|_| {
+                    on_frame();
+                    received += 1;
+                    if received >= frames {
+                        ControlFlow::Break(())
+                    } else {
+                        ControlFlow::Continue(())
+                    }
+                })?;
+                Ok(stats.load().dropped.saturating_sub(dropped_before))
+            }
+            Self::Synthetic(interval) => {
+                let mut next = Instant::now();
+                for _ in 0..frames {
+                    next += *interval;
+                    thread::sleep(next.saturating_duration_since(Instant::now()));
+                    on_frame();
+                }
+                Ok(0)
+            }
+        }
+    }
+}
+
+struct Report {
+    strategy: DeliveryStrategy,
+    received: usize,
+    // 受信からの遅延（表示側が取り出したフレームのみ）
+    latencies: Vec<Duration>,
+    dropped: u64,
+    ndi_dropped: u64,
+}
+
+fn measure(
+    source: &mut FrameSource,
+    strategy: DeliveryStrategy,
+    frames: usize,
+    display_interval: Duration,
+) -> Result<Report> {
+    let delivery = Delivery::<Instant>::new(strategy);
+    let done = Arc::new(AtomicBool::new(false));
+
+    // 表示側: 描画のたびに1枚取り出すのを固定間隔で模擬する
+    let display = thread::spawn({
+        let delivery = delivery.clone();
+        let done = done.clone();
+        move || {
+            let mut latencies = Vec::new();
+            let mut next = Instant::now();
+            loop {
+                next += display_interval;
+                thread::sleep(next.saturating_duration_since(Instant::now()));
+                let finished = done.load(Ordering::Relaxed);
+                match delivery.pop() {
+                    Some(received_at) => latencies.push(received_at.elapsed()),
+                    None if finished => break,
+                    None => {}
+                }
+            }
+            latencies
+        }
+    });
+
+    let result = source.run(frames, || delivery.push(Instant::now()));
+    done.store(true, Ordering::Relaxed);
+    let latencies = display
+        .join()
+        .map_err(|_| anyhow::anyhow!("Display thread panicked"))?;
+
+    Ok(Report {
+        strategy,
+        received: frames,
+        latencies,
+        dropped: delivery.dropped(),
+        ndi_dropped: result?,
+    })
+}
+
+fn print_table(reports: &[Report]) {
+    println!(
+        "\n{:<28} {:>8} {:>7} {:>8} {:>8} {:>9} {:>9} {:>9} {:>9}",
+        "Strategy",
+        "Received",
+        "Shown",
+        "Dropped",
+        "Pending",
+        "NDI drop",
+        "Avg ms",
+        "p95 ms",
+        "Max ms"
+    );
+    for report in reports {
+        let mut latencies = report.latencies.clone();
+        latencies.sort();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let shown = latencies.len();
+        let avg = latencies.iter().sum::<Duration>() / shown.max(1) as u32;
+        let p95 = latencies
+            .get((shown * 95 / 100).min(shown.saturating_sub(1)))
+            .copied()
+            .unwrap_or_default();
+        let max = latencies.last().copied().unwrap_or_default();
+        // 遅延バッファに残ったまま終わったフレーム
+        let pending = report
+            .received
+            .saturating_sub(shown + report.dropped as usize);
+        println!(
+            "{:<28} {:>8} {:>7} {:>8} {:>8} {:>9} {:>9.1} {:>9.1} {:>9.1}",
+            report.strategy.to_string(),
+            report.received,
+            shown,
+            report.dropped,
+            pending,
+            report.ndi_dropped,
+            ms(avg),
+            ms(p95),
+            ms(max)
+        );
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let bench = BenchArgs::parse();
+    let args = CliArgs::parse_from(bench.rest);
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+
+    let mut source = match bench.synthetic_interval {
+        Some(interval) => FrameSource::Synthetic(interval),
+        None => {
+            let source_name = (!TARGET_SOURCE_NAME.is_empty()).then_some(TARGET_SOURCE_NAME);
+            match NdiReceiver::connect_to(source_name, &args) {
+                Ok(receiver) => FrameSource::Ndi(Box::new(receiver)),
+                Err(e) => {
+                    exit_if_given_up(&e);
+                    return Err(e);
+                }
+            }
+        }
+    };
+
+    let mut reports = Vec::new();
+    for &strategy in STRATEGIES {
+        info!("Measuring {strategy} over {} frames ...", bench.frames);
+        let report = measure(&mut source, strategy, bench.frames, bench.display_interval);
+        if let Err(e) = &report {
+            exit_if_given_up(e);
+        }
+        reports.push(report?);
+    }
+    print_table(&reports);
+    Ok(())
+}
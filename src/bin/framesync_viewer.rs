@@ -3,90 +3,149 @@
 //! Uses the FrameSync API for time-base corrected capture. Ideal for smooth
 //! playback synced to display refresh (e.g. GPU v-sync). FrameSync captures
 //! return immediately and handle clock drift between sender and receiver.
+//!
+//! 取り込みは `update` の中で1回の描画につき1フレーム行い、間隔はv-syncに任せる。
+//! 以前は別スレッドで33ms固定のsleepを挟んで取り込んでいたが、その周期はディスプレイの
+//! リフレッシュと無関係なので、60Hzでは1枚の画が2回・1回・3回…と不揃いに表示され
+//! （sleepの誤差とスレッドの起床の揺れでリズムが崩れる）、120Hzではさらに細かくずれてジャダーになる。
+//! 60fpsのソースも30fps程度に間引かれていた。描画のたびに取り込めば、FrameSyncが
+//! ソースのフレームをディスプレイの時計に合わせて均等に繰り返し・間引きしてくれる。
 
 use anyhow::Result;
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use eframe::egui;
 use grafton_ndi::{
-    Finder, FinderOptions, FrameSync, LineStrideOrSize, PixelFormat, Receiver,
-    ReceiverColorFormat, ReceiverOptions, ScanType, NDI,
+    Finder, FinderOptions, FrameSync, LineStrideOrSize, PixelFormat, Receiver, ReceiverOptions,
+    ScanType,
+};
+use rust_ndi_viewer::convert;
+use rust_ndi_viewer::deinterlace::{Deinterlace, FieldOrder};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, display_size, handle_common_input, init_ndi, init_tracing, status_label,
+    with_discovery_groups, CliArgs, ConnectionState, FrameError, FrameTexture, GiveUp, HelpOverlay,
+    InfoOverlay, NdiError, Playback, Shortcut, SourceOverlay, StreamStats, Toast, TransformControl,
+    DEFAULT_COLOR_FORMAT, TARGET_SOURCE_NAME,
 };
-use rust_ndi_viewer::{create_native_options, TARGET_SOURCE_NAME};
-use std::env;
-use std::sync::Arc;
+use std::process;
+use std::sync::{Arc, OnceLock};
 use std::thread;
 use std::time::Duration;
+use tracing::{error, info, warn};
+
+const APP_TITLE: &str = "NDI FrameSync Viewer";
 
-const CAPTURE_INTERVAL_MS: u64 = 33; // ~30 fps display rate
+const SHORTCUT_DEINTERLACE: Shortcut = Shortcut {
+    keys: &[egui::Key::D],
+    description: "Cycle deinterlacing: none / bob / blend",
+};
+
+const FRAMESYNC_VIEWER_SHORTCUTS: &[Shortcut] = &[SHORTCUT_DEINTERLACE];
 
 struct NdiApp {
-    frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
-    texture: Option<egui::TextureHandle>,
+    // 接続できたら受信スレッドが入れる。以後は描画のたびにここから1フレーム取り込む
+    framesync: Arc<OnceLock<FrameSync<'static>>>,
+    // RGBA以外のフォーマットやデインターレースするフレームの変換先（使い回す）
+    rgba: Vec<u8>,
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // 受信スレッドが接続できたソースを書き込む
+    current_source: Arc<ArcSwapOption<String>>,
+    // 映像が出るまでの表示（探索中・接続中・失敗の理由）
+    connection_state: Arc<ArcSwap<ConnectionState>>,
+
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+    // FrameSyncは受信統計を取らないので、HUD用にタイムコードと解像度（と表示できない理由）だけ入れる
+    stats: StreamStats,
+
+    // D で切り替えるデインターレースの方法
+    deinterlace: Deinterlace,
+    // 切り替えた方法の表示
+    toast: Toast,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
-        let frame_buffer_clone = frame_buffer.clone();
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
+        let framesync = Arc::new(OnceLock::new());
+        let framesync_clone = framesync.clone();
+        let current_source = Arc::new(ArcSwapOption::empty());
+        let current_source_clone = current_source.clone();
+        let connection_state = Arc::new(ArcSwap::from_pointee(ConnectionState::Searching));
+        let connection_state_clone = connection_state.clone();
         let ctx = cc.egui_ctx.clone();
+        let deinterlace = args.deinterlace;
+        let connect_args = args.clone();
 
         thread::spawn(move || {
-            let args: Vec<String> = env::args().collect();
-            let mut extra_ips = Vec::new();
-            let mut i = 1;
-            while i < args.len() {
-                if !args[i].starts_with("--") {
-                    extra_ips.push(args[i].as_str());
-                }
-                i += 1;
-            }
-
-            println!("NDI FrameSync Viewer");
-            println!("====================\n");
+            let extra_ips = &connect_args.extra_ips;
+            // 受信スレッドを諦めるときは、理由をウィンドウにも出す
+            let fail = |message: String| {
+                error!("{message}");
+                connection_state_clone.store(Arc::new(ConnectionState::Failed(message)));
+                ctx.request_repaint();
+            };
 
-            let ndi = match NDI::new() {
+            let ndi = match init_ndi() {
                 Ok(n) => n,
-                Err(e) => {
-                    eprintln!("NDI init failed: {e}");
+                // ランタイムが無いだけなら終了せず、入れ方をウィンドウに出す
+                Err(e) if NdiError::of(&e).is_some() => {
+                    error!("{e}");
+                    connection_state_clone.store(Arc::new(ConnectionState::failed(&e)));
+                    ctx.request_repaint();
                     return;
                 }
+                Err(e) => {
+                    error!("NDI init failed: {e}");
+                    process::exit(GiveUp::NdiInit.exit_code());
+                }
             };
 
-            let mut builder = FinderOptions::builder().show_local_sources(true);
+            let mut builder = with_discovery_groups(
+                FinderOptions::builder().show_local_sources(true),
+                connect_args.groups.as_deref(),
+            );
             if !extra_ips.is_empty() {
-                println!("Searching additional IPs/subnets:");
-                for ip in &extra_ips {
-                    println!("  - {ip}");
-                    builder = builder.extra_ips(*ip);
+                info!("Searching additional IPs/subnets:");
+                for ip in extra_ips {
+                    info!("  - {ip}");
+                    builder = builder.extra_ips(ip.as_str());
                 }
-                println!();
             }
 
             let finder = match Finder::new(&ndi, &builder.build()) {
                 Ok(f) => f,
                 Err(e) => {
-                    eprintln!("Finder failed: {e}");
+                    fail(format!("Finder failed: {e}"));
                     return;
                 }
             };
 
-            println!("Looking for sources ...");
+            info!("Looking for sources ...");
             let sources = loop {
                 if let Err(e) = finder.wait_for_sources(Duration::from_secs(1)) {
-                    eprintln!("wait_for_sources: {e}");
+                    fail(format!("wait_for_sources: {e}"));
                     return;
                 }
                 match finder.sources(Duration::ZERO) {
                     Ok(s) if !s.is_empty() => {
-                        println!("Found {} source(s):", s.len());
+                        info!("Found {} source(s):", s.len());
                         for (i, source) in s.iter().enumerate() {
-                            println!("  {}. {source}", i + 1);
+                            info!("  {}. {source}", i + 1);
                         }
                         break s;
                     }
                     Ok(_) => {}
                     Err(e) => {
-                        eprintln!("sources: {e}");
+                        fail(format!("sources: {e}"));
                         return;
                     }
                 }
@@ -98,129 +157,204 @@ impl NdiApp {
                 match sources.iter().find(|s| s.name == TARGET_SOURCE_NAME) {
                     Some(s) => s,
                     None => {
-                        eprintln!("No NDI source named \"{TARGET_SOURCE_NAME}\" available");
-                        return;
+                        error!("No NDI source named \"{TARGET_SOURCE_NAME}\" available");
+                        process::exit(GiveUp::Discovery.exit_code());
                     }
                 }
             };
 
-            println!("\nCreating receiver for: {source}");
+            info!("Creating receiver for: {source}");
+            connection_state_clone.store(Arc::new(ConnectionState::Connecting));
+            current_source_clone.store(Some(Arc::new(source.to_string())));
             let recv_opts = ReceiverOptions::builder(source.clone())
-                .color(ReceiverColorFormat::RGBX_RGBA)
+                .color(connect_args.color_format.unwrap_or(DEFAULT_COLOR_FORMAT))
+                .bandwidth(connect_args.bandwidth)
                 .build();
             let receiver = match Receiver::new(&ndi, &recv_opts) {
                 Ok(r) => r,
                 Err(e) => {
-                    eprintln!("Receiver failed: {e}");
+                    fail(format!("Receiver failed: {e}"));
                     return;
                 }
             };
 
-            println!("Creating FrameSync for clock-corrected capture...");
-            let framesync = match FrameSync::new(&receiver) {
+            // FrameSyncは受信機を借りるので、GUIスレッドに渡せるよう受信機はプロセスの終わりまで残す
+            // （接続し直すことはないので1つだけ）
+            let receiver: &'static Receiver = Box::leak(Box::new(receiver));
+            info!("Creating FrameSync for clock-corrected capture...");
+            let framesync = match FrameSync::new(receiver) {
                 Ok(fs) => fs,
                 Err(e) => {
-                    eprintln!("FrameSync failed: {e}");
+                    fail(format!("FrameSync failed: {e}"));
                     return;
                 }
             };
-            println!("FrameSync created. Starting capture loop...\n");
-
-            loop {
-                if let Some(video) = framesync.capture_video(ScanType::Progressive) {
-                    if let Some(image) = validate_and_convert(&video) {
-                        frame_buffer_clone.store(Arc::new(Some(image)));
-                        ctx.request_repaint();
-                    }
-                }
-                thread::sleep(Duration::from_millis(CAPTURE_INTERVAL_MS));
-            }
+            info!("FrameSync created. Capturing once per display refresh...");
+            let _ = framesync_clone.set(framesync);
+            ctx.request_repaint();
         });
 
         Self {
-            frame_buffer,
-            texture: None,
+            framesync,
+            rgba: Vec::new(),
+            texture: FrameTexture::from_args(&args),
+            help: HelpOverlay::default().with_extra(FRAMESYNC_VIEWER_SHORTCUTS),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            current_source,
+            connection_state,
+            info: InfoOverlay::default(),
+            stats: StreamStats::default(),
+            deinterlace,
+            toast: Toast::default(),
+            args,
+        }
+    }
+
+    /// 今の時点のフレームを1枚取り込んでテクスチャに載せる（描画のたびに呼ぶ）
+    ///
+    /// FrameSyncはすぐに返り、ソースより描画が速ければ同じフレームを、遅ければ間引いたフレームを返す。
+    fn capture(&mut self, ctx: &egui::Context) {
+        let Some(framesync) = self.framesync.get() else {
+            return;
+        };
+        // 一時停止中は取り込まない（FrameSyncは溜め込まないので取り出す必要もない）
+        if Playback::is_paused(ctx) && self.texture.handle().is_some() {
+            return;
+        }
+        let Some(video) = framesync.capture_video(ScanType::Progressive) else {
+            return;
+        };
+        // サイズが空のフレームと圧縮フレームは変換できないので、捨てる理由を待機画面に出す
+        let frame_error = FrameError::empty_size(video.width(), video.height())
+            .or_else(|| FrameError::compressed(video.line_stride_or_size(), video.pixel_format()));
+        if let Some(e) = frame_error
+            && self.stats.frame_error.is_none()
+        {
+            warn!("{e}; skipping its frames.");
+        }
+        let image = if frame_error.is_none() {
+            validate_and_convert(&video, &mut self.rgba, self.deinterlace)
+        } else {
+            None
+        };
+        if image.is_some() || frame_error.is_some() {
+            self.stats = StreamStats {
+                timecode: video.timecode(),
+                frame_size: [video.width(), video.height()],
+                frame_error,
+                ..StreamStats::default()
+            };
+        }
+        if let Some(image) = image {
+            self.texture.update(ctx, image);
         }
     }
 }
 
 fn validate_and_convert(
     video: &grafton_ndi::FrameSyncVideoRef<'_>,
+    rgba: &mut Vec<u8>,
+    mode: Deinterlace,
 ) -> Option<egui::ColorImage> {
-    match video.pixel_format() {
-        PixelFormat::RGBA | PixelFormat::RGBX => {}
-        _ => return None,
-    }
-
     let line_stride = match video.line_stride_or_size() {
         LineStrideOrSize::LineStrideBytes(s) => s,
-        LineStrideOrSize::DataSizeBytes(_) => return None,
+        LineStrideOrSize::DataSizeBytes(_) => return None, // compressed
     };
-    let width = video.width();
-    let height = video.height();
-    if line_stride != width * 4 {
+    // 0以下のサイズを usize にすると、下のサイズ計算が0になるか桁あふれする
+    if FrameError::empty_size(video.width(), video.height()).is_some() {
         return None;
     }
-
-    let expected_size = (width * height * 4) as usize;
+    let width = video.width() as usize;
+    let height = video.height() as usize;
+    let format = video.pixel_format();
     let data = video.data();
-    if data.len() < expected_size {
-        return None; // truncated or compressed
-    }
 
-    let image = egui::ColorImage::from_rgba_unmultiplied(
-        [width as usize, height as usize],
-        &data[..expected_size],
-    );
+    // 詰まったRGBA/RGBXはそのまま使う。それ以外（パディング付きの行や --color-format fastest 等）は
+    // 行ごとに `width*4` に詰めたRGBAにそろえる。デインターレースするフレームは書き換えるので、
+    // 詰まっていても `rgba` に写してから
+    let interlaced = video.scan_type() == ScanType::Interlaced && mode != Deinterlace::None;
+    let packed_size = match convert::rgba_size(width, height) {
+        Ok(size) => size,
+        Err(e) => {
+            warn!("{e}; skipping frame.");
+            return None;
+        }
+    };
+    let pixels = if matches!(format, PixelFormat::RGBA | PixelFormat::RGBX)
+        && line_stride as usize == width * 4
+        && data.len() >= packed_size
+        && !interlaced
+    {
+        &data[..packed_size]
+    } else {
+        if let Err(e) = convert::to_rgba(format, width, height, line_stride as usize, data, rgba) {
+            warn!("{e}; skipping frame.");
+            return None;
+        }
+        if interlaced {
+            mode.apply(rgba, height, FieldOrder::TopFirst);
+        }
+        rgba.as_slice()
+    };
+
+    let mut image = egui::ColorImage::from_rgba_unmultiplied([width, height], pixels);
+    image.source_size = display_size(width as i32, height as i32, video.picture_aspect_ratio());
     Some(image)
 }
 
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        if SHORTCUT_DEINTERLACE.pressed(ctx) {
+            self.deinterlace = self.deinterlace.next();
+            self.toast
+                .post(format!("Deinterlace: {}", self.deinterlace.name()));
+        }
+        self.capture(ctx);
+        self.toast.show(ctx);
+        self.source_overlay
+            .show(ctx, self.current_source.load_full());
+        self.info
+            .show(ctx, self.current_source.load_full(), &self.stats);
+
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
         egui::CentralPanel::default()
             .frame(panel_frame)
             .show(ctx, |ui| {
-                let new_image = self.frame_buffer.swap(Arc::new(None));
-                let new_image = Arc::try_unwrap(new_image).unwrap_or_else(|arc| (*arc).clone());
-
-                if let Some(image) = new_image {
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR,
-                    ));
-                }
-
-                if let Some(texture) = &self.texture {
-                    let size = ui.available_size();
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
                 } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                    status_label(ui, &self.connection_state.load(), &self.stats);
                 }
             });
+
+        // 接続できていれば次の描画もすぐ頼み、v-syncで取り込みの間隔を決める
+        if self.framesync.get().is_some() {
+            ctx.request_repaint();
+        }
     }
 }
 
 fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
 
     eframe::run_native(
-        "NDI FrameSync Viewer",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
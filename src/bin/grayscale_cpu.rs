@@ -1,86 +1,98 @@
 use anyhow::Result;
 use arc_swap::ArcSwap;
 use eframe::egui;
-use rust_ndi_viewer::{create_native_options, NdiReceiver, TARGET_SOURCE_NAME};
+use rust_ndi_viewer::filter::Grayscale;
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, CliArgs, FrameTexture, HelpOverlay, InfoOverlay, Pipeline, ReceiverHandle,
+    SourceOverlay, StreamStats, TransformControl,
+};
 use std::sync::Arc;
-use std::thread;
+use tracing::trace;
+
+const APP_TITLE: &str = "NDI Grayscale Viewer (CPU)";
 
 struct NdiApp {
     // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
     frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
 
     // egui用のテクスチャハンドル
-    texture: Option<egui::TextureHandle>,
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
         let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
         let frame_buffer_clone = frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
 
         // egui::Contextをクローンしてスレッドで使用
         let ctx = cc.egui_ctx.clone();
 
-        // NDI receiver thread
-        thread::spawn(move || {
-            let receiver = match NdiReceiver::connect() {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("Failed to connect to NDI: {e}");
-                    return;
-                }
-            };
-
-            let _ = receiver.run_loop(|frame| {
-                // CPUでグレースケール変換（ITU-R BT.601）
-                // 整数演算で高速化（固定小数点: 256倍スケール）
-                // copy frame
-                let mut grayscale_data = frame.data.to_owned();
-
-                for chunk in grayscale_data.chunks_exact_mut(4) {
-                    let r = chunk[0] as u32;
-                    let g = chunk[1] as u32;
-                    let b = chunk[2] as u32;
-
-                    // 輝度計算: Y = 0.299*R + 0.587*G + 0.114*B
-                    // 固定小数点: 77*R + 150*G + 29*B >> 8
-                    let gray = ((77 * r + 150 * g + 29 * b) >> 8) as u8;
-
-                    // R, G, B を gray 値に置換
-                    chunk[0] = gray;
-                    chunk[1] = gray;
-                    chunk[2] = gray;
-                    // Alpha は元のまま（chunk[3]）
-                }
-
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        // CPUでグレースケール変換（ITU-R BT.601）はパイプライン側で適用される
+        let receiver = Pipeline::builder()
+            .args(args.clone())
+            .filter(Grayscale)
+            .stats(stats_clone)
+            .on_frame(move |frame| {
                 // Convert to egui::ColorImage
-                let image = egui::ColorImage::from_rgba_unmultiplied(
-                    [frame.width as usize, frame.height as usize],
-                    &grayscale_data,
-                );
+                let image = frame.to_color_image();
 
-                // Store using ArcSwap (lock-free)
-                frame_buffer_clone.store(Arc::new(Some(image)));
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&frame_buffer_clone, image, &ctx);
 
-                // これをしないとマウスカーソルを動かさないと再描画されない
-                ctx.request_repaint();
-
-                println!(
-                    "Frame received (grayscale CPU): {}x{}, timecode={}",
-                    frame.width, frame.height, frame.timecode
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (grayscale CPU)"
                 );
-            });
-        });
+            })
+            .spawn();
 
         Self {
             frame_buffer,
-            texture: None,
+            texture: FrameTexture::from_args(&args),
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            receiver,
+            args,
         }
     }
 }
 
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
         egui::CentralPanel::default()
@@ -91,47 +103,41 @@ impl eframe::App for NdiApp {
                 let new_image = Arc::try_unwrap(new_image).unwrap_or_else(|arc| (*arc).clone());
 
                 // 新しい画像が来ていればテクスチャを更新
-                let has_new_frame = new_image.is_some();
                 if let Some(image) = new_image {
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR,
-                    ));
+                    self.texture.update(ctx, image);
                 }
 
                 // テクスチャがあれば描画
-                if let Some(texture) = &self.texture {
-                    let size = ui.available_size();
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
 
                     // Note: NDIスレッドがrequest_repaintを呼ぶため、
                     // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
                 } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
                 }
             });
     }
 }
 
 fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
 
     eframe::run_native(
-        "NDI Grayscale Viewer (CPU)",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
@@ -0,0 +1,23 @@
+// grayscale.wgsl のテクスチャ版: 入力テクスチャから読み、eguiが描画するテクスチャに直接書く
+@group(0) @binding(0) var input: texture_2d<u32>;
+@group(0) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    // テクスチャの範囲チェック（端のワークグループははみ出す）
+    let size = textureDimensions(input);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+
+    // 入力は Rgba8Uint なので 0-255 の整数のまま読める
+    let pixel = textureLoad(input, vec2<u32>(id.xy), 0);
+
+    // ITU-R BT.601 輝度計算（grayscale.wgsl と同じ整数演算で、結果もCPU版と一致する）
+    // 固定小数点: (77*R + 150*G + 29*B) >> 8
+    let gray = (77u * pixel.r + 150u * pixel.g + 29u * pixel.b) >> 8u;
+
+    // グレースケール値を R, G, B に設定し、Alpha は 1.0
+    let value = f32(gray) / 255.0;
+    textureStore(output, vec2<u32>(id.xy), vec4<f32>(value, value, value, 1.0));
+}
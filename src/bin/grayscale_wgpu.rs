@@ -1,107 +1,281 @@
 use anyhow::Result;
 use arc_swap::ArcSwap;
 use eframe::egui;
-use rust_ndi_viewer::{create_native_options, NdiReceiver, TARGET_SOURCE_NAME};
+use rust_ndi_viewer::filter::{Filter, Grayscale};
+use rust_ndi_viewer::gpu::{self, EguiTexture, TextureCompute, TilePlanner};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, CliArgs, FrameTexture, HelpOverlay, InfoOverlay, NdiReceiver, OwnedFrame,
+    Playback, ReceiverHandle, SourceOverlay, StreamStats, TransformControl,
+};
 use std::sync::Arc;
-use std::thread;
-use wgpu::util::DeviceExt;
+use tracing::{error, info, trace, warn};
+
+const APP_TITLE: &str = "NDI Grayscale Viewer (WGPU)";
 
 // コンピュートシェーダーのワークグループサイズ
 const WORKGROUP_SIZE: u32 = 256;
 
-// 生のRGBAフレームデータ（wgpuで処理する前）
-#[derive(Clone)]
-struct RawFrame {
-    width: i32,
-    height: i32,
-    data: Vec<u8>,
+// グレースケール変換のコンピュートパイプライン
+struct GrayscaleCompute {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+}
+
+// GrayscaleCompute の入出力・ステージングバッファとバインドグループ
+//
+// 60fpsで毎フレーム作り直すとGPUメモリの確保・解放が続くので、フレームをまたいで使い回し、
+// 幅・高さが変わったときだけ作り直す。シェーダーは arrayLength で範囲を決めるので、大きめに確保して
+// 使い回すことはしない（横帯に分けたフレームの最後の帯のように高さが違えば作り直す）。
+struct BufferPool {
+    width: u32,
+    height: u32,
+    input: wgpu::Buffer,
+    output: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl BufferPool {
+    fn new(compute: &GrayscaleCompute, width: u32, height: u32) -> Self {
+        let byte_size = width as u64 * height as u64 * 4;
+        let device = &compute.device;
+
+        // 入力バッファ（毎フレーム write_buffer で書き込む）
+        let input = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Input Buffer"),
+            size: byte_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // 出力バッファ
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Output Buffer"),
+            size: byte_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        // CPUに読み戻すためのステージングバッファ
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Staging Buffer"),
+            size: byte_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // バインドグループもバッファと一緒に使い回す
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Grayscale Bind Group"),
+            layout: &compute.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: output.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            width,
+            height,
+            input,
+            output,
+            staging,
+            bind_group,
+        }
+    }
+
+    // Dropを待たずにGPUメモリを解放する
+    fn destroy(self) {
+        self.input.destroy();
+        self.output.destroy();
+        self.staging.destroy();
+    }
+}
+
+// GPUアダプタが無い環境（ヘッドレスCI、最小構成のRaspberry Pi OSなど）ではCPUで同じ変換をする
+enum GrayscaleBackend {
+    // eguiのテクスチャに直接書く（eframeがwgpuレンダラーで、そのデバイスでコンピュートが使えるとき）。
+    // Gpu はアップロード → 読み戻し → eguiテクスチャへの再アップロードと3回コピーするが、こちらは1回で済む
+    Texture(Box<TextureCompute>),
+    // 上限を超える大きなフレームは横帯に分けて処理する（バッファはフレームをまたいで使い回す）
+    Gpu(GrayscaleCompute, Option<BufferPool>, TilePlanner),
+    Cpu,
+}
+
+impl GrayscaleBackend {
+    /// 解像度が変わったときに、前の解像度のバッファを先に解放する（次のフレームで作り直す）
+    ///
+    /// `Texture` は [`TextureCompute::process`] が自分で作り直す。
+    fn resize(&mut self) {
+        if let Self::Gpu(_, buffers, _) = self
+            && let Some(pool) = buffers.take()
+        {
+            pool.destroy();
+        }
+    }
+
+    /// 戻り値は `(data, width, height)`（デバイスの上限によっては縮小される）
+    ///
+    /// `Texture` は結果をCPUに戻さないので、ここでは使わない（[`TextureCompute::process`]）。
+    fn process(&mut self, width: u32, height: u32, input: &[u8]) -> Result<(Vec<u8>, u32, u32)> {
+        match self {
+            Self::Texture(_) => anyhow::bail!("The texture backend does not read back to the CPU"),
+            Self::Gpu(compute, buffers, tiles) => tiles.run(
+                width,
+                height,
+                input,
+                |width| compute.max_rows(width),
+                |width, height, input| compute.process(buffers, width, height, input),
+            ),
+            Self::Cpu => {
+                let mut data = input.to_vec();
+                Grayscale.apply(width as usize, height as usize, &mut data);
+                Ok((data, width, height))
+            }
+        }
+    }
 }
 
 struct NdiApp {
     // スレッド間で共有する生フレームバッファ（ArcSwapでロックフリー）
-    raw_frame_buffer: Arc<ArcSwap<Option<RawFrame>>>,
+    raw_frame_buffer: Arc<ArcSwap<Option<OwnedFrame>>>,
 
-    // wgpuリソース
-    device: Arc<wgpu::Device>,
-    queue: Arc<wgpu::Queue>,
-    pipeline: wgpu::ComputePipeline,
-    bind_group_layout: wgpu::BindGroupLayout,
+    // wgpuリソース（使えなければCPUフォールバック）
+    compute: GrayscaleBackend,
 
     // egui用のテクスチャハンドル
-    texture: Option<egui::TextureHandle>,
+    texture: FrameTexture,
+
+    // 直近のフレームの (幅, 高さ)（変わったらバッファを作り直す）
+    last_dimensions: Option<(u32, u32)>,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
         let raw_frame_buffer = Arc::new(ArcSwap::from_pointee(None));
         let raw_frame_buffer_clone = raw_frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
 
         // egui::Contextをクローンしてスレッドで使用
         let ctx = cc.egui_ctx.clone();
 
-        // NDI receiver thread
-        thread::spawn(move || {
-            let receiver = match NdiReceiver::connect() {
-                Ok(r) => r,
-                Err(e) => {
-                    eprintln!("Failed to connect to NDI: {e}");
-                    return;
-                }
-            };
-
-            let _ = receiver.run_loop(|frame| {
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        let connect_args = args.clone();
+        let receiver = ReceiverHandle::spawn(
+            move |stop| {
+                Ok(NdiReceiver::connect_with_stop(&connect_args, stop)?.with_stats(stats_clone))
+            },
+            move |frame| {
                 // copy frame
-                let data = frame.data.to_vec();
-                let raw = RawFrame {
-                    width: frame.width,
-                    height: frame.height,
-                    data,
-                };
+                let raw = frame.to_owned();
 
-                // Store using ArcSwap (lock-free)
-                raw_frame_buffer_clone.store(Arc::new(Some(raw)));
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&raw_frame_buffer_clone, raw, &ctx);
 
-                // これをしないとマウスカーソルを動かさないと再描画されない
-                ctx.request_repaint();
-
-                println!(
-                    "Frame received (for wgpu): {}x{}, timecode={}",
-                    frame.width, frame.height, frame.timecode
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (for wgpu)"
                 );
-            });
-        });
-
-        // wgpuインスタンスを手動で作成
-        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
-        });
-
-        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::HighPerformance,
-            compatible_surface: None,
-            force_fallback_adapter: false,
-        }))
-        .expect("Failed to find an appropriate adapter");
-
-        // Raspi4 (Mobile/Downlevel向け) の制限設定
-        let mut limits = wgpu::Limits::downlevel_defaults();
-        limits.max_color_attachments = 4;
-
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: Some("Device"),
-                required_features: wgpu::Features::empty(),
-                required_limits: limits,
-                memory_hints: Default::default(),
             },
-            None,
-        ))
-        .expect("Failed to create device");
+        );
+
+        // eguiがwgpuで描画していれば、同じデバイスで処理して結果を直接表示する。
+        // --backend-order を指定したときは、従来どおり別デバイスを作って読み戻す
+        // （--backend / --adapter はeguiのデバイスの選択にも使うので、このままでよい）
+        let shared = match (&cc.wgpu_render_state, &args.backend_order) {
+            (Some(render_state), None) => {
+                info!(
+                    "egui renders on {}",
+                    gpu::describe_adapter(&render_state.adapter.get_info())
+                );
+                let output = EguiTexture::new(render_state).with_filter(args.texture_filter);
+                TextureCompute::new(
+                    output,
+                    "Grayscale Texture",
+                    include_str!("grayscale_texture.wgsl"),
+                    args.max_texture_side,
+                )
+                .inspect_err(|e| warn!("{e:#}; falling back to GPU readback."))
+                .ok()
+            }
+            _ => None,
+        };
+        let compute = match shared {
+            Some(compute) => {
+                info!("Using egui's wgpu device (zero-copy texture output)");
+                GrayscaleBackend::Texture(Box::new(compute))
+            }
+            None => readback_backend(&args),
+        };
+
+        Self {
+            raw_frame_buffer,
+            compute,
+            texture: FrameTexture::from_args(&args),
+            last_dimensions: None,
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            receiver,
+            args,
+        }
+    }
+}
 
-        let device = Arc::new(device);
-        let queue = Arc::new(queue);
+// 別のwgpuデバイスで処理してCPUに読み戻す（使えなければCPUで変換する）
+fn readback_backend(args: &CliArgs) -> GrayscaleBackend {
+    let backend_order = gpu::backend_order(args);
+    let compute = gpu::init_with_fallback(&backend_order, args.adapter, |device, queue| {
+        let compute = GrayscaleCompute::new(device, queue);
+        // 実行時に失敗するバックエンドもあるので、試しに1回ディスパッチしておく
+        let (width, height) = (WORKGROUP_SIZE, 1);
+        let input = vec![0u8; (width * height * 4) as usize];
+        compute.process(&mut None, width, height, &input)?;
+        Ok(compute)
+    });
+    match compute {
+        // 画素ごとの変換なので、帯の間ののりしろは要らない
+        Ok(compute) => GrayscaleBackend::Gpu(compute, None, TilePlanner::new(0)),
+        Err(e) => {
+            warn!("{e:#}; falling back to CPU grayscale.");
+            GrayscaleBackend::Cpu
+        }
+    }
+}
 
+impl GrayscaleCompute {
+    fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
         // コンピュートシェーダーのコンパイル
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Grayscale Compute Shader"),
@@ -153,59 +327,47 @@ impl NdiApp {
         });
 
         Self {
-            raw_frame_buffer,
             device,
             queue,
             pipeline,
             bind_group_layout,
-            texture: None,
         }
     }
 
-    fn process_frame_with_wgpu(&self, raw: &RawFrame) -> Vec<u8> {
-        let pixel_count = (raw.width as u32) * (raw.height as u32);
-        let byte_size = (pixel_count * 4) as usize;
-
-        // 入力バッファを作成してデータをアップロード
-        let input_buffer = self
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Input Buffer"),
-                contents: &raw.data,
-                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            });
-
-        // 出力バッファを作成
-        let output_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Output Buffer"),
-            size: byte_size as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
-
-        // CPUに読み戻すためのステージングバッファ
-        let staging_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Staging Buffer"),
-            size: byte_size as u64,
-            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+    // 1回のディスパッチで処理できる最大行数（バインドサイズとワークグループ数の上限から）
+    fn max_rows(&self, width: u32) -> u32 {
+        let limits = self.device.limits();
+        let row_bytes = width as u64 * 4;
+        let max_bytes = limits
+            .max_buffer_size
+            .min(limits.max_storage_buffer_binding_size as u64);
+        let max_pixels = limits.max_compute_workgroups_per_dimension as u64 * WORKGROUP_SIZE as u64;
+        (max_bytes / row_bytes)
+            .min(max_pixels / width as u64)
+            .min(u32::MAX as u64) as u32
+    }
 
-        // バインドグループを作成
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Grayscale Bind Group"),
-            layout: &self.bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: input_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: output_buffer.as_entire_binding(),
-                },
-            ],
-        });
+    /// `buffers` は前回のフレームのバッファ（サイズが同じなら使い回し、違えば作り直す）
+    fn process(
+        &self,
+        buffers: &mut Option<BufferPool>,
+        width: u32,
+        height: u32,
+        input: &[u8],
+    ) -> Result<Vec<u8>> {
+        let pixel_count = width * height;
+        // u32のまま掛けると大きなフレームで桁あふれするので、u64で求める（BufferPool::new と同じ）
+        let byte_size = width as u64 * height as u64 * 4;
+
+        // 解像度が変わらないストリームでは毎フレーム同じバッファを使う。
+        // 途中で失敗したらマップ待ちのまま残らないように捨て、次のフレームで作り直す
+        let pool = match buffers.take() {
+            Some(pool) if (pool.width, pool.height) == (width, height) => pool,
+            _ => BufferPool::new(self, width, height),
+        };
+
+        // 入力データをアップロード
+        self.queue.write_buffer(&pool.input, 0, input);
 
         // コマンドエンコーダーを作成
         let mut encoder = self
@@ -220,42 +382,52 @@ impl NdiApp {
                 timestamp_writes: None,
             });
             compute_pass.set_pipeline(&self.pipeline);
-            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.set_bind_group(0, &pool.bind_group, &[]);
 
             // ワークグループ数を計算（切り上げ除算）
-            let workgroup_count = (pixel_count + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE;
+            let workgroup_count = pixel_count.div_ceil(WORKGROUP_SIZE);
             compute_pass.dispatch_workgroups(workgroup_count, 1, 1);
         }
 
         // 出力バッファからステージングバッファにコピー
-        encoder.copy_buffer_to_buffer(&output_buffer, 0, &staging_buffer, 0, byte_size as u64);
+        encoder.copy_buffer_to_buffer(&pool.output, 0, &pool.staging, 0, byte_size);
 
         // コマンドを送信
         self.queue.submit(Some(encoder.finish()));
 
         // ステージングバッファをマップして結果を読み取る
-        let buffer_slice = staging_buffer.slice(..);
+        let buffer_slice = pool.staging.slice(..);
         let (sender, receiver) = std::sync::mpsc::channel();
         buffer_slice.map_async(wgpu::MapMode::Read, move |result| {
             sender.send(result).unwrap();
         });
 
         // デバイスをポーリングしてマップ完了を待つ
-        self.device.poll(wgpu::Maintain::Wait);
-        receiver.recv().unwrap().unwrap();
+        self.device.poll(wgpu::PollType::wait_indefinitely())?;
+        receiver.recv()??;
 
         // データを取得
         let data = buffer_slice.get_mapped_range();
         let result = data.to_vec();
         drop(data);
-        staging_buffer.unmap();
+        // 次のフレームでまた書き込めるようにアンマップしておく
+        pool.staging.unmap();
+        *buffers = Some(pool);
 
-        result
+        Ok(result)
     }
 }
 
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
         egui::CentralPanel::default()
@@ -266,55 +438,83 @@ impl eframe::App for NdiApp {
                 let new_raw_frame =
                     Arc::try_unwrap(new_raw_frame).unwrap_or_else(|arc| (*arc).clone());
 
-                // 新しいフレームが来ていればwgpuで処理
-                let has_new_frame = new_raw_frame.is_some();
-                if let Some(raw) = new_raw_frame {
-                    let grayscale_data = self.process_frame_with_wgpu(&raw);
-
-                    let image = egui::ColorImage::from_rgba_unmultiplied(
-                        [raw.width as usize, raw.height as usize],
-                        &grayscale_data,
-                    );
-
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR,
-                    ));
+                // 新しいフレームが来ていればwgpu（またはCPUフォールバック）で処理
+                // 一時停止中は取り出したフレームを捨て、GPUの処理も飛ばす
+                if let Some(raw) = new_raw_frame
+                    && !Playback::is_paused(ctx)
+                {
+                    if let GrayscaleBackend::Texture(compute) = &mut self.compute {
+                        // 結果はeguiのテクスチャに直接書かれる
+                        compute.process(&raw);
+                    } else {
+                        self.process_readback(ctx, &raw);
+                    }
                 }
 
-                // テクスチャがあれば描画
-                if let Some(texture) = &self.texture {
-                    let size = ui.available_size();
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
-
-                    // Note: NDIスレッドがrequest_repaintを呼ぶため、
-                    // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
-                } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                // テクスチャがあれば描画（表示アスペクト比を保ってレターボックス表示）
+                let shown = match &mut self.compute {
+                    GrayscaleBackend::Texture(compute) => compute.show(ui).is_some(),
+                    _ if self.texture.handle().is_some() => {
+                        self.texture.show(ui);
+                        true
+                    }
+                    _ => false,
+                };
+
+                // Note: NDIスレッドがrequest_repaintを呼ぶため、
+                // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
+                if !shown {
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
                 }
             });
     }
 }
 
+impl NdiApp {
+    // 読み戻したRGBAをFrameTextureにアップロードする（Texture以外のバックエンド）
+    fn process_readback(&mut self, ctx: &egui::Context, raw: &OwnedFrame) {
+        let dimensions = (raw.width as u32, raw.height as u32);
+        if self.last_dimensions != Some(dimensions) {
+            self.compute.resize();
+            self.last_dimensions = Some(dimensions);
+        }
+        match self
+            .compute
+            .process(raw.width as u32, raw.height as u32, &raw.data)
+        {
+            Ok((grayscale_data, width, height)) => {
+                let mut image = egui::ColorImage::from_rgba_unmultiplied(
+                    [width as usize, height as usize],
+                    &grayscale_data,
+                );
+                image.source_size = raw.display_size();
+
+                self.texture.update(ctx, image);
+            }
+            Err(e) => error!("Grayscale processing failed: {e}"),
+        }
+    }
+}
+
 fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let mut options = create_native_options(&args);
+    // eguiもwgpuで描画させ、コンピュートシェーダーの出力テクスチャをそのまま表示する
+    gpu::use_wgpu_renderer(&mut options, &args);
 
     eframe::run_native(
-        "NDI Grayscale Viewer (WGPU)",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
@@ -0,0 +1,262 @@
+//! 見つかったソースを最大 `--max-sources` 個まで並べて同時に表示するマルチビューア
+//!
+//! ソースごとに受信スレッドを立て、見つかった順にタイルを足していく。並びは数に合わせて
+//! 1x1、2x1、2x2、3x2、3x3 …と広げる。消えたソースのタイルも残し、受信機は同じソースを待ち続ける。
+
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use eframe::egui;
+use grafton_ndi::{Source, NDI};
+use rust_ndi_viewer::{
+    create_native_options, exit_if_given_up, handle_common_input, init_ndi, init_tracing,
+    spawn_source_finder, status_label, store_latest, CliArgs, ConnectionState, FrameTexture,
+    HelpOverlay, NdiError, NdiReceiver, ReceiverHandle, SourceInfo, StreamStats, TransformControl,
+};
+use std::sync::Arc;
+use tracing::{error, trace};
+
+const APP_TITLE: &str = "NDI Grid Viewer";
+
+const DEFAULT_MAX_SOURCES: usize = 9;
+
+// タイル同士の隙間
+const TILE_SPACING: f32 = 4.0;
+
+/// 1ソース分の表示
+struct Tile {
+    // "名前@アドレス"（同名ソースを区別する）
+    key: String,
+    name: String,
+
+    // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
+    frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
+
+    // egui用のテクスチャハンドル
+    texture: FrameTexture,
+
+    // 受信統計（映像なしのソースの表示用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（タイルをDropすると止める）
+    receiver: ReceiverHandle,
+}
+
+impl Tile {
+    fn spawn(ndi: &NDI, source: Source, args: &CliArgs, ctx: &egui::Context) -> Self {
+        let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
+        let frame_buffer_clone = frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+        let key = source.to_string();
+        let name = source.name.clone();
+
+        let ndi = ndi.clone();
+        let receiver_args = args.clone();
+        let ctx = ctx.clone();
+        let receiver = ReceiverHandle::spawn(
+            move |stop| {
+                Ok(NdiReceiver::connect_source(&ndi, source, &receiver_args)?
+                    .with_stop_flag(stop)
+                    .with_stats(stats_clone))
+            },
+            move |frame| {
+                // Convert to egui::ColorImage
+                let image = frame.to_color_image();
+
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&frame_buffer_clone, image, &ctx);
+
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (grid)"
+                );
+            },
+        );
+
+        Self {
+            key,
+            name,
+            frame_buffer,
+            texture: FrameTexture::from_args(args),
+            stats,
+            receiver,
+        }
+    }
+
+    fn show(&mut self, ui: &mut egui::Ui) {
+        // 最新フレームがあるかチェック（ArcSwapでロックフリー読み取り）
+        let new_image = self.frame_buffer.swap(Arc::new(None));
+        let new_image = Arc::try_unwrap(new_image).unwrap_or_else(|arc| (*arc).clone());
+        if let Some(image) = new_image {
+            self.texture.update(ui.ctx(), image);
+        }
+
+        let rect = ui.max_rect();
+        if self.texture.handle().is_some() {
+            // 表示アスペクト比を保ってタイルの中にレターボックス表示
+            self.texture.show(ui);
+        } else {
+            status_label(ui, &self.receiver.connection_state(), &self.stats.load());
+        }
+
+        // ソース名は左上に重ねる
+        ui.scope_builder(
+            egui::UiBuilder::new()
+                .max_rect(rect.shrink(8.0))
+                .layout(egui::Layout::top_down(egui::Align::LEFT)),
+            |ui| {
+                ui.label(
+                    egui::RichText::new(&self.name)
+                        .color(egui::Color32::WHITE)
+                        .background_color(egui::Color32::from_black_alpha(160)),
+                );
+            },
+        );
+    }
+}
+
+/// `count` 個のタイルを並べる列数と行数（列を先に増やす）
+fn grid_shape(count: usize) -> (usize, usize) {
+    let columns = (1..=count).find(|c| c * c >= count).unwrap_or(1);
+    (columns, count.div_ceil(columns).max(1))
+}
+
+struct NdiApp {
+    // NDIのランタイムが無ければ None（タイルは作らず、empty_state に説明を出す）
+    ndi: Option<NDI>,
+    args: CliArgs,
+    max_sources: usize,
+
+    // 探索スレッドが更新するソース一覧
+    sources: Arc<ArcSwap<Vec<SourceInfo>>>,
+
+    // 表示中のタイル（見つかった順）
+    tiles: Vec<Tile>,
+    // タイルが1つもないときに出す状態（探索中か、NDIを使えない理由）
+    empty_state: ConnectionState,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+}
+
+impl NdiApp {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs, ndi: Result<NDI>) -> Self {
+        let sources = Arc::new(ArcSwap::from_pointee(Vec::new()));
+        let (ndi, empty_state) = match ndi {
+            Ok(ndi) => {
+                spawn_source_finder(
+                    args.extra_ips.clone(),
+                    args.groups.clone(),
+                    sources.clone(),
+                    cc.egui_ctx.clone(),
+                );
+                (Some(ndi), ConnectionState::Searching)
+            }
+            Err(e) => (None, ConnectionState::failed(&e)),
+        };
+
+        Self {
+            ndi,
+            max_sources: args.max_sources.unwrap_or(DEFAULT_MAX_SOURCES),
+            args,
+            sources,
+            tiles: Vec::new(),
+            empty_state,
+            help: HelpOverlay::default(),
+        }
+    }
+
+    // 新しく見つかったソースのタイルを、上限まで足す
+    fn add_new_sources(&mut self, ctx: &egui::Context) {
+        let Some(ndi) = &self.ndi else {
+            return;
+        };
+        for source in self.sources.load().iter() {
+            if self.tiles.len() >= self.max_sources {
+                break;
+            }
+            let key = source.to_string();
+            if !self.tiles.iter().any(|tile| tile.key == key) {
+                self.tiles
+                    .push(Tile::spawn(ndi, source.source().clone(), &self.args, ctx));
+            }
+        }
+    }
+}
+
+impl eframe::App for NdiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.add_new_sources(ctx);
+
+        let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
+
+        egui::CentralPanel::default()
+            .frame(panel_frame)
+            .show(ctx, |ui| {
+                if self.tiles.is_empty() {
+                    // まだソースが1つも見つかっていない（か、NDIを使えない）
+                    status_label(ui, &self.empty_state, &StreamStats::default());
+                    return;
+                }
+
+                let (columns, rows) = grid_shape(self.tiles.len());
+                let area = ui.available_rect_before_wrap();
+                let cell = egui::vec2(
+                    (area.width() - TILE_SPACING * (columns - 1) as f32) / columns as f32,
+                    (area.height() - TILE_SPACING * (rows - 1) as f32) / rows as f32,
+                )
+                .max(egui::Vec2::ZERO);
+                for (i, tile) in self.tiles.iter_mut().enumerate() {
+                    let (column, row) = (i % columns, i / columns);
+                    let min = area.min
+                        + egui::vec2(
+                            column as f32 * (cell.x + TILE_SPACING),
+                            row as f32 * (cell.y + TILE_SPACING),
+                        );
+                    let rect = egui::Rect::from_min_size(min, cell);
+                    ui.scope_builder(egui::UiBuilder::new().max_rect(rect), |ui| {
+                        ui.set_clip_rect(rect);
+                        tile.show(ui);
+                    });
+                }
+            });
+    }
+}
+
+impl Drop for NdiApp {
+    fn drop(&mut self) {
+        // 先に全部の停止フラグを立て、各タイルのDropで待つ時間が重ならないようにする
+        for tile in &self.tiles {
+            tile.receiver.stop();
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    // 受信機はすべてこのNDIインスタンスから作る（ランタイムが無ければ、終了せずウィンドウに説明を出す）
+    let ndi = init_ndi().inspect_err(|e| {
+        error!("{e}");
+        if NdiError::of(e).is_none() {
+            exit_if_given_up(e);
+        }
+    });
+    let options = create_native_options(&args);
+
+    eframe::run_native(
+        APP_TITLE,
+        options,
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args, ndi)))),
+    )
+    .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
+}
@@ -0,0 +1,48 @@
+// 3D LUT（.cube）によるカラーグレーディング: 入力の各画素のRGBで格子を引き、
+// 周りの8つの格子点を三線形補間した色をeguiが描画するテクスチャに書く（CubeLut::lookup と同じ計算）
+@group(0) @binding(0) var input: texture_2d<u32>;
+@group(0) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;
+@group(0) @binding(2) var lut: texture_3d<f32>;
+
+// LUTの入力の範囲（DOMAIN_MIN / DOMAIN_MAX、wは使わない）
+struct Domain {
+    domain_min: vec4<f32>,
+    domain_max: vec4<f32>,
+}
+@group(0) @binding(3) var<uniform> domain: Domain;
+
+fn at(p: vec3<i32>) -> vec3<f32> {
+    return textureLoad(lut, p, 0).rgb;
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    // テクスチャの範囲チェック（端のワークグループははみ出す）
+    let size = textureDimensions(input);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+
+    let pixel = textureLoad(input, vec2<i32>(id.xy), 0);
+    let rgb = vec3<f32>(pixel.rgb) / 255.0;
+
+    // 格子上の位置。上端は1つ手前の格子から frac = 1 で補間する
+    let last = f32(textureDimensions(lut).x - 1u);
+    let range = domain.domain_max.xyz - domain.domain_min.xyz;
+    let t = clamp((rgb - domain.domain_min.xyz) / range, vec3<f32>(0.0), vec3<f32>(1.0));
+    let pos = t * last;
+    let base = min(vec3<i32>(pos), vec3<i32>(i32(last) - 1));
+    let frac = pos - vec3<f32>(base);
+
+    // 赤 → 緑 → 青の順に補間
+    let c00 = mix(at(base), at(base + vec3<i32>(1, 0, 0)), frac.x);
+    let c10 = mix(at(base + vec3<i32>(0, 1, 0)), at(base + vec3<i32>(1, 1, 0)), frac.x);
+    let c01 = mix(at(base + vec3<i32>(0, 0, 1)), at(base + vec3<i32>(1, 0, 1)), frac.x);
+    let c11 = mix(at(base + vec3<i32>(0, 1, 1)), at(base + vec3<i32>(1, 1, 1)), frac.x);
+    let c0 = mix(c00, c10, frac.y);
+    let c1 = mix(c01, c11, frac.y);
+    let color = clamp(mix(c0, c1, frac.z), vec3<f32>(0.0), vec3<f32>(1.0));
+
+    // Alpha は元のまま
+    textureStore(output, id.xy, vec4<f32>(color, f32(pixel.a) / 255.0));
+}
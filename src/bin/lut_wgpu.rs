@@ -0,0 +1,245 @@
+use anyhow::{Context, Result};
+use arc_swap::ArcSwap;
+use eframe::egui;
+use rust_ndi_viewer::filter::Filter;
+use rust_ndi_viewer::gpu::{self, EguiTexture, TextureCompute};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, CliArgs, CubeLut, FrameTexture, HelpOverlay, InfoOverlay, NdiReceiver,
+    OwnedFrame, Playback, ReceiverHandle, Shortcut, SourceOverlay, StreamStats, Toast,
+    TransformControl,
+};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+const APP_TITLE: &str = "NDI LUT Viewer (WGPU)";
+
+const SHORTCUT_BYPASS: Shortcut = Shortcut {
+    keys: &[egui::Key::B],
+    description: "Compare: toggle the LUT off and on",
+};
+
+const LUT_VIEWER_SHORTCUTS: &[Shortcut] = &[SHORTCUT_BYPASS];
+
+// eguiのデバイスでコンピュートが使えなければ（GL ESなど）CPUで同じ補間をする
+enum LutBackend {
+    // 入力テクスチャの画素でLUTを引き、eguiのテクスチャに直接書く
+    Texture(Box<TextureCompute>),
+    Cpu(CubeLut),
+}
+
+struct NdiApp {
+    // スレッド間で共有する生フレームバッファ（ArcSwapでロックフリー）
+    raw_frame_buffer: Arc<ArcSwap<Option<OwnedFrame>>>,
+
+    // wgpuリソース（使えなければCPUフォールバック）
+    compute: LutBackend,
+
+    // egui用のテクスチャハンドル（CPUフォールバックと、LUTを外して比べるとき用）
+    texture: FrameTexture,
+
+    // B でLUTを外して元の映像を見ている
+    bypass: bool,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // B の切り替えを下に出す
+    toast: Toast,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
+}
+
+impl NdiApp {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs, lut: CubeLut) -> Self {
+        let raw_frame_buffer = Arc::new(ArcSwap::from_pointee(None));
+        let raw_frame_buffer_clone = raw_frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+
+        // egui::Contextをクローンしてスレッドで使用
+        let ctx = cc.egui_ctx.clone();
+
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        let connect_args = args.clone();
+        let receiver = ReceiverHandle::spawn(
+            move |stop| {
+                Ok(NdiReceiver::connect_with_stop(&connect_args, stop)?.with_stats(stats_clone))
+            },
+            move |frame| {
+                // copy frame
+                let raw = frame.to_owned();
+
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&raw_frame_buffer_clone, raw, &ctx);
+            },
+        );
+
+        if args.backend_order.is_some() {
+            warn!("lut-wgpu always uses egui's wgpu device; ignoring --backend-order.");
+        }
+
+        // 読み戻し版は持たないので、eguiのデバイスで動かなければCPUで補間する
+        let compute = match &cc.wgpu_render_state {
+            Some(render_state) => {
+                info!(
+                    "egui renders on {}",
+                    gpu::describe_adapter(&render_state.adapter.get_info())
+                );
+                let output = EguiTexture::new(render_state).with_filter(args.texture_filter);
+                TextureCompute::with_lut(
+                    output,
+                    "LUT Texture",
+                    include_str!("lut.wgsl"),
+                    args.max_texture_side,
+                    &lut,
+                )
+                .inspect_err(|e| warn!("{e:#}; falling back to the CPU LUT."))
+                .ok()
+            }
+            None => {
+                warn!("egui is not rendering with wgpu; falling back to the CPU LUT.");
+                None
+            }
+        };
+        let compute = match compute {
+            Some(compute) => {
+                info!("Using egui's wgpu device (zero-copy texture output)");
+                LutBackend::Texture(Box::new(compute))
+            }
+            None => LutBackend::Cpu(lut),
+        };
+
+        Self {
+            raw_frame_buffer,
+            compute,
+            texture: FrameTexture::from_args(&args),
+            bypass: false,
+            help: HelpOverlay::default().with_extra(LUT_VIEWER_SHORTCUTS),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            toast: Toast::default(),
+            stats,
+            receiver,
+            args,
+        }
+    }
+}
+
+impl eframe::App for NdiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        if SHORTCUT_BYPASS.pressed(ctx) {
+            self.bypass = !self.bypass;
+            let state = if self.bypass { "off" } else { "on" };
+            self.toast.post(format!("LUT: {state}"));
+        }
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+        self.toast.show(ctx);
+
+        let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
+
+        egui::CentralPanel::default()
+            .frame(panel_frame)
+            .show(ctx, |ui| {
+                // 新しいフレームがあるかチェック（ArcSwapでロックフリー読み取り）
+                let new_raw_frame = self.raw_frame_buffer.swap(Arc::new(None));
+                let new_raw_frame =
+                    Arc::try_unwrap(new_raw_frame).unwrap_or_else(|arc| (*arc).clone());
+
+                // 一時停止中は取り出したフレームを捨て、GPUの処理も飛ばす
+                if let Some(raw) = new_raw_frame
+                    && !Playback::is_paused(ctx)
+                {
+                    match &mut self.compute {
+                        // 比べている間はLUTをかけずにそのまま出す
+                        _ if self.bypass => {
+                            self.texture
+                                .update(ctx, raw.as_validated().to_color_image());
+                        }
+                        // 結果はeguiのテクスチャに直接書かれる
+                        LutBackend::Texture(compute) => compute.process(&raw),
+                        LutBackend::Cpu(lut) => {
+                            let (width, height) = (raw.width as usize, raw.height as usize);
+                            let mut data = raw.data.clone();
+                            lut.apply(width, height, &mut data);
+                            let mut image =
+                                egui::ColorImage::from_rgba_unmultiplied([width, height], &data);
+                            image.source_size = raw.display_size();
+                            self.texture.update(ctx, image);
+                        }
+                    }
+                }
+
+                // テクスチャがあれば描画（表示アスペクト比を保ってレターボックス表示）
+                let shown = match &mut self.compute {
+                    LutBackend::Texture(compute) if !self.bypass => compute.show(ui).is_some(),
+                    _ if self.texture.handle().is_some() => {
+                        self.texture.show(ui);
+                        true
+                    }
+                    _ => false,
+                };
+
+                if !shown {
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
+                }
+            });
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    // ウィンドウを開く前に読んで、壊れたファイルならエラーで終わる
+    let path = args
+        .lut
+        .as_deref()
+        .context("lut-wgpu needs a LUT: --lut <file.cube>")?;
+    let lut = CubeLut::load(path)?;
+    info!(
+        "Loaded {}x{0}x{0} LUT{} from {}",
+        lut.size(),
+        lut.title()
+            .map(|title| format!(" \"{title}\""))
+            .unwrap_or_default(),
+        path.display()
+    );
+
+    let mut options = create_native_options(&args);
+    // eguiもwgpuで描画させ、コンピュートシェーダーの出力テクスチャをそのまま表示する
+    gpu::use_wgpu_renderer(&mut options, &args);
+
+    eframe::run_native(
+        APP_TITLE,
+        options,
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args, lut)))),
+    )
+    .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
+}
@@ -1,66 +1,761 @@
 use anyhow::Result;
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use eframe::egui;
-use rust_ndi_viewer::{create_native_options, NdiReceiver, TARGET_SOURCE_NAME};
-use std::sync::Arc;
+use grafton_ndi::PixelFormat;
+use rust_ndi_viewer::convert::rgba_to_color_image;
+use rust_ndi_viewer::crop::{crop_rgba, AutoCrop, CropRect};
+use rust_ndi_viewer::delivery::RateConverter;
+use rust_ndi_viewer::filter::{Filter, Watermark};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::snapshot::{self, save_frame_png};
+use rust_ndi_viewer::verify::{Checked, FrameVerifier};
+use rust_ndi_viewer::{
+    compute_histogram, create_native_options, exit_if_given_up, handle_common_input, init_tracing,
+    quality_indicator, sample_pixel, spawn_source_finder, status_label, store_latest, CliArgs,
+    ConnectionState, FramePacer, FrameTexture, GiveUp, HelpOverlay, Histogram, InfoOverlay,
+    NdiError, NdiReceiver, ParamHandle, Params, PtzControl, ReconnectEvent, ReconnectPolicy,
+    Shortcut, SourceInfo, SourceOverlay, StreamStats, Toast, TransformControl, ValidatedFrame,
+};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, info, trace, warn};
+
+const APP_TITLE: &str = "NDI Raw Viewer";
+
+// --smooth 時に提示ジッタを、--target-fps 時に間引き・重複の数をログに出す間隔
+const JITTER_LOG_INTERVAL: Duration = Duration::from_secs(5);
+
+const SHORTCUT_SOURCES: Shortcut = Shortcut {
+    keys: &[egui::Key::L],
+    description: "Show / hide the source list",
+};
+
+const SHORTCUT_SNAPSHOT: Shortcut = Shortcut {
+    keys: &[egui::Key::S],
+    description: "Save the current frame as PNG",
+};
+
+const SHORTCUT_HISTOGRAM: Shortcut = Shortcut {
+    keys: &[egui::Key::H],
+    description: "Cycle the histogram: RGB / luma / off",
+};
+
+const SHORTCUT_PTZ_LEFT: Shortcut = Shortcut {
+    keys: &[egui::Key::ArrowLeft],
+    description: "PTZ camera: pan left (hold)",
+};
+
+const SHORTCUT_PTZ_RIGHT: Shortcut = Shortcut {
+    keys: &[egui::Key::ArrowRight],
+    description: "PTZ camera: pan right (hold)",
+};
+
+const SHORTCUT_PTZ_UP: Shortcut = Shortcut {
+    keys: &[egui::Key::ArrowUp],
+    description: "PTZ camera: tilt up (hold)",
+};
+
+const SHORTCUT_PTZ_DOWN: Shortcut = Shortcut {
+    keys: &[egui::Key::ArrowDown],
+    description: "PTZ camera: tilt down (hold)",
+};
+
+const SHORTCUT_PTZ_ZOOM_IN: Shortcut = Shortcut {
+    keys: &[egui::Key::Plus, egui::Key::Equals],
+    description: "PTZ camera: zoom in (hold)",
+};
+
+const SHORTCUT_PTZ_ZOOM_OUT: Shortcut = Shortcut {
+    keys: &[egui::Key::Minus],
+    description: "PTZ camera: zoom out (hold)",
+};
+
+const RAW_VIEWER_SHORTCUTS: &[Shortcut] = &[
+    SHORTCUT_SOURCES,
+    SHORTCUT_SNAPSHOT,
+    SHORTCUT_HISTOGRAM,
+    SHORTCUT_PTZ_LEFT,
+    SHORTCUT_PTZ_RIGHT,
+    SHORTCUT_PTZ_UP,
+    SHORTCUT_PTZ_DOWN,
+    SHORTCUT_PTZ_ZOOM_IN,
+    SHORTCUT_PTZ_ZOOM_OUT,
+];
+
+// ヒストグラムを数え直す間隔（毎フレーム数えるとCPUを食うので約10Hzに抑える）
+const HISTOGRAM_INTERVAL: Duration = Duration::from_millis(100);
+
+// ヒストグラムのグラフの大きさ（横は256段階を1ポイントずつ）
+const HISTOGRAM_SIZE: egui::Vec2 = egui::vec2(256.0, 100.0);
+
+/// H キーで切り替えるヒストグラムの表示
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum HistogramMode {
+    #[default]
+    Off,
+    /// R・G・Bの3本を重ねて描く
+    Rgb,
+    /// 輝度の1本だけ
+    Luma,
+}
+
+impl HistogramMode {
+    fn next(self) -> Self {
+        match self {
+            Self::Off => Self::Rgb,
+            Self::Rgb => Self::Luma,
+            Self::Luma => Self::Off,
+        }
+    }
+}
+
+/// マウスの下の画素（受信スレッドが読んで書く）
+#[derive(Debug, Clone, Copy)]
+struct PixelProbe {
+    x: usize,
+    y: usize,
+    rgba: [u8; 4],
+}
+
+// 矢印キー・+/- を押している間のパン・チルト・ズームの速さ（-1.0..=1.0 のうち）
+const PTZ_KEY_SPEED: f32 = 0.5;
 
 struct NdiApp {
     // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
-    // NDIスレッドが書き込み、GUIスレッドが読み込む
-    frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
+    // NDIスレッドが書き込み、GUIスレッドが読み込む（--verify ならチェックサム付き）
+    frame_buffer: Arc<ArcSwap<Option<Checked>>>,
 
     // egui用のテクスチャハンドル
-    texture: Option<egui::TextureHandle>,
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+    // 受信スレッドが接続できたソースを書き込む
+    current_source: Arc<ArcSwapOption<String>>,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+    // 映像が出るまでの表示（探索中・接続中・失敗の理由）
+    connection_state: Arc<ArcSwap<ConnectionState>>,
+
+    // 接続断から復帰するまでの状態（受信スレッドが `ReconnectEvent` から書く）
+    reconnect_status: Arc<ArcSwapOption<String>>,
+
+    // 左の一覧に出す、見つかっているソース（探索スレッドが書く）
+    sources: Arc<ArcSwap<Vec<SourceInfo>>>,
+    show_sources: bool,
+    // 一覧で選んだソースを受信スレッドに伝える
+    params: Arc<ArcSwap<Params>>,
+    switch_source: Arc<AtomicBool>,
+
+    // S キーで立て、受信スレッドが次のフレームを保存して下ろす
+    snapshot_requested: Arc<AtomicBool>,
+    // スナップショットの保存結果（とPTZ操作のエラー）
+    toast: Toast,
+
+    // H キーで切り替えるヒストグラムの表示と、受信スレッドに数えさせるかどうか
+    histogram_mode: HistogramMode,
+    histogram_enabled: Arc<AtomicBool>,
+    // 受信スレッドが HISTOGRAM_INTERVAL ごとに数えて書く
+    histogram: Arc<ArcSwapOption<Histogram>>,
+
+    // マウスが映像の上にあるときの位置（映像の左上が0、右下が1）
+    probe_point: ParamHandle<Option<[f32; 2]>>,
+    // その位置の画素（受信スレッドが次のフレームで読む）
+    probe: ParamHandle<Option<PixelProbe>>,
+
+    // 接続中のソースへのPTZコマンド（受信スレッドが受信機をつなぐ）
+    ptz: PtzControl,
+    // 最後に送ったパン・チルト・ズームの速さ（キーの状態が変わったときだけ送る）
+    ptz_motion: [f32; 3],
+
+    // --smooth のときだけ、公称間隔で提示するための1フレームバッファ
+    pacer: Option<FramePacer<Checked>>,
+
+    // --target-fps のときだけ、受信スレッドが積んだフレームを表示の刻みごとに取り出す
+    rate_converter: Option<Arc<Mutex<RateConverter<Checked>>>>,
+
+    // --verify のチェックサムの照合（付いていなければ何もしない）
+    verifier: FrameVerifier,
+
+    // 提示ジッタを最後にログに出した時刻
+    last_jitter_log: Instant,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
         let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
         let frame_buffer_clone = frame_buffer.clone();
+        let current_source = Arc::new(ArcSwapOption::empty());
+        let current_source_clone = current_source.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+        let connection_state = Arc::new(ArcSwap::from_pointee(ConnectionState::Searching));
+        let connection_state_clone = connection_state.clone();
+        // 接続断から復帰するまでの状態（None なら表示しない）
+        let reconnect_status = Arc::new(ArcSwapOption::empty());
+        let reconnect_status_clone = reconnect_status.clone();
 
         // egui::Contextをクローンしてスレッドで使用
         let ctx = cc.egui_ctx.clone();
 
+        let sources = Arc::new(ArcSwap::from_pointee(Vec::new()));
+        spawn_source_finder(
+            args.extra_ips.clone(),
+            args.groups.clone(),
+            sources.clone(),
+            ctx.clone(),
+        );
+
+        // ソース・フィルタ設定（リモート操作から差し替えられる）
+        let params = Arc::new(ArcSwap::from_pointee(Params::from_args(&args)));
+        // trueになると受信ループを抜けて params.source に再接続する
+        let switch_source = Arc::new(AtomicBool::new(false));
+
+        #[cfg(feature = "control")]
+        if let Some(addr) = &args.control_addr
+            && let Err(e) = rust_ndi_viewer::control::spawn_control_server(
+                addr,
+                params.clone(),
+                switch_source.clone(),
+            )
+        {
+            error!("Failed to start remote control on {addr}: {e}");
+        }
+        #[cfg(not(feature = "control"))]
+        if args.control_addr.is_some() {
+            warn!("--control requires building with `--features control`, ignoring.");
+        }
+
+        let texture = FrameTexture::from_args(&args);
+        let rate_converter = args
+            .target_fps
+            .map(|fps| Arc::new(Mutex::new(RateConverter::new(fps))));
+        if rate_converter.is_some() && args.smooth {
+            warn!("--smooth has no effect with --target-fps, ignoring.");
+        }
+        let pacer = (args.smooth && rate_converter.is_none()).then(FramePacer::default);
+        let rate_converter_clone = rate_converter.clone();
+        let verify = args.verify;
+        if verify {
+            info!("Verifying frame checksums between the receiver thread and upload");
+        }
+
+        // --watermark のロゴは起動時に1回だけ読み込む
+        let mut watermark = args.watermark.as_ref().and_then(|path| {
+            let opacity = args.watermark_opacity.unwrap_or(Watermark::DEFAULT_OPACITY);
+            Watermark::load(path, args.watermark_corner, opacity)
+                .inspect_err(|e| warn!("Failed to load watermark: {e:#}"))
+                .ok()
+        });
+
+        // --auto-crop の範囲（ロック時は再接続しても同じ範囲を使う）
+        let mut auto_crop = args.auto_crop.map(|threshold| {
+            AutoCrop::new(threshold, args.auto_crop_background).with_lock(args.auto_crop_lock)
+        });
+
+        let params_clone = params.clone();
+        let switch_source_clone = switch_source.clone();
+        let snapshot_requested = Arc::new(AtomicBool::new(false));
+        let snapshot_requested_clone = snapshot_requested.clone();
+        let toast = Toast::default();
+        let toast_clone = toast.clone();
+        let ptz = PtzControl::default();
+        let ptz_clone = ptz.clone();
+        let histogram_enabled = Arc::new(AtomicBool::new(false));
+        let histogram_enabled_clone = histogram_enabled.clone();
+        let histogram = Arc::new(ArcSwapOption::empty());
+        let histogram_clone = histogram.clone();
+        // 最後にヒストグラムを数えた時刻（ソースを切り替えても続ける）
+        let mut last_histogram: Option<Instant> = None;
+        let probe_point: ParamHandle<Option<[f32; 2]>> = ParamHandle::default();
+        let probe_point_clone = probe_point.clone();
+        let probe = ParamHandle::default();
+        let probe_clone = probe.clone();
+        let receiver_args = args.clone();
+
         // NDI receiver thread - finds a source and pushes the latest frame to the shared buffer
-        thread::spawn(move || {
-            let receiver = match NdiReceiver::connect() {
-                Ok(r) => r,
+        // （ソースを切り替えるときは受信ループを抜けて受信機を捨て、同じスレッドで接続し直す）
+        thread::spawn(move || loop {
+            let selected = params.load_full();
+            // 一覧で選んだソースは名前とアドレスで決まるので、--source-index は使わない
+            let connect_args = match &selected.source_address {
+                Some(address) => CliArgs {
+                    address: Some(address.clone()),
+                    source_index: None,
+                    ..receiver_args.clone()
+                },
+                None => receiver_args.clone(),
+            };
+            let source = selected.source.as_deref();
+            connection_state_clone.store(Arc::new(ConnectionState::Searching));
+            let mut receiver = match NdiReceiver::connect_to(source, &connect_args) {
+                Ok(r) => {
+                    current_source_clone.store(Some(Arc::new(r.source_label())));
+                    r.with_stop_flag(switch_source.clone())
+                        .with_stats(stats_clone.clone())
+                        .with_connection_state(connection_state_clone.clone())
+                        .with_ptz(ptz_clone.clone())
+                        // 送信側のメタデータ（XML）は届いたまま表示する
+                        .with_metadata(|metadata| {
+                            info!(timecode = metadata.timecode, "Metadata: {}", metadata.data)
+                        })
+                }
                 Err(e) => {
-                    eprintln!("Failed to connect to NDI: {e}");
-                    return;
+                    error!("Failed to connect to NDI: {e}");
+                    connection_state_clone.store(Arc::new(ConnectionState::failed(&e)));
+                    // NDIのランタイムが無ければ選び直しても同じなので、終了せずに入れ方を出したままにする
+                    if NdiError::of(&e).is_some() {
+                        ctx.request_repaint();
+                        return;
+                    }
+                    // ソースが見つからないだけなら、一覧やリモート操作で別のソースを選べるので待つ
+                    let can_pick =
+                        receiver_args.control_addr.is_some() || selected.source_address.is_some();
+                    if !can_pick || GiveUp::of(&e) != Some(GiveUp::Discovery) {
+                        exit_if_given_up(&e);
+                    }
+                    let status = format!("Failed to connect: {e}");
+                    reconnect_status_clone.store(Some(Arc::new(status)));
+                    ctx.request_repaint();
+                    // 別のソースが指定されるまで待つ
+                    while !switch_source.swap(false, Ordering::Relaxed) {
+                        thread::sleep(Duration::from_millis(250));
+                    }
+                    continue;
                 }
             };
 
-            let _ = receiver.run_loop(|frame| {
+            // --filter で指定されたCPUフィルタ（未指定なら無加工）
+            let mut current_params = params.load_full();
+            let mut filter = current_params.build_filter();
+
+            let on_reconnect = |event| {
+                let status = match event {
+                    ReconnectEvent::Lost(reason) => format!("Connection lost ({reason})"),
+                    ReconnectEvent::Reconnecting { attempt } => {
+                        format!("Reconnecting\u{2026} (attempt {attempt})")
+                    }
+                    ReconnectEvent::Restored => {
+                        reconnect_status_clone.store(None);
+                        ctx.request_repaint();
+                        return;
+                    }
+                };
+                reconnect_status_clone.store(Some(Arc::new(status)));
+                ctx.request_repaint();
+            };
+            let policy = ReconnectPolicy::from_args(&receiver_args);
+            // 切り替え中の表示は、新しいソースの最初のフレームまで出しておく
+            let mut first_frame = true;
+            let on_frame = |frame: ValidatedFrame<'_>| {
+                if first_frame {
+                    first_frame = false;
+                    reconnect_status_clone.store(None);
+                }
+
+                // 受信したままのフレームを保存する（PNGのエンコードで受信を止めないよう別スレッドで）
+                if snapshot_requested_clone.swap(false, Ordering::Relaxed) {
+                    let owned = frame.to_owned();
+                    let source = current_source_clone.load_full();
+                    let source = source.as_deref().map_or("", String::as_str).to_string();
+                    let sidecar = receiver_args.snapshot_sidecar;
+                    let toast = toast_clone.clone();
+                    let ctx = ctx.clone();
+                    thread::spawn(move || {
+                        let name = snapshot::snapshot_file_name(SystemTime::now());
+                        let path = Path::new(&name);
+                        match save_frame_png(&owned.as_validated(), path, &source, sidecar) {
+                            Ok(()) => toast.post(format!("Saved {name}")),
+                            Err(e) => {
+                                error!("Failed to save snapshot: {e:#}");
+                                toast.post(format!("Snapshot failed: {e:#}"));
+                            }
+                        }
+                        ctx.request_repaint();
+                    });
+                }
+
+                // ヒストグラムは受信したままの画で数える（フィルタの色ではなく露出を見るため）
+                if histogram_enabled_clone.load(Ordering::Relaxed)
+                    && last_histogram.is_none_or(|last| last.elapsed() >= HISTOGRAM_INTERVAL)
+                {
+                    histogram_clone.store(Some(Arc::new(compute_histogram(&frame))));
+                    last_histogram = Some(Instant::now());
+                }
+
+                let mut size = [frame.width as usize, frame.height as usize];
+                let mut display_size = frame.display_size();
+                // 表示する範囲（--auto-crop で切り出したら受信したフレームの中のその範囲）
+                let mut region = CropRect::full(size[0], size[1]);
+
+                // 設定が差し替えられていればフィルタを作り直す
+                let latest_params = params.load_full();
+                if !Arc::ptr_eq(&latest_params, &current_params) {
+                    filter = latest_params.build_filter();
+                    current_params = latest_params;
+                }
+
                 // Convert NDI frame into egui::ColorImage
-                let image = egui::ColorImage::from_rgba_unmultiplied(
-                    [frame.width as usize, frame.height as usize],
-                    &frame.data,
-                );
+                let mut image = if filter.is_some() || watermark.is_some() || auto_crop.is_some() {
+                    let mut data = frame.to_owned().data;
+                    // RGBXでもフィルタ（ルミナンスキーなど）が書いたアルファは使う
+                    let mut format = frame.format;
+                    if let Some(filter) = filter.as_mut() {
+                        filter.apply(size[0], size[1], &mut data);
+                        format = PixelFormat::RGBA;
+                    }
+                    // ルミナンスキーで抜いた後の範囲で切り出す（ロゴは切り出した画の角に置く）
+                    if let Some(auto_crop) = auto_crop.as_mut() {
+                        let rect = auto_crop.bounds(size[0], size[1], &data);
+                        if [rect.width, rect.height] != size {
+                            data = crop_rgba(&data, size[0], rect);
+                            region = rect;
+                            // 表示サイズも同じ割合で縮める（非正方ピクセルの補正は保つ）
+                            let ratio = egui::vec2(
+                                rect.width as f32 / size[0] as f32,
+                                rect.height as f32 / size[1] as f32,
+                            );
+                            display_size = display_size * ratio;
+                            size = [rect.width, rect.height];
+                        }
+                    }
+                    // ロゴはフィルタの後に重ねる（フォルスカラーで色が変わらないように）
+                    if let Some(watermark) = watermark.as_mut() {
+                        watermark.apply(size[0], size[1], &mut data);
+                    }
+                    rgba_to_color_image(size, &data, format)
+                } else {
+                    rgba_to_color_image(size, frame.data, frame.format)
+                };
+                image.source_size = display_size;
+                // --verify なら、ここから先の受け渡しで変わっていないか表示前に確かめる
+                let image = Checked::new(image, verify);
 
-                // Store it using ArcSwap (lock-free)
-                frame_buffer_clone.store(Arc::new(Some(image)));
+                // マウスの下の画素は受信したままの画で読む（スナップショットと同じく --filter の前）
+                // 幅か高さが0の範囲には読める画素がない
+                if let Some([u, v]) = probe_point_clone.get() {
+                    let sampled = (region.width > 0 && region.height > 0)
+                        .then(|| {
+                            let x = region.x
+                                + ((u * region.width as f32) as usize).min(region.width - 1);
+                            let y = region.y
+                                + ((v * region.height as f32) as usize).min(region.height - 1);
+                            sample_pixel(&frame, x, y).map(|rgba| PixelProbe { x, y, rgba })
+                        })
+                        .flatten();
+                    probe_clone.store(sampled);
+                }
 
-                // これをしないとマウスカーソルを動かさないと再描画されない
-                ctx.request_repaint();
+                // --target-fps なら間引き・重複を決めてから積む（受信した全フレームを数える必要がある）
+                // GUIが表示の刻みごとに再描画を頼み直すので、ここでは頼まない
+                match &rate_converter_clone {
+                    Some(converter) => {
+                        let interval = stats_clone.load().frame_interval;
+                        if let Ok(mut converter) = converter.lock() {
+                            converter.push(image, interval);
+                        }
+                    }
+                    // Store it using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                    None => {
+                        store_latest(&frame_buffer_clone, image, &ctx);
+                    }
+                }
 
-                println!(
-                    "Frame received: {}x{}, timecode={}",
-                    frame.width, frame.height, frame.timecode
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received"
                 );
-            });
+                ControlFlow::Continue(())
+            };
+            let result = receiver.run_loop_with_policy(policy, on_frame, on_reconnect);
+            if let Err(e) = &result {
+                exit_if_given_up(e);
+            }
+
+            // ソース切り替えで抜けた場合は再接続する
+            switch_source.store(false, Ordering::Relaxed);
         });
 
         Self {
             frame_buffer,
-            texture: None,
+            texture,
+            help: HelpOverlay::default().with_extra(RAW_VIEWER_SHORTCUTS),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            current_source,
+            stats,
+            connection_state,
+            reconnect_status,
+            sources,
+            show_sources: true,
+            params: params_clone,
+            switch_source: switch_source_clone,
+            snapshot_requested,
+            toast,
+            histogram_mode: HistogramMode::Off,
+            histogram_enabled,
+            histogram,
+            probe_point,
+            probe,
+            ptz,
+            ptz_motion: [0.0; 3],
+            pacer,
+            rate_converter,
+            verifier: FrameVerifier::default(),
+            last_jitter_log: Instant::now(),
+            args,
+        }
+    }
+}
+
+impl NdiApp {
+    /// 見つかっているソースの一覧。選ぶとそのソースに切り替える（前の画は新しい画が来るまで残す）
+    fn source_panel(&mut self, ctx: &egui::Context) {
+        if SHORTCUT_SOURCES.pressed(ctx) {
+            self.show_sources = !self.show_sources;
+        }
+        if !self.show_sources {
+            return;
+        }
+
+        let current = self.current_source.load_full();
+        let mut selected = None;
+        egui::SidePanel::left("source-list")
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.heading("Sources");
+                ui.separator();
+                let sources = self.sources.load();
+                if sources.is_empty() {
+                    ui.label("Searching\u{2026}");
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for source in sources.iter() {
+                        let key = source.to_string();
+                        let is_current = current.as_deref() == Some(&key);
+                        // 同じ名前のソースが並んでも送信元を見分けられるよう、アドレスを添える
+                        let mut label = egui::text::LayoutJob::default();
+                        label.append(
+                            &source.name,
+                            0.0,
+                            egui::TextFormat {
+                                // 選択・ホバーの色に合わせる
+                                color: egui::Color32::PLACEHOLDER,
+                                ..Default::default()
+                            },
+                        );
+                        if let Some(address) = &source.url_address {
+                            label.append(
+                                &format!("\n{address}"),
+                                0.0,
+                                egui::TextFormat {
+                                    font_id: egui::FontId::proportional(11.0),
+                                    color: ui.visuals().weak_text_color(),
+                                    ..Default::default()
+                                },
+                            );
+                        }
+                        if ui.selectable_label(is_current, label).clicked() && !is_current {
+                            selected = Some(source.clone());
+                        }
+                    }
+                });
+            });
+
+        if let Some(source) = selected {
+            let params = Params {
+                source: Some(source.name.clone()),
+                source_address: source.host().map(str::to_string),
+                ..Params::clone(&self.params.load())
+            };
+            self.params.store(Arc::new(params));
+            self.reconnect_status
+                .store(Some(Arc::new(format!("Switching to {source}\u{2026}"))));
+            // 受信ループを抜けさせる（受信機はそこで捨てられ、受信スレッドが新しいソースにつなぐ）
+            self.switch_source.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl NdiApp {
+    /// ヒストグラムの表示を RGB → 輝度 → なし の順に切り替える
+    fn cycle_histogram(&mut self) {
+        self.histogram_mode = self.histogram_mode.next();
+        let enabled = self.histogram_mode != HistogramMode::Off;
+        self.histogram_enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            // 次に開いたとき古いグラフを出さないように
+            self.histogram.store(None);
+        }
+    }
+
+    /// 表示中なら、受信スレッドが数えたヒストグラムを `panel` の左下に重ねて描く
+    fn histogram_overlay(&self, ui: &egui::Ui, panel: egui::Rect) {
+        let Some(histogram) = self.histogram.load_full() else {
+            return;
+        };
+        let (channels, max) = match self.histogram_mode {
+            HistogramMode::Off => return,
+            HistogramMode::Rgb => (
+                vec![
+                    (&histogram.red, egui::Color32::from_rgb(0xFF, 0x50, 0x50)),
+                    (&histogram.green, egui::Color32::from_rgb(0x50, 0xFF, 0x50)),
+                    (&histogram.blue, egui::Color32::from_rgb(0x60, 0x80, 0xFF)),
+                ],
+                histogram.max_rgb(),
+            ),
+            HistogramMode::Luma => (
+                vec![(&histogram.luma, egui::Color32::WHITE)],
+                histogram.max_luma(),
+            ),
+        };
+        if max == 0 {
+            return;
+        }
+
+        let rect = egui::Rect::from_min_size(
+            panel.left_bottom() + egui::vec2(12.0, -12.0 - HISTOGRAM_SIZE.y),
+            HISTOGRAM_SIZE,
+        );
+        let painter = ui.painter();
+        painter.rect_filled(rect, 4.0, egui::Color32::from_black_alpha(160));
+        // 縦軸は一番多いビンで正規化する（線形）
+        for (bins, color) in channels {
+            let points = bins
+                .iter()
+                .enumerate()
+                .map(|(i, &count)| {
+                    egui::pos2(
+                        rect.left() + i as f32 * rect.width() / 255.0,
+                        rect.bottom() - count as f32 / max as f32 * rect.height(),
+                    )
+                })
+                .collect();
+            painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, color)));
+        }
+    }
+
+    /// マウスが映像の上にあれば、その画素のRGBAとカラーコードをカーソルの横に出す
+    ///
+    /// `response` は映像を描いた領域（[`FrameTexture::show`] の戻り値）。黒帯の上では出さない。
+    fn pixel_probe(&self, ctx: &egui::Context, response: &egui::Response) {
+        let hover = response.hover_pos().and_then(|pos| {
+            let rect = self.texture.image_rect(ctx, response.rect)?;
+            rect.contains(pos)
+                .then(|| (pos, (pos - rect.min) / rect.size()))
+        });
+        let Some((pos, uv)) = hover else {
+            self.probe_point.store(None);
+            // 次に映像の上に来たとき、前の位置の値を出さないように
+            self.probe.store(None);
+            return;
+        };
+        self.probe_point.store(Some([uv.x, uv.y]));
+
+        let Some(PixelProbe { x, y, rgba }) = self.probe.get() else {
+            return;
+        };
+        let [r, g, b, a] = rgba;
+        egui::Area::new(egui::Id::new("pixel-probe"))
+            .fixed_pos(pos + egui::vec2(16.0, 16.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{x}, {y}\nR {r:3} G {g:3} B {b:3} A {a:3}\n#{r:02X}{g:02X}{b:02X}{a:02X}"
+                    ))
+                    .monospace()
+                    .color(egui::Color32::WHITE)
+                    .background_color(egui::Color32::from_black_alpha(160)),
+                );
+            });
+    }
+
+    /// 矢印キーと +/- を押している間、PTZカメラをその向きに動かす
+    ///
+    /// 押したときに速さを送り、離したときに 0 を送って止める。PTZに対応していないソースなら
+    /// 押したときにその旨をトーストで出す。
+    fn ptz_keys(&mut self, ctx: &egui::Context) {
+        let axis = |positive: &Shortcut, negative: &Shortcut| {
+            (positive.down(ctx) as i8 - negative.down(ctx) as i8) as f32 * PTZ_KEY_SPEED
+        };
+        let motion = [
+            axis(&SHORTCUT_PTZ_RIGHT, &SHORTCUT_PTZ_LEFT),
+            axis(&SHORTCUT_PTZ_UP, &SHORTCUT_PTZ_DOWN),
+            axis(&SHORTCUT_PTZ_ZOOM_IN, &SHORTCUT_PTZ_ZOOM_OUT),
+        ];
+        if motion == self.ptz_motion {
+            return;
+        }
+        let previous = std::mem::replace(&mut self.ptz_motion, motion);
+
+        let mut result = Ok(());
+        if motion[..2] != previous[..2] {
+            result = self.ptz.pan_tilt_speed(motion[0], motion[1]);
+        }
+        if motion[2] != previous[2] {
+            result = result.and_then(|()| self.ptz.zoom_speed(motion[2]));
+        }
+        // 離したとき（止めるとき）の失敗は、押したときに出しているので繰り返さない
+        if let Err(e) = result
+            && motion != [0.0; 3]
+        {
+            warn!("PTZ command failed: {e:#}");
+            self.toast.post(format!("{e:#}"));
         }
     }
 }
 
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_panel(ctx);
+        self.source_overlay
+            .show(ctx, self.current_source.load_full());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.current_source.load_full(), &self.stats.load());
+        self.ptz_keys(ctx);
+        if SHORTCUT_HISTOGRAM.pressed(ctx) {
+            self.cycle_histogram();
+        }
+        if SHORTCUT_SNAPSHOT.pressed(ctx) {
+            if self.texture.handle().is_some() {
+                self.snapshot_requested.store(true, Ordering::Relaxed);
+            } else {
+                self.toast.post("No frame to save yet");
+            }
+        }
+        self.toast.show(ctx);
+        if let Some(status) = self.reconnect_status.load_full() {
+            egui::Area::new(egui::Id::new("reconnect-overlay"))
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 12.0])
+                .show(ctx, |ui| {
+                    ui.label(
+                        egui::RichText::new(status.as_str())
+                            .color(egui::Color32::WHITE)
+                            .background_color(egui::Color32::from_black_alpha(160))
+                            .size(20.0),
+                    );
+                });
+        }
+
         // 背景を黒にする
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
@@ -71,54 +766,88 @@ impl eframe::App for NdiApp {
                 let new_image = self.frame_buffer.swap(Arc::new(None));
                 let new_image = Arc::try_unwrap(new_image).unwrap_or_else(|arc| (*arc).clone());
 
+                // --target-fps なら表示の刻みごとに出すフレームを取り出し、次の刻みで再描画する
+                let new_image = match self.rate_converter.as_ref().map(|c| c.lock()) {
+                    Some(Ok(mut converter)) => {
+                        let now = Instant::now();
+                        let (image, wait) = converter.tick(now);
+                        ctx.request_repaint_after(wait);
+                        if now.duration_since(self.last_jitter_log) >= JITTER_LOG_INTERVAL {
+                            info!(
+                                "Frame rate conversion: dropped {}, repeated {}",
+                                converter.dropped(),
+                                converter.repeated()
+                            );
+                            self.last_jitter_log = now;
+                        }
+                        image
+                    }
+                    _ => new_image,
+                };
+
+                // --smooth なら早く届いたフレームを公称間隔まで持っておく
+                let new_image = match self.pacer.as_mut() {
+                    Some(pacer) => {
+                        let now = Instant::now();
+                        let stats = self.stats.load();
+                        let (image, wait) = pacer.update(new_image, now, stats.frame_interval);
+                        if let Some(wait) = wait {
+                            ctx.request_repaint_after(wait);
+                        }
+                        if now.duration_since(self.last_jitter_log) >= JITTER_LOG_INTERVAL {
+                            info!(
+                                "Presentation jitter: {:.1} ms (arrival jitter {:.1} ms)",
+                                pacer.jitter().as_secs_f64() * 1000.0,
+                                stats.jitter.as_secs_f64() * 1000.0
+                            );
+                            self.last_jitter_log = now;
+                        }
+                        image
+                    }
+                    None => new_image,
+                };
+
                 // 新しい画像が来ていればテクスチャを更新
-                let has_new_frame = new_image.is_some();
                 if let Some(image) = new_image {
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR, // 拡大縮小時のフィルタ
-                    ));
+                    let image = self.verifier.verify(image);
+                    self.texture.update(ctx, image);
                 }
 
                 // テクスチャがあれば描画
-                if let Some(texture) = &self.texture {
-                    // アスペクト比を維持しつつ画面最大に表示
-                    // 利用可能なサイズを取得
-                    let size = ui.available_size();
-
-                    // 画像ウィジェットを表示
-                    // shrink_to_fit() でアスペクト比維持
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
+                if self.texture.handle().is_some() {
+                    let panel = ui.max_rect();
+                    // 表示アスペクト比（非正方ピクセルを含む）を維持しつつ画面最大に表示
+                    let response = self.texture.show(ui);
+                    self.histogram_overlay(ui, panel);
+                    self.pixel_probe(ctx, &response);
 
                     // Note: NDIスレッドがrequest_repaintを呼ぶため、
                     // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
                 } else {
                     // まだ映像が来ていない時の表示
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                    status_label(ui, &self.connection_state.load(), &self.stats.load());
                 }
             });
     }
 }
 
 fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
 
     eframe::run_native(
-        "NDI Raw Viewer",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
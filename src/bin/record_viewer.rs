@@ -0,0 +1,324 @@
+//! 表示しながら、R キーで受信したフレームを動画ファイルに録画するビューア
+//!
+//! 録画は受信スレッドで [`FrameRecorder`] に渡す（エンコードは `ffmpeg` の子プロセス）。
+//! ファイルはカレントディレクトリの `recording-YYYYMMDD-HHMMSS-mmm.mkv`（`--record-format mp4` でmp4）。
+//! 録画中に解像度が変わったら、そのファイルを閉じて新しいファイルで続ける。
+
+use anyhow::Result;
+use arc_swap::{ArcSwap, ArcSwapOption};
+use eframe::egui;
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::record::RecordFormat;
+use rust_ndi_viewer::snapshot::timestamped_file_name;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    CliArgs, FrameRecorder, FrameTexture, HelpOverlay, InfoOverlay, NdiReceiver, ReceiverHandle,
+    Shortcut, SourceOverlay, StreamStats, Toast, TransformControl, ValidatedFrame,
+};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{error, trace};
+
+const APP_TITLE: &str = "NDI Record Viewer";
+
+const SHORTCUT_RECORD: Shortcut = Shortcut {
+    keys: &[egui::Key::R],
+    description: "Start / stop recording to a video file",
+};
+
+const RECORD_VIEWER_SHORTCUTS: &[Shortcut] = &[SHORTCUT_RECORD];
+
+/// 録画中のファイル（右下の REC 表示用）
+struct Recording {
+    file_name: String,
+    since: Instant,
+}
+
+/// 受信スレッド側の録画の状態
+struct RecorderState {
+    format: RecordFormat,
+    // R キーで切り替える（受信スレッドが次のフレームで開始・終了する）
+    requested: Arc<AtomicBool>,
+    recorder: Option<FrameRecorder>,
+    // GUIに見せる録画中のファイル
+    recording: Arc<ArcSwapOption<Recording>>,
+    // 受信統計（録画のフレームレートに公称値を使う）
+    stats: Arc<ArcSwap<StreamStats>>,
+    toast: Toast,
+    // 別スレッドで保存し終えたときのトーストを出すため
+    ctx: egui::Context,
+}
+
+impl RecorderState {
+    /// 要求に合わせて録画を開始・終了し、録画中なら `frame` を書く
+    fn handle(&mut self, frame: &ValidatedFrame) {
+        let requested = self.requested.load(Ordering::Relaxed);
+        let size = (frame.width as u32, frame.height as u32);
+
+        // 止めるとき、解像度が変わったときは今のファイルを閉じる
+        let resized = self.recorder.as_ref().is_some_and(|r| r.size() != size);
+        if let Some(recorder) = self.recorder.take_if(|_| !requested || resized) {
+            self.finish(recorder);
+        }
+        if !requested {
+            return;
+        }
+
+        if self.recorder.is_none() {
+            let file_name =
+                timestamped_file_name("recording", self.format.extension(), SystemTime::now());
+            let interval = self.stats.load().frame_interval;
+            let fps = 1.0 / interval.as_secs_f64();
+            match FrameRecorder::start(Path::new(&file_name), size.0, size.1, fps) {
+                Ok(recorder) => {
+                    self.toast.post(if resized {
+                        format!("Resolution changed; recording to {file_name}")
+                    } else {
+                        format!("Recording to {file_name}")
+                    });
+                    self.recording.store(Some(Arc::new(Recording {
+                        file_name,
+                        since: Instant::now(),
+                    })));
+                    self.recorder = Some(recorder);
+                }
+                Err(e) => {
+                    error!("Failed to start recording: {e:#}");
+                    self.toast.post(format!("Recording failed: {e:#}"));
+                    self.requested.store(false, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+
+        if let Some(recorder) = self.recorder.as_mut()
+            && let Err(e) = recorder.push(frame)
+        {
+            error!("Recording stopped: {e:#}");
+            self.toast.post(format!("Recording stopped: {e:#}"));
+            self.requested.store(false, Ordering::Relaxed);
+            if let Some(recorder) = self.recorder.take() {
+                self.finish(recorder);
+            }
+        }
+    }
+
+    // ffmpegが書き終えるのを待つ間も受信を止めないよう、別スレッドで閉じる
+    fn finish(&self, recorder: FrameRecorder) {
+        self.recording.store(None);
+        let toast = self.toast.clone();
+        let ctx = self.ctx.clone();
+        thread::spawn(move || {
+            let name = recorder.path().display().to_string();
+            let (frames, dropped) = (recorder.frames(), recorder.dropped());
+            match recorder.finish() {
+                Ok(()) if dropped > 0 => {
+                    toast.post(format!("Saved {name} ({frames} frames, {dropped} dropped)"))
+                }
+                Ok(()) => toast.post(format!("Saved {name} ({frames} frames)")),
+                Err(e) => {
+                    error!("Failed to finish recording: {e:#}");
+                    toast.post(format!("Recording failed: {e:#}"));
+                }
+            }
+            ctx.request_repaint();
+        });
+    }
+}
+
+struct NdiApp {
+    // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
+    frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
+
+    // egui用のテクスチャハンドル
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // R キーで立て下ろしし、受信スレッドが録画を開始・終了する
+    record_requested: Arc<AtomicBool>,
+    // 録画中のファイル（受信スレッドが書く）
+    recording: Arc<ArcSwapOption<Recording>>,
+    // 録画の開始・保存・エラー
+    toast: Toast,
+
+    // 受信スレッド（アプリ終了時にDropで止める、録画中ならファイルを閉じてから終わる）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
+}
+
+impl NdiApp {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
+        let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
+        let frame_buffer_clone = frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+        let record_requested = Arc::new(AtomicBool::new(false));
+        let recording = Arc::new(ArcSwapOption::empty());
+        let toast = Toast::default();
+
+        // egui::Contextをクローンしてスレッドで使用
+        let ctx = cc.egui_ctx.clone();
+
+        let mut recorder = RecorderState {
+            format: args.record_format,
+            requested: record_requested.clone(),
+            recorder: None,
+            recording: recording.clone(),
+            stats: stats.clone(),
+            toast: toast.clone(),
+            ctx: ctx.clone(),
+        };
+
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        let connect_args = args.clone();
+        let receiver = ReceiverHandle::spawn(
+            move |stop| {
+                Ok(NdiReceiver::connect_with_stop(&connect_args, stop)?.with_stats(stats_clone))
+            },
+            move |frame| {
+                // 受信したままのフレームを録画する
+                recorder.handle(&frame);
+
+                // Convert to egui::ColorImage
+                let image = frame.to_color_image();
+
+                // Store using ArcSwap (lock-free)
+                frame_buffer_clone.store(Arc::new(Some(image)));
+
+                // これをしないとマウスカーソルを動かさないと再描画されない
+                ctx.request_repaint();
+
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (record)"
+                );
+            },
+        );
+
+        Self {
+            frame_buffer,
+            texture: FrameTexture::from_args(&args),
+            help: HelpOverlay::default().with_extra(RECORD_VIEWER_SHORTCUTS),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            record_requested,
+            recording,
+            toast,
+            receiver,
+            args,
+        }
+    }
+
+    // 録画中は右下に赤い丸と経過時間を出す
+    fn recording_indicator(&self, ctx: &egui::Context) {
+        let Some(recording) = self.recording.load_full() else {
+            return;
+        };
+        let elapsed = recording.since.elapsed().as_secs();
+        egui::Area::new(egui::Id::new("recording-indicator"))
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-12.0, -12.0])
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let (rect, _) =
+                        ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+                    ui.painter()
+                        .circle_filled(rect.center(), 7.0, egui::Color32::RED);
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "REC {:02}:{:02}  {}",
+                            elapsed / 60,
+                            elapsed % 60,
+                            recording.file_name
+                        ))
+                        .color(egui::Color32::WHITE)
+                        .background_color(egui::Color32::from_black_alpha(160)),
+                    );
+                });
+            });
+        // 経過時間を進めるため、映像が止まっていても1秒ごとに描き直す
+        ctx.request_repaint_after(Duration::from_secs(1));
+    }
+}
+
+impl eframe::App for NdiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+        if SHORTCUT_RECORD.pressed(ctx) {
+            if self.texture.handle().is_some() {
+                // 実際の開始・終了は受信スレッドが次のフレームで行う
+                self.record_requested.fetch_xor(true, Ordering::Relaxed);
+            } else {
+                self.toast.post("No frame to record yet");
+            }
+        }
+        self.recording_indicator(ctx);
+        self.toast.show(ctx);
+
+        let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
+
+        egui::CentralPanel::default()
+            .frame(panel_frame)
+            .show(ctx, |ui| {
+                // 最新フレームがあるかチェック（ArcSwapでロックフリー読み取り）
+                let new_image = self.frame_buffer.swap(Arc::new(None));
+                let new_image = Arc::try_unwrap(new_image).unwrap_or_else(|arc| (*arc).clone());
+
+                // 新しい画像が来ていればテクスチャを更新
+                if let Some(image) = new_image {
+                    self.texture.update(ctx, image);
+                }
+
+                // テクスチャがあれば描画（表示アスペクト比を保ってレターボックス表示）
+                if self.texture.handle().is_some() {
+                    self.texture.show(ui);
+                } else {
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
+                }
+            });
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
+
+    eframe::run_native(
+        APP_TITLE,
+        options,
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
+    )
+    .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use eframe::egui;
+use rust_ndi_viewer::filter::{SharpenSettings, UnsharpMask};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, CliArgs, FrameTexture, HelpOverlay, InfoOverlay, ParamHandle, Pipeline,
+    ReceiverHandle, SourceOverlay, StreamStats, TransformControl,
+};
+use std::sync::Arc;
+use tracing::trace;
+
+const APP_TITLE: &str = "NDI Sharpen Viewer (CPU)";
+
+struct NdiApp {
+    // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
+    frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
+
+    // egui用のテクスチャハンドル
+    texture: FrameTexture,
+
+    // 強さと半径（スライダーで変え、受信スレッドのフィルタが次のフレームから使う）
+    settings: ParamHandle<SharpenSettings>,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
+}
+
+impl NdiApp {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
+        let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
+        let frame_buffer_clone = frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+
+        // egui::Contextをクローンしてスレッドで使用
+        let ctx = cc.egui_ctx.clone();
+
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        // CPUフィルタはパイプライン側で適用される（ガウシアンでぼかしたアンシャープマスク）
+        let sharpen = UnsharpMask::default();
+        let settings = sharpen.settings();
+        let receiver = Pipeline::builder()
+            .args(args.clone())
+            .filter(sharpen)
+            .stats(stats_clone)
+            .on_frame(move |frame| {
+                // Convert to egui::ColorImage
+                let image = frame.to_color_image();
+
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&frame_buffer_clone, image, &ctx);
+
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (sharpen CPU)"
+                );
+            })
+            .spawn();
+
+        Self {
+            frame_buffer,
+            texture: FrameTexture::from_args(&args),
+            settings,
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            receiver,
+            args,
+        }
+    }
+}
+
+impl NdiApp {
+    // 左下の強さ・半径の調整
+    fn sharpen_sliders(&self, ctx: &egui::Context) {
+        egui::Window::new("Sharpen")
+            .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(8.0, -8.0))
+            .resizable(false)
+            .show(ctx, |ui| {
+                let mut settings = self.settings.get();
+                let amount = egui::Slider::new(&mut settings.amount, 0.0..=UnsharpMask::MAX_AMOUNT)
+                    .text("amount");
+                let mut changed = ui.add(amount).changed();
+                let radius = egui::Slider::new(
+                    &mut settings.radius,
+                    UnsharpMask::MIN_RADIUS..=UnsharpMask::MAX_RADIUS,
+                )
+                .text("radius (px)");
+                changed |= ui.add(radius).changed();
+                if changed {
+                    self.settings.store(settings);
+                }
+            });
+    }
+}
+
+impl eframe::App for NdiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+        self.sharpen_sliders(ctx);
+
+        let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
+
+        egui::CentralPanel::default()
+            .frame(panel_frame)
+            .show(ctx, |ui| {
+                // 最新フレームがあるかチェック（ArcSwapでロックフリー読み取り）
+                let new_image = self.frame_buffer.swap(Arc::new(None));
+                let new_image = Arc::try_unwrap(new_image).unwrap_or_else(|arc| (*arc).clone());
+
+                // 新しい画像が来ていればテクスチャを更新
+                if let Some(image) = new_image {
+                    self.texture.update(ctx, image);
+                }
+
+                // テクスチャがあれば描画
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
+
+                    // Note: NDIスレッドがrequest_repaintを呼ぶため、
+                    // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
+                } else {
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
+                }
+            });
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
+
+    eframe::run_native(
+        APP_TITLE,
+        options,
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
+    )
+    .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
+}
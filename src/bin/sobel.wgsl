@@ -0,0 +1,45 @@
+// Sobelフィルタによるエッジ検出: 入力テクスチャの3x3近傍の輝度から勾配の大きさを求め、
+// eguiが描画するテクスチャにグレーで書く（filter::Sobel と同じ計算）
+@group(0) @binding(0) var input: texture_2d<u32>;
+@group(0) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;
+
+// 勾配の大きさの理論上の最大値 4 * 255 * sqrt(2)（これで 0-1 に正規化する）
+const MAX_MAGNITUDE: f32 = 1442.4978;
+
+// (x, y) の輝度。端からはみ出した座標は一番近い画素にクランプする
+fn luma(x: i32, y: i32, size: vec2<i32>) -> i32 {
+    let p = clamp(vec2<i32>(x, y), vec2<i32>(0, 0), size - vec2<i32>(1, 1));
+    let pixel = textureLoad(input, p, 0);
+    // ITU-R BT.601 輝度（grayscale.wgsl と同じ固定小数点: (77*R + 150*G + 29*B) >> 8）
+    return i32((77u * pixel.r + 150u * pixel.g + 29u * pixel.b) >> 8u);
+}
+
+@compute @workgroup_size(16, 16)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    // テクスチャの範囲チェック（端のワークグループははみ出す）
+    let size = vec2<i32>(textureDimensions(input));
+    let x = i32(id.x);
+    let y = i32(id.y);
+    if (x >= size.x || y >= size.y) {
+        return;
+    }
+
+    // 3x3近傍の輝度
+    let tl = luma(x - 1, y - 1, size);
+    let t = luma(x, y - 1, size);
+    let tr = luma(x + 1, y - 1, size);
+    let l = luma(x - 1, y, size);
+    let r = luma(x + 1, y, size);
+    let bl = luma(x - 1, y + 1, size);
+    let b = luma(x, y + 1, size);
+    let br = luma(x + 1, y + 1, size);
+
+    // Sobelカーネルで横・縦の勾配
+    let gx = (tr + 2 * r + br) - (tl + 2 * l + bl);
+    let gy = (bl + 2 * b + br) - (tl + 2 * t + tr);
+    let magnitude = sqrt(f32(gx * gx + gy * gy));
+
+    // 0-255 に正規化したグレーを R, G, B に設定し、Alpha は 1.0
+    let value = min(magnitude / MAX_MAGNITUDE, 1.0);
+    textureStore(output, id.xy, vec4<f32>(value, value, value, 1.0));
+}
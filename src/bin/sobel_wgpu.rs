@@ -0,0 +1,202 @@
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use eframe::egui;
+use rust_ndi_viewer::filter::{Filter, Sobel};
+use rust_ndi_viewer::gpu::{self, EguiTexture, TextureCompute};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, handle_common_input, init_tracing, quality_indicator, status_label,
+    store_latest, CliArgs, FrameTexture, HelpOverlay, InfoOverlay, NdiReceiver, OwnedFrame,
+    Playback, ReceiverHandle, SourceOverlay, StreamStats, TransformControl,
+};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+const APP_TITLE: &str = "NDI Sobel Viewer (WGPU)";
+
+// eguiのデバイスでコンピュートが使えなければ（GL ESなど）CPUで同じ計算をする
+enum SobelBackend {
+    // 入力テクスチャから近傍を読み、eguiのテクスチャに直接書く
+    Texture(Box<TextureCompute>),
+    Cpu(Sobel),
+}
+
+struct NdiApp {
+    // スレッド間で共有する生フレームバッファ（ArcSwapでロックフリー）
+    raw_frame_buffer: Arc<ArcSwap<Option<OwnedFrame>>>,
+
+    // wgpuリソース（使えなければCPUフォールバック）
+    compute: SobelBackend,
+
+    // egui用のテクスチャハンドル（CPUフォールバック用）
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+
+    // 受信スレッド（アプリ終了時にDropで止める）
+    receiver: ReceiverHandle,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
+}
+
+impl NdiApp {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
+        let raw_frame_buffer = Arc::new(ArcSwap::from_pointee(None));
+        let raw_frame_buffer_clone = raw_frame_buffer.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+
+        // egui::Contextをクローンしてスレッドで使用
+        let ctx = cc.egui_ctx.clone();
+
+        // NDI receiver thread（ハンドルをDropすると停止して終了を待つ）
+        let connect_args = args.clone();
+        let receiver = ReceiverHandle::spawn(
+            move |stop| {
+                Ok(NdiReceiver::connect_with_stop(&connect_args, stop)?.with_stats(stats_clone))
+            },
+            move |frame| {
+                // copy frame
+                let raw = frame.to_owned();
+
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&raw_frame_buffer_clone, raw, &ctx);
+            },
+        );
+
+        if args.backend_order.is_some() {
+            warn!("sobel-wgpu always uses egui's wgpu device; ignoring --backend-order.");
+        }
+
+        // 読み戻し版は持たないので、eguiのデバイスで動かなければCPUで計算する
+        let compute = match &cc.wgpu_render_state {
+            Some(render_state) => {
+                info!(
+                    "egui renders on {}",
+                    gpu::describe_adapter(&render_state.adapter.get_info())
+                );
+                let output = EguiTexture::new(render_state).with_filter(args.texture_filter);
+                TextureCompute::new(
+                    output,
+                    "Sobel Texture",
+                    include_str!("sobel.wgsl"),
+                    args.max_texture_side,
+                )
+                .inspect_err(|e| warn!("{e:#}; falling back to CPU Sobel."))
+                .ok()
+            }
+            None => {
+                warn!("egui is not rendering with wgpu; falling back to CPU Sobel.");
+                None
+            }
+        };
+        let compute = match compute {
+            Some(compute) => {
+                info!("Using egui's wgpu device (zero-copy texture output)");
+                SobelBackend::Texture(Box::new(compute))
+            }
+            None => SobelBackend::Cpu(Sobel::default()),
+        };
+
+        Self {
+            raw_frame_buffer,
+            compute,
+            texture: FrameTexture::from_args(&args),
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            stats,
+            receiver,
+            args,
+        }
+    }
+}
+
+impl eframe::App for NdiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.receiver.current_source());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.receiver.current_source(), &self.stats.load());
+
+        let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
+
+        egui::CentralPanel::default()
+            .frame(panel_frame)
+            .show(ctx, |ui| {
+                // 新しいフレームがあるかチェック（ArcSwapでロックフリー読み取り）
+                let new_raw_frame = self.raw_frame_buffer.swap(Arc::new(None));
+                let new_raw_frame =
+                    Arc::try_unwrap(new_raw_frame).unwrap_or_else(|arc| (*arc).clone());
+
+                // 一時停止中は取り出したフレームを捨て、GPUの処理も飛ばす
+                if let Some(raw) = new_raw_frame
+                    && !Playback::is_paused(ctx)
+                {
+                    match &mut self.compute {
+                        // 結果はeguiのテクスチャに直接書かれる
+                        SobelBackend::Texture(compute) => compute.process(&raw),
+                        SobelBackend::Cpu(sobel) => {
+                            let (width, height) = (raw.width as usize, raw.height as usize);
+                            let mut data = raw.data.clone();
+                            sobel.apply(width, height, &mut data);
+                            let mut image =
+                                egui::ColorImage::from_rgba_unmultiplied([width, height], &data);
+                            image.source_size = raw.display_size();
+                            self.texture.update(ctx, image);
+                        }
+                    }
+                }
+
+                // テクスチャがあれば描画（表示アスペクト比を保ってレターボックス表示）
+                let shown = match &mut self.compute {
+                    SobelBackend::Texture(compute) => compute.show(ui).is_some(),
+                    SobelBackend::Cpu(_) if self.texture.handle().is_some() => {
+                        self.texture.show(ui);
+                        true
+                    }
+                    SobelBackend::Cpu(_) => false,
+                };
+
+                if !shown {
+                    status_label(ui, &self.receiver.connection_state(), &self.stats.load());
+                }
+            });
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let mut options = create_native_options(&args);
+    // eguiもwgpuで描画させ、コンピュートシェーダーの出力テクスチャをそのまま表示する
+    gpu::use_wgpu_renderer(&mut options, &args);
+
+    eframe::run_native(
+        APP_TITLE,
+        options,
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
+    )
+    .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
+}
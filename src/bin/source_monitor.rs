@@ -0,0 +1,406 @@
+//! 見つかったすべてのソースに同時に接続して受信状態を一覧するヘッドレスの診断ツール
+//!
+//! ネットワークの立ち上げ時に、問題のあるソースを見つけるためのもの。既定では低帯域
+//! （プレビュー用のプロキシストリーム）で受信するので、解像度はプロキシのものになる。
+//! 同時に開く受信機は `--max-receivers` までで、残りは空くまで待たせる。
+//! 表は `--interval` ごとに描き直し、`--json` なら代わりに1ソース1行のJSONを出す。
+
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use grafton_ndi::{
+    Error, Finder, FinderOptions, PixelFormat, Receiver, ReceiverBandwidth, ReceiverColorFormat,
+    ReceiverOptions, Source, NDI,
+};
+use rust_ndi_viewer::{
+    cli, init_ndi, init_tracing, json, with_discovery_groups, CliArgs, GiveUp, TransformControl,
+    CAPTURE_TIMEOUT,
+};
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, warn};
+
+const DEFAULT_MAX_RECEIVERS: usize = 16;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+// この時間フレームが来なければ「映像なし」とみなす
+const NO_VIDEO_AFTER: Duration = Duration::from_secs(2);
+// 直近の区間でこれを超えてドロップしていれば問題あり（`Quality::Bad` と同じ5%）
+const DROP_RATIO_WARN: f64 = 0.05;
+// キャプチャエラーの後、次に受信するまでの待ち時間
+const ERROR_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// モニター専用のオプション（残りは `CliArgs` に渡す）
+struct MonitorArgs {
+    json: bool,
+    max_receivers: usize,
+    interval: Duration,
+    bandwidth: ReceiverBandwidth,
+    rest: Vec<String>,
+}
+
+impl MonitorArgs {
+    fn parse() -> Self {
+        let mut parsed = Self {
+            json: false,
+            max_receivers: DEFAULT_MAX_RECEIVERS,
+            interval: DEFAULT_INTERVAL,
+            bandwidth: ReceiverBandwidth::Lowest,
+            rest: Vec::new(),
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let name = arg.as_str();
+            if name == "--json" {
+                parsed.json = true;
+                continue;
+            }
+            if !matches!(name, "--max-receivers" | "--interval" | "--bandwidth") {
+                parsed.rest.push(arg);
+                continue;
+            }
+            let Some(value) = args.next() else {
+                warn!("{name} requires a value");
+                break;
+            };
+            match name {
+                "--max-receivers" => match value.parse() {
+                    Ok(max) if max > 0 => parsed.max_receivers = max,
+                    _ => warn!("Invalid receiver count \"{value}\", ignoring."),
+                },
+                "--interval" => match value.parse::<f64>() {
+                    Ok(secs) if secs > 0.0 && secs.is_finite() => {
+                        parsed.interval = Duration::from_secs_f64(secs)
+                    }
+                    _ => warn!("Invalid interval \"{value}\", ignoring."),
+                },
+                _ => match cli::parse_bandwidth(&value) {
+                    Some(bandwidth) => parsed.bandwidth = bandwidth,
+                    None => warn!(
+                        "Unknown bandwidth \"{value}\" (expected lowest or highest), \
+                         using lowest."
+                    ),
+                },
+            }
+        }
+        parsed
+    }
+}
+
+/// 受信スレッドが区間ごとに更新する1ソースの状態
+#[derive(Debug, Clone, Default)]
+struct SourceStatus {
+    connected: bool,
+    width: i32,
+    height: i32,
+    format: Option<PixelFormat>,
+    // 送信側が名乗るフレームレートと、直近の区間で実際に届いたレート
+    nominal_fps: f64,
+    fps: f64,
+    received: u64,
+    dropped: u64,
+    recent_drop_ratio: f64,
+    last_frame: Option<Instant>,
+    error: Option<String>,
+}
+
+/// 1ソース分の受信スレッド（Dropで止める）
+struct Monitor {
+    stop: Arc<AtomicBool>,
+    status: Arc<ArcSwap<SourceStatus>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Monitor {
+    fn spawn(ndi: &NDI, source: Source, bandwidth: ReceiverBandwidth) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let status = Arc::new(ArcSwap::from_pointee(SourceStatus::default()));
+        let ndi = ndi.clone();
+        let thread = thread::spawn({
+            let stop = stop.clone();
+            let status = status.clone();
+            move || {
+                let options = ReceiverOptions::builder(source)
+                    .bandwidth(bandwidth)
+                    // 変換しないので、ソースのネイティブ形式のまま受け取る
+                    .color(ReceiverColorFormat::Fastest)
+                    .build();
+                if let Err(e) = receive(&ndi, &options, &stop, &status) {
+                    let mut failed = SourceStatus::clone(&status.load());
+                    failed.error = Some(e.to_string());
+                    status.store(Arc::new(failed));
+                }
+            }
+        });
+        Self {
+            stop,
+            status,
+            thread: Some(thread),
+        }
+    }
+
+    /// 受信スレッドがまだ動いているか（接続エラーで終わったら受信機の枠を空ける）
+    fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// 停止するまで受信し、1秒ごとに `status` を更新する
+fn receive(
+    ndi: &NDI,
+    options: &ReceiverOptions,
+    stop: &AtomicBool,
+    status: &ArcSwap<SourceStatus>,
+) -> Result<()> {
+    let receiver = Receiver::new(ndi, options)?;
+    let mut current = SourceStatus::default();
+    let mut window_start = Instant::now();
+    let mut window_frames = 0;
+    let (mut window_received, mut window_dropped) = (0, 0);
+
+    while !stop.load(Ordering::Relaxed) {
+        match receiver.capture_video_ref(CAPTURE_TIMEOUT) {
+            Ok(Some(frame)) => {
+                current.width = frame.width();
+                current.height = frame.height();
+                current.format = Some(frame.pixel_format());
+                let (rate_n, rate_d) = (frame.frame_rate_n(), frame.frame_rate_d());
+                if rate_n > 0 && rate_d > 0 {
+                    current.nominal_fps = rate_n as f64 / rate_d as f64;
+                }
+                current.last_frame = Some(Instant::now());
+                current.error = None;
+                window_frames += 1;
+            }
+            Ok(None) | Err(Error::Timeout(_) | Error::FrameTimeout { .. }) => {}
+            Err(e) => {
+                current.error = Some(e.to_string());
+                // すぐに同じエラーで戻ってくることがあるので空回りしないように待つ
+                thread::sleep(ERROR_RETRY_DELAY);
+            }
+        }
+
+        let elapsed = window_start.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            let connection = receiver.connection_stats();
+            current.connected = receiver.is_connected();
+            current.received = connection.video_frames_received;
+            current.dropped = connection.video_frames_dropped;
+            let received = current.received.saturating_sub(window_received);
+            let dropped = current.dropped.saturating_sub(window_dropped);
+            current.recent_drop_ratio = if received + dropped > 0 {
+                dropped as f64 / (received + dropped) as f64
+            } else {
+                0.0
+            };
+            current.fps = window_frames as f64 / elapsed.as_secs_f64();
+            (window_received, window_dropped) = (current.received, current.dropped);
+            window_frames = 0;
+            window_start = Instant::now();
+            status.store(Arc::new(current.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// 一覧の1行
+enum Entry {
+    /// `--max-receivers` に達していて、受信機が空くのを待っている
+    Waiting(Source),
+    Monitoring(Monitor),
+    /// 探索結果から消えた（受信機は閉じた）
+    Gone,
+}
+
+impl Entry {
+    /// 状態の表示名と、問題があるかどうか
+    fn state(&self, now: Instant) -> (String, bool) {
+        let status = match self {
+            Self::Waiting(_) => return ("waiting".to_string(), false),
+            Self::Gone => return ("gone".to_string(), true),
+            Self::Monitoring(monitor) => monitor.status.load(),
+        };
+        if let Some(error) = &status.error {
+            return (format!("error: {error}"), true);
+        }
+        let silent = status
+            .last_frame
+            .is_none_or(|last| now.duration_since(last) >= NO_VIDEO_AFTER);
+        if !status.connected && status.last_frame.is_none() {
+            ("connecting".to_string(), false)
+        } else if !status.connected {
+            ("disconnected".to_string(), true)
+        } else if silent {
+            ("no video".to_string(), true)
+        } else if status.recent_drop_ratio > DROP_RATIO_WARN {
+            ("dropping".to_string(), true)
+        } else {
+            ("ok".to_string(), false)
+        }
+    }
+
+    /// 1枚でもフレームを受け取っていれば、その状態とフォーマット
+    fn received(&self) -> Option<(Arc<SourceStatus>, PixelFormat)> {
+        let Self::Monitoring(monitor) = self else {
+            return None;
+        };
+        let status = monitor.status.load_full();
+        let format = status.format?;
+        Some((status, format))
+    }
+}
+
+/// 探索結果に合わせて受信機を開け閉めする（キーは "名前@アドレス"）
+fn sync_sources(
+    entries: &mut BTreeMap<String, Entry>,
+    sources: Vec<Source>,
+    ndi: &NDI,
+    args: &MonitorArgs,
+) {
+    let mut seen: Vec<String> = Vec::with_capacity(sources.len());
+    for source in sources {
+        let key = source.to_string();
+        match entries.get(&key) {
+            Some(Entry::Waiting(_) | Entry::Monitoring(_)) => {}
+            Some(Entry::Gone) | None => {
+                entries.insert(key.clone(), Entry::Waiting(source));
+            }
+        }
+        seen.push(key);
+    }
+    // 消えたソースは受信機を閉じる（Dropでスレッドを止める）が、行は残して目立たせる
+    for (key, entry) in entries.iter_mut() {
+        if !seen.contains(key) && !matches!(entry, Entry::Gone) {
+            *entry = Entry::Gone;
+        }
+    }
+
+    // 空いた分だけ、待っているソースを名前順に開く
+    let mut active = entries
+        .values()
+        .filter(|entry| matches!(entry, Entry::Monitoring(monitor) if monitor.is_running()))
+        .count();
+    for entry in entries.values_mut() {
+        if active >= args.max_receivers {
+            break;
+        }
+        if let Entry::Waiting(source) = entry {
+            *entry = Entry::Monitoring(Monitor::spawn(ndi, source.clone(), args.bandwidth));
+            active += 1;
+        }
+    }
+}
+
+fn print_table(entries: &BTreeMap<String, Entry>, started: Instant) {
+    let now = Instant::now();
+    // 画面を消して左上から描き直す
+    print!("\x1b[2J\x1b[H");
+    println!(
+        "NDI source monitor: {} source(s), {:.0}s elapsed (Ctrl+C to quit)\n",
+        entries.len(),
+        started.elapsed().as_secs_f64()
+    );
+    println!(
+        "  {:<40} {:<14} {:>11} {:<6} {:>13} {:>9} {:>8} {:>6}",
+        "Source", "State", "Resolution", "Format", "FPS (nominal)", "Received", "Dropped", "Drop%"
+    );
+    for (key, entry) in entries {
+        let (state, problem) = entry.state(now);
+        let marker = if problem { '!' } else { ' ' };
+        let Some((status, format)) = entry.received() else {
+            println!("{marker} {key:<40} {state:<14}");
+            continue;
+        };
+        println!(
+            "{marker} {key:<40} {state:<14} {:>11} {:<6} {:>13} {:>9} {:>8} {:>6.1}",
+            format!("{}x{}", status.width, status.height),
+            format!("{format:?}"),
+            format!("{:.1} ({:.2})", status.fps, status.nominal_fps),
+            status.received,
+            status.dropped,
+            status.recent_drop_ratio * 100.0
+        );
+    }
+}
+
+fn print_json_lines(entries: &BTreeMap<String, Entry>, started: Instant) {
+    let now = Instant::now();
+    let elapsed = started.elapsed().as_secs_f64();
+    for (key, entry) in entries {
+        let (state, problem) = entry.state(now);
+        let mut line = format!(
+            "{{\"elapsed_s\": {elapsed:.3}, \"source\": \"{}\", \"state\": \"{}\", \"problem\": {problem}",
+            json::escape(key),
+            json::escape(&state)
+        );
+        if let Some((status, format)) = entry.received() {
+            line += &format!(
+                ", \"width\": {}, \"height\": {}, \"format\": \"{format:?}\", \"fps\": {:.2}, \
+                 \"nominal_fps\": {:.3}, \"received\": {}, \"dropped\": {}, \"drop_ratio\": {:.4}",
+                status.width,
+                status.height,
+                status.fps,
+                status.nominal_fps,
+                status.received,
+                status.dropped,
+                status.recent_drop_ratio
+            );
+        }
+        line.push('}');
+        println!("{line}");
+    }
+}
+
+fn main() -> Result<()> {
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = MonitorArgs::parse();
+    let cli = CliArgs::parse_from(args.rest.clone());
+    if cli.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&cli);
+
+    let ndi = match init_ndi() {
+        Ok(ndi) => ndi,
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(GiveUp::NdiInit.exit_code());
+        }
+    };
+    let mut builder = with_discovery_groups(
+        FinderOptions::builder().show_local_sources(true),
+        cli.groups.as_deref(),
+    );
+    for ip in &cli.extra_ips {
+        builder = builder.extra_ips(ip.as_str());
+    }
+    let finder = Finder::new(&ndi, &builder.build())?;
+
+    let started = Instant::now();
+    let mut entries = BTreeMap::new();
+    loop {
+        // ソースの増減があれば早めに戻る
+        finder.wait_for_sources(args.interval)?;
+        sync_sources(&mut entries, finder.sources(Duration::ZERO)?, &ndi, &args);
+        if args.json {
+            print_json_lines(&entries, started);
+        } else {
+            print_table(&entries, started);
+        }
+    }
+}
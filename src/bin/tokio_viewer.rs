@@ -1,69 +1,123 @@
 use anyhow::Result;
-use arc_swap::ArcSwap;
+use arc_swap::{ArcSwap, ArcSwapOption};
 use eframe::egui;
-use rust_ndi_viewer::{create_native_options, NdiReceiver, TARGET_SOURCE_NAME};
+use rust_ndi_viewer::probe;
+use rust_ndi_viewer::{
+    create_native_options, exit_if_given_up, handle_common_input, init_tracing, quality_indicator,
+    status_label, store_latest, CliArgs, ConnectionState, FrameTexture, HelpOverlay, InfoOverlay,
+    NdiError, NdiReceiver, SourceOverlay, StreamStats, TransformControl,
+};
 use std::sync::Arc;
+use tracing::{error, trace};
+
+const APP_TITLE: &str = "NDI Tokio Viewer";
 
 struct NdiApp {
     // スレッド間で共有する画像バッファ（ArcSwapでロックフリー）
     frame_buffer: Arc<ArcSwap<Option<egui::ColorImage>>>,
 
     // egui用のテクスチャハンドル
-    texture: Option<egui::TextureHandle>,
+    texture: FrameTexture,
+
+    // ?/F1 のショートカット一覧
+    help: HelpOverlay,
+
+    // 接続中のソース名（タイトルと左上）
+    source_overlay: SourceOverlay,
+    // I で開閉するタイムコード・ソース名・解像度のHUD
+    info: InfoOverlay,
+    // 受信スレッドが接続できたソースを書き込む
+    current_source: Arc<ArcSwapOption<String>>,
+
+    // 受信統計（右上の品質インジケータ用）
+    stats: Arc<ArcSwap<StreamStats>>,
+    // 映像が出るまでの表示（探索中・接続中・失敗の理由）
+    connection_state: Arc<ArcSwap<ConnectionState>>,
+
+    // mainで読んだコマンドライン引数（設定ファイルの場所など）
+    args: CliArgs,
 }
 
 impl NdiApp {
-    fn new(cc: &eframe::CreationContext<'_>) -> Self {
+    fn new(cc: &eframe::CreationContext<'_>, args: CliArgs) -> Self {
         let frame_buffer = Arc::new(ArcSwap::from_pointee(None));
         let frame_buffer_clone = frame_buffer.clone();
+        let current_source = Arc::new(ArcSwapOption::empty());
+        let current_source_clone = current_source.clone();
+        let stats = Arc::new(ArcSwap::from_pointee(StreamStats::default()));
+        let stats_clone = stats.clone();
+        let connection_state = Arc::new(ArcSwap::from_pointee(ConnectionState::Searching));
+        let connection_state_clone = connection_state.clone();
 
         // egui::Contextをクローンして非同期タスクで使用
         let ctx = cc.egui_ctx.clone();
 
         // Tokio runtime for NDI receiver
+        let connect_args = args.clone();
         tokio::spawn(async move {
-            let receiver = match NdiReceiver::connect() {
-                Ok(r) => r,
+            let receiver = match NdiReceiver::connect(&connect_args) {
+                Ok(r) => {
+                    current_source_clone.store(Some(Arc::new(r.source_label())));
+                    r.with_stats(stats_clone)
+                        .with_connection_state(connection_state_clone)
+                }
                 Err(e) => {
-                    eprintln!("Failed to connect to NDI: {e}");
+                    error!("Failed to connect to NDI: {e}");
+                    connection_state_clone.store(Arc::new(ConnectionState::failed(&e)));
+                    // NDIのランタイムが無ければ、終了せずに入れ方をウィンドウに出す
+                    if NdiError::of(&e).is_none() {
+                        exit_if_given_up(&e);
+                    }
+                    ctx.request_repaint();
                     return;
                 }
             };
 
-            // Note: run_loopは同期的なので、spawn_blockingで実行
-            tokio::task::spawn_blocking(move || {
-                let _ = receiver.run_loop(|frame| {
-                    // Convert NDI frame into egui::ColorImage
-                    let image = egui::ColorImage::from_rgba_unmultiplied(
-                        [frame.width as usize, frame.height as usize],
-                        &frame.data,
-                    );
-
-                    // Store using ArcSwap (lock-free)
-                    frame_buffer_clone.store(Arc::new(Some(image)));
-
-                    // 受信時に再描画をリクエスト
-                    ctx.request_repaint();
-
-                    println!(
-                        "Frame received (tokio): {}x{}, timecode={}",
-                        frame.width, frame.height, frame.timecode
-                    );
-                });
-            })
-            .await
-            .ok();
+            // 受信ループは別スレッドで回り、フレームはコピーされてストリームで届く
+            let mut frames = receiver.into_stream();
+            while let Some(frame) = frames.next().await {
+                // Convert NDI frame into egui::ColorImage
+                let image = frame.as_validated().to_color_image();
+
+                // Store using ArcSwap (lock-free)。前のフレームが表示済みのときだけ再描画を頼む
+                store_latest(&frame_buffer_clone, image, &ctx);
+
+                trace!(
+                    width = frame.width,
+                    height = frame.height,
+                    timecode = frame.timecode,
+                    "Frame received (tokio)"
+                );
+            }
+            if let Some(e) = frames.take_error() {
+                exit_if_given_up(&e);
+            }
         });
 
         Self {
             frame_buffer,
-            texture: None,
+            texture: FrameTexture::from_args(&args),
+            help: HelpOverlay::default(),
+            source_overlay: SourceOverlay::new(APP_TITLE),
+            info: InfoOverlay::default(),
+            current_source,
+            stats,
+            connection_state,
+            args,
         }
     }
 }
 
 impl eframe::App for NdiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        handle_common_input(ctx, &self.args);
+        self.help.show(ctx);
+        self.source_overlay
+            .show(ctx, self.current_source.load_full());
+        quality_indicator(ctx, &self.stats.load());
+        self.info
+            .show(ctx, self.current_source.load_full(), &self.stats.load());
+
         let panel_frame = egui::Frame::central_panel(&ctx.style()).fill(egui::Color32::BLACK);
 
         egui::CentralPanel::default()
@@ -75,33 +129,18 @@ impl eframe::App for NdiApp {
 
                 // 新しい画像が来ていればテクスチャを更新
                 if let Some(image) = new_image {
-                    self.texture = Some(ctx.load_texture(
-                        "ndi-frame",
-                        image,
-                        egui::TextureOptions::LINEAR,
-                    ));
+                    self.texture.update(ctx, image);
                 }
 
                 // テクスチャがあれば描画
-                if let Some(texture) = &self.texture {
-                    let size = ui.available_size();
-                    ui.centered_and_justified(|ui| {
-                        ui.image((texture.id(), size));
-                    });
+                if self.texture.handle().is_some() {
+                    // 表示アスペクト比を保ってレターボックス表示
+                    self.texture.show(ui);
 
                     // Note: tokioタスクがrequest_repaintを呼ぶため、
                     // ここでは明示的に呼ばなくても新しいフレームが来たら自動的に再描画される
                 } else {
-                    ui.centered_and_justified(|ui| {
-                        ui.label(
-                            egui::RichText::new(format!(
-                                "Waiting for NDI Source: {}...",
-                                TARGET_SOURCE_NAME
-                            ))
-                            .color(egui::Color32::WHITE)
-                            .size(32.0),
-                        );
-                    });
+                    status_label(ui, &self.connection_state.load(), &self.stats.load());
                 }
             });
     }
@@ -109,12 +148,22 @@ impl eframe::App for NdiApp {
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let options = create_native_options();
+    init_tracing(env!("CARGO_CRATE_NAME"));
+    let args = CliArgs::parse();
+    if args.help {
+        CliArgs::print_help();
+        return Ok(());
+    }
+    TransformControl::init(&args);
+    if args.probe {
+        return probe::run(&args);
+    }
+    let options = create_native_options(&args);
 
     eframe::run_native(
-        "NDI Tokio Viewer",
+        APP_TITLE,
         options,
-        Box::new(|cc| Ok(Box::new(NdiApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(NdiApp::new(cc, args)))),
     )
     .map_err(|e| anyhow::anyhow!("GUI error: {}", e))
 }
@@ -0,0 +1,713 @@
+//! コマンドライン引数の解析
+//!
+//! `--` で始まらない引数は追加のディスカバリIP/サブネットとして扱う。
+//! 数が多い場合は `--extra-ips-file` で1行1件のファイルからも読める。
+
+use crate::audio::ChannelMix;
+use crate::deinterlace::Deinterlace;
+#[cfg(feature = "gui")]
+use crate::display::TextureFilter;
+use crate::filter::{
+    Corner, FalseColorThresholds, FilterKind, LumaKeySettings, SharpenSettings, UnsharpMask,
+};
+#[cfg(feature = "gui")]
+use crate::gpu;
+use crate::record::RecordFormat;
+use crate::transform::{Rotation, Transform};
+use crate::{CaptureErrorPolicy, Validation, ViewerConfig, CAPTURE_TIMEOUT, MAX_DOWNSCALE};
+use grafton_ndi::{ReceiverBandwidth, ReceiverColorFormat};
+use std::env;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::warn;
+
+/// `--help` の表示内容（オプションを増やしたらこことREADMEの表にも書くこと）
+const HELP: &str = "\
+Usage: <viewer> [OPTIONS] [EXTRA_IPS...]
+
+Positional arguments are extra IPs/subnets/host names used for NDI discovery.
+
+Options:
+  --filter <name>                        false-color (exposure overlay), luma-key or sharpen
+  --false-color-thresholds <c,ml,mh,cl>  8-bit luma thresholds for false color (default 5,100,130,250)
+  --luma-key <threshold,softness>        8-bit luma key threshold and edge ramp width (default 16,8)
+  --luma-key-above                       Key out pixels brighter than the threshold instead
+  --luma-key-background <rrggbb|none>    Color composited behind the key (default 808080)
+  --sharpen-amount <x>                   Unsharp mask strength 0-5 for --filter sharpen (default 1.0)
+  --sharpen-radius <px>                  Unsharp mask blur radius 2-30 (default 2)
+  --max-texture-size <px>                Cap the uploaded texture size
+  --scale <1/n>                          Downscale every received frame by 1/2, 1/4 ... 1/8 (box filter)
+  --texture-filter <mode>                auto (default), linear or nearest scaling
+  --source-index <n>                     Connect to the n-th (1-based) discovered source
+  --address <host>                       Only consider sources at this address
+  --groups <list>                        Only discover sources in these NDI groups, e.g. studio,news
+  --backend-order <list>                 wgpu backends to try in order, e.g. vulkan,gl
+  --backend <name>                       wgpu backend: vulkan, gl, metal or dx12
+  --adapter <index>                      Use the n-th (0-based) wgpu adapter of the backend
+  --color-format <fmt>                   rgba (default), bgra, uyvy, uyvy-bgra, fastest or best
+  --bandwidth <lowest|highest>           Receive the sender's low-bandwidth proxy or the full stream (default)
+  --on-capture-error <policy>            reconnect (default), retry or fail
+  --strict                               Drop frames whose stride or size doesn't match (default)
+  --lenient                              Repack/black-fill such frames instead and log what was fixed
+  --auto-crop <threshold>                Crop away transparent margins (alpha <= threshold, raw-viewer)
+  --auto-crop-background <rrggbb|none>   Treat pixels close to this color as margin instead of alpha
+  --auto-crop-lock                       Keep the first crop box instead of tracking every frame
+  --max-connect-attempts <n>             Give up after n consecutive failed connect/capture attempts
+  --no-tone-map                          Show HDR sources without SDR tone mapping
+  --msaa <1|2|4>                         Multisample anti-aliasing (default 1 = off)
+  --discovery-timeout <secs>             Give up if no source is found in time
+  --reconnect-after <secs>               Rediscover and reconnect after this long without a connection (raw-viewer)
+  --capture-timeout <ms>                 Wait at most this long for each video frame (1-500, default 500)
+  --extra-ips-file <path>                Read extra discovery IPs/subnets from a file
+  --config <path>                        Remember the source and window geometry in this file
+  --no-config                            Don't read or write the config file
+  --watermark <png>                      Blend a PNG logo onto every frame (raw-viewer)
+  --watermark-corner <corner>            top-left, top-right, bottom-left or bottom-right (default)
+  --watermark-opacity <0-1>              Watermark opacity (default 0.5)
+  --audio-channels <list>                Pass only these audio channels (0-based), e.g. 0,1
+  --downmix stereo                       Downmix multichannel audio to stereo
+  --freeze-stride <px>                   Freeze detection sample spacing (default 8, 0 = off)
+  --snapshot-sidecar                     Also write snapshot metadata to a JSON file next to the PNG
+  --target-fps <fps>                     Drop/repeat frames to show exactly this rate (raw-viewer)
+  --smooth                               Present frames at the nominal frame interval (raw-viewer)
+  --verify                               Checksum each frame across the thread handoff, warn on mismatch (raw-viewer)
+  --max-sources <n>                      Show at most n sources side by side (grid-viewer, default 9)
+  --buffer-frames <n>                    Hold at most n frames (buffered-viewer, default 180)
+  --buffer-latency <ms>                  Target display delay (buffered-viewer, default 1000)
+  --deinterlace <mode>                   none, bob or blend (default) for interlaced frames (framesync-viewer)
+  --record-format <fmt>                  mkv (default) or mp4 for R-key recordings (record-viewer)
+  --rotate <0|90|180|270>                Rotate the picture clockwise (T cycles it while running)
+  --flip <none|h|v|hv>                   Mirror the picture horizontally and/or vertically (F / V)
+  --lut <file.cube>                      Grade the picture with this 3D LUT (lut-wgpu)
+  --probe                                Print the first frame's header and checks, then exit
+  --control <addr>                       Serve the web remote panel (raw-viewer, `control` feature)
+  -h, --help                             Show this help
+
+Exit codes:
+  0  Clean shutdown (window closed)
+  1  Any other error
+  2  NDI initialization failed (windowed viewers show a missing runtime instead)
+  3  Gave up discovering sources (--discovery-timeout, or no source matches the name/index/address)
+  4  Source vanished: --max-connect-attempts consecutive failures";
+
+/// 各ビューア共通のコマンドライン設定
+#[derive(Debug, Clone, Default)]
+pub struct CliArgs {
+    /// 追加で探索するIP/サブネット（位置引数と `--extra-ips-file` の両方から）
+    pub extra_ips: Vec<String>,
+    /// `--filter <name>`
+    pub filter: Option<FilterKind>,
+    /// `--false-color-thresholds crushed,mid_low,mid_high,clipped`
+    pub false_color_thresholds: FalseColorThresholds,
+    /// `--luma-key threshold,softness` / `--luma-key-above` / `--luma-key-background <rrggbb|none>`
+    pub luma_key: LumaKeySettings,
+    /// `--sharpen-amount <x>` / `--sharpen-radius <px>` アンシャープマスクの強さとぼかし半径
+    pub sharpen: SharpenSettings,
+    /// `--max-texture-size <px>`（バックエンドの上限より小さく抑えたい場合）
+    pub max_texture_side: Option<usize>,
+    /// `--texture-filter <auto|linear|nearest>` 拡大縮小フィルタ（既定は表示倍率から自動）
+    #[cfg(feature = "gui")]
+    pub texture_filter: TextureFilter,
+    /// `--control <addr>` リモート操作用HTTPの待ち受けアドレス（`control` feature）
+    pub control_addr: Option<String>,
+    /// `--source-index <n>` 一覧に表示された番号（1始まり）でソースを選ぶ
+    pub source_index: Option<usize>,
+    /// `--address <host>` 同名ソースをアドレス（IP/ホスト名の部分一致）で絞り込む
+    pub address: Option<String>,
+    /// `--groups <list>` 探索するNDIグループ（カンマ区切り、未指定・空ならすべてのソース）
+    pub groups: Option<String>,
+    /// `--backend-order vulkan,gl` wgpuバックエンドを試す順序（wgpu系ビューア）
+    #[cfg(feature = "gui")]
+    pub backend_order: Option<Vec<wgpu::Backends>>,
+    /// `--backend <vulkan|gl|metal|dx12>` 使うwgpuバックエンド（wgpu系ビューア、eguiの描画にも使う）
+    #[cfg(feature = "gui")]
+    pub backend: Option<wgpu::Backends>,
+    /// `--adapter <index>` バックエンドが列挙したアダプタのうち何番目（0始まり）を使うか
+    #[cfg(feature = "gui")]
+    pub adapter: Option<usize>,
+    /// `--color-format <rgba|bgra|uyvy|uyvy-bgra|fastest|best>` 受信フォーマット
+    /// （未指定なら [`crate::DEFAULT_COLOR_FORMAT`]）
+    ///
+    /// `fastest` はソースのネイティブ形式をそのまま受け取るので遅延は小さいが、
+    /// 届くフォーマットはソース次第になる（RGBAへの変換はこちらで行う）。
+    pub color_format: Option<ReceiverColorFormat>,
+    /// `--bandwidth <lowest|highest>` 受信する帯域（既定は highest、lowest は送信側のプロキシ映像）
+    ///
+    /// lowest では解像度が送信側のプロキシ（多くは640x360程度）に下がる。リモート監視向け。
+    pub bandwidth: ReceiverBandwidth,
+    /// `--on-capture-error <retry|reconnect|fail>` タイムアウト以外のキャプチャエラー時の動作
+    pub capture_error_policy: CaptureErrorPolicy,
+    /// `--strict` / `--lenient` 行ストライドやデータ長が合わないフレームを捨てるか補って表示するか
+    pub validation: Validation,
+    /// `--no-tone-map` HDR（PQ/HLG）のフレームをSDRにトーンマッピングしない
+    pub no_tone_map: bool,
+    /// `--msaa <1|2|4>` egui描画のMSAAサンプル数（未指定・1ならMSAAなし）
+    pub msaa_samples: Option<u16>,
+    /// `--discovery-timeout <secs>` この時間ソースが見つからなければ諦める（未指定なら待ち続ける）
+    pub discovery_timeout: Option<Duration>,
+    /// `--reconnect-after <secs>` この時間ソースにつながらなければ探し直して再接続する（raw-viewer）
+    pub reconnect_after: Option<Duration>,
+    /// `--capture-timeout <ms>` 映像1フレームを待つ時間（未指定なら `CAPTURE_TIMEOUT`、それより長くはできない）
+    pub capture_timeout: Option<Duration>,
+    /// `--watermark <png>` 映像の角に重ねるロゴ
+    pub watermark: Option<PathBuf>,
+    /// `--watermark-corner <top-left|top-right|bottom-left|bottom-right>`（既定は右下）
+    pub watermark_corner: Corner,
+    /// `--watermark-opacity <0-1>`（未指定なら `Watermark::DEFAULT_OPACITY`）
+    pub watermark_opacity: Option<f32>,
+    /// `--auto-crop <threshold>` 透明な余白を切り落とす（アルファがしきい値以下を余白とみなす、raw-viewer）
+    pub auto_crop: Option<u8>,
+    /// `--auto-crop-background <rrggbb|none>` アルファの代わりにこの色に近い画素を余白とみなす
+    pub auto_crop_background: Option<[u8; 3]>,
+    /// `--auto-crop-lock` 範囲を最初に求めたもので固定する（既定はフレームごとに追従）
+    pub auto_crop_lock: bool,
+    /// `--scale 1/<n>` 受信したフレームを1/nに縮小してからコールバックに渡す（未指定なら縮小しない）
+    pub downscale: Option<usize>,
+    /// `--max-connect-attempts <n>` 続けてn回接続・受信に失敗したら諦めて終了する（未指定なら無制限）
+    pub max_connect_attempts: Option<u32>,
+    /// `--audio-channels 0,1` / `--downmix stereo` 音声のチャンネル選択（既定は全チャンネル）
+    pub audio_mix: ChannelMix,
+    /// `--freeze-stride <px>` フリーズ検出の標本間隔（未指定なら8、0で無効）
+    pub freeze_stride: Option<usize>,
+    /// `--snapshot-sidecar` スナップショットのPNGの隣にメタデータのJSONも書く
+    pub snapshot_sidecar: bool,
+    /// `--target-fps <fps>` 表示をこのフレームレートにそろえる（0次ホールドで間引き・重複、raw-viewer）
+    pub target_fps: Option<f64>,
+    /// `--smooth` フレームを公称間隔で提示して到着のばらつきを均す（最大1フレーム遅延、raw-viewer）
+    pub smooth: bool,
+    /// `--verify` 受信スレッドで付けたチェックサムをテクスチャに載せる前に照合する（raw-viewer）
+    pub verify: bool,
+    /// `--max-sources <n>` 並べて表示するソースの数の上限（grid-viewer、未指定なら9）
+    pub max_sources: Option<usize>,
+    /// `--buffer-frames <n>` バッファに溜めるフレーム数の上限（buffered-viewer、未指定なら180）
+    pub buffer_frames: Option<usize>,
+    /// `--buffer-latency <ms>` 到着から表示までの目標遅延（buffered-viewer、未指定なら1000ms）
+    pub buffer_latency: Option<Duration>,
+    /// `--deinterlace <none|bob|blend>` インターレースのフレームの扱い（framesync-viewer、実行中は D で切り替え）
+    pub deinterlace: Deinterlace,
+    /// `--record-format <mkv|mp4>` 録画ファイルのコンテナ（record-viewer）
+    pub record_format: RecordFormat,
+    /// `--rotate <0|90|180|270>` / `--flip <none|h|v|hv>` 受信したフレームの回転・反転（実行中は T / F / V）
+    pub transform: Transform,
+    /// `--lut <file.cube>` 映像にかける3D LUT（lut-wgpu）
+    pub lut: Option<PathBuf>,
+    /// `--probe` 最初の1フレームの情報と検証結果を表示して終了する（ウィンドウは開かない）
+    pub probe: bool,
+    /// `--config <path>` 前回のソースとウィンドウの位置・大きさを覚えておくファイル
+    ///
+    /// `parse()` は未指定なら [`ViewerConfig::default_path`] を入れる。`parse_from` や
+    /// `Default` で組み立てた場合は `None` のままなので、ライブラリとしての利用では読み書きしない。
+    pub config_path: Option<PathBuf>,
+    /// `--no-config` 設定ファイルを読み書きしない
+    pub no_config: bool,
+    /// `--help` / `-h` 使い方を表示して終了する（表示と終了は `main` が [`CliArgs::print_help`] で行う）
+    pub help: bool,
+}
+
+impl CliArgs {
+    /// `env::args()` から解析する（`--config` 未指定なら既定の設定ファイルを使う）
+    pub fn parse() -> Self {
+        let mut parsed = Self::parse_from(env::args().skip(1));
+        if parsed.config_path.is_none() {
+            parsed.config_path = ViewerConfig::default_path();
+        }
+        parsed
+    }
+
+    /// `--help` の内容を標準出力に書く
+    pub fn print_help() {
+        println!("{HELP}");
+    }
+
+    /// 読み書きする設定ファイル（`--no-config` なら `None`）
+    pub fn config_file(&self) -> Option<&Path> {
+        if self.no_config {
+            None
+        } else {
+            self.config_path.as_deref()
+        }
+    }
+
+    /// 任意の引数列から解析する（先頭のプログラム名は含めない）
+    pub fn parse_from<I, S>(args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let args: Vec<String> = args.into_iter().map(Into::into).collect();
+        let mut parsed = Self::default();
+        let mut i = 0;
+        while i < args.len() {
+            let arg = args[i].as_str();
+            match arg {
+                "--luma-key-above" => parsed.luma_key.key_above = true,
+                "--no-tone-map" => parsed.no_tone_map = true,
+                "--smooth" => parsed.smooth = true,
+                "--verify" => parsed.verify = true,
+                "--auto-crop-lock" => parsed.auto_crop_lock = true,
+                "--snapshot-sidecar" => parsed.snapshot_sidecar = true,
+                "--probe" => parsed.probe = true,
+                "--no-config" => parsed.no_config = true,
+                "--strict" => parsed.validation = Validation::Strict,
+                "--lenient" => parsed.validation = Validation::Lenient,
+                "--help" | "-h" => parsed.help = true,
+                // 値を取るオプションかどうかは apply_option が受け付けたかで決まる
+                _ if arg.starts_with("--") => match args.get(i + 1) {
+                    Some(value) if parsed.apply_option(arg, value) => i += 1,
+                    Some(_) => warn!("Unknown option {arg}, ignoring."),
+                    None => warn!("{arg} requires a value or is not a known option, ignoring."),
+                },
+                _ => parsed.extra_ips.push(args[i].clone()),
+            }
+            i += 1;
+        }
+        parsed
+    }
+
+    /// 値を取るオプション `name` に `value` を適用する。値を取るオプションでなければ何もせず false
+    ///
+    /// 値を取るオプションの一覧はこの `match` だけにある。
+    fn apply_option(&mut self, name: &str, value: &str) -> bool {
+        match name {
+            "--filter" => match FilterKind::parse(value) {
+                Some(kind) => self.filter = Some(kind),
+                None => warn!("Unknown filter \"{value}\", ignoring."),
+            },
+            "--false-color-thresholds" => match FalseColorThresholds::parse(value) {
+                Some(thresholds) => self.false_color_thresholds = thresholds,
+                None => warn!(
+                    "Invalid false-color thresholds \"{value}\" \
+                     (expected ascending crushed,mid_low,mid_high,clipped), using defaults."
+                ),
+            },
+            "--luma-key" => match self.luma_key.parse(value) {
+                Some(luma_key) => self.luma_key = luma_key,
+                None => warn!(
+                    "Invalid luma key \"{value}\" (expected threshold,softness), using defaults."
+                ),
+            },
+            "--luma-key-background" => match LumaKeySettings::parse_background(value) {
+                Some(background) => self.luma_key.background = background,
+                None => warn!(
+                    "Invalid luma key background \"{value}\" (expected rrggbb or none), ignoring."
+                ),
+            },
+            "--sharpen-amount" => match value.parse::<f32>() {
+                Ok(amount) if amount.is_finite() && amount >= 0.0 => {
+                    if amount > UnsharpMask::MAX_AMOUNT {
+                        warn!(
+                            "Sharpen amount {amount} is above the maximum, using {}.",
+                            UnsharpMask::MAX_AMOUNT
+                        );
+                    }
+                    self.sharpen.amount = amount.min(UnsharpMask::MAX_AMOUNT)
+                }
+                _ => warn!("Invalid sharpen amount \"{value}\", using 1.0."),
+            },
+            "--sharpen-radius" => match value.parse() {
+                Ok(radius)
+                    if (UnsharpMask::MIN_RADIUS..=UnsharpMask::MAX_RADIUS).contains(&radius) =>
+                {
+                    self.sharpen.radius = radius
+                }
+                _ => warn!(
+                    "Invalid sharpen radius \"{value}\" (expected {}-{}), using 2.",
+                    UnsharpMask::MIN_RADIUS,
+                    UnsharpMask::MAX_RADIUS
+                ),
+            },
+            "--max-texture-size" => match value.parse() {
+                Ok(max) if max > 0 => self.max_texture_side = Some(max),
+                _ => warn!("Invalid max texture size \"{value}\", ignoring."),
+            },
+            #[cfg(feature = "gui")]
+            "--texture-filter" => match TextureFilter::parse(value) {
+                Some(filter) => self.texture_filter = filter,
+                None => warn!(
+                    "Unknown texture filter \"{value}\" \
+                     (expected auto, linear or nearest), using auto."
+                ),
+            },
+            "--control" => self.control_addr = Some(value.to_string()),
+            "--source-index" => match value.parse() {
+                Ok(index) if index > 0 => self.source_index = Some(index),
+                _ => {
+                    warn!("Invalid source index \"{value}\" (starts at 1), ignoring.")
+                }
+            },
+            "--address" => self.address = Some(value.to_string()),
+            // 空ならグループを指定しない（すべてのソースを探す）
+            "--groups" => self.groups = Some(value.trim().to_string()).filter(|g| !g.is_empty()),
+            #[cfg(feature = "gui")]
+            "--backend-order" => match gpu::parse_backend_order(value) {
+                Some(order) => self.backend_order = Some(order),
+                None => warn!(
+                    "Invalid backend order \"{value}\" \
+                     (expected e.g. vulkan,metal,dx12,gl), using all."
+                ),
+            },
+            #[cfg(feature = "gui")]
+            "--backend" => match gpu::parse_backend(value) {
+                Some(backend) => self.backend = Some(backend),
+                None => warn!(
+                    "Unknown backend \"{value}\" (expected vulkan, gl, metal or dx12), ignoring."
+                ),
+            },
+            #[cfg(feature = "gui")]
+            "--adapter" => match value.parse() {
+                Ok(index) => self.adapter = Some(index),
+                _ => warn!("Invalid adapter index \"{value}\" (starts at 0), ignoring."),
+            },
+            // GUIなし（`gui` feature を外した）ビルドでは表示・wgpuの指定は使わない
+            #[cfg(not(feature = "gui"))]
+            "--texture-filter" | "--backend-order" | "--backend" | "--adapter" => {}
+            "--color-format" => match parse_color_format(value) {
+                Some(format) => self.color_format = Some(format),
+                None => warn!(
+                    "Unknown color format \"{value}\" \
+                     (expected rgba, bgra, uyvy, uyvy-bgra, fastest or best), using rgba."
+                ),
+            },
+            "--bandwidth" => match parse_bandwidth(value) {
+                Some(bandwidth) => self.bandwidth = bandwidth,
+                None => warn!(
+                    "Unknown bandwidth \"{value}\" (expected lowest or highest), using highest."
+                ),
+            },
+            "--on-capture-error" => match CaptureErrorPolicy::parse(value) {
+                Some(policy) => self.capture_error_policy = policy,
+                None => warn!(
+                    "Unknown capture error policy \"{value}\" \
+                     (expected retry, reconnect or fail), using reconnect."
+                ),
+            },
+            "--msaa" => match value.parse() {
+                Ok(samples @ (1 | 2 | 4)) => self.msaa_samples = Some(samples),
+                _ => warn!(
+                    "Invalid MSAA sample count \"{value}\" (expected 1, 2 or 4), ignoring."
+                ),
+            },
+            "--discovery-timeout" => match value.parse::<f32>() {
+                Ok(secs) if secs > 0.0 && secs.is_finite() => {
+                    self.discovery_timeout = Some(Duration::from_secs_f32(secs))
+                }
+                _ => warn!("Invalid discovery timeout \"{value}\", ignoring."),
+            },
+            "--reconnect-after" => match value.parse::<f32>() {
+                Ok(secs) if secs > 0.0 && secs.is_finite() => {
+                    self.reconnect_after = Some(Duration::from_secs_f32(secs))
+                }
+                _ => warn!("Invalid reconnect delay \"{value}\", ignoring."),
+            },
+            "--capture-timeout" => match value.parse::<u64>() {
+                Ok(ms) if ms > 0 && ms <= CAPTURE_TIMEOUT.as_millis() as u64 => {
+                    self.capture_timeout = Some(Duration::from_millis(ms))
+                }
+                _ => warn!(
+                    "Invalid capture timeout \"{value}\" (expected 1-{} ms), using {}.",
+                    CAPTURE_TIMEOUT.as_millis(),
+                    CAPTURE_TIMEOUT.as_millis()
+                ),
+            },
+            "--extra-ips-file" => match fs::read_to_string(value) {
+                Ok(contents) => self
+                    .extra_ips
+                    .extend(parse_extra_ips_file(value, &contents)),
+                Err(e) => warn!("Failed to read extra IPs file \"{value}\": {e}"),
+            },
+            "--config" => self.config_path = Some(PathBuf::from(value)),
+            "--watermark" => self.watermark = Some(PathBuf::from(value)),
+            "--watermark-corner" => match Corner::parse(value) {
+                Some(corner) => self.watermark_corner = corner,
+                None => warn!(
+                    "Unknown watermark corner \"{value}\" \
+                     (expected top-left, top-right, bottom-left or bottom-right), using bottom-right."
+                ),
+            },
+            "--watermark-opacity" => match value.parse::<f32>() {
+                Ok(opacity) if (0.0..=1.0).contains(&opacity) => {
+                    self.watermark_opacity = Some(opacity)
+                }
+                _ => warn!("Invalid watermark opacity \"{value}\" (expected 0-1), ignoring."),
+            },
+            "--auto-crop" => match value.parse() {
+                Ok(threshold) => self.auto_crop = Some(threshold),
+                Err(_) => warn!(
+                    "Invalid auto-crop threshold \"{value}\" (expected 0-255), ignoring."
+                ),
+            },
+            "--auto-crop-background" => match LumaKeySettings::parse_background(value) {
+                Some(background) => self.auto_crop_background = background,
+                None => warn!(
+                    "Invalid auto-crop background \"{value}\" (expected rrggbb or none), ignoring."
+                ),
+            },
+            "--scale" => match parse_scale(value) {
+                Some(factor) => self.downscale = Some(factor),
+                None => warn!(
+                    "Invalid scale \"{value}\" \
+                     (expected 1/n with n from 1 to {MAX_DOWNSCALE}), ignoring."
+                ),
+            },
+            "--max-connect-attempts" => match value.parse() {
+                Ok(attempts) if attempts > 0 => self.max_connect_attempts = Some(attempts),
+                _ => warn!("Invalid max connect attempts \"{value}\", ignoring."),
+            },
+            "--audio-channels" => match ChannelMix::parse_channels(value) {
+                Some(mix) => self.audio_mix = mix,
+                None => warn!(
+                    "Invalid audio channels \"{value}\" (expected e.g. 0,1), using all."
+                ),
+            },
+            "--downmix" => match ChannelMix::parse_downmix(value) {
+                Some(mix) => self.audio_mix = mix,
+                None => warn!(
+                    "Unknown downmix \"{value}\" (expected stereo), using all channels."
+                ),
+            },
+            "--freeze-stride" => match value.parse() {
+                Ok(stride) => self.freeze_stride = Some(stride),
+                Err(_) => warn!("Invalid freeze stride \"{value}\", ignoring."),
+            },
+            "--target-fps" => match value.parse::<f64>() {
+                Ok(fps) if fps > 0.0 && fps.is_finite() => self.target_fps = Some(fps),
+                _ => warn!("Invalid target frame rate \"{value}\", ignoring."),
+            },
+            "--max-sources" => match value.parse() {
+                Ok(max) if max > 0 => self.max_sources = Some(max),
+                _ => warn!("Invalid source count \"{value}\", ignoring."),
+            },
+            "--buffer-frames" => match value.parse() {
+                Ok(frames) if frames > 0 => self.buffer_frames = Some(frames),
+                _ => warn!("Invalid buffer size \"{value}\", ignoring."),
+            },
+            "--buffer-latency" => match value.parse() {
+                Ok(ms) => self.buffer_latency = Some(Duration::from_millis(ms)),
+                Err(_) => {
+                    warn!("Invalid buffer latency \"{value}\" (expected milliseconds), ignoring.")
+                }
+            },
+            "--deinterlace" => match Deinterlace::parse(value) {
+                Some(mode) => self.deinterlace = mode,
+                None => warn!(
+                    "Unknown deinterlace mode \"{value}\" (expected none, bob or blend), \
+                     using blend."
+                ),
+            },
+            "--rotate" => match Rotation::parse(value) {
+                Some(rotation) => self.transform.rotation = rotation,
+                None => warn!("Invalid rotation \"{value}\" (expected 0, 90, 180 or 270), ignoring."),
+            },
+            "--flip" => match self.transform.with_flip(value) {
+                Some(transform) => self.transform = transform,
+                None => warn!("Invalid flip \"{value}\" (expected none, h, v or hv), ignoring."),
+            },
+            "--lut" => self.lut = Some(PathBuf::from(value)),
+            "--record-format" => match RecordFormat::parse(value) {
+                Some(format) => self.record_format = format,
+                None => warn!("Unknown record format \"{value}\" (expected mkv or mp4), using mkv."),
+            },
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// 1行に1つのIP/サブネット/ホスト名。空行と `#` 以降は無視し、形式が不正な行は警告して飛ばす
+fn parse_extra_ips_file(path: &str, contents: &str) -> Vec<String> {
+    let mut ips = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let entry = line.split('#').next().unwrap_or("").trim();
+        if entry.is_empty() {
+            continue;
+        }
+        if looks_like_address(entry) {
+            ips.push(entry.to_string());
+        } else {
+            warn!(
+                "{path}:{}: \"{entry}\" is not an IP, subnet or host name, ignoring.",
+                i + 1
+            );
+        }
+    }
+    ips
+}
+
+fn looks_like_address(entry: &str) -> bool {
+    // サブネット（192.168.1.0/24）
+    if let Some((ip, prefix)) = entry.split_once('/') {
+        let Ok(ip) = ip.parse::<IpAddr>() else {
+            return false;
+        };
+        let max_prefix = if ip.is_ipv4() { 32 } else { 128 };
+        return prefix
+            .parse::<u8>()
+            .is_ok_and(|prefix| prefix <= max_prefix);
+    }
+    if entry.parse::<IpAddr>().is_ok() {
+        return true;
+    }
+    // ホスト名（数字とドットだけのものは壊れたIPとみなす）
+    entry.len() <= 253
+        && entry.chars().any(|c| c.is_ascii_alphabetic())
+        && entry.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// `--bandwidth` の値（`lowest` / `highest`）
+pub fn parse_bandwidth(name: &str) -> Option<ReceiverBandwidth> {
+    match name {
+        "lowest" => Some(ReceiverBandwidth::Lowest),
+        "highest" => Some(ReceiverBandwidth::Highest),
+        _ => None,
+    }
+}
+
+/// `--scale` の値（`1/2` のような1/n）を縮小の割る数nにする
+fn parse_scale(value: &str) -> Option<usize> {
+    let factor = value.trim().strip_prefix("1/")?.trim().parse().ok()?;
+    (1..=MAX_DOWNSCALE).contains(&factor).then_some(factor)
+}
+
+fn parse_color_format(name: &str) -> Option<ReceiverColorFormat> {
+    match name {
+        "rgba" => Some(ReceiverColorFormat::RGBX_RGBA),
+        "bgra" => Some(ReceiverColorFormat::BGRX_BGRA),
+        "uyvy" => Some(ReceiverColorFormat::UYVY_RGBA),
+        "uyvy-bgra" => Some(ReceiverColorFormat::UYVY_BGRA),
+        "fastest" => Some(ReceiverColorFormat::Fastest),
+        "best" => Some(ReceiverColorFormat::Best),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_is_reported_instead_of_exiting() {
+        let args = CliArgs::parse_from(["--scale", "1/2", "-h", "10.0.0.5"]);
+        assert!(args.help);
+        // 残りの引数もそのまま解析される
+        assert_eq!(args.downscale, Some(2));
+        assert_eq!(args.extra_ips, ["10.0.0.5"]);
+        assert!(!CliArgs::parse_from(["--probe"]).help);
+    }
+
+    #[test]
+    fn value_options_consume_their_value() {
+        let args = CliArgs::parse_from([
+            "--sharpen-amount",
+            "2.5",
+            "--sharpen-radius",
+            "4",
+            "--rotate",
+            "90",
+            "10.0.0.5",
+        ]);
+        assert_eq!(args.sharpen.amount, 2.5);
+        assert_eq!(args.sharpen.radius, 4);
+        assert_eq!(args.transform.rotation, Rotation::Cw90);
+        assert_eq!(args.extra_ips, ["10.0.0.5"]);
+    }
+
+    #[test]
+    fn unknown_options_are_skipped() {
+        // 知らないオプションは値を取るかわからないので、次の引数は普通に解析する
+        let args = CliArgs::parse_from(["--no-such-option", "10.0.0.5", "--probe", "--bogus"]);
+        assert_eq!(args.extra_ips, ["10.0.0.5"]);
+        assert!(args.probe);
+        // 値が足りない既知のオプションも無視する
+        assert_eq!(CliArgs::parse_from(["--scale"]).downscale, None);
+    }
+
+    #[test]
+    fn sharpen_amount_is_clamped() {
+        let amount = |value: &str| {
+            CliArgs::parse_from(["--sharpen-amount", value])
+                .sharpen
+                .amount
+        };
+        assert_eq!(amount("100"), UnsharpMask::MAX_AMOUNT);
+        assert_eq!(amount("inf"), 1.0);
+        assert_eq!(amount("-1"), 1.0);
+        assert_eq!(amount("0"), 0.0);
+    }
+
+    #[test]
+    fn extra_ips_file_skips_comments_and_invalid_lines() {
+        let contents = "\
+# studio subnets
+192.168.1.0/24
+
+  10.0.0.5   # camera 1
+fe80::/64
+999.1.1.1
+10.0.0.0/33
+fe80::/129
+ndi-router.local
+-bad.example
+";
+        assert_eq!(
+            parse_extra_ips_file("ips.txt", contents),
+            [
+                "192.168.1.0/24",
+                "10.0.0.5",
+                "fe80::/64",
+                "ndi-router.local"
+            ]
+        );
+    }
+
+    #[test]
+    fn addresses_and_subnets() {
+        for valid in [
+            "10.0.0.5",
+            "::1",
+            "fe80::1",
+            "192.168.1.0/0",
+            "192.168.1.0/32",
+            "2001:db8::/128",
+        ] {
+            assert!(looks_like_address(valid), "{valid}");
+        }
+        for invalid in [
+            "999.1.1.1",
+            "10.0.0",
+            "10.0.0.0/33",
+            "10.0.0.0/",
+            "10.0.0.0/x",
+            "2001:db8::/129",
+            "host.local/24",
+        ] {
+            assert!(!looks_like_address(invalid), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn host_names() {
+        for valid in ["studio-pc", "ndi-router.local", "cam1.studio.example.com"] {
+            assert!(looks_like_address(valid), "{valid}");
+        }
+        let long_label = "a".repeat(64);
+        for invalid in [
+            "-studio",
+            "studio-",
+            "cam.-bad.local",
+            "cam..local",
+            "cam_1.local",
+            "カメラ.local",
+            long_label.as_str(),
+        ] {
+            assert!(!looks_like_address(invalid), "{invalid}");
+        }
+    }
+}
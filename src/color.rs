@@ -0,0 +1,142 @@
+//! 色空間・HDRメタデータの解釈とSDRへのトーンマッピング
+//!
+//! grafton-ndi は色空間を構造化して渡してくれないため、フレームのメタデータXMLにある
+//! `<ndi_color_info transfer="..." matrix="..." primaries="..."/>` を読んで判断する。
+//! 情報が無いフレームはSDR（BT.709）とみなす。
+
+/// 伝達関数（ガンマ）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransferFunction {
+    /// BT.709 / sRGB 相当
+    #[default]
+    Sdr,
+    /// SMPTE ST 2084 (PQ)
+    Pq,
+    /// ARIB STD-B67 (HLG)
+    Hlg,
+}
+
+impl TransferFunction {
+    pub fn is_hdr(self) -> bool {
+        self != Self::Sdr
+    }
+}
+
+/// フレームに付いていた色情報
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColorInfo {
+    /// 送信側が指定した伝達関数
+    pub transfer: TransferFunction,
+    /// BT.2020 の色域か（色域変換はしていない）
+    pub wide_gamut: bool,
+    /// 受信時にSDRへトーンマッピング済みか
+    pub tone_mapped: bool,
+}
+
+impl ColorInfo {
+    /// フレームのメタデータXMLから読み取る（該当タグが無ければデフォルト）
+    pub fn parse(metadata: &str) -> Self {
+        let Some(start) = metadata.find("<ndi_color_info") else {
+            return Self::default();
+        };
+        let tag = &metadata[start..];
+        let tag = &tag[..tag.find('>').unwrap_or(tag.len())];
+
+        let transfer = match attribute(tag, "transfer") {
+            Some(t) if t.contains("pq") || t.contains("2084") => TransferFunction::Pq,
+            Some(t) if t.contains("hlg") || t.contains("b67") => TransferFunction::Hlg,
+            _ => TransferFunction::Sdr,
+        };
+        let wide_gamut = attribute(tag, "primaries").is_some_and(|p| p.contains("2020"));
+
+        Self {
+            transfer,
+            wide_gamut,
+            tone_mapped: false,
+        }
+    }
+}
+
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{name}=\"");
+    let start = tag.find(&key)? + key.len();
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+// SDRの基準白（ITU-R BT.2408）
+const SDR_WHITE_NITS: f64 = 203.0;
+// HLGの公称ピーク輝度
+const HLG_PEAK_NITS: f64 = 1000.0;
+
+/// HDRの8bit信号値をSDRに変換するLUT
+///
+/// 輝度をSDR基準白で正規化し、拡張Reinhardで圧縮してからガンマ2.2で符号化する。
+/// チャンネルごとに適用する簡易版なので、色相は多少ずれる。
+pub struct ToneMapper {
+    transfer: TransferFunction,
+    lut: [u8; 256],
+}
+
+impl ToneMapper {
+    pub fn new(transfer: TransferFunction) -> Self {
+        let peak = match transfer {
+            TransferFunction::Pq => 10_000.0,
+            _ => HLG_PEAK_NITS,
+        } / SDR_WHITE_NITS;
+
+        let mut lut = [0u8; 256];
+        for (code, entry) in lut.iter_mut().enumerate() {
+            let signal = code as f64 / 255.0;
+            let relative = match transfer {
+                TransferFunction::Sdr => signal.powf(2.2),
+                TransferFunction::Pq => pq_to_nits(signal) / SDR_WHITE_NITS,
+                // システムガンマ1.2のOOTFをかけてディスプレイ輝度にする
+                TransferFunction::Hlg => {
+                    hlg_to_scene(signal).powf(1.2) * HLG_PEAK_NITS / SDR_WHITE_NITS
+                }
+            };
+            let mapped = relative * (1.0 + relative / (peak * peak)) / (1.0 + relative);
+            *entry = (mapped.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0).round() as u8;
+        }
+        Self { transfer, lut }
+    }
+
+    pub fn transfer(&self) -> TransferFunction {
+        self.transfer
+    }
+
+    /// RGBAバッファのRGBをその場で変換する（Alphaはそのまま）
+    pub fn apply(&self, data: &mut [u8]) {
+        for chunk in data.chunks_exact_mut(4) {
+            for value in &mut chunk[..3] {
+                *value = self.lut[*value as usize];
+            }
+        }
+    }
+}
+
+/// SMPTE ST 2084 のEOTF（信号値 → nits）
+fn pq_to_nits(signal: f64) -> f64 {
+    const M1: f64 = 2610.0 / 16384.0;
+    const M2: f64 = 2523.0 / 4096.0 * 128.0;
+    const C1: f64 = 3424.0 / 4096.0;
+    const C2: f64 = 2413.0 / 4096.0 * 32.0;
+    const C3: f64 = 2392.0 / 4096.0 * 32.0;
+
+    let p = signal.powf(1.0 / M2);
+    10_000.0 * ((p - C1).max(0.0) / (C2 - C3 * p)).powf(1.0 / M1)
+}
+
+/// ARIB STD-B67 の逆OETF（信号値 → シーンリニア 0-1）
+fn hlg_to_scene(signal: f64) -> f64 {
+    const A: f64 = 0.178_832_77;
+    const B: f64 = 0.284_668_92;
+    const C: f64 = 0.559_910_73;
+
+    if signal <= 0.5 {
+        signal * signal / 3.0
+    } else {
+        (((signal - C) / A).exp() + B) / 12.0
+    }
+}
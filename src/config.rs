@@ -0,0 +1,420 @@
+//! 前回の接続先とウィンドウの位置・大きさを覚えておく設定ファイル（キオスク向け）
+//!
+//! 起動時に読み、接続したときとウィンドウを動かしたときに書く。書式は平らなTOML
+//! （`key = value` の行だけ）なので、serdeやtomlクレートは入れずに自前で読み書きする。
+//! 場所は `--config <path>`、未指定なら [`ViewerConfig::default_path`]。`--no-config` で読み書きしない。
+
+use crate::json;
+use anyhow::{Context, Result};
+#[cfg(feature = "gui")]
+use eframe::egui;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "gui")]
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// ウィンドウの移動・リサイズがこれだけ落ち着いてから書く（ドラッグ中に何度も書かないように）
+#[cfg(feature = "gui")]
+const WINDOW_SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// 既定のウィンドウサイズ（設定ファイルがなければこれ）
+pub const DEFAULT_WINDOW_SIZE: [f32; 2] = [1920.0, 1080.0];
+
+/// 設定ファイルの中身
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewerConfig {
+    /// 最後に接続したソース名（空なら覚えていない）
+    pub source_name: String,
+    /// 探索に使った追加のIP/サブネット（次回はコマンドラインの分に足して使う）
+    pub extra_ips: Vec<String>,
+    /// フルスクリーンでないときのウィンドウの中身の大きさ（論理ピクセル）
+    pub window_size: [f32; 2],
+    /// ウィンドウの左上の位置（取れないプラットフォームでは `None`、Waylandなど）
+    pub window_position: Option<[f32; 2]>,
+    pub fullscreen: bool,
+}
+
+impl Default for ViewerConfig {
+    fn default() -> Self {
+        Self {
+            source_name: String::new(),
+            extra_ips: Vec::new(),
+            window_size: DEFAULT_WINDOW_SIZE,
+            window_position: None,
+            fullscreen: false,
+        }
+    }
+}
+
+impl ViewerConfig {
+    /// プラットフォームの設定ディレクトリの `rust-ndi-viewer/<ビューア名>.toml`
+    ///
+    /// ビューアごとに分けるので、同じマシンで別のビューアを動かしても上書きし合わない。
+    /// Linuxなどは `$XDG_CONFIG_HOME`（なければ `~/.config`）、macOSは
+    /// `~/Library/Application Support`、Windowsは `%APPDATA%`。どれも分からなければ `None`。
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = if cfg!(windows) {
+            PathBuf::from(env::var_os("APPDATA")?)
+        } else if cfg!(target_os = "macos") {
+            PathBuf::from(env::var_os("HOME")?).join("Library/Application Support")
+        } else {
+            match env::var_os("XDG_CONFIG_HOME").map(PathBuf::from) {
+                Some(dir) if dir.is_absolute() => dir,
+                _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+            }
+        };
+        let viewer = env::current_exe()
+            .ok()
+            .and_then(|exe| {
+                exe.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "viewer".to_string());
+        Some(dir.join("rust-ndi-viewer").join(format!("{viewer}.toml")))
+    }
+
+    /// `path` から読む。ファイルがなければ `Ok(None)`（初回起動）
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        Self::parse(&contents)
+            .map(Some)
+            .with_context(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// `path` に書く（ディレクトリがなければ作る）
+    ///
+    /// 書きかけのファイルを残さないよう、隣の一時ファイルに書いてから置き換える。
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let temp = path.with_extension("toml.tmp");
+        fs::write(&temp, self.to_toml())
+            .with_context(|| format!("Failed to write {}", temp.display()))?;
+        fs::rename(&temp, path).with_context(|| format!("Failed to replace {}", path.display()))
+    }
+
+    /// TOMLとして書き出す
+    pub fn to_toml(&self) -> String {
+        let string = |s: &str| format!("\"{}\"", json::escape(s));
+        let pair = |[a, b]: [f32; 2]| format!("[{a:?}, {b:?}]");
+
+        let mut out = String::from("# rust-ndi-viewer: last source and window geometry\n");
+        out += &format!("source_name = {}\n", string(&self.source_name));
+        let ips: Vec<String> = self.extra_ips.iter().map(|ip| string(ip)).collect();
+        out += &format!("extra_ips = [{}]\n", ips.join(", "));
+        out += &format!("window_size = {}\n", pair(self.window_size));
+        if let Some(position) = self.window_position {
+            out += &format!("window_position = {}\n", pair(position));
+        }
+        out += &format!("fullscreen = {}\n", self.fullscreen);
+        out
+    }
+
+    /// [`to_toml`](Self::to_toml) の出力を読む
+    ///
+    /// 1行に `key = value` が1つの平らなTOMLだけを扱う。空行と `#` の行は飛ばし、
+    /// 知らないキーは（新しい版が書いたものとして）無視する。無いキーは既定値のまま。
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut config = Self::default();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let line_number = i + 1;
+            let (key, value) = line
+                .split_once('=')
+                .with_context(|| format!("line {line_number}: expected key = value"))?;
+            let value = value.trim();
+            let invalid = || format!("line {line_number}: invalid value for {}", key.trim());
+            match key.trim() {
+                "source_name" => config.source_name = parse_string(value).with_context(invalid)?,
+                "extra_ips" => {
+                    config.extra_ips = parse_string_array(value).with_context(invalid)?
+                }
+                "window_size" => config.window_size = parse_pair(value).with_context(invalid)?,
+                "window_position" => {
+                    config.window_position = Some(parse_pair(value).with_context(invalid)?)
+                }
+                "fullscreen" => config.fullscreen = value.parse().ok().with_context(invalid)?,
+                _ => {}
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// `path` の設定を読む。読めなければ警告を出して `None`（既定の動作のまま続ける）
+pub fn load_or_warn(path: &Path) -> Option<ViewerConfig> {
+    ViewerConfig::load(path).unwrap_or_else(|e| {
+        warn!("{e:#}; ignoring the saved config.");
+        None
+    })
+}
+
+/// `update(&mut config)` で書き換えて保存する（ファイルがまだなければ既定値から）
+///
+/// 保存に失敗しても表示は続けられるので、警告だけ出す。
+pub fn update_or_warn(path: &Path, update: impl FnOnce(&mut ViewerConfig)) {
+    let mut config = load_or_warn(path).unwrap_or_default();
+    update(&mut config);
+    if let Err(e) = config.save(path) {
+        warn!("Failed to save config: {e:#}");
+    }
+}
+
+// ウィンドウの位置・大きさの追跡状態（egui::Context の一時データに置く）
+#[cfg(feature = "gui")]
+#[derive(Clone, Default)]
+struct WindowTracker {
+    // 設定ファイルの場所（最初のフレームで決める、Noneなら追跡しない）
+    path: Option<PathBuf>,
+    // 最後に保存した（または読み込んだ）値
+    saved: Option<WindowGeometry>,
+    // 保存待ちの値と、最後に変わった時刻
+    pending: Option<(WindowGeometry, Instant)>,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Clone, Copy, PartialEq)]
+struct WindowGeometry {
+    size: [f32; 2],
+    position: Option<[f32; 2]>,
+    fullscreen: bool,
+}
+
+/// ウィンドウの位置・大きさ・フルスクリーン状態が変わったら、落ち着いたところで `path` に保存する
+///
+/// [`crate::handle_common_input`] から毎フレーム呼ばれる。フルスクリーン中の大きさは画面の大きさなので、
+/// そのときはフルスクリーンかどうかだけを書き、元に戻したときの大きさは前の値を残す。
+#[cfg(feature = "gui")]
+pub fn remember_window(ctx: &egui::Context, path: impl FnOnce() -> Option<PathBuf>) {
+    let id = egui::Id::new("viewer-config-window");
+    let mut tracker = ctx
+        .data_mut(|d| d.get_temp::<WindowTracker>(id))
+        .unwrap_or_else(|| {
+            let path = path();
+            let saved = path
+                .as_deref()
+                .and_then(load_or_warn)
+                .map(|config| WindowGeometry {
+                    size: config.window_size,
+                    position: config.window_position,
+                    fullscreen: config.fullscreen,
+                });
+            WindowTracker {
+                path,
+                saved,
+                pending: None,
+            }
+        });
+    track_window(ctx, &mut tracker);
+    ctx.data_mut(|d| d.insert_temp(id, tracker));
+}
+
+#[cfg(feature = "gui")]
+fn track_window(ctx: &egui::Context, tracker: &mut WindowTracker) {
+    let Some(path) = &tracker.path else {
+        return;
+    };
+    let previous = tracker.saved;
+    let current = ctx.input(|i| {
+        let viewport = i.viewport();
+        let fullscreen = viewport.fullscreen.unwrap_or(false);
+        let (size, position) = match previous {
+            Some(previous) if fullscreen => (previous.size, previous.position),
+            _ if fullscreen => (DEFAULT_WINDOW_SIZE, None),
+            _ => (
+                viewport
+                    .inner_rect
+                    .map(|rect| [rect.width(), rect.height()])
+                    .or(previous.map(|previous| previous.size))
+                    .unwrap_or(DEFAULT_WINDOW_SIZE),
+                viewport
+                    .outer_rect
+                    .map(|rect| [rect.min.x, rect.min.y])
+                    .or(previous.and_then(|previous| previous.position)),
+            ),
+        };
+        WindowGeometry {
+            size,
+            position,
+            fullscreen,
+        }
+    });
+
+    if previous == Some(current) {
+        tracker.pending = None;
+        return;
+    }
+    match tracker.pending {
+        Some((pending, since)) if pending == current => {
+            let elapsed = since.elapsed();
+            if elapsed < WINDOW_SAVE_DELAY {
+                ctx.request_repaint_after(WINDOW_SAVE_DELAY - elapsed);
+                return;
+            }
+            update_or_warn(path, |config| {
+                config.window_size = current.size;
+                config.window_position = current.position;
+                config.fullscreen = current.fullscreen;
+            });
+            tracker.saved = Some(current);
+            tracker.pending = None;
+        }
+        _ => {
+            tracker.pending = Some((current, Instant::now()));
+            ctx.request_repaint_after(WINDOW_SAVE_DELAY);
+        }
+    }
+}
+
+fn parse_string(value: &str) -> Option<String> {
+    let (s, rest) = take_string(value)?;
+    rest.trim().is_empty().then_some(s)
+}
+
+// 先頭の `"..."` を読み、残りを返す
+fn take_string(value: &str) -> Option<(String, &str)> {
+    let body = value.strip_prefix('"')?;
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some((json::unescape(&body[..i])?, &body[i + 1..])),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_string_array(value: &str) -> Option<Vec<String>> {
+    let mut rest = value.strip_prefix('[')?.trim_start();
+    let mut items = Vec::new();
+    loop {
+        if let Some(after) = rest.strip_prefix(']') {
+            return after.trim().is_empty().then_some(items);
+        }
+        let (item, after) = take_string(rest)?;
+        items.push(item);
+        rest = after.trim_start();
+        if let Some(after) = rest.strip_prefix(',') {
+            rest = after.trim_start();
+        } else if !rest.starts_with(']') {
+            return None;
+        }
+    }
+}
+
+fn parse_pair(value: &str) -> Option<[f32; 2]> {
+    let inner = value.strip_prefix('[')?.strip_suffix(']')?;
+    let (a, b) = inner.split_once(',')?;
+    let pair = [a.trim().parse().ok()?, b.trim().parse().ok()?];
+    pair.iter().all(|v: &f32| v.is_finite()).then_some(pair)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_toml_round_trips_through_parse() {
+        let config = ViewerConfig {
+            source_name: "STUDIO \"A\" (C:\\ndi) カメラ1 ✓".to_string(),
+            extra_ips: vec!["192.168.0.10".to_string(), "10.0.0.0/24".to_string()],
+            window_size: [1280.5, 720.0],
+            window_position: Some([-1920.0, 32.0]),
+            fullscreen: true,
+        };
+        assert_eq!(ViewerConfig::parse(&config.to_toml()).unwrap(), config);
+
+        let empty = ViewerConfig::default();
+        let toml = empty.to_toml();
+        assert!(toml.contains("extra_ips = []\n"), "{toml}");
+        assert!(!toml.contains("window_position"), "{toml}");
+        assert_eq!(ViewerConfig::parse(&toml).unwrap(), empty);
+    }
+
+    #[test]
+    fn unknown_keys_comments_and_missing_keys_are_ignored() {
+        let config = ViewerConfig::parse(
+            "# written by a newer version\n\
+             \n\
+             theme = \"dark\"\n\
+             source_name = \"CAM\"\n\
+             volume = [1, 2]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            ViewerConfig {
+                source_name: "CAM".to_string(),
+                ..ViewerConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn malformed_strings_are_errors() {
+        for value in [
+            "CAM",
+            "\"CAM",
+            "\"CAM\" trailing",
+            "'CAM'",
+            "\"bad \\uzzzz escape\"",
+        ] {
+            let error = ViewerConfig::parse(&format!("source_name = {value}\n")).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "line 1: invalid value for source_name",
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_arrays_are_errors() {
+        for value in [
+            "\"10.0.0.1\"",
+            "[\"10.0.0.1\"",
+            "[\"10.0.0.1\" \"10.0.0.2\"]",
+            "[\"10.0.0.1\",, \"10.0.0.2\"]",
+            "[10.0.0.1]",
+            "[\"10.0.0.1\"] trailing",
+        ] {
+            let text = format!("source_name = \"CAM\"\nextra_ips = {value}\n");
+            let error = ViewerConfig::parse(&text).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                "line 2: invalid value for extra_ips",
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_lines_and_numbers_are_errors() {
+        let error = ViewerConfig::parse("fullscreen\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: expected key = value");
+        for line in [
+            "fullscreen = yes",
+            "window_size = [1280]",
+            "window_size = [1280, inf]",
+            "window_position = 0, 0",
+        ] {
+            assert!(ViewerConfig::parse(line).is_err(), "{line}");
+        }
+    }
+}
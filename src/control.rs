@@ -0,0 +1,203 @@
+//! リモート操作用の最小限のHTTPサーバー（`control` feature）
+//!
+//! `GET /` で操作ページを返し、`POST /` のフォーム送信で [`Params`] を差し替える。
+//! 認証は一切ないので、信頼できるローカルネットワーク内でのみ使うこと。
+
+use crate::filter::{FalseColorThresholds, FilterKind};
+use crate::params::Params;
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use tracing::{info, warn};
+
+// フォーム送信の上限（これ以上は切り捨て）
+const MAX_BODY_SIZE: usize = 4096;
+
+/// 操作用HTTPサーバーをバックグラウンドスレッドで起動する
+///
+/// ソース名が変わったときは `switch_source` をtrueにし、受信スレッドに再接続を促す。
+pub fn spawn_control_server(
+    addr: &str,
+    params: Arc<ArcSwap<Params>>,
+    switch_source: Arc<AtomicBool>,
+) -> Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Remote control panel: http://{}/", listener.local_addr()?);
+
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!("Control connection failed: {e}");
+                    continue;
+                }
+            };
+            if let Err(e) = handle_connection(stream, &params, &switch_source) {
+                warn!("Control request failed: {e}");
+            }
+        }
+    }))
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    params: &ArcSwap<Params>,
+    switch_source: &AtomicBool,
+) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+
+    let mut stream = stream;
+    match (method.as_str(), path.as_str()) {
+        ("GET", "/") => {
+            let page = render_page(&params.load());
+            write_response(&mut stream, "200 OK", "text/html; charset=utf-8", &page)
+        }
+        ("POST", "/") => {
+            let mut body = vec![0u8; content_length.min(MAX_BODY_SIZE)];
+            reader.read_exact(&mut body)?;
+            apply_form(&String::from_utf8_lossy(&body), params, switch_source);
+            write!(
+                stream,
+                "HTTP/1.1 303 See Other\r\nLocation: /\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            )?;
+            Ok(())
+        }
+        _ => write_response(&mut stream, "404 Not Found", "text/plain", "Not Found"),
+    }
+}
+
+fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+/// フォームの内容を現在の設定に反映する（不正な値は無視）
+fn apply_form(body: &str, params: &ArcSwap<Params>, switch_source: &AtomicBool) {
+    let mut new_params = Params::clone(&params.load());
+
+    for pair in body.split('&') {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = url_decode(value);
+        let value = value.trim();
+        match key {
+            "source" => {
+                new_params.source = (!value.is_empty()).then(|| value.to_string());
+            }
+            "filter" => new_params.filter = FilterKind::parse(value),
+            "false_color_thresholds" => {
+                if let Some(thresholds) = FalseColorThresholds::parse(value) {
+                    new_params.false_color_thresholds = thresholds;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let source_changed = new_params.source != params.load().source;
+    if source_changed {
+        // 名前で指定し直したので、一覧で選んだときのアドレスは使わない
+        new_params.source_address = None;
+    }
+    info!("Remote control update: {new_params:?}");
+    params.store(Arc::new(new_params));
+    if source_changed {
+        switch_source.store(true, Ordering::Relaxed);
+    }
+}
+
+fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    Err(_) => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_page(params: &Params) -> String {
+    let source = html_escape(params.source.as_deref().unwrap_or(""));
+    let mut filter_options = format!(
+        "<option value=\"\"{}>none</option>",
+        if params.filter.is_none() { " selected" } else { "" }
+    );
+    for kind in FilterKind::ALL {
+        let selected = if params.filter == Some(*kind) { " selected" } else { "" };
+        filter_options.push_str(&format!(
+            "<option value=\"{name}\"{selected}>{name}</option>",
+            name = kind.name()
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>NDI Viewer Control</title>
+<style>body {{ font-family: sans-serif; margin: 1em; }} label {{ display: block; margin: 0.8em 0; }} input, select, button {{ font-size: 1.1em; width: 100%; }}</style>
+</head>
+<body>
+<h1>NDI Viewer Control</h1>
+<form method="post" action="/">
+<label>Source (empty = first found)<input name="source" value="{source}"></label>
+<label>Filter<select name="filter">{filter_options}</select></label>
+<label>False-color thresholds<input name="false_color_thresholds" value="{thresholds}"></label>
+<button type="submit">Apply</button>
+</form>
+</body>
+</html>
+"#,
+        thresholds = params.false_color_thresholds,
+    )
+}
@@ -0,0 +1,627 @@
+//! 画素データの変換ヘルパー
+
+use anyhow::{Context, Result};
+#[cfg(feature = "gui")]
+use eframe::egui;
+use grafton_ndi::PixelFormat;
+
+/// RGBAバッファを `factor` 分の1にボックスフィルタで縮小する
+///
+/// 端の半端なブロックはあるだけの画素で平均する。戻り値は `(data, width, height)`。
+pub fn downscale_rgba(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    factor: usize,
+) -> (Vec<u8>, usize, usize) {
+    if factor <= 1 {
+        return (data.to_vec(), width, height);
+    }
+
+    let out_width = width.div_ceil(factor);
+    let out_height = height.div_ceil(factor);
+    let mut out = vec![0u8; out_width * out_height * 4];
+
+    for oy in 0..out_height {
+        let y0 = oy * factor;
+        let y1 = (y0 + factor).min(height);
+        for ox in 0..out_width {
+            let x0 = ox * factor;
+            let x1 = (x0 + factor).min(width);
+
+            let mut sum = [0u32; 4];
+            for y in y0..y1 {
+                let row = &data[(y * width + x0) * 4..(y * width + x1) * 4];
+                for px in row.chunks_exact(4) {
+                    for c in 0..4 {
+                        sum[c] += px[c] as u32;
+                    }
+                }
+            }
+
+            let count = ((y1 - y0) * (x1 - x0)) as u32;
+            let out_idx = (oy * out_width + ox) * 4;
+            for c in 0..4 {
+                out[out_idx + c] = ((sum[c] + count / 2) / count) as u8;
+            }
+        }
+    }
+
+    (out, out_width, out_height)
+}
+
+/// egui の `ColorImage` を、NDIで送信できる詰めたRGBA（ストレートアルファ、`width*4` バイト/行）に戻す
+///
+/// `ColorImage` はsRGB空間の乗算済みアルファで持っているので、アルファで割り戻す。
+/// 不透明な画素は `from_rgba_unmultiplied` と完全に往復する。半透明の画素は丸めでずれ
+/// （およそ ±128/アルファ。アルファ128で±1、32で±4）、完全に透明な画素の色は失われる（黒になる）。
+#[cfg(feature = "gui")]
+pub fn color_image_to_rgba(image: &egui::ColorImage, out: &mut Vec<u8>) {
+    out.clear();
+    out.reserve(image.pixels.len() * 4);
+    for pixel in &image.pixels {
+        out.extend_from_slice(&pixel.to_srgba_unmultiplied());
+    }
+}
+
+/// 詰めたRGBA/RGBX（`width*4` バイト/行）を egui の `ColorImage` にする
+///
+/// RGBXの4バイト目は未定義（0のこともある）なので読まずに不透明として扱う。
+/// RGBAはストレートアルファとして `from_rgba_unmultiplied` に渡す。
+#[cfg(feature = "gui")]
+pub fn rgba_to_color_image(size: [usize; 2], data: &[u8], format: PixelFormat) -> egui::ColorImage {
+    if format != PixelFormat::RGBX {
+        return egui::ColorImage::from_rgba_unmultiplied(size, data);
+    }
+    let pixels = data
+        .chunks_exact(4)
+        .map(|p| egui::Color32::from_rgb(p[0], p[1], p[2]))
+        .collect();
+    egui::ColorImage::new(size, pixels)
+}
+
+/// ストレートアルファのRGBA `overlay` を `frame` の (x, y) に `opacity` をかけて合成する
+///
+/// 高さはバッファ長から求める。フレームからはみ出す部分は切り捨て、フレーム側のアルファは変えない。
+pub fn blend_rgba(
+    frame: &mut [u8],
+    frame_width: usize,
+    overlay: &[u8],
+    overlay_width: usize,
+    x: usize,
+    y: usize,
+    opacity: f32,
+) {
+    if frame_width == 0 || overlay_width == 0 {
+        return;
+    }
+    let frame_height = frame.len() / (frame_width * 4);
+    let overlay_height = overlay.len() / (overlay_width * 4);
+    // 0-255 の不透明度（NaNは0になる）
+    let opacity = (opacity.clamp(0.0, 1.0) * 255.0).round() as u32;
+    if opacity == 0 || x >= frame_width || y >= frame_height {
+        return;
+    }
+
+    let width = overlay_width.min(frame_width - x);
+    let height = overlay_height.min(frame_height - y);
+    for row in 0..height {
+        let src = &overlay[row * overlay_width * 4..][..width * 4];
+        let dst = &mut frame[((y + row) * frame_width + x) * 4..][..width * 4];
+        for (d, s) in dst.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            // 画素のアルファ×不透明度（0-255*255）
+            let alpha = s[3] as u32 * opacity;
+            for c in 0..3 {
+                let blended = s[c] as u32 * alpha + d[c] as u32 * (255 * 255 - alpha);
+                d[c] = ((blended + 255 * 255 / 2) / (255 * 255)) as u8;
+            }
+        }
+    }
+}
+
+/// YUV→RGBの係数（固定小数点: 256倍スケール、リミテッドレンジ）
+#[derive(Clone, Copy)]
+struct YuvMatrix {
+    v_to_r: i32,
+    u_to_g: i32,
+    v_to_g: i32,
+    u_to_b: i32,
+}
+
+// ITU-R BT.601（SD）
+const BT601: YuvMatrix = YuvMatrix {
+    v_to_r: 409,
+    u_to_g: 100,
+    v_to_g: 208,
+    u_to_b: 516,
+};
+
+// ITU-R BT.709（HD以上）
+const BT709: YuvMatrix = YuvMatrix {
+    v_to_r: 459,
+    u_to_g: 55,
+    v_to_g: 136,
+    u_to_b: 541,
+};
+
+impl YuvMatrix {
+    /// NDIの慣例どおり、720p未満はBT.601、それ以上はBT.709とみなす
+    fn for_height(height: usize) -> Self {
+        if height < 720 { BT601 } else { BT709 }
+    }
+
+    #[inline]
+    fn to_rgb(self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let c = 298 * (y as i32 - 16);
+        let d = u as i32 - 128;
+        let e = v as i32 - 128;
+        let clamp = |x: i32| ((x + 128) >> 8).clamp(0, 255) as u8;
+        [
+            clamp(c + self.v_to_r * e),
+            clamp(c - self.u_to_g * d - self.v_to_g * e),
+            clamp(c + self.u_to_b * d),
+        ]
+    }
+}
+
+/// `width`x`height` を `width*4` で詰めたRGBAのバイト数（`usize` で表せなければエラー）
+pub fn rgba_size(width: usize, height: usize) -> Result<usize> {
+    width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(4))
+        .with_context(|| format!("Frame size {width}x{height} is too large to address"))
+}
+
+/// `format` のフレームに必要な（1行の最低バイト数, データ全体の最低バイト数）
+///
+/// 未対応のフォーマットはエラー。`height` は1以上であること。変換先のRGBA（[`rgba_size`]）や
+/// データ全体のバイト数が `usize` で表せない（桁あふれする）サイズもエラーにする。
+pub fn required_sizes(
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    line_stride: usize,
+) -> Result<(usize, usize)> {
+    // width*height*4 が収まるので、幅と画素数から求める値はそのまま計算してよい
+    rgba_size(width, height)?;
+    // 4:2:2は2画素で1組なので、奇数幅（幅1を含む）でも最後の組まで読めること
+    let pair_bytes = width.div_ceil(2) * 4;
+    // 行ストライドは幅と関係なく届くので、掛ける・足すところはすべて検査する
+    let last_row = line_stride.checked_mul(height - 1);
+    let plane = line_stride.checked_mul(height);
+    let (min_stride, required) = match format {
+        PixelFormat::RGBA | PixelFormat::RGBX | PixelFormat::BGRA | PixelFormat::BGRX => {
+            (width * 4, last_row.and_then(|n| n.checked_add(width * 4)))
+        }
+        PixelFormat::UYVY => (pair_bytes, last_row.and_then(|n| n.checked_add(pair_bytes))),
+        // UYVYの後ろに width バイト/行 のアルファプレーン
+        PixelFormat::UYVA => (
+            pair_bytes,
+            plane.and_then(|n| n.checked_add(width * height)),
+        ),
+        // 16bitのYプレーンの後ろに同じストライドのUVプレーン
+        PixelFormat::P216 => (pair_bytes, plane.and_then(|n| n.checked_mul(2))),
+        // さらに後ろに同じストライドの16bitアルファプレーン
+        PixelFormat::PA16 => (pair_bytes, plane.and_then(|n| n.checked_mul(3))),
+        // 4:2:0: Yプレーンの後ろに縦横半分の色差。色差の行もYと同じく偶数幅ぶん読めること
+        PixelFormat::NV12 => {
+            // U V が交互に並んだ1プレーン（ストライドはYと同じ）
+            let chroma_rows = height.div_ceil(2);
+            let even_width = width.div_ceil(2) * 2;
+            // 色差の行数はYの行数以下なので、plane が収まれば色差プレーンの分も収まる
+            let required = plane
+                .and_then(|n| n.checked_add(line_stride * (chroma_rows - 1)))
+                .and_then(|n| n.checked_add(even_width));
+            (even_width, required)
+        }
+        PixelFormat::I420 | PixelFormat::YV12 => {
+            // U と V が別プレーン（ストライドはYの半分、YV12はVが先）
+            let chroma_rows = height.div_ceil(2);
+            let chroma_stride = line_stride / 2;
+            // 2つの色差プレーンを合わせてもYプレーン以下なので、plane が収まればそれぞれも収まる
+            let required = plane
+                .and_then(|n| n.checked_add(chroma_stride * chroma_rows))
+                .and_then(|n| n.checked_add(chroma_stride * (chroma_rows - 1)))
+                .and_then(|n| n.checked_add(width.div_ceil(2)));
+            (width.div_ceil(2) * 2, required)
+        }
+        other => anyhow::bail!("Unsupported pixel format {other:?}"),
+    };
+    let required = required.with_context(|| {
+        format!(
+            "{format:?} frame {width}x{height} with line stride {line_stride} \
+             is too large to address"
+        )
+    })?;
+    Ok((min_stride, required))
+}
+
+/// 1プレーンのフレームを行ストライド `min_stride` に詰め直して `out` に書き込む（`--lenient`）
+///
+/// 行が `min_stride` より短ければ残りを、データが途中で切れていれば以降を黒で埋める。
+/// 何を補ったかの説明を返す。複数プレーンのフォーマット（UYVA、P216、PA16、4:2:0）は直せないのでエラー。
+pub fn repack(
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    line_stride: usize,
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<String> {
+    if width == 0 || height == 0 {
+        anyhow::bail!("Empty {format:?} frame ({width}x{height})");
+    }
+    // 黒の4バイトの並び（UYVYは U Y V Y）
+    let black: [u8; 4] = match format {
+        PixelFormat::RGBA | PixelFormat::RGBX | PixelFormat::BGRA | PixelFormat::BGRX => {
+            [0, 0, 0, 255]
+        }
+        PixelFormat::UYVY => [128, 16, 128, 16],
+        other => anyhow::bail!("Can't repair a {other:?} frame"),
+    };
+    let (min_stride, _) = required_sizes(format, width, height, line_stride)?;
+
+    out.clear();
+    out.reserve(min_stride * height);
+    let mut short_rows = 0;
+    for y in 0..height {
+        let start = (y * line_stride).min(data.len());
+        let end = (start + line_stride.min(min_stride)).min(data.len());
+        let row = &data[start..end];
+        if row.len() < min_stride {
+            short_rows += 1;
+        }
+        out.extend_from_slice(row);
+        // min_stride は4の倍数なので、行頭からの位置で模様がそろう
+        out.extend((row.len()..min_stride).map(|i| black[i % 4]));
+    }
+
+    let mut repairs = Vec::new();
+    if line_stride != min_stride {
+        repairs.push(format!(
+            "repacked line stride {line_stride} to {min_stride}"
+        ));
+    }
+    if short_rows > 0 {
+        repairs.push(format!(
+            "filled {short_rows} of {height} incomplete rows with black"
+        ));
+    }
+    Ok(repairs.join(", "))
+}
+
+/// 受信したフレームを `width*4` で詰めたRGBAに変換して `out` に書き込む
+///
+/// `Fastest` / `Best` の受信フォーマットではソース次第でYUVやBGRAが届くため、
+/// ここで共通のRGBAにそろえる。未対応のフォーマットやデータ不足はエラー。
+pub fn to_rgba(
+    format: PixelFormat,
+    width: usize,
+    height: usize,
+    line_stride: usize,
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    if width == 0 || height == 0 {
+        anyhow::bail!("Empty {format:?} frame ({width}x{height})");
+    }
+
+    let (min_stride, required) = required_sizes(format, width, height, line_stride)?;
+    if line_stride < min_stride {
+        anyhow::bail!("Line stride {line_stride} is too small for {format:?} at width {width}");
+    }
+    if data.len() < required {
+        anyhow::bail!(
+            "{format:?} frame is truncated: {} bytes (expected {required})",
+            data.len()
+        );
+    }
+
+    out.clear();
+    out.resize(rgba_size(width, height)?, 0);
+    let matrix = YuvMatrix::for_height(height);
+
+    for (y, dst) in out.chunks_exact_mut(width * 4).enumerate() {
+        let row = &data[y * line_stride..];
+        match format {
+            PixelFormat::RGBA | PixelFormat::RGBX => dst.copy_from_slice(&row[..width * 4]),
+            PixelFormat::BGRA | PixelFormat::BGRX => {
+                for (d, s) in dst.chunks_exact_mut(4).zip(row.chunks_exact(4)) {
+                    d.copy_from_slice(&[s[2], s[1], s[0], s[3]]);
+                }
+            }
+            PixelFormat::UYVY | PixelFormat::UYVA => {
+                let alpha = (format == PixelFormat::UYVA)
+                    .then(|| &data[line_stride * height + y * width..][..width]);
+                for x in 0..width {
+                    // U Y0 V Y1 で2画素
+                    let pair = &row[(x / 2) * 4..(x / 2) * 4 + 4];
+                    let luma = pair[1 + (x % 2) * 2];
+                    let [r, g, b] = matrix.to_rgb(luma, pair[0], pair[2]);
+                    let a = alpha.map_or(255, |alpha| alpha[x]);
+                    dst[x * 4..x * 4 + 4].copy_from_slice(&[r, g, b, a]);
+                }
+            }
+            PixelFormat::P216 | PixelFormat::PA16 => {
+                // 16bitリトルエンディアンの上位8bitだけを使う
+                let high = |plane: usize, x: usize| {
+                    data[plane * line_stride * height + y * line_stride + x * 2 + 1]
+                };
+                for x in 0..width {
+                    let chroma = (x / 2) * 2;
+                    let [r, g, b] = matrix.to_rgb(high(0, x), high(1, chroma), high(1, chroma + 1));
+                    let a = if format == PixelFormat::PA16 { high(2, x) } else { 255 };
+                    dst[x * 4..x * 4 + 4].copy_from_slice(&[r, g, b, a]);
+                }
+            }
+            PixelFormat::NV12 | PixelFormat::I420 | PixelFormat::YV12 => {
+                // 2x2画素で色差1組
+                let luma_plane = line_stride * height;
+                let chroma_stride = line_stride / 2;
+                let chroma_plane = chroma_stride * height.div_ceil(2);
+                let (u_plane, v_plane) = match format {
+                    PixelFormat::I420 => (luma_plane, luma_plane + chroma_plane),
+                    _ => (luma_plane + chroma_plane, luma_plane),
+                };
+                for x in 0..width {
+                    let (u, v) = if format == PixelFormat::NV12 {
+                        let uv = luma_plane + (y / 2) * line_stride + (x / 2) * 2;
+                        (data[uv], data[uv + 1])
+                    } else {
+                        let offset = (y / 2) * chroma_stride + x / 2;
+                        (data[u_plane + offset], data[v_plane + offset])
+                    };
+                    let [r, g, b] = matrix.to_rgb(row[x], u, v);
+                    dst[x * 4..x * 4 + 4].copy_from_slice(&[r, g, b, 255]);
+                }
+            }
+            _ => unreachable!("checked above"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 4x3のフレームの (x, y) の画素
+    fn pixel(frame: &[u8], x: usize, y: usize) -> &[u8] {
+        &frame[(y * 4 + x) * 4..][..4]
+    }
+
+    #[test]
+    fn rgba_size_of_8k_and_overflowing_sizes() {
+        assert_eq!(rgba_size(7680, 4320).unwrap(), 132_710_400);
+        assert_eq!(rgba_size(1, 1).unwrap(), 4);
+        // 画素数か、その4倍があふれる
+        assert!(rgba_size(usize::MAX, 2).is_err());
+        assert!(rgba_size(usize::MAX / 4 + 1, 1).is_err());
+        let error = rgba_size(usize::MAX, usize::MAX).unwrap_err();
+        assert!(error.to_string().contains("is too large to address"));
+        // ちょうど収まる大きさまでは通す
+        assert_eq!(rgba_size(usize::MAX / 4, 1).unwrap(), usize::MAX / 4 * 4);
+    }
+
+    // R = 値、G = 市松模様（0/255）、B = 7、A = 255 の画像
+    fn gradient(width: usize, height: usize, value: impl Fn(usize, usize) -> u8) -> Vec<u8> {
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| [value(x, y), (x + y) as u8 % 2 * 255, 7, 255])
+            .collect()
+    }
+
+    #[test]
+    fn downscale_averages_each_block() {
+        let data = gradient(4, 4, |x, y| (x + 4 * y) as u8 * 10);
+        let (out, width, height) = downscale_rgba(&data, 4, 4, 2);
+        assert_eq!((width, height), (2, 2));
+        // 2x2ごとの平均。市松模様の 127.5 は切り上げ
+        let expected: Vec<u8> = [25, 45, 105, 125]
+            .into_iter()
+            .flat_map(|r| [r, 128, 7, 255])
+            .collect();
+        assert_eq!(out, expected);
+
+        let (out, width, height) = downscale_rgba(&data, 4, 4, 4);
+        assert_eq!((width, height), (1, 1));
+        assert_eq!(out, [75, 128, 7, 255]);
+
+        // 1以下は縮小しない
+        for factor in [0, 1] {
+            assert_eq!(downscale_rgba(&data, 4, 4, factor), (data.clone(), 4, 4));
+        }
+    }
+
+    #[test]
+    fn downscale_averages_partial_edge_blocks() {
+        // 5x3を1/2にすると3x2。右端の列と下端の行のブロックは1画素幅
+        let data = gradient(5, 3, |x, y| (x * 10 + y * 100) as u8);
+        let (out, width, height) = downscale_rgba(&data, 5, 3, 2);
+        assert_eq!((width, height), (3, 2));
+        let red: Vec<u8> = out.chunks_exact(4).map(|px| px[0]).collect();
+        assert_eq!(red, [55, 75, 90, 205, 225, 240]);
+        // 2画素のブロックは市松模様の平均、右下の1画素だけのブロックはその画素の色
+        let green: Vec<u8> = out.chunks_exact(4).map(|px| px[1]).collect();
+        assert_eq!(green, [128, 128, 128, 128, 128, 0]);
+        assert!(out.chunks_exact(4).all(|px| px[2..] == [7, 255]));
+    }
+
+    #[test]
+    fn repack_drops_the_line_padding() {
+        // 2x3、各行の後ろに6バイトのパディング（最後の行はパディングなしで終わる）
+        for (format, pixel) in [
+            (PixelFormat::RGBA, [10, 20, 30, 255]),
+            (PixelFormat::BGRX, [30, 20, 10, 255]),
+            (PixelFormat::UYVY, [128, 60, 128, 70]),
+        ] {
+            let row_bytes = if format == PixelFormat::UYVY { 4 } else { 8 };
+            let stride = row_bytes + 6;
+            let mut data = Vec::new();
+            for y in 0..3 {
+                data.extend(pixel.repeat(row_bytes / 4));
+                if y < 2 {
+                    data.extend([0xEE; 6]);
+                }
+            }
+            let mut out = Vec::new();
+            let repairs = repack(format, 2, 3, stride, &data, &mut out).unwrap();
+            assert_eq!(
+                repairs,
+                format!("repacked line stride {stride} to {row_bytes}"),
+                "{format:?}"
+            );
+            assert_eq!(out, pixel.repeat(row_bytes / 4 * 3), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn blend_opacity_zero_and_full() {
+        let background = [10, 20, 30, 40].repeat(4 * 3);
+        let overlay = [200, 100, 0, 255].repeat(2 * 2);
+
+        let mut frame = background.clone();
+        blend_rgba(&mut frame, 4, &overlay, 2, 1, 1, 0.0);
+        assert_eq!(frame, background);
+
+        // 不透明度1で不透明な画素はそのまま置き換わる（フレームのアルファは変えない）
+        let mut frame = background.clone();
+        blend_rgba(&mut frame, 4, &overlay, 2, 1, 1, 1.0);
+        for y in 0..3 {
+            for x in 0..4 {
+                let expected = if (1..3).contains(&x) && (1..3).contains(&y) {
+                    [200, 100, 0, 40]
+                } else {
+                    [10, 20, 30, 40]
+                };
+                assert_eq!(pixel(&frame, x, y), expected, "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn blend_mixes_by_pixel_alpha_times_opacity() {
+        let mut frame = [0, 0, 0, 255].to_vec();
+        // アルファ255 × 不透明度0.5 ≒ 128/255
+        blend_rgba(&mut frame, 1, &[255, 255, 255, 255], 1, 0, 0, 0.5);
+        assert_eq!(frame, [128, 128, 128, 255]);
+
+        let mut frame = [0, 0, 0, 255].to_vec();
+        blend_rgba(&mut frame, 1, &[255, 255, 255, 0], 1, 0, 0, 1.0);
+        assert_eq!(frame, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn blend_clips_at_the_frame_edge() {
+        let background = [0, 0, 0, 255].repeat(4 * 3);
+        let overlay = [255, 255, 255, 255].repeat(3 * 3);
+
+        // 右下の角に置いた3x3は、フレームに入る2x1だけ描かれる
+        let mut frame = background.clone();
+        blend_rgba(&mut frame, 4, &overlay, 3, 2, 2, 1.0);
+        for y in 0..3 {
+            for x in 0..4 {
+                let inside = x >= 2 && y == 2;
+                let expected = if inside { [255; 4] } else { [0, 0, 0, 255] };
+                assert_eq!(pixel(&frame, x, y), expected, "({x}, {y})");
+            }
+        }
+
+        // 完全に外なら何もしない
+        let mut frame = background.clone();
+        blend_rgba(&mut frame, 4, &overlay, 3, 4, 0, 1.0);
+        blend_rgba(&mut frame, 4, &overlay, 3, 0, 3, 1.0);
+        assert_eq!(frame, background);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn color_image_round_trip() {
+        for alpha in 0..=255u8 {
+            let data: Vec<u8> = (0..=255u8)
+                .flat_map(|v| [v, 255 - v, v / 2, alpha])
+                .collect();
+            let image = egui::ColorImage::from_rgba_unmultiplied([256, 1], &data);
+            let mut out = Vec::new();
+            color_image_to_rgba(&image, &mut out);
+            assert_eq!(out.len(), data.len());
+
+            // 乗算済みの8bitに丸めた分、アルファで割り戻すとずれる（およそ ±128/アルファ）
+            let tolerance = match alpha {
+                0 => 255,
+                255 => 0,
+                _ => 128u8.div_ceil(alpha),
+            };
+            for (o, i) in out.chunks_exact(4).zip(data.chunks_exact(4)) {
+                assert_eq!(o[3], alpha);
+                for c in 0..3 {
+                    assert!(
+                        o[c].abs_diff(i[c]) <= tolerance,
+                        "alpha {alpha}: {i:?} came back as {o:?}"
+                    );
+                }
+            }
+            match alpha {
+                // 完全に透明な画素の色は失われる
+                0 => assert!(out.chunks_exact(4).all(|px| px == [0, 0, 0, 0])),
+                128 => assert_eq!(tolerance, 1),
+                32 => assert_eq!(tolerance, 4),
+                _ => {}
+            }
+        }
+    }
+
+    // (RGB, BT.601 の Y U V, BT.709 の Y U V)。YUVはリミテッドレンジ
+    const UYVY_COLORS: [([u8; 3], [u8; 3], [u8; 3]); 7] = [
+        ([255, 0, 0], [81, 90, 240], [63, 102, 240]),
+        ([0, 255, 0], [145, 54, 34], [173, 42, 26]),
+        ([0, 0, 255], [41, 240, 110], [32, 240, 118]),
+        ([255, 255, 255], [235, 128, 128], [235, 128, 128]),
+        ([0, 0, 0], [16, 128, 128], [16, 128, 128]),
+        ([128, 128, 128], [126, 128, 128], [126, 128, 128]),
+        ([200, 120, 40], [132, 81, 169], [129, 85, 166]),
+    ];
+
+    // 色ごとに1組（U Y V Y）を使い、最後の組は1画素だけの奇数幅（13画素）にして変換する
+    fn convert_uyvy(height: usize, yuv: impl Fn(usize) -> [u8; 3]) -> Vec<u8> {
+        let width = UYVY_COLORS.len() * 2 - 1;
+        let row: Vec<u8> = (0..UYVY_COLORS.len())
+            .flat_map(|i| {
+                let [y, u, v] = yuv(i);
+                [u, y, v, y]
+            })
+            .collect();
+        let (stride, data) = (row.len(), row.repeat(height));
+        let mut out = Vec::new();
+        to_rgba(PixelFormat::UYVY, width, height, stride, &data, &mut out).unwrap();
+        assert_eq!(out.len(), width * height * 4);
+        out
+    }
+
+    fn assert_colors(out: &[u8], height: usize) {
+        let width = UYVY_COLORS.len() * 2 - 1;
+        for y in [0, height - 1] {
+            for x in 0..width {
+                let (rgb, _, _) = UYVY_COLORS[x / 2];
+                let px = &out[(y * width + x) * 4..][..4];
+                for c in 0..3 {
+                    assert!(px[c].abs_diff(rgb[c]) <= 1, "({x}, {y}): {px:?} != {rgb:?}");
+                }
+                assert_eq!(px[3], 255);
+            }
+        }
+    }
+
+    #[test]
+    fn uyvy_uses_bt601_below_720p() {
+        assert_colors(&convert_uyvy(576, |i| UYVY_COLORS[i].1), 576);
+    }
+
+    #[test]
+    fn uyvy_uses_bt709_from_720p() {
+        assert_colors(&convert_uyvy(720, |i| UYVY_COLORS[i].2), 720);
+        // BT.709の赤をBT.601で読むとずれる（行列の選び間違いに気づけること）
+        let out = convert_uyvy(2, |i| UYVY_COLORS[i].2);
+        assert_eq!(out[..4], [234, 0, 2, 255]);
+    }
+}
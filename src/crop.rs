@@ -0,0 +1,217 @@
+//! 透明な余白の自動クロップ（`--auto-crop`）
+//!
+//! ロワーサードなどのキー付きグラフィックスは、フレームの大半が透明で中身が小さいことが多い。
+//! 透明でない（または背景色でない）画素を囲む最小の矩形を求めて切り出し、中身をウィンドウいっぱいに表示する。
+
+/// 切り出す範囲（画素）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CropRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl CropRect {
+    pub fn full(width: usize, height: usize) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+}
+
+/// 中身を囲む範囲をフレームごとに（`with_lock` なら1回だけ）求める
+pub struct AutoCrop {
+    threshold: u8,
+    background: Option<[u8; 3]>,
+    lock: bool,
+    // 直近の範囲と、それを求めたフレームのサイズ
+    last: Option<(CropRect, [usize; 2])>,
+}
+
+impl AutoCrop {
+    /// アルファが `threshold` より大きい画素を中身とみなす
+    ///
+    /// `background` があればアルファの代わりに、どれかのチャンネルが背景色から
+    /// `threshold` より離れている画素を中身とみなす（アルファのないソース向け）。
+    pub fn new(threshold: u8, background: Option<[u8; 3]>) -> Self {
+        Self {
+            threshold,
+            background,
+            lock: false,
+            last: None,
+        }
+    }
+
+    /// trueなら最初に中身が見つかったフレームの範囲に固定する（フレームサイズが変わるまで）
+    pub fn with_lock(mut self, lock: bool) -> Self {
+        self.lock = lock;
+        self
+    }
+
+    /// `width*4` で詰めたRGBAの中で切り出す範囲
+    ///
+    /// 中身が1画素もないフレームでは直前の範囲を使い、それもなければフレーム全体を返す
+    /// （グラフィックスが消えている間に全体表示へ戻ってちらつかないように）。
+    pub fn bounds(&mut self, width: usize, height: usize, data: &[u8]) -> CropRect {
+        let size = [width, height];
+        let last = self.last.filter(|(_, last_size)| *last_size == size);
+        if self.lock
+            && let Some((rect, _)) = last
+        {
+            return rect;
+        }
+        match self.scan(width, height, data) {
+            Some(rect) => {
+                self.last = Some((rect, size));
+                rect
+            }
+            None => last.map_or(CropRect::full(width, height), |(rect, _)| rect),
+        }
+    }
+
+    fn is_content(&self, pixel: &[u8]) -> bool {
+        match self.background {
+            Some(background) => pixel[..3]
+                .iter()
+                .zip(background)
+                .any(|(&c, b)| c.abs_diff(b) > self.threshold),
+            None => pixel[3] > self.threshold,
+        }
+    }
+
+    // 上下は中身のある行を外側から探し、左右はその間の行で今の範囲より外側だけを見る
+    fn scan(&self, width: usize, height: usize, data: &[u8]) -> Option<CropRect> {
+        let row = |y: usize| &data[y * width * 4..(y + 1) * width * 4];
+        let row_has_content = |y: usize| row(y).chunks_exact(4).any(|p| self.is_content(p));
+
+        let top = (0..height).find(|&y| row_has_content(y))?;
+        let bottom = (top..height).rev().find(|&y| row_has_content(y))?;
+
+        let (mut left, mut right) = (width, 0);
+        for y in top..=bottom {
+            let row = row(y);
+            let content = |x: usize| self.is_content(&row[x * 4..x * 4 + 4]);
+            if let Some(x) = (0..left).find(|&x| content(x)) {
+                left = x;
+            }
+            if let Some(x) = (right.max(left)..width).rev().find(|&x| content(x)) {
+                right = x;
+            }
+            if left == 0 && right == width - 1 {
+                break;
+            }
+        }
+
+        Some(CropRect {
+            x: left,
+            y: top,
+            width: right - left + 1,
+            height: bottom - top + 1,
+        })
+    }
+}
+
+/// `width*4` で詰めたRGBAから `rect` の範囲をコピーする
+pub fn crop_rgba(data: &[u8], width: usize, rect: CropRect) -> Vec<u8> {
+    let mut out = Vec::with_capacity(rect.width * rect.height * 4);
+    for y in rect.y..rect.y + rect.height {
+        let start = (y * width + rect.x) * 4;
+        out.extend_from_slice(&data[start..start + rect.width * 4]);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 8;
+    const HEIGHT: usize = 6;
+
+    // 透明な8x6に、指定した画素だけ不透明な白を置く
+    fn transparent_with(pixels: &[(usize, usize)]) -> Vec<u8> {
+        let mut data = vec![0; WIDTH * HEIGHT * 4];
+        for &(x, y) in pixels {
+            data[(y * WIDTH + x) * 4..][..4].copy_from_slice(&[255; 4]);
+        }
+        data
+    }
+
+    #[test]
+    fn bounds_enclose_every_content_pixel() {
+        let mut crop = AutoCrop::new(0, None);
+        let data = transparent_with(&[(2, 1), (5, 3), (3, 4)]);
+        let rect = crop.bounds(WIDTH, HEIGHT, &data);
+        assert_eq!(
+            rect,
+            CropRect {
+                x: 2,
+                y: 1,
+                width: 4,
+                height: 4
+            }
+        );
+        let cropped = crop_rgba(&data, WIDTH, rect);
+        assert_eq!(cropped.len(), 4 * 4 * 4);
+        // 左上が (2, 1)、右下の1つ上が (5, 3)
+        assert_eq!(cropped[..4], [255; 4]);
+        assert_eq!(cropped[(2 * 4 + 3) * 4..][..4], [255; 4]);
+    }
+
+    #[test]
+    fn bounds_of_a_letterboxed_frame_by_background_color() {
+        // 黒い額縁の中に2画素幅の灰色の絵。額縁にはしきい値以下のノイズがある
+        let mut data = [0, 0, 0, 255].repeat(WIDTH * HEIGHT);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let value = if (3..5).contains(&x) && (1..5).contains(&y) {
+                    128
+                } else {
+                    ((x + y) % 3 * 8) as u8
+                };
+                data[(y * WIDTH + x) * 4..][..3].fill(value);
+            }
+        }
+        let mut crop = AutoCrop::new(16, Some([0, 0, 0]));
+        assert_eq!(
+            crop.bounds(WIDTH, HEIGHT, &data),
+            CropRect {
+                x: 3,
+                y: 1,
+                width: 2,
+                height: 4
+            }
+        );
+    }
+
+    #[test]
+    fn empty_frames_keep_the_last_bounds_until_the_size_changes() {
+        let mut crop = AutoCrop::new(0, None);
+        let empty = transparent_with(&[]);
+        assert_eq!(
+            crop.bounds(WIDTH, HEIGHT, &empty),
+            CropRect::full(WIDTH, HEIGHT)
+        );
+
+        let rect = crop.bounds(WIDTH, HEIGHT, &transparent_with(&[(6, 5)]));
+        assert_eq!(crop.bounds(WIDTH, HEIGHT, &empty), rect);
+        // 大きさが変われば直前の範囲は使わない
+        let other = vec![0; 4 * 4 * 4];
+        assert_eq!(crop.bounds(4, 4, &other), CropRect::full(4, 4));
+    }
+
+    #[test]
+    fn locked_bounds_ignore_later_frames() {
+        let mut crop = AutoCrop::new(0, None).with_lock(true);
+        let first = crop.bounds(WIDTH, HEIGHT, &transparent_with(&[(1, 1)]));
+        let moved = transparent_with(&[(0, 0), (7, 5)]);
+        assert_eq!(crop.bounds(WIDTH, HEIGHT, &moved), first);
+
+        let unlocked = AutoCrop::new(0, None).bounds(WIDTH, HEIGHT, &moved);
+        assert_eq!(unlocked, CropRect::full(WIDTH, HEIGHT));
+    }
+}
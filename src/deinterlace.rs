@@ -0,0 +1,193 @@
+//! インターレースのフレームのCPUデインターレース（`--deinterlace`、framesync-viewer）
+//!
+//! 2つのフィールドが1行おきに織り込まれたフレームをそのまま出すと、動きのある部分が櫛状になる。
+//! 片方のフィールドだけから補間する（bob）か、上下の行と混ぜる（blend）かで消す。
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// デインターレースの方法
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Deinterlace {
+    /// 織り込まれたまま出す
+    None,
+    /// 先のフィールドの行だけ残し、もう片方の行は上下の平均で埋める（縦の解像度は半分になる）
+    Bob,
+    /// 各行を上下の行と 1:2:1 で混ぜる（動きのある部分は2つのフィールドが半透明に重なる）
+    #[default]
+    Blend,
+}
+
+/// 先に表示されるフィールド
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FieldOrder {
+    /// 偶数行（0行目から）が先。NDIのインターレースのフレームはこちら
+    #[default]
+    TopFirst,
+    BottomFirst,
+}
+
+impl Deinterlace {
+    pub const ALL: &[Self] = &[Self::None, Self::Bob, Self::Blend];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|mode| mode.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Bob => "bob",
+            Self::Blend => "blend",
+        }
+    }
+
+    /// 次の方法（実行中の切り替え用、最後の次は最初に戻る）
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Bob,
+            Self::Bob => Self::Blend,
+            Self::Blend => Self::None,
+        }
+    }
+
+    /// 詰めたRGBAの `rgba`（`height` 行）をその場でデインターレースする
+    ///
+    /// 1行のバイト数は `rgba.len() / height`。上下の端の行は、隣がなければ内側の行を使う。
+    pub fn apply(self, rgba: &mut [u8], height: usize, order: FieldOrder) {
+        if height < 2 || !rgba.len().is_multiple_of(height) {
+            return;
+        }
+        let row_len = rgba.len() / height;
+        match self {
+            Self::None => {}
+            Self::Bob => {
+                // 残すフィールドの行の偶奇
+                let kept = match order {
+                    FieldOrder::TopFirst => 0,
+                    FieldOrder::BottomFirst => 1,
+                };
+                for y in (0..height).filter(|y| y % 2 != kept) {
+                    let above = if y > 0 { y - 1 } else { y + 1 };
+                    let below = if y + 1 < height { y + 1 } else { above };
+                    for x in 0..row_len {
+                        let sum =
+                            rgba[above * row_len + x] as u16 + rgba[below * row_len + x] as u16;
+                        rgba[y * row_len + x] = sum.div_ceil(2) as u8;
+                    }
+                }
+            }
+            Self::Blend => {
+                // 上の行は書き換える前の値が要るので取っておく
+                let mut above = rgba[row_len..2 * row_len].to_vec();
+                let mut current = Vec::with_capacity(row_len);
+                for y in 0..height {
+                    current.clear();
+                    current.extend_from_slice(&rgba[y * row_len..(y + 1) * row_len]);
+                    let (row, rest) = rgba[y * row_len..].split_at_mut(row_len);
+                    let below = if y + 1 < height {
+                        &rest[..row_len]
+                    } else {
+                        above.as_slice()
+                    };
+                    for (x, out) in row.iter_mut().enumerate() {
+                        let sum = above[x] as u16 + 2 * current[x] as u16 + below[x] as u16;
+                        *out = ((sum + 2) / 4) as u8;
+                    }
+                    std::mem::swap(&mut above, &mut current);
+                }
+            }
+        }
+    }
+}
+
+/// 実行中に切り替えるための共有の [`Deinterlace`]（受信スレッドが毎フレーム読む）
+#[derive(Debug)]
+pub struct SharedDeinterlace(AtomicU8);
+
+impl SharedDeinterlace {
+    pub fn new(mode: Deinterlace) -> Self {
+        Self(AtomicU8::new(Self::index(mode)))
+    }
+
+    pub fn get(&self) -> Deinterlace {
+        Deinterlace::ALL[self.0.load(Ordering::Relaxed) as usize]
+    }
+
+    pub fn set(&self, mode: Deinterlace) {
+        self.0.store(Self::index(mode), Ordering::Relaxed);
+    }
+
+    fn index(mode: Deinterlace) -> u8 {
+        Deinterlace::ALL
+            .iter()
+            .position(|m| *m == mode)
+            .unwrap_or(0) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1行1画素のグレーのフレーム
+    fn column(values: &[u8]) -> Vec<u8> {
+        values.iter().flat_map(|&v| [v, v, v, 255]).collect()
+    }
+
+    fn deinterlace(mode: Deinterlace, values: &[u8], order: FieldOrder) -> Vec<u8> {
+        let mut rgba = column(values);
+        mode.apply(&mut rgba, values.len(), order);
+        assert!(rgba.chunks_exact(4).all(|px| px[3] == 255));
+        rgba.chunks_exact(4).map(|px| px[0]).collect()
+    }
+
+    const ROWS: [u8; 5] = [0, 100, 50, 200, 80];
+
+    #[test]
+    fn bob_keeps_the_first_field() {
+        // 奇数行を上下の平均で埋める。最後の行に下がなければ上の行を使う
+        let top = FieldOrder::TopFirst;
+        assert_eq!(
+            deinterlace(Deinterlace::Bob, &ROWS, top),
+            [0, 25, 50, 65, 80]
+        );
+        assert_eq!(
+            deinterlace(Deinterlace::Bob, &ROWS[..4], top),
+            [0, 25, 50, 50]
+        );
+
+        // 偶数行を埋める。最初の行は下の行、最後の行は上の行を使う
+        let bottom = FieldOrder::BottomFirst;
+        assert_eq!(
+            deinterlace(Deinterlace::Bob, &ROWS, bottom),
+            [100, 100, 150, 200, 200]
+        );
+    }
+
+    #[test]
+    fn blend_mirrors_the_edge_rows() {
+        // 端の行は外側の代わりに内側の行を2回使う（1:2:1）
+        for order in [FieldOrder::TopFirst, FieldOrder::BottomFirst] {
+            assert_eq!(
+                deinterlace(Deinterlace::Blend, &ROWS[..4], order),
+                [50, 63, 100, 125]
+            );
+        }
+    }
+
+    #[test]
+    fn none_and_short_frames_are_left_alone() {
+        assert_eq!(
+            deinterlace(Deinterlace::None, &ROWS, FieldOrder::TopFirst),
+            ROWS
+        );
+        for mode in Deinterlace::ALL {
+            assert_eq!(deinterlace(*mode, &[42], FieldOrder::TopFirst), [42]);
+            assert_eq!(deinterlace(*mode, &[], FieldOrder::TopFirst), []);
+            // 行の長さがそろわないバッファも触らない
+            let mut odd = vec![1, 2, 3, 4, 5];
+            mode.apply(&mut odd, 2, FieldOrder::TopFirst);
+            assert_eq!(odd, [1, 2, 3, 4, 5]);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+//! 受信スレッドから表示側へのフレームの受け渡し方
+//!
+//! ビューアごとに暗黙に選んでいた方式（raw-viewer の `ArcSwap` 最新1枚、
+//! buffered-viewer の固定遅延 `VecDeque`）と、容量付きチャネルを同じ形で扱えるようにする。
+//! `delivery-bench` はこれを使って方式ごとの遅延とドロップを比べる。
+//! 表示のフレームレートに合わせる変換（[`RateConverter`]）もここに置く。
+
+use arc_swap::ArcSwapOption;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// フレームの受け渡し方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStrategy {
+    /// 最新の1枚だけを持つ（`ArcSwap`）。表示が追いつかなければ古い方を上書きする
+    Latest,
+    /// 容量付きチャネル。満杯なら新しく届いた方を捨てる
+    Bounded(usize),
+    /// `VecDeque` に溜め、`delay` 枚を超えてから古い順に出す（固定遅延）。
+    /// `capacity` を超えたら古い方を捨て、ロックが取れなかったフレームも捨てる
+    Buffered { capacity: usize, delay: usize },
+}
+
+impl fmt::Display for DeliveryStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Latest => write!(f, "latest (ArcSwap)"),
+            Self::Bounded(capacity) => write!(f, "bounded channel ({capacity})"),
+            Self::Buffered { capacity, delay } => {
+                write!(f, "buffered VecDeque ({delay}/{capacity})")
+            }
+        }
+    }
+}
+
+/// 受信側（`push`）と表示側（`pop`）で共有する受け渡し口
+///
+/// `T: Clone` は `Latest` で表示側がまだ参照を持っていた場合のコピー用。
+pub struct Delivery<T> {
+    slot: Slot<T>,
+    // 上書き・満杯・ロック競合で表示されずに捨てたフレーム数
+    dropped: AtomicU64,
+}
+
+enum Slot<T> {
+    Latest(ArcSwapOption<T>),
+    Bounded(SyncSender<T>, Mutex<Receiver<T>>),
+    Buffered {
+        frames: Mutex<VecDeque<T>>,
+        capacity: usize,
+        delay: usize,
+    },
+}
+
+impl<T: Clone + Send> Delivery<T> {
+    pub fn new(strategy: DeliveryStrategy) -> Arc<Self> {
+        let slot = match strategy {
+            DeliveryStrategy::Latest => Slot::Latest(ArcSwapOption::empty()),
+            DeliveryStrategy::Bounded(capacity) => {
+                let (sender, receiver) = mpsc::sync_channel(capacity);
+                Slot::Bounded(sender, Mutex::new(receiver))
+            }
+            DeliveryStrategy::Buffered { capacity, delay } => Slot::Buffered {
+                frames: Mutex::new(VecDeque::new()),
+                capacity,
+                delay,
+            },
+        };
+        Arc::new(Self {
+            slot,
+            dropped: AtomicU64::new(0),
+        })
+    }
+
+    /// 受信側からフレームを渡す（ブロックしない）
+    pub fn push(&self, frame: T) {
+        let dropped = match &self.slot {
+            Slot::Latest(latest) => latest.swap(Some(Arc::new(frame))).is_some() as u64,
+            Slot::Bounded(sender, _) => match sender.try_send(frame) {
+                Ok(()) => 0,
+                Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => 1,
+            },
+            Slot::Buffered {
+                frames, capacity, ..
+            } => match frames.try_lock() {
+                Ok(mut frames) => {
+                    frames.push_back(frame);
+                    let overflow = frames.len().saturating_sub(*capacity);
+                    frames.drain(..overflow);
+                    overflow as u64
+                }
+                Err(_) => 1,
+            },
+        };
+        self.dropped.fetch_add(dropped, Ordering::Relaxed);
+    }
+
+    /// 表示側で次に出すフレームを取り出す
+    pub fn pop(&self) -> Option<T> {
+        match &self.slot {
+            Slot::Latest(latest) => latest
+                .swap(None)
+                .map(|frame| Arc::try_unwrap(frame).unwrap_or_else(|arc| (*arc).clone())),
+            Slot::Bounded(_, receiver) => receiver.lock().ok()?.try_recv().ok(),
+            Slot::Buffered { frames, delay, .. } => {
+                let mut frames = frames.try_lock().ok()?;
+                if frames.len() > *delay {
+                    frames.pop_front()
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// 表示されずに捨てたフレーム数
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+// 表示待ちにしておくフレームの上限（送信側の時計が速いときに遅延が溜まらないように）
+const RATE_CONVERTER_MAX_QUEUED: usize = 3;
+
+/// 0次ホールドのフレームレート変換（`--target-fps`）
+///
+/// 受信したフレームごとに、公称のフレーム間隔を積算して出力の刻み（`target_interval`）何回分
+/// 表示するかを決める。0回なら捨て、2回以上なら同じフレームを出し続ける（25fps→30fpsなら
+/// 5枚に1枚が2刻み表示になる）。動き補間はしないので、動きの滑らかさは元のフレームレートのまま。
+/// 表示側は [`RateConverter::tick`] を描画のたびに呼び、刻みごとに出すフレームを受け取る。
+pub struct RateConverter<T> {
+    target_interval: Duration,
+    // 出力の刻みに換算しきれていない時間
+    accumulated: Duration,
+    // 表示待ちのフレームと、それぞれを表示する刻みの数
+    queue: VecDeque<(T, u32)>,
+    // 今表示しているフレームが残り何刻み表示されるか
+    holding: u32,
+    next_tick: Option<Instant>,
+    dropped: u64,
+    repeated: u64,
+}
+
+impl<T> RateConverter<T> {
+    pub fn new(target_fps: f64) -> Self {
+        Self {
+            target_interval: Duration::from_secs_f64(1.0 / target_fps).max(Duration::from_nanos(1)),
+            accumulated: Duration::ZERO,
+            queue: VecDeque::new(),
+            holding: 0,
+            next_tick: None,
+            dropped: 0,
+            repeated: 0,
+        }
+    }
+
+    /// 受信したフレームを渡す
+    ///
+    /// `source_interval` はソースの公称フレーム間隔（不明なら0で、1枚を1刻み表示する）。
+    pub fn push(&mut self, frame: T, source_interval: Duration) {
+        self.accumulated += if source_interval.is_zero() {
+            self.target_interval
+        } else {
+            source_interval
+        };
+        let ticks = (self.accumulated.as_nanos() / self.target_interval.as_nanos()) as u32;
+        self.accumulated = self
+            .accumulated
+            .saturating_sub(self.target_interval * ticks);
+        if ticks == 0 {
+            self.dropped += 1;
+            return;
+        }
+        self.queue.push_back((frame, ticks));
+        if self.queue.len() > RATE_CONVERTER_MAX_QUEUED {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+    }
+
+    /// 描画のたびに呼ぶ。出力の刻みが来ていて新しいフレームに替わるならそれを返す
+    ///
+    /// 2つ目の値は次の刻みまでの時間（この後に再描画を予約する）。描画が遅れて刻みを
+    /// 飛ばした場合は、飛ばした刻みの分だけ進めて最後に替わったフレームを返す。
+    pub fn tick(&mut self, now: Instant) -> (Option<T>, Duration) {
+        let next_tick = *self.next_tick.get_or_insert(now);
+        if now < next_tick {
+            return (None, next_tick - now);
+        }
+        let elapsed = (now - next_tick).as_secs_f64() / self.target_interval.as_secs_f64();
+        let ticks = elapsed as u32 + 1;
+
+        let mut frame = None;
+        for _ in 0..ticks {
+            if self.holding > 1 {
+                self.holding -= 1;
+                self.repeated += 1;
+            } else if let Some((next, ticks)) = self.queue.pop_front() {
+                frame = Some(next);
+                self.holding = ticks;
+            } else if self.holding > 0 {
+                // 次のフレームが間に合わなかったので、今のを出し続ける
+                self.repeated += 1;
+            }
+        }
+        // 大きく遅れたら（ウィンドウが隠れていたなど）今から刻み直す
+        let next_tick = if ticks > RATE_CONVERTER_MAX_QUEUED as u32 {
+            now + self.target_interval
+        } else {
+            next_tick + self.target_interval * ticks
+        };
+        self.next_tick = Some(next_tick);
+        (frame, next_tick.saturating_duration_since(now))
+    }
+
+    /// 表示されずに捨てたフレーム数
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// 同じフレームを続けて表示した刻みの数
+    pub fn repeated(&self) -> u64 {
+        self.repeated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 表示の刻みはフレームの到着から少し遅らせる（次のフレームが間に合うように）
+    const DISPLAY_DELAY: Duration = Duration::from_millis(45);
+
+    /// `source_fps` で届く `frames` 枚を `target_fps` で `ticks` 刻み表示し、tickが返したフレームの番号を返す
+    fn convert(
+        source_fps: f64,
+        target_fps: f64,
+        frames: usize,
+        ticks: u32,
+    ) -> (Vec<usize>, RateConverter<usize>) {
+        let source_interval = Duration::from_secs_f64(1.0 / source_fps);
+        let mut converter = RateConverter::new(target_fps);
+        let start = Instant::now();
+        let mut pushed = 0;
+        let mut shown = Vec::new();
+        for tick in 0..ticks {
+            let now = start + DISPLAY_DELAY + converter.target_interval * tick;
+            while pushed < frames && start + source_interval * pushed as u32 <= now {
+                converter.push(pushed, source_interval);
+                pushed += 1;
+            }
+            let (frame, wait) = converter.tick(now);
+            assert_eq!(wait, converter.target_interval, "tick {tick}");
+            shown.extend(frame);
+        }
+        (shown, converter)
+    }
+
+    #[test]
+    fn rate_converter_25_to_30_fps_repeats_every_fifth_frame() {
+        // 1秒分。5枚ごとに1枚が2刻み表示になる
+        let (shown, converter) = convert(25.0, 30.0, 25, 30);
+        assert_eq!(shown, (0..25).collect::<Vec<_>>());
+        assert_eq!(converter.repeated(), 5);
+        assert_eq!(converter.dropped(), 0);
+    }
+
+    #[test]
+    fn rate_converter_30_to_25_fps_drops_one_frame_in_six() {
+        // 1秒分。最初の1枚は1刻みに満たないので捨て、以降は6枚に1枚を捨てる
+        // （1/30秒をナノ秒に切り捨てた誤差で、2枚目以降の捨てる位置が1枚前にずれる）
+        let (shown, converter) = convert(30.0, 25.0, 30, 25);
+        let dropped = [0, 5, 11, 17, 23, 29];
+        let expected: Vec<usize> = (0..30).filter(|i| !dropped.contains(i)).collect();
+        assert_eq!(shown, expected);
+        assert_eq!(converter.dropped(), dropped.len() as u64);
+        // 最後の刻みでは表示するフレームがもうないので、直前のフレームを出し続ける
+        assert_eq!(converter.repeated(), 1);
+    }
+
+    #[test]
+    fn rate_converter_catches_up_after_a_late_tick() {
+        let mut converter = RateConverter::new(30.0);
+        let interval = converter.target_interval;
+        let start = Instant::now();
+        for frame in 0..3 {
+            converter.push(frame, interval);
+        }
+        assert_eq!(converter.tick(start).0, Some(0));
+        // 2刻み遅れたら飛ばした分だけ進めて、最後に替わったフレームを出す
+        let (frame, wait) = converter.tick(start + interval * 2 + interval / 2);
+        assert_eq!(frame, Some(2));
+        assert_eq!(wait, interval - interval / 2);
+        assert_eq!(converter.repeated(), 0);
+    }
+}
@@ -0,0 +1,727 @@
+//! egui表示まわりの共通ヘルパー
+
+use crate::convert::downscale_rgba;
+use crate::{
+    fit_preserve_aspect, format_timecode, CliArgs, ConnectionState, Shortcut, StreamStats,
+    SHORTCUTS, SHORTCUT_HELP, SHORTCUT_INFO, TARGET_SOURCE_NAME,
+};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use eframe::egui;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+// `TextureFilter::Auto` でこの倍率以上に拡大していればNEARESTにする。
+// 2倍未満の拡大でNEARESTにすると、画素の幅が1と2のように不揃いになって縞が見える
+const NEAREST_MIN_SCALE: f32 = 2.0;
+
+/// テクスチャの拡大縮小フィルタ（`--texture-filter`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextureFilter {
+    /// 表示倍率から選ぶ: 縮小や小さな拡大はLINEAR、2倍以上の拡大はNEAREST
+    #[default]
+    Auto,
+    Linear,
+    Nearest,
+}
+
+impl TextureFilter {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(Self::Auto),
+            "linear" => Some(Self::Linear),
+            "nearest" => Some(Self::Nearest),
+            _ => None,
+        }
+    }
+
+    /// テクセル1つを画面上で `scale` 倍に描くときのテクスチャオプション
+    pub fn options_for(self, scale: f32) -> egui::TextureOptions {
+        match self {
+            Self::Auto if scale >= NEAREST_MIN_SCALE => egui::TextureOptions::NEAREST,
+            Self::Auto | Self::Linear => egui::TextureOptions::LINEAR,
+            Self::Nearest => egui::TextureOptions::NEAREST,
+        }
+    }
+}
+
+/// 受信フレームを表示するテクスチャ
+///
+/// バックエンドの最大テクスチャサイズを超えるフレームは、アップロード前に縮小する。
+/// 表示アスペクト比は `ColorImage::source_size` で受け取り、縮小しても保持する。
+/// テクスチャは1枚を使い回し、フレームごとに作り直さない。
+///
+/// 拡大縮小フィルタは [`TextureFilter`] に従い、描画時の倍率から選ぶ。サンプラーは
+/// テクスチャの確保時に決まるので、フィルタが変わったら次のフレームで確保し直して反映する。
+pub struct FrameTexture {
+    handle: Option<egui::TextureHandle>,
+    filter: TextureFilter,
+    // 次のアップロードで使うオプション（`show` で表示倍率から決める）と、今のテクスチャのオプション
+    options: egui::TextureOptions,
+    uploaded_options: egui::TextureOptions,
+    // 表示アスペクト比を反映したサイズ（非正方ピクセルなら幅がテクセル数と異なる）
+    display_size: egui::Vec2,
+    // --max-texture-size による上限（バックエンドの上限より大きくはできない）
+    max_side_override: Option<usize>,
+    // 直近の縮小率（変化したときだけ警告を出す）
+    downscale_factor: usize,
+}
+
+impl FrameTexture {
+    pub fn new(max_side_override: Option<usize>) -> Self {
+        Self {
+            handle: None,
+            filter: TextureFilter::Auto,
+            options: egui::TextureOptions::LINEAR,
+            uploaded_options: egui::TextureOptions::LINEAR,
+            display_size: egui::Vec2::ZERO,
+            max_side_override,
+            downscale_factor: 1,
+        }
+    }
+
+    /// `--max-texture-size` と `--texture-filter` を反映して作る
+    pub fn from_args(args: &CliArgs) -> Self {
+        Self::new(args.max_texture_side).with_filter(args.texture_filter)
+    }
+
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self.options = filter.options_for(1.0);
+        self
+    }
+
+    /// 描画に使う最大テクスチャサイズ（一辺のピクセル数）
+    pub fn max_side(&self, ctx: &egui::Context) -> usize {
+        let backend_max = ctx.input(|i| i.max_texture_side);
+        match self.max_side_override {
+            Some(max) => max.min(backend_max),
+            None => backend_max,
+        }
+    }
+
+    /// 新しいフレームをアップロードする
+    ///
+    /// 一時停止中（[`Playback::Paused`]）は、まだ何も表示していなければ最初の1枚だけ受け取り、
+    /// それ以外は捨てる。
+    pub fn update(&mut self, ctx: &egui::Context, image: egui::ColorImage) {
+        if Playback::is_paused(ctx) && self.handle.is_some() {
+            return;
+        }
+        let max_side = self.max_side(ctx).max(1);
+        let [width, height] = image.size;
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.display_size = image.source_size;
+        let factor = width.max(height).div_ceil(max_side).max(1);
+
+        if factor != self.downscale_factor {
+            if factor > 1 {
+                warn!(
+                    "Frame {width}x{height} exceeds max texture size {max_side}; \
+                     downscaling by 1/{factor} before upload."
+                );
+            }
+            self.downscale_factor = factor;
+        }
+
+        let image = if factor > 1 {
+            // ColorImageは乗算済みアルファなので、そのまま平均して戻す
+            let (data, w, h) = downscale_rgba(image.as_raw(), width, height, factor);
+            egui::ColorImage::from_rgba_premultiplied([w, h], &data)
+        } else {
+            image
+        };
+
+        // ハンドルは使い回し、サイズが同じなら確保済みのテクスチャに上書きする。
+        // 全体の差し替え（`load_texture` / `set`）はバックエンドがGPUテクスチャを確保し直すが、
+        // 位置付きの部分更新は egui-wgpu なら既存テクスチャとバインドグループへの write_texture、
+        // glow なら tex_sub_image_2d だけで済む（4Kでは毎フレーム約33MBの確保がなくなる）。
+        // 部分更新ではサンプラーが変わらないので、フィルタを変えるときは確保し直す
+        let options = self.options;
+        match &mut self.handle {
+            Some(handle) if handle.size() == image.size && self.uploaded_options == options => {
+                handle.set_partial([0, 0], image, options)
+            }
+            Some(handle) => handle.set(image, options),
+            None => self.handle = Some(ctx.load_texture("ndi-frame", image, options)),
+        }
+        self.uploaded_options = options;
+    }
+
+    pub fn handle(&self) -> Option<&egui::TextureHandle> {
+        self.handle.as_ref()
+    }
+
+    /// 表示アスペクト比を反映したフレームのサイズ
+    pub fn display_size(&self) -> egui::Vec2 {
+        self.display_size
+    }
+
+    /// 利用可能な領域いっぱいに、アスペクト比を保って中央に描画する（レターボックス）
+    ///
+    /// 表示倍率に合ったフィルタがアップロード時と違えば、次の `update` で切り替える。
+    pub fn show(&mut self, ui: &mut egui::Ui) -> egui::Response {
+        let Some(handle) = &self.handle else {
+            return ui
+                .allocate_exact_size(ui.available_size(), egui::Sense::hover())
+                .1;
+        };
+        let [width, height] = handle.size();
+        let texels = egui::vec2(width as f32, height as f32);
+        let (response, scale) = paint_letterboxed(ui, handle.id(), texels, self.display_size);
+        self.options = self.filter.options_for(scale);
+        response
+    }
+
+    /// `show` が返した領域 `area` のうち、映像が描かれている範囲（まだテクスチャがなければ `None`）
+    pub fn image_rect(&self, ctx: &egui::Context, area: egui::Rect) -> Option<egui::Rect> {
+        self.handle.as_ref()?;
+        Some(letterbox_rect(ctx, area, self.display_size))
+    }
+}
+
+/// 映像を表示領域にどう収めるか（全ビューア共通、`A` で切り替える）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// アスペクト比を保って中央に収める（レターボックス）
+    #[default]
+    Fit,
+    /// アスペクト比を無視して表示領域いっぱいに引き伸ばす
+    Fill,
+}
+
+impl ScaleMode {
+    // egui::Context の一時データに置く（ビューアの構造体に持たせなくて済むように）
+    fn id() -> egui::Id {
+        egui::Id::new("viewer-scale-mode")
+    }
+
+    /// 今の収め方
+    pub fn get(ctx: &egui::Context) -> Self {
+        ctx.data(|d| d.get_temp(Self::id())).unwrap_or_default()
+    }
+
+    /// Fit と Fill を入れ替える
+    pub fn toggle(ctx: &egui::Context) {
+        let next = match Self::get(ctx) {
+            Self::Fit => Self::Fill,
+            Self::Fill => Self::Fit,
+        };
+        ctx.data_mut(|d| d.insert_temp(Self::id(), next));
+    }
+}
+
+/// 映像の透明な部分の後ろに何を描くか（全ビューア共通、`C` で切り替える）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlphaBackground {
+    /// 背景の黒がそのまま透ける
+    #[default]
+    Black,
+    /// 市松模様を敷く（透明な部分と黒い部分を見分けられる）
+    Checkerboard,
+}
+
+impl AlphaBackground {
+    fn id() -> egui::Id {
+        egui::Id::new("viewer-alpha-background")
+    }
+
+    /// 今の背景
+    pub fn get(ctx: &egui::Context) -> Self {
+        ctx.data(|d| d.get_temp(Self::id())).unwrap_or_default()
+    }
+
+    /// 背景を決める（キーで抜くビューアが最初から市松模様にするときなど）
+    pub fn set(ctx: &egui::Context, background: Self) {
+        ctx.data_mut(|d| d.insert_temp(Self::id(), background));
+    }
+
+    /// 黒と市松模様を入れ替える
+    pub fn toggle(ctx: &egui::Context) {
+        let next = match Self::get(ctx) {
+            Self::Black => Self::Checkerboard,
+            Self::Checkerboard => Self::Black,
+        };
+        Self::set(ctx, next);
+    }
+}
+
+/// 表示を止めているか（全ビューア共通、`Space` で切り替える）
+///
+/// 止めている間もビューアは受信スレッドからのフレームを取り出し続け（溜め込まない）、
+/// [`FrameTexture::update`] が差し替えずに捨てる。処理の重いビューアは取り出した後の処理も飛ばす。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Playback {
+    #[default]
+    Playing,
+    /// 今のフレームのまま止めている
+    Paused,
+}
+
+impl Playback {
+    fn id() -> egui::Id {
+        egui::Id::new("viewer-playback")
+    }
+
+    /// 今の状態
+    pub fn get(ctx: &egui::Context) -> Self {
+        ctx.data(|d| d.get_temp(Self::id())).unwrap_or_default()
+    }
+
+    /// 止めているか
+    pub fn is_paused(ctx: &egui::Context) -> bool {
+        Self::get(ctx) == Self::Paused
+    }
+
+    /// 止める・再開するを入れ替える
+    pub fn toggle(ctx: &egui::Context) {
+        let next = match Self::get(ctx) {
+            Self::Playing => Self::Paused,
+            Self::Paused => Self::Playing,
+        };
+        ctx.data_mut(|d| d.insert_temp(Self::id(), next));
+    }
+}
+
+// 一時停止中に映像の上端中央に出す表示
+fn paint_paused_badge(ui: &egui::Ui, image_rect: egui::Rect) {
+    let painter = ui.painter();
+    let galley = painter.layout_no_wrap(
+        "\u{23F8} Paused".to_string(),
+        egui::FontId::proportional(16.0),
+        egui::Color32::WHITE,
+    );
+    let padding = egui::vec2(8.0, 4.0);
+    let rect = egui::Rect::from_center_size(
+        egui::pos2(
+            image_rect.center().x,
+            image_rect.top() + 12.0 + galley.size().y / 2.0 + padding.y,
+        ),
+        galley.size() + padding * 2.0,
+    );
+    painter.rect_filled(rect, 4.0, egui::Color32::from_black_alpha(160));
+    painter.galley(rect.min + padding, galley, egui::Color32::WHITE);
+}
+
+// 市松模様の1マスの大きさ（ポイント）
+const CHECKER_SIZE: f32 = 8.0;
+
+// 市松模様を `rect` に敷く。模様は画像の左上に合わせるので、ウィンドウの大きさを変えても画像と一緒に動く
+fn paint_checkerboard(ui: &egui::Ui, rect: egui::Rect) {
+    let texture = checker_texture(ui.ctx());
+    // 2x2のテクスチャを繰り返すので、UVの1は2マス分
+    let uv = egui::Rect::from_min_size(egui::Pos2::ZERO, rect.size() / (2.0 * CHECKER_SIZE));
+    ui.painter()
+        .image(texture.id(), rect, uv, egui::Color32::WHITE);
+}
+
+// 2x2の市松模様のテクスチャ（最初の1回だけ作って egui::Context に置いておく）
+fn checker_texture(ctx: &egui::Context) -> egui::TextureHandle {
+    let id = egui::Id::new("viewer-checkerboard-texture");
+    if let Some(texture) = ctx.data(|d| d.get_temp::<egui::TextureHandle>(id)) {
+        return texture;
+    }
+    let light = egui::Color32::from_gray(204);
+    let dark = egui::Color32::from_gray(153);
+    let image = egui::ColorImage::new([2, 2], vec![light, dark, dark, light]);
+    let texture = ctx.load_texture("checkerboard", image, egui::TextureOptions::NEAREST_REPEAT);
+    ctx.data_mut(|d| d.insert_temp(id, texture.clone()));
+    texture
+}
+
+/// 表示領域 `area` のうち、アスペクト比 `display_size` の映像を描く範囲（[`ScaleMode`] に従う）
+///
+/// [`paint_letterboxed`] と同じ配置なので、マウス位置を映像の画素に戻すのに使える。
+pub fn letterbox_rect(
+    ctx: &egui::Context,
+    area: egui::Rect,
+    display_size: egui::Vec2,
+) -> egui::Rect {
+    let size = match ScaleMode::get(ctx) {
+        ScaleMode::Fit => fit_preserve_aspect(area.size(), display_size),
+        ScaleMode::Fill => area.size(),
+    };
+    egui::Rect::from_center_size(area.center(), size)
+}
+
+/// テクスチャ `id`（`texels` ピクセル）を、アスペクト比 `display_size` のまま利用可能な領域の中央に描く
+///
+/// [`ScaleMode::Fill`] のときはアスペクト比を無視して領域いっぱいに描く。
+/// [`AlphaBackground::Checkerboard`] のときは画像の後ろに市松模様を敷く。
+/// 戻り値の2つ目は、テクセル1つが画面上で何ピクセルになるか（縦横で小さい方）。
+pub fn paint_letterboxed(
+    ui: &mut egui::Ui,
+    id: egui::TextureId,
+    texels: egui::Vec2,
+    display_size: egui::Vec2,
+) -> (egui::Response, f32) {
+    let (rect, response) = ui.allocate_exact_size(ui.available_size(), egui::Sense::hover());
+    let image_rect = letterbox_rect(ui.ctx(), rect, display_size);
+    let scale = (image_rect.size() * ui.ctx().pixels_per_point() / texels).min_elem();
+    if AlphaBackground::get(ui.ctx()) == AlphaBackground::Checkerboard {
+        paint_checkerboard(ui, image_rect);
+    }
+    ui.painter().image(
+        id,
+        image_rect,
+        egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0)),
+        egui::Color32::WHITE,
+    );
+    if Playback::is_paused(ui.ctx()) {
+        paint_paused_badge(ui, image_rect);
+    }
+    (response, scale)
+}
+
+// 提示時刻のこれだけ前なら出してしまう（再描画タイミングの粒度の分）
+const PACING_TOLERANCE: Duration = Duration::from_millis(2);
+
+/// 届いたフレームを公称のフレーム間隔で提示するための1フレーム分のバッファ（`--smooth`）
+///
+/// ネットワーク到着のばらつきで早く来たフレームは、前の提示から1フレーム間隔経つまで持っておく。
+/// 遅延は最大1フレーム分。提示前に次のフレームが来たら、持っていた方をすぐ出す。
+pub struct FramePacer<T> {
+    pending: Option<T>,
+    // 提示リズムの基準（期限どおりに出したときは期限の時刻）
+    last_due: Option<Instant>,
+    // 実際に提示した時刻（ジッタ計測用）
+    last_actual: Option<Instant>,
+    jitter: Duration,
+}
+
+impl<T> Default for FramePacer<T> {
+    fn default() -> Self {
+        Self {
+            pending: None,
+            last_due: None,
+            last_actual: None,
+            jitter: Duration::ZERO,
+        }
+    }
+}
+
+impl<T> FramePacer<T> {
+    /// 新しいフレーム（あれば）を預け、今表示すべきフレームと、次に確認すべきまでの時間を返す
+    ///
+    /// `interval` はソースの公称フレーム間隔（不明なら0で、来たらすぐ出す）。
+    pub fn update(
+        &mut self,
+        frame: Option<T>,
+        now: Instant,
+        interval: Duration,
+    ) -> (Option<T>, Option<Duration>) {
+        if let Some(frame) = frame
+            && let Some(previous) = self.pending.replace(frame)
+        {
+            self.presented(now, now, interval);
+            return (Some(previous), Some(interval));
+        }
+        if self.pending.is_none() {
+            return (None, None);
+        }
+
+        let due = match self.last_due {
+            Some(last) if !interval.is_zero() => last + interval,
+            _ => now,
+        };
+        if now + PACING_TOLERANCE < due {
+            return (None, Some(due - now));
+        }
+        // 大きく遅れていたら今を基準にリズムを取り直す
+        let base = if now.saturating_duration_since(due) < interval / 2 {
+            due
+        } else {
+            now
+        };
+        self.presented(base, now, interval);
+        (self.pending.take(), None)
+    }
+
+    /// 提示間隔の公称値からのずれ（RFC 3550と同じく1/16で平滑化）
+    pub fn jitter(&self) -> Duration {
+        self.jitter
+    }
+
+    fn presented(&mut self, base: Instant, now: Instant, interval: Duration) {
+        if let Some(last) = self.last_actual {
+            let deviation = now.duration_since(last).abs_diff(interval);
+            self.jitter = if deviation > self.jitter {
+                self.jitter + (deviation - self.jitter) / 16
+            } else {
+                self.jitter - (self.jitter - deviation) / 16
+            };
+        }
+        self.last_due = Some(base);
+        self.last_actual = Some(now);
+    }
+}
+
+/// ショートカット一覧のオーバーレイ（`?` / F1 で開閉）
+#[derive(Default)]
+pub struct HelpOverlay {
+    visible: bool,
+    // そのビューアだけのショートカット（共通のものの後に並べる）
+    extra: &'static [Shortcut],
+}
+
+impl HelpOverlay {
+    /// [`SHORTCUTS`] に加えて、このビューアだけのショートカットも一覧に出す
+    pub fn with_extra(mut self, extra: &'static [Shortcut]) -> Self {
+        self.extra = extra;
+        self
+    }
+
+    /// 開閉キーを処理し、開いていれば一覧を描画する（毎フレーム呼ぶ）
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if SHORTCUT_HELP.pressed(ctx) {
+            self.visible = !self.visible;
+        }
+        if !self.visible {
+            return;
+        }
+
+        egui::Window::new("Keyboard shortcuts")
+            .open(&mut self.visible)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                egui::Grid::new("shortcuts")
+                    .striped(true)
+                    .spacing([24.0, 6.0])
+                    .show(ui, |ui| {
+                        for shortcut in SHORTCUTS.iter().chain(self.extra) {
+                            ui.monospace(shortcut.key_label());
+                            ui.label(shortcut.description);
+                            ui.end_row();
+                        }
+                    });
+            });
+    }
+}
+
+/// 接続中のソースをウィンドウタイトルと左上に表示する（毎フレーム呼ぶ）
+///
+/// 名前の部分一致や同名ソースの選び分けで、指定した名前と実際の接続先が
+/// 違うことがあるので、どこにつながっているかを常に見えるようにする。
+pub struct SourceOverlay {
+    app_title: &'static str,
+    // タイトルに反映済みのソース
+    shown: Option<Arc<String>>,
+}
+
+impl SourceOverlay {
+    /// `app_title` は `eframe::run_native` に渡したのと同じタイトル
+    pub fn new(app_title: &'static str) -> Self {
+        Self {
+            app_title,
+            shown: None,
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context, source: Option<Arc<String>>) {
+        if source != self.shown {
+            let title = match &source {
+                Some(source) => format!("{source} - {}", self.app_title),
+                None => self.app_title.to_string(),
+            };
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title));
+            self.shown = source;
+        }
+        let Some(source) = &self.shown else {
+            return;
+        };
+
+        egui::Area::new(egui::Id::new("source-overlay"))
+            .anchor(egui::Align2::LEFT_TOP, [12.0, 12.0])
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(source.as_str())
+                        .color(egui::Color32::WHITE)
+                        .background_color(egui::Color32::from_black_alpha(160)),
+                );
+            });
+    }
+}
+
+// トーストを出しておく時間
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+/// 下中央にしばらく出す一行の通知（スナップショットの保存結果など）
+///
+/// クローンしたものは同じ通知を共有するので、別スレッドから [`post`](Self::post) できる。
+#[derive(Clone, Default)]
+pub struct Toast {
+    message: Arc<ArcSwapOption<(String, Instant)>>,
+}
+
+impl Toast {
+    /// `message` を今から [`TOAST_DURATION`] の間出す（前の通知は置き換える）
+    pub fn post(&self, message: impl Into<String>) {
+        self.message
+            .store(Some(Arc::new((message.into(), Instant::now()))));
+    }
+
+    /// 毎フレーム呼ぶ。消える時刻に再描画を頼むので、映像が止まっていても消える
+    pub fn show(&self, ctx: &egui::Context) {
+        let Some(message) = self.message.load_full() else {
+            return;
+        };
+        let (text, posted) = &*message;
+        let Some(remaining) = TOAST_DURATION.checked_sub(posted.elapsed()) else {
+            self.message.store(None);
+            return;
+        };
+        ctx.request_repaint_after(remaining);
+
+        egui::Area::new(egui::Id::new("toast"))
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -24.0])
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(text)
+                        .color(egui::Color32::WHITE)
+                        .background_color(egui::Color32::from_black_alpha(160))
+                        .size(16.0),
+                );
+            });
+    }
+}
+
+/// タイムコード・ソース名・解像度を右下に出すHUD（`I` で開閉、既定は非表示）
+#[derive(Default)]
+pub struct InfoOverlay {
+    visible: bool,
+}
+
+impl InfoOverlay {
+    /// 開閉キーを処理し、開いていれば最後に届いたフレームの情報を描画する（毎フレーム呼ぶ）
+    pub fn show(&mut self, ctx: &egui::Context, source: Option<Arc<String>>, stats: &StreamStats) {
+        if !ctx.wants_keyboard_input() && SHORTCUT_INFO.pressed(ctx) {
+            self.visible = !self.visible;
+        }
+        if !self.visible {
+            return;
+        }
+
+        let [width, height] = stats.frame_size;
+        let text = if width > 0 && height > 0 {
+            format!(
+                "{}\n{}\n{width}x{height}",
+                format_timecode(stats.timecode),
+                source.as_deref().map_or("", String::as_str)
+            )
+        } else {
+            "No video".to_string()
+        };
+
+        let painter = ctx.layer_painter(egui::LayerId::new(
+            egui::Order::Foreground,
+            egui::Id::new("info-overlay"),
+        ));
+        let galley =
+            painter.layout_no_wrap(text, egui::FontId::monospace(16.0), egui::Color32::WHITE);
+        let margin = egui::vec2(12.0, 12.0);
+        let padding = egui::vec2(6.0, 4.0);
+        let max = ctx.content_rect().max - margin;
+        let rect = egui::Rect::from_min_max(max - galley.size() - padding * 2.0, max);
+        painter.rect_filled(rect, 4.0, egui::Color32::from_black_alpha(160));
+        painter.galley(rect.min + padding, galley, egui::Color32::WHITE);
+    }
+}
+
+/// 受信スレッドから最新フレームを `slot` に置き、GUIが前のフレームを取り出し済みだったときだけ再描画を頼む
+///
+/// 前のフレームがまだ残っていれば、それを置いたときに頼んだ再描画がまだ済んでいない。
+/// 表示が受信に追いつかないときに依頼を重ねず、フレームが来ない間は何も頼まない。
+/// 戻り値は再描画を頼んだか。
+pub fn store_latest<T>(slot: &ArcSwap<Option<T>>, frame: T, ctx: &egui::Context) -> bool {
+    let was_empty = slot.swap(Arc::new(Some(frame))).is_none();
+    if was_empty {
+        ctx.request_repaint();
+    }
+    was_empty
+}
+
+/// まだ映像が来ていない間の中央の表示
+///
+/// 音声/メタデータだけのソースにつながっているなら（[`StreamStats::no_video`]）、
+/// 映像は届くが表示できないなら（[`StreamStats::frame_error`]）その旨を出す。
+/// 映像が来ないと受信スレッドは再描画を頼まないので、状態の変化を拾えるよう定期的に描き直す。
+pub fn waiting_label(ui: &mut egui::Ui, stats: &StreamStats) {
+    let text = stats_message(stats)
+        .unwrap_or_else(|| format!("Waiting for NDI Source: {}...", TARGET_SOURCE_NAME));
+    centered_message(ui, text);
+}
+
+/// `waiting_label` の接続状態つき版（[`crate::ReceiverHandle::connection_state`]）
+///
+/// 「Waiting for NDI Source」の代わりに、探索中・映像待ち・再接続中・失敗（理由つき）を出す。
+/// 映像なし・表示できない映像の説明があればそちらを優先する。
+pub fn status_label(ui: &mut egui::Ui, state: &ConnectionState, stats: &StreamStats) {
+    let text = stats_message(stats).unwrap_or_else(|| state.to_string());
+    centered_message(ui, text);
+}
+
+// 受信統計から分かる、映像が出ない理由
+fn stats_message(stats: &StreamStats) -> Option<String> {
+    match (stats.frame_error, stats.no_video) {
+        (Some(frame_error), _) => Some(frame_error.to_string()),
+        (None, Some(no_video)) => Some(no_video.label().to_string()),
+        (None, None) => None,
+    }
+}
+
+fn centered_message(ui: &mut egui::Ui, text: String) {
+    ui.centered_and_justified(|ui| {
+        ui.label(
+            egui::RichText::new(text)
+                .color(egui::Color32::WHITE)
+                .size(32.0),
+        );
+    });
+    ui.ctx().request_repaint_after(Duration::from_secs(1));
+}
+
+/// 受信状態を右上に色付きの丸で表示する（ホバーで詳細）
+///
+/// まだフレームを受信していなければ何も描画しない。
+pub fn quality_indicator(ctx: &egui::Context, stats: &StreamStats) {
+    if stats.frame_interval.is_zero() {
+        return;
+    }
+    let quality = stats.quality();
+
+    egui::Area::new(egui::Id::new("quality-indicator"))
+        .anchor(egui::Align2::RIGHT_TOP, [-12.0, 12.0])
+        .show(ctx, |ui| {
+            let (rect, response) =
+                ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+            ui.painter().circle_filled(rect.center(), 7.0, quality.color());
+            let mut text = format!(
+                "Connection: {}\nReceived: {}, dropped: {} ({:.1}% recently)\n\
+                 Jitter: {:.1} ms (frame interval {:.1} ms)",
+                quality.label(),
+                stats.received,
+                stats.dropped,
+                stats.recent_drop_ratio * 100.0,
+                stats.jitter.as_secs_f64() * 1000.0,
+                stats.frame_interval.as_secs_f64() * 1000.0,
+            );
+            if !stats.frozen_for.is_zero() {
+                text.push_str(&format!(
+                    "\nSame picture for {:.1} s",
+                    stats.frozen_for.as_secs_f64()
+                ));
+            }
+            // 映像が途中で止まり、音声/メタデータだけになった場合
+            if let Some(no_video) = stats.no_video {
+                text.push_str(&format!("\n{}", no_video.label()));
+            }
+            if let Some(frame_error) = stats.frame_error {
+                text.push_str(&format!("\n{frame_error}"));
+            }
+            response.on_hover_text(text);
+        });
+}
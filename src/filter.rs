@@ -0,0 +1,1494 @@
+//! RGBAフレームに適用するCPUフィルタ群
+
+use crate::convert::{blend_rgba, downscale_rgba};
+use crate::ParamHandle;
+use anyhow::{Context, Result};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// パックされたRGBAバッファをその場で加工するフィルタ
+pub trait Filter: Send {
+    fn apply(&mut self, width: usize, height: usize, data: &mut [u8]);
+}
+
+/// ITU-R BT.601 輝度（固定小数点: (77*R + 150*G + 29*B) >> 8）
+#[inline]
+pub fn luma_bt601(r: u8, g: u8, b: u8) -> u8 {
+    ((77 * r as u32 + 150 * g as u32 + 29 * b as u32) >> 8) as u8
+}
+
+/// `data` を1行（`row_len` 要素）ずつ `f(y, row)` で処理する
+///
+/// `rayon` feature が有効なら行単位で並列化する。行同士は独立なので結果は直列と同じ。
+pub(crate) fn for_each_row<T, F>(data: &mut [T], row_len: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Send + Sync,
+{
+    if row_len == 0 {
+        return;
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        data.par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row));
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    for (y, row) in data.chunks_mut(row_len).enumerate() {
+        f(y, row);
+    }
+}
+
+/// グレースケール変換（ITU-R BT.601）
+#[derive(Default)]
+pub struct Grayscale;
+
+impl Filter for Grayscale {
+    fn apply(&mut self, width: usize, _height: usize, data: &mut [u8]) {
+        for_each_row(data, width * 4, |_, row| {
+            for chunk in row.chunks_exact_mut(4) {
+                let gray = luma_bt601(chunk[0], chunk[1], chunk[2]);
+
+                // R, G, B を gray 値に置換
+                chunk[0] = gray;
+                chunk[1] = gray;
+                chunk[2] = gray;
+                // Alpha は元のまま（chunk[3]）
+            }
+        });
+    }
+}
+
+// 1次元カーネルの重みの合計（11bit固定小数点）。2パス後は最大 255 << 22 でu32に収まる
+const GAUSSIAN_KERNEL_SHIFT: u32 = 11;
+
+/// ガウシアンブラー（横→縦の2パス、境界はクランプ）
+///
+/// 1次元カーネルは `sigma` から作り（半径は `ceil(3*sigma)`）、変わったときだけ作り直す。
+/// 横パスの結果は丸めずにu32で持つので、2次元カーネルで畳み込むのと同じ値になる。
+/// `sigma` はGUIから [`GaussianBlur::sigma`] のハンドルで変えられ、次のフレームの `apply` で反映する。
+pub struct GaussianBlur {
+    sigma: ParamHandle<f32>,
+    // 今のカーネルを作ったときのsigmaと、合計が 1 << GAUSSIAN_KERNEL_SHIFT の重み（長さ 2*radius+1）
+    kernel_sigma: f32,
+    kernel: Vec<u32>,
+    // 横パスの結果（フレームごとの確保を避けるため使い回す）
+    horizontal: Vec<u32>,
+}
+
+impl GaussianBlur {
+    pub const MIN_SIGMA: f32 = 0.5;
+    pub const MAX_SIGMA: f32 = 10.0;
+    /// 既定は従来の5x5（二項係数 1,4,6,4,1）とほぼ同じぼけ方
+    pub const DEFAULT_SIGMA: f32 = 1.0;
+
+    pub fn new(sigma: f32) -> Self {
+        Self {
+            sigma: ParamHandle::new(sigma),
+            kernel_sigma: f32::NAN,
+            kernel: Vec::new(),
+            horizontal: Vec::new(),
+        }
+    }
+
+    /// 実行中にぼかしの強さを変えるためのハンドル
+    ///
+    /// 範囲外の値は [`MIN_SIGMA`](Self::MIN_SIGMA)..=[`MAX_SIGMA`](Self::MAX_SIGMA) に丸めて使う。
+    pub fn sigma(&self) -> ParamHandle<f32> {
+        self.sigma.clone()
+    }
+
+    /// `sigma` に対するカーネルの半径（画素）
+    pub fn radius_for(sigma: f32) -> usize {
+        (3.0 * sigma).ceil().max(1.0) as usize
+    }
+
+    // sigmaが変わっていればカーネルを作り直す
+    fn update_kernel(&mut self) {
+        let sigma = match self.sigma.get() {
+            sigma if sigma.is_nan() => Self::DEFAULT_SIGMA,
+            sigma => sigma.clamp(Self::MIN_SIGMA, Self::MAX_SIGMA),
+        };
+        if sigma == self.kernel_sigma {
+            return;
+        }
+        let radius = Self::radius_for(sigma) as isize;
+        let weights: Vec<f64> = (-radius..=radius)
+            .map(|x| (-((x * x) as f64) / (2.0 * sigma as f64 * sigma as f64)).exp())
+            .collect();
+        let total: f64 = weights.iter().sum();
+        let scale = (1u32 << GAUSSIAN_KERNEL_SHIFT) as f64 / total;
+        self.kernel = weights.iter().map(|w| (w * scale).round() as u32).collect();
+
+        // 丸めで合計がずれた分は中央の重みで吸収する（明るさが変わらないように）
+        let sum: u32 = self.kernel.iter().sum();
+        let center = &mut self.kernel[radius as usize];
+        *center = (*center + (1 << GAUSSIAN_KERNEL_SHIFT)) - sum;
+        self.kernel_sigma = sigma;
+    }
+}
+
+impl Default for GaussianBlur {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SIGMA)
+    }
+}
+
+impl Filter for GaussianBlur {
+    fn apply(&mut self, width: usize, height: usize, data: &mut [u8]) {
+        // 幅・高さが1や2でもクランプで端の画素を繰り返すだけなので問題ない。
+        // 空のフレームやサイズが合わないバッファは触らない
+        if width == 0 || height == 0 || data.len() < width * height * 4 {
+            return;
+        }
+        self.update_kernel();
+        let data = &mut data[..width * height * 4];
+        let kernel = &self.kernel;
+        let radius = kernel.len() / 2;
+
+        // 横方向（丸めずにu32で持つ）
+        self.horizontal.resize(data.len(), 0);
+        let source = &*data;
+        for_each_row(&mut self.horizontal, width * 4, |y, row| {
+            let src_row = &source[y * width * 4..(y + 1) * width * 4];
+
+            // 内側は窓がはみ出さないので、タップごとにずらした行をまとめて足し込む
+            // （幅がカーネルより狭ければ内側はない）
+            let inner_end = width.saturating_sub(radius);
+            let inner = radius.min(inner_end)..inner_end;
+            if !inner.is_empty() {
+                let inner_row = &mut row[inner.start * 4..inner.end * 4];
+                inner_row.fill(0);
+                for (k, &weight) in kernel.iter().enumerate() {
+                    let shifted = &src_row[k * 4..k * 4 + inner_row.len()];
+                    for (sum, value) in inner_row.iter_mut().zip(shifted) {
+                        *sum += *value as u32 * weight;
+                    }
+                }
+            }
+
+            // 端の画素は窓がはみ出すので、境界処理: クランプ
+            for x in (0..inner.start).chain(inner.end..width) {
+                let mut sums = [0u32; 4];
+                for (k, &weight) in kernel.iter().enumerate() {
+                    let px = (x + k).saturating_sub(radius).min(width - 1);
+                    for (sum, value) in sums.iter_mut().zip(&src_row[px * 4..px * 4 + 4]) {
+                        *sum += *value as u32 * weight;
+                    }
+                }
+                row[x * 4..x * 4 + 4].copy_from_slice(&sums);
+            }
+        });
+
+        // 縦方向（2パス分の固定小数点を戻して四捨五入）。
+        // 行の一部ずつ、タップごとに同じ列の並びを足し込む（連続したメモリを読むので速い）
+        let horizontal = &self.horizontal;
+        let shift = 2 * GAUSSIAN_KERNEL_SHIFT;
+        for_each_row(data, width * 4, |y, row| {
+            const CHUNK: usize = 64;
+            for (chunk_index, out) in row.chunks_mut(CHUNK).enumerate() {
+                let start = chunk_index * CHUNK;
+                let mut sums = [0u32; CHUNK];
+                let sums = &mut sums[..out.len()];
+                for (k, &weight) in kernel.iter().enumerate() {
+                    // 境界処理: クランプ
+                    let py = (y + k).saturating_sub(radius).min(height - 1);
+                    let offset = py * width * 4 + start;
+                    let column = &horizontal[offset..offset + out.len()];
+                    for (sum, value) in sums.iter_mut().zip(column) {
+                        *sum += value * weight;
+                    }
+                }
+                for (out, sum) in out.iter_mut().zip(sums.iter()) {
+                    *out = ((sum + (1 << (shift - 1))) >> shift) as u8;
+                }
+            }
+        });
+    }
+}
+
+/// `width*4` で詰めたRGBAを1回だけガウシアンでぼかす（アルファもぼかす）
+///
+/// 呼ぶたびにカーネルと作業バッファを作るので、フレームごとに使うなら [`GaussianBlur`] を持っておく。
+pub fn gaussian_blur_rgba(width: usize, height: usize, data: &mut [u8], sigma: f32) {
+    GaussianBlur::new(sigma).apply(width, height, data);
+}
+
+/// 半径を指定できるボックスブラー（横→縦の2パス、境界はクランプ）
+pub struct BoxBlur {
+    radius: usize,
+    // 各パスの入力のコピー
+    source: Vec<u8>,
+}
+
+impl BoxBlur {
+    /// `radius` 画素（窓の幅は `2*radius+1`）。0なら何もしない
+    pub fn new(radius: usize) -> Self {
+        Self {
+            radius,
+            source: Vec::new(),
+        }
+    }
+}
+
+impl Filter for BoxBlur {
+    fn apply(&mut self, width: usize, height: usize, data: &mut [u8]) {
+        if self.radius == 0 || width == 0 || height == 0 || data.len() < width * height * 4 {
+            return;
+        }
+        let data = &mut data[..width * height * 4];
+        let radius = self.radius;
+        let taps = 2 * radius + 1;
+
+        // 横方向
+        self.source.clear();
+        self.source.extend_from_slice(data);
+        let source = &self.source;
+        for_each_row(data, width * 4, |y, row| {
+            let src_row = &source[y * width * 4..(y + 1) * width * 4];
+            for x in 0..width {
+                let mut sums = [0u32; 4];
+                for k in 0..taps {
+                    let px = (x + k).saturating_sub(radius).min(width - 1);
+                    for (sum, value) in sums.iter_mut().zip(&src_row[px * 4..px * 4 + 4]) {
+                        *sum += *value as u32;
+                    }
+                }
+                for (out, sum) in row[x * 4..x * 4 + 4].iter_mut().zip(sums) {
+                    *out = ((sum + taps as u32 / 2) / taps as u32) as u8;
+                }
+            }
+        });
+
+        // 縦方向
+        self.source.clear();
+        self.source.extend_from_slice(data);
+        let source = &self.source;
+        for_each_row(data, width * 4, |y, row| {
+            for (i, out) in row.iter_mut().enumerate() {
+                let mut sum = 0u32;
+                for k in 0..taps {
+                    let py = (y + k).saturating_sub(radius).min(height - 1);
+                    sum += source[py * width * 4 + i] as u32;
+                }
+                *out = ((sum + taps as u32 / 2) / taps as u32) as u8;
+            }
+        });
+    }
+}
+
+/// ウォーターマークを置く角（`--watermark-corner`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "top-left" => Some(Self::TopLeft),
+            "top-right" => Some(Self::TopRight),
+            "bottom-left" => Some(Self::BottomLeft),
+            "bottom-right" => Some(Self::BottomRight),
+            _ => None,
+        }
+    }
+}
+
+// フレームの端からロゴまでの余白（フレームが小さければ詰める）
+const WATERMARK_MARGIN: usize = 16;
+
+/// PNGのロゴを角に半透明で重ねるフィルタ（`--watermark`）
+///
+/// フレームより大きいロゴは収まるまで整数分の1に縮小する。
+pub struct Watermark {
+    rgba: Vec<u8>,
+    width: usize,
+    height: usize,
+    corner: Corner,
+    opacity: f32,
+    // 縮小済みのロゴ（フレームサイズ, data, width）。フレームサイズが変わったら作り直す
+    scaled: Option<((usize, usize), Vec<u8>, usize)>,
+}
+
+impl Watermark {
+    /// `--watermark-opacity` の既定値
+    pub const DEFAULT_OPACITY: f32 = 0.5;
+
+    /// PNGを読み込む（パレット・グレースケール・16bitも8bit RGBAにそろえる）
+    pub fn load(path: &Path, corner: Corner, opacity: f32) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size().context("PNG is too large")?];
+        let info = reader.next_frame(&mut buf)?;
+        let pixels = &buf[..info.buffer_size()];
+
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => pixels.to_vec(),
+            png::ColorType::Rgb => pixels
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => pixels
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+            png::ColorType::Indexed => anyhow::bail!("Indexed PNG was not expanded"),
+        };
+
+        Ok(Self {
+            rgba,
+            width: info.width as usize,
+            height: info.height as usize,
+            corner,
+            opacity,
+            scaled: None,
+        })
+    }
+}
+
+impl Filter for Watermark {
+    fn apply(&mut self, width: usize, height: usize, data: &mut [u8]) {
+        if width == 0 || height == 0 || self.width == 0 || self.height == 0 {
+            return;
+        }
+
+        let factor = self.width.div_ceil(width).max(self.height.div_ceil(height));
+        let (logo, logo_width) = if factor > 1 {
+            if self
+                .scaled
+                .as_ref()
+                .is_none_or(|(size, ..)| *size != (width, height))
+            {
+                let (scaled, w, _) = downscale_rgba(&self.rgba, self.width, self.height, factor);
+                self.scaled = Some(((width, height), scaled, w));
+            }
+            let (_, scaled, w) = self.scaled.as_ref().expect("set above");
+            (scaled.as_slice(), *w)
+        } else {
+            (self.rgba.as_slice(), self.width)
+        };
+        let logo_height = logo.len() / (logo_width * 4);
+
+        let margin_x = WATERMARK_MARGIN.min((width - logo_width) / 2);
+        let margin_y = WATERMARK_MARGIN.min((height - logo_height) / 2);
+        let x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => margin_x,
+            Corner::TopRight | Corner::BottomRight => width - logo_width - margin_x,
+        };
+        let y = match self.corner {
+            Corner::TopLeft | Corner::TopRight => margin_y,
+            Corner::BottomLeft | Corner::BottomRight => height - logo_height - margin_y,
+        };
+        blend_rgba(data, width, logo, logo_width, x, y, self.opacity);
+    }
+}
+
+/// `--filter` で選べるフィルタの種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    FalseColor,
+    LumaKey,
+    Sharpen,
+}
+
+impl FilterKind {
+    pub const ALL: &[FilterKind] = &[Self::FalseColor, Self::LumaKey, Self::Sharpen];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// `--filter` に渡す名前
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FalseColor => "false-color",
+            Self::LumaKey => "luma-key",
+            Self::Sharpen => "sharpen",
+        }
+    }
+}
+
+/// フォルスカラーの輝度しきい値（0-255の8bit輝度）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FalseColorThresholds {
+    /// この値以下は黒つぶれ（紫）
+    pub crushed: u8,
+    /// 中間調（緑）の下限
+    pub mid_low: u8,
+    /// 中間調（緑）の上限
+    pub mid_high: u8,
+    /// この値以上は白飛び（赤）
+    pub clipped: u8,
+}
+
+impl Default for FalseColorThresholds {
+    fn default() -> Self {
+        // 約2% / 18%グレー付近 / 約98%
+        Self {
+            crushed: 5,
+            mid_low: 100,
+            mid_high: 130,
+            clipped: 250,
+        }
+    }
+}
+
+impl FalseColorThresholds {
+    /// "crushed,mid_low,mid_high,clipped" 形式をパースする
+    pub fn parse(s: &str) -> Option<Self> {
+        let values: Vec<u8> = s
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let [crushed, mid_low, mid_high, clipped] = values[..] else {
+            return None;
+        };
+        if crushed < mid_low && mid_low <= mid_high && mid_high < clipped {
+            Some(Self {
+                crushed,
+                mid_low,
+                mid_high,
+                clipped,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for FalseColorThresholds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.crushed, self.mid_low, self.mid_high, self.clipped
+        )
+    }
+}
+
+// 色覚多様性に配慮した Okabe-Ito パレット
+const FALSE_COLOR_CRUSHED: [u8; 3] = [0xCC, 0x79, 0xA7]; // reddish purple
+const FALSE_COLOR_MID: [u8; 3] = [0x00, 0x9E, 0x73]; // bluish green
+const FALSE_COLOR_CLIPPED: [u8; 3] = [0xD5, 0x5E, 0x00]; // vermillion
+
+/// 露出確認用のフォルスカラー表示
+///
+/// しきい値外の画素は輝度のグレースケールで表示する。
+pub struct FalseColor {
+    lut: [[u8; 3]; 256],
+}
+
+impl FalseColor {
+    pub fn new(thresholds: FalseColorThresholds) -> Self {
+        let mut lut = [[0u8; 3]; 256];
+        for (luma, entry) in lut.iter_mut().enumerate() {
+            let luma = luma as u8;
+            *entry = if luma <= thresholds.crushed {
+                FALSE_COLOR_CRUSHED
+            } else if luma >= thresholds.clipped {
+                FALSE_COLOR_CLIPPED
+            } else if (thresholds.mid_low..=thresholds.mid_high).contains(&luma) {
+                FALSE_COLOR_MID
+            } else {
+                [luma; 3]
+            };
+        }
+        Self { lut }
+    }
+}
+
+impl Default for FalseColor {
+    fn default() -> Self {
+        Self::new(FalseColorThresholds::default())
+    }
+}
+
+impl Filter for FalseColor {
+    fn apply(&mut self, width: usize, _height: usize, data: &mut [u8]) {
+        let lut = &self.lut;
+        for_each_row(data, width * 4, |_, row| {
+            for chunk in row.chunks_exact_mut(4) {
+                let luma = luma_bt601(chunk[0], chunk[1], chunk[2]);
+                chunk[..3].copy_from_slice(&lut[luma as usize]);
+                // Alpha は元のまま（chunk[3]）
+            }
+        });
+    }
+}
+
+/// ルミナンスキーの設定（`--luma-key`, `--luma-key-above`, `--luma-key-background`）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LumaKeySettings {
+    /// 抜く境目の8bit輝度
+    pub threshold: u8,
+    /// しきい値を中心にアルファを直線的に変化させる幅（0なら硬いエッジ）
+    pub softness: u8,
+    /// trueならしきい値より明るい側を抜く（既定は暗い側）
+    pub key_above: bool,
+    /// 抜いた後に合成する背景色（`None` ならアルファを残したまま）
+    pub background: Option<[u8; 3]>,
+}
+
+impl Default for LumaKeySettings {
+    fn default() -> Self {
+        // 黒背景の炎・煙素材向け。背景はキーの抜け具合が見える中間グレー
+        Self {
+            threshold: 16,
+            softness: 8,
+            key_above: false,
+            background: Some([0x80; 3]),
+        }
+    }
+}
+
+impl LumaKeySettings {
+    /// "threshold,softness" 形式をパースして、それ以外の設定は残したまま差し替える
+    pub fn parse(self, s: &str) -> Option<Self> {
+        let values: Vec<u8> = s
+            .split(',')
+            .map(|v| v.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        let [threshold, softness] = values[..] else {
+            return None;
+        };
+        Some(Self {
+            threshold,
+            softness,
+            ..self
+        })
+    }
+
+    /// "rrggbb"（`#` は省略可）か "none" をパースする
+    pub fn parse_background(s: &str) -> Option<Option<[u8; 3]>> {
+        if s == "none" {
+            return Some(None);
+        }
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Some([channel(0)?, channel(2)?, channel(4)?]))
+    }
+}
+
+/// 輝度がしきい値より低い（`key_above` なら高い）画素を透明にするルミナンスキー
+///
+/// しきい値 ± softness/2 の範囲でアルファを直線的に変化させ、元のアルファに掛ける。
+/// 背景色があればその上に合成して不透明にする。
+pub struct LumaKey {
+    // 輝度ごとのキーのアルファ（0 = 完全に抜く）
+    lut: [u8; 256],
+    background: Option<[u8; 3]>,
+}
+
+impl LumaKey {
+    pub fn new(settings: LumaKeySettings) -> Self {
+        let low = settings.threshold as i32 - settings.softness as i32 / 2;
+        let mut lut = [0u8; 256];
+        for (luma, alpha) in lut.iter_mut().enumerate() {
+            let luma = luma as i32;
+            let keep = if settings.softness == 0 {
+                if luma < settings.threshold as i32 {
+                    0
+                } else {
+                    255
+                }
+            } else {
+                ((luma - low) * 255 / settings.softness as i32).clamp(0, 255)
+            };
+            *alpha = if settings.key_above { 255 - keep } else { keep } as u8;
+        }
+        Self {
+            lut,
+            background: settings.background,
+        }
+    }
+
+    /// 輝度に対するキーのアルファ（0 = 完全に抜く、255 = そのまま）
+    pub fn alpha(&self, luma: u8) -> u8 {
+        self.lut[luma as usize]
+    }
+}
+
+impl Default for LumaKey {
+    fn default() -> Self {
+        Self::new(LumaKeySettings::default())
+    }
+}
+
+impl Filter for LumaKey {
+    fn apply(&mut self, width: usize, _height: usize, data: &mut [u8]) {
+        let lut = &self.lut;
+        let background = self.background;
+        for_each_row(data, width * 4, |_, row| {
+            for chunk in row.chunks_exact_mut(4) {
+                let key = lut[luma_bt601(chunk[0], chunk[1], chunk[2]) as usize] as u32;
+                let alpha = (chunk[3] as u32 * key + 127) / 255;
+                match background {
+                    Some(background) => {
+                        for c in 0..3 {
+                            let blended =
+                                chunk[c] as u32 * alpha + background[c] as u32 * (255 - alpha);
+                            chunk[c] = ((blended + 127) / 255) as u8;
+                        }
+                        chunk[3] = 255;
+                    }
+                    None => chunk[3] = alpha as u8,
+                }
+            }
+        });
+    }
+}
+
+/// アンシャープマスクの設定（`--sharpen-amount`, `--sharpen-radius`）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SharpenSettings {
+    /// 元の画とぼかした画の差を足す倍率（0なら何もしない）
+    pub amount: f32,
+    /// ぼかしの半径（画素）
+    pub radius: usize,
+}
+
+impl Default for SharpenSettings {
+    fn default() -> Self {
+        // 5x5ガウシアンと同じ広さの窓で、差を等倍で足す
+        Self {
+            amount: 1.0,
+            radius: 2,
+        }
+    }
+}
+
+impl SharpenSettings {
+    /// 強さを 0..=[`UnsharpMask::MAX_AMOUNT`]（NaNは0）、半径を
+    /// [`UnsharpMask::MIN_RADIUS`]..=[`UnsharpMask::MAX_RADIUS`] に丸める
+    pub fn clamped(self) -> Self {
+        Self {
+            amount: if self.amount.is_finite() {
+                self.amount.clamp(0.0, UnsharpMask::MAX_AMOUNT)
+            } else {
+                0.0
+            },
+            radius: self
+                .radius
+                .clamp(UnsharpMask::MIN_RADIUS, UnsharpMask::MAX_RADIUS),
+        }
+    }
+}
+
+/// アンシャープマスク: `out = in + amount * (in - blurred)`（0-255にクランプ）
+///
+/// ぼかしは [`GaussianBlur`]（`sigma = radius / 3`、つまり窓の半径がちょうど `radius`）。アルファは元のまま。
+/// `--filter sharpen` と sharpen-cpu のどちらもこれを使い、強さと半径は [`settings`](Self::settings)
+/// のハンドルから実行中に変えられる。
+pub struct UnsharpMask {
+    settings: ParamHandle<SharpenSettings>,
+    // ぼかしのsigmaを合わせてある半径（変わったときだけ設定し直す）
+    radius: usize,
+    blur: GaussianBlur,
+    blurred: Vec<u8>,
+}
+
+impl UnsharpMask {
+    pub const MAX_AMOUNT: f32 = 5.0;
+    /// `GaussianBlur::MIN_SIGMA` の窓の半径
+    pub const MIN_RADIUS: usize = 2;
+    /// `GaussianBlur::MAX_SIGMA` の窓の半径
+    pub const MAX_RADIUS: usize = 30;
+
+    /// 強さと半径は適用のたびに [`SharpenSettings::clamped`] で丸める
+    pub fn new(settings: SharpenSettings) -> Self {
+        Self {
+            settings: ParamHandle::new(settings),
+            radius: 0,
+            blur: GaussianBlur::default(),
+            blurred: Vec::new(),
+        }
+    }
+
+    /// 実行中に強さと半径を変えるためのハンドル
+    pub fn settings(&self) -> ParamHandle<SharpenSettings> {
+        self.settings.clone()
+    }
+}
+
+impl Default for UnsharpMask {
+    fn default() -> Self {
+        Self::new(SharpenSettings::default())
+    }
+}
+
+impl Filter for UnsharpMask {
+    fn apply(&mut self, width: usize, height: usize, data: &mut [u8]) {
+        let settings = self.settings.get().clamped();
+        let amount = (settings.amount * 256.0).round() as i32;
+        if amount == 0 || width == 0 || height == 0 || data.len() < width * height * 4 {
+            return;
+        }
+        if settings.radius != self.radius {
+            self.blur.sigma.store(settings.radius as f32 / 3.0);
+            self.radius = settings.radius;
+        }
+        self.blurred.clear();
+        self.blurred.extend_from_slice(&data[..width * height * 4]);
+        self.blur.apply(width, height, &mut self.blurred);
+        add_detail(width, height, data, &self.blurred, amount);
+    }
+}
+
+// `data += amount * (data - blurred)`（amountは8bit固定小数点、i32で計算して0-255にクランプ）
+fn add_detail(width: usize, height: usize, data: &mut [u8], blurred: &[u8], amount: i32) {
+    for_each_row(&mut data[..width * height * 4], width * 4, |y, row| {
+        let blurred_row = &blurred[y * width * 4..(y + 1) * width * 4];
+        for (chunk, blurred) in row.chunks_exact_mut(4).zip(blurred_row.chunks_exact(4)) {
+            for c in 0..3 {
+                let value = chunk[c] as i32;
+                let detail = (value - blurred[c] as i32) * amount;
+                chunk[c] = (value + (detail + 128).div_euclid(256)).clamp(0, 255) as u8;
+            }
+            // Alpha は元のまま（chunk[3]）
+        }
+    });
+}
+
+// Sobelの勾配の大きさの理論上の最大値（縦か横の勾配がどちらも 4*255 のとき）
+const SOBEL_MAX_MAGNITUDE: f32 = 4.0 * 255.0 * std::f32::consts::SQRT_2;
+
+/// Sobelフィルタによるエッジ検出（sobel-wgpu のCPUフォールバック、sobel.wgsl と同じ計算）
+///
+/// BT.601 の輝度に3x3のSobelカーネルをかけて横・縦の勾配 `gx`, `gy` を求め、大きさ
+/// `sqrt(gx² + gy²)` を理論上の最大値 `4 * 255 * √2` で割って 0-255 のグレーにする。
+/// 端ははみ出した分を一番近い画素で埋める（クランプ）。アルファは255。
+#[derive(Default)]
+pub struct Sobel {
+    luma: Vec<u8>,
+}
+
+impl Filter for Sobel {
+    fn apply(&mut self, width: usize, height: usize, data: &mut [u8]) {
+        let len = width * height * 4;
+        if width == 0 || height == 0 || data.len() < len {
+            return;
+        }
+        // 近傍を読むので、書き換える前に輝度だけ取っておく
+        self.luma.clear();
+        self.luma.extend(
+            data[..len]
+                .chunks_exact(4)
+                .map(|p| luma_bt601(p[0], p[1], p[2])),
+        );
+        let luma = &self.luma;
+        for_each_row(&mut data[..len], width * 4, |y, row| {
+            let rows = [y.saturating_sub(1), y, (y + 1).min(height - 1)]
+                .map(|y| &luma[y * width..(y + 1) * width]);
+            for (x, chunk) in row.chunks_exact_mut(4).enumerate() {
+                let xs = [x.saturating_sub(1), x, (x + 1).min(width - 1)];
+                let l = |row: usize, column: usize| rows[row][xs[column]] as i32;
+                let gx = (l(0, 2) + 2 * l(1, 2) + l(2, 2)) - (l(0, 0) + 2 * l(1, 0) + l(2, 0));
+                let gy = (l(2, 0) + 2 * l(2, 1) + l(2, 2)) - (l(0, 0) + 2 * l(0, 1) + l(0, 2));
+                let magnitude = ((gx * gx + gy * gy) as f32).sqrt();
+                let value = (magnitude / SOBEL_MAX_MAGNITUDE * 255.0).round() as u8;
+                chunk[..3].fill(value);
+                chunk[3] = 255;
+            }
+        });
+    }
+}
+
+/// 1チャンネル分の明るさ・コントラスト・ガンマ（colorcorrect-cpu）
+///
+/// 0-1に正規化した値 `v` を、コントラストで中間の灰色（0.5）を中心に伸ばし、明るさを足し、
+/// 0-1に丸めてから `v^(1/gamma)` にする。既定値では元のまま。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelGrade {
+    /// -1..=1（フルスケールに対する加算量）
+    pub brightness: f32,
+    /// 0..=3（1で元のまま）
+    pub contrast: f32,
+    /// 0.2..=5（1で元のまま、大きいほど中間調が明るい）
+    pub gamma: f32,
+}
+
+impl Default for ChannelGrade {
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl ChannelGrade {
+    pub const BRIGHTNESS_RANGE: RangeInclusive<f32> = -1.0..=1.0;
+    pub const CONTRAST_RANGE: RangeInclusive<f32> = 0.0..=3.0;
+    pub const GAMMA_RANGE: RangeInclusive<f32> = 0.2..=5.0;
+
+    /// 各値を範囲内に丸める（NaNは既定値）
+    pub fn clamped(self) -> Self {
+        let clamp = |value: f32, range: RangeInclusive<f32>, default: f32| {
+            if value.is_nan() {
+                default
+            } else {
+                value.clamp(*range.start(), *range.end())
+            }
+        };
+        let default = Self::default();
+        Self {
+            brightness: clamp(self.brightness, Self::BRIGHTNESS_RANGE, default.brightness),
+            contrast: clamp(self.contrast, Self::CONTRAST_RANGE, default.contrast),
+            gamma: clamp(self.gamma, Self::GAMMA_RANGE, default.gamma),
+        }
+    }
+
+    /// 入力の0-255それぞれに対する出力の表
+    pub fn lut(&self) -> [u8; 256] {
+        let Self {
+            brightness,
+            contrast,
+            gamma,
+        } = self.clamped();
+        std::array::from_fn(|i| {
+            let v = (i as f32 / 255.0 - 0.5) * contrast + 0.5 + brightness;
+            (v.clamp(0.0, 1.0).powf(1.0 / gamma) * 255.0).round() as u8
+        })
+    }
+}
+
+/// R・G・Bそれぞれの [`ChannelGrade`]（colorcorrect-cpu、[`ColorCorrect::settings`] で実行中に変えられる）
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ColorCorrectSettings {
+    /// 0 = R、1 = G、2 = B
+    pub grades: [ChannelGrade; 3],
+}
+
+impl ColorCorrectSettings {
+    /// 3チャンネルとも同じ値
+    pub fn uniform(grade: ChannelGrade) -> Self {
+        Self { grades: [grade; 3] }
+    }
+
+    /// 各チャンネルの値を範囲内に丸める（NaNは既定値）
+    pub fn clamped(self) -> Self {
+        Self {
+            grades: self.grades.map(ChannelGrade::clamped),
+        }
+    }
+}
+
+/// チャンネルごとのルックアップテーブルで明るさ・コントラスト・ガンマを調整する（アルファは元のまま）
+///
+/// 表は [`ColorCorrectSettings`] の値が変わったフレームでだけ作り直す。
+pub struct ColorCorrect {
+    settings: ParamHandle<ColorCorrectSettings>,
+    // 今の表を作ったときの値
+    lut_settings: Option<ColorCorrectSettings>,
+    luts: [[u8; 256]; 3],
+}
+
+impl ColorCorrect {
+    pub fn new(settings: ColorCorrectSettings) -> Self {
+        Self {
+            settings: ParamHandle::new(settings),
+            lut_settings: None,
+            luts: [[0; 256]; 3],
+        }
+    }
+
+    /// 実行中に値を変えるためのハンドル（範囲外の値は適用するときに丸める）
+    pub fn settings(&self) -> ParamHandle<ColorCorrectSettings> {
+        self.settings.clone()
+    }
+}
+
+impl Default for ColorCorrect {
+    fn default() -> Self {
+        Self::new(ColorCorrectSettings::default())
+    }
+}
+
+impl Filter for ColorCorrect {
+    fn apply(&mut self, width: usize, _height: usize, data: &mut [u8]) {
+        let settings = self.settings.get().clamped();
+        if self.lut_settings != Some(settings) {
+            self.luts = settings.grades.map(|grade| grade.lut());
+            self.lut_settings = Some(settings);
+        }
+        let [r, g, b] = &self.luts;
+        for_each_row(data, width * 4, |_, row| {
+            for chunk in row.chunks_exact_mut(4) {
+                chunk[0] = r[chunk[0] as usize];
+                chunk[1] = g[chunk[1] as usize];
+                chunk[2] = b[chunk[2] as usize];
+            }
+        });
+    }
+}
+
+/// クロマキーの設定（chromakey-cpu）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChromaKeySettings {
+    /// 抜く色（RGB）。使うのは色相だけ
+    pub key_color: [u8; 3],
+    /// 抜く色の色相からこの角度（度）以内なら完全に抜く
+    pub tolerance: f32,
+    /// 許容幅の外側で、アルファを直線的に戻す角度（度、0なら硬いエッジ）
+    pub softness: f32,
+}
+
+impl Default for ChromaKeySettings {
+    fn default() -> Self {
+        // グリーンバック向け。照明ムラで色相が少し揺れても抜けるよう、許容幅は広め
+        Self {
+            key_color: [0, 255, 0],
+            tolerance: 30.0,
+            softness: 15.0,
+        }
+    }
+}
+
+impl ChromaKeySettings {
+    pub const TOLERANCE_RANGE: RangeInclusive<f32> = 0.0..=180.0;
+    pub const SOFTNESS_RANGE: RangeInclusive<f32> = 0.0..=90.0;
+
+    /// 角度を範囲内に丸める（NaNは既定値）
+    pub fn clamped(self) -> Self {
+        let clamp = |value: f32, range: RangeInclusive<f32>, default: f32| {
+            if value.is_nan() {
+                default
+            } else {
+                value.clamp(*range.start(), *range.end())
+            }
+        };
+        let default = Self::default();
+        Self {
+            key_color: self.key_color,
+            tolerance: clamp(self.tolerance, Self::TOLERANCE_RANGE, default.tolerance),
+            softness: clamp(self.softness, Self::SOFTNESS_RANGE, default.softness),
+        }
+    }
+}
+
+// 彩度・明度がこれより低い画素は色相が当てにならないので抜かない。
+// ここから CHROMA_KEY_RAMP だけ上までで、キーの効きを直線的に強める（灰色・黒とのなめらかな境目）
+const CHROMA_KEY_MIN_SATURATION: f32 = 0.15;
+const CHROMA_KEY_MIN_VALUE: f32 = 0.15;
+const CHROMA_KEY_RAMP: f32 = 0.1;
+
+/// RGBをHSV（色相は度で0..360、彩度・明度は0..=1）にする。無彩色の色相は0
+pub fn rgb_to_hsv([r, g, b]: [u8; 3]) -> (f32, f32, f32) {
+    let [r, g, b] = [r, g, b].map(|c| c as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let chroma = max - r.min(g).min(b);
+    if chroma == 0.0 {
+        return (0.0, 0.0, max);
+    }
+    let sector = if max == r {
+        ((g - b) / chroma).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    (sector * 60.0, chroma / max, max)
+}
+
+/// 画素に対するクロマキーのアルファ（0 = 完全に抜く、255 = そのまま）
+///
+/// 画素の色相と `key_hue` の差（円周上、0..=180度）が `tolerance` 以内なら抜き、
+/// そこから `softness` の間でアルファを戻す。彩度か明度が低い画素（灰色・白・黒）は残す。
+pub fn chroma_key_alpha(pixel: [u8; 3], key_hue: f32, tolerance: f32, softness: f32) -> u8 {
+    let (hue, saturation, value) = rgb_to_hsv(pixel);
+    let ramp = |x: f32, min: f32| ((x - min) / CHROMA_KEY_RAMP).clamp(0.0, 1.0);
+    let strength = ramp(saturation, CHROMA_KEY_MIN_SATURATION) * ramp(value, CHROMA_KEY_MIN_VALUE);
+    if strength == 0.0 {
+        return 255;
+    }
+
+    let distance = (hue - key_hue).abs();
+    let distance = distance.min(360.0 - distance);
+    let key = if distance <= tolerance {
+        1.0
+    } else if softness > 0.0 {
+        (1.0 - (distance - tolerance) / softness).max(0.0)
+    } else {
+        0.0
+    };
+    ((1.0 - key * strength) * 255.0).round() as u8
+}
+
+/// 指定した色相に近い画素を透明にするクロマキー（[`chroma_key_alpha`] を元のアルファに掛ける）
+///
+/// 背景は合成しないので、抜けたところは表示側で市松模様（ビューアの `C`）などを敷いて見せる。
+pub struct ChromaKey {
+    settings: ParamHandle<ChromaKeySettings>,
+}
+
+impl ChromaKey {
+    pub fn new(settings: ChromaKeySettings) -> Self {
+        Self {
+            settings: ParamHandle::new(settings),
+        }
+    }
+
+    /// 実行中に値を変えるためのハンドル（範囲外の角度は適用するときに丸める）
+    pub fn settings(&self) -> ParamHandle<ChromaKeySettings> {
+        self.settings.clone()
+    }
+}
+
+impl Default for ChromaKey {
+    fn default() -> Self {
+        Self::new(ChromaKeySettings::default())
+    }
+}
+
+impl Filter for ChromaKey {
+    fn apply(&mut self, width: usize, _height: usize, data: &mut [u8]) {
+        let settings = self.settings.get().clamped();
+        let (key_hue, _, _) = rgb_to_hsv(settings.key_color);
+        for_each_row(data, width * 4, |_, row| {
+            for chunk in row.chunks_exact_mut(4) {
+                let key = chroma_key_alpha(
+                    [chunk[0], chunk[1], chunk[2]],
+                    key_hue,
+                    settings.tolerance,
+                    settings.softness,
+                ) as u32;
+                chunk[3] = ((chunk[3] as u32 * key + 127) / 255) as u8;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 輝度 0-31 の灰色を1行に並べる（灰色の輝度は値そのもの）
+    fn gray_ramp() -> Vec<u8> {
+        (0..32u8).flat_map(|v| [v, v, v, 255]).collect()
+    }
+
+    #[test]
+    fn luma_key_ramps_alpha_around_the_threshold() {
+        // しきい値16、幅8 なら輝度12から20にかけてアルファが0から255になる
+        let mut key = LumaKey::new(LumaKeySettings {
+            background: None,
+            ..LumaKeySettings::default()
+        });
+        let mut data = gray_ramp();
+        key.apply(32, 1, &mut data);
+        let alpha: Vec<u8> = data.chunks_exact(4).map(|px| px[3]).collect();
+
+        assert!(alpha[..=12].iter().all(|&a| a == 0), "{alpha:?}");
+        assert!(alpha[20..].iter().all(|&a| a == 255), "{alpha:?}");
+        assert!(alpha[13..20].iter().all(|&a| a > 0 && a < 255), "{alpha:?}");
+        assert!(alpha.windows(2).all(|w| w[0] <= w[1]), "{alpha:?}");
+        assert_eq!(alpha[16], 127);
+        // 色は変えない
+        assert_eq!(data[16 * 4..16 * 4 + 3], [16, 16, 16]);
+    }
+
+    #[test]
+    fn luma_key_above_and_hard_edge() {
+        let mut above = LumaKey::new(LumaKeySettings {
+            key_above: true,
+            background: None,
+            ..LumaKeySettings::default()
+        });
+        assert_eq!(
+            (above.alpha(0), above.alpha(16), above.alpha(31)),
+            (255, 128, 0)
+        );
+        let mut data = gray_ramp();
+        above.apply(32, 1, &mut data);
+        assert_eq!(data[16 * 4 + 3], 128);
+
+        let hard = LumaKey::new(LumaKeySettings {
+            softness: 0,
+            ..LumaKeySettings::default()
+        });
+        assert_eq!((hard.alpha(15), hard.alpha(16)), (0, 255));
+    }
+
+    #[test]
+    fn luma_key_composites_partial_alpha_over_the_background() {
+        let mut key = LumaKey::new(LumaKeySettings {
+            background: Some([200, 0, 100]),
+            ..LumaKeySettings::default()
+        });
+        let mut data = gray_ramp();
+        key.apply(32, 1, &mut data);
+        // 抜けた画素は背景色、しきい値ちょうどは半々、それより上は元の色
+        assert_eq!(data[..4], [200, 0, 100, 255]);
+        assert_eq!(data[16 * 4..17 * 4], [108, 8, 58, 255]);
+        assert_eq!(data[31 * 4..], [31, 31, 31, 255]);
+    }
+
+    // 60 と 180 の縦の段差（8x4）
+    fn step_edge() -> Vec<u8> {
+        (0..4)
+            .flat_map(|_| {
+                (0..8).flat_map(|x| {
+                    if x < 4 {
+                        [60, 60, 60, 200]
+                    } else {
+                        [180, 180, 180, 200]
+                    }
+                })
+            })
+            .collect()
+    }
+
+    // 段差のある行の赤の値
+    fn red_values(row: &[u8]) -> Vec<u8> {
+        row.chunks_exact(4).map(|px| px[0]).collect()
+    }
+
+    #[test]
+    fn gaussian_blur_leaves_a_uniform_image_unchanged() {
+        let uniform = [10, 200, 77, 255].repeat(7 * 5);
+        // sigma 2 の窓（半径6）はフレームより広いので、どの画素も端のクランプを通る
+        for sigma in [GaussianBlur::MIN_SIGMA, 1.0, 2.0, GaussianBlur::MAX_SIGMA] {
+            let mut data = uniform.clone();
+            gaussian_blur_rgba(7, 5, &mut data, sigma);
+            assert_eq!(data, uniform, "sigma {sigma}");
+        }
+    }
+
+    #[test]
+    fn gaussian_blur_spreads_a_corner_pixel_with_clamped_taps() {
+        const SIZE: usize = 9;
+        let mut blur = GaussianBlur::new(1.0);
+        blur.update_kernel();
+        let kernel: Vec<u64> = blur.kernel.iter().map(|&w| w as u64).collect();
+        let radius = kernel.len() / 2;
+        // 左上の画素に届く重み。窓の外（負の座標）のタップも端の画素に寄せられる
+        let weight_at = |x: usize| kernel[..=radius.saturating_sub(x)].iter().sum::<u64>();
+
+        let mut data = vec![0; SIZE * SIZE * 4];
+        data[..4].fill(255);
+        blur.apply(SIZE, SIZE, &mut data);
+
+        let value = |x: usize, y: usize| data[(y * SIZE + x) * 4];
+        let shift = 2 * GAUSSIAN_KERNEL_SHIFT;
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let expected = if x > radius || y > radius {
+                    0
+                } else {
+                    (255 * weight_at(x) * weight_at(y) + (1 << (shift - 1))) >> shift
+                };
+                assert_eq!(value(x, y) as u64, expected, "({x}, {y})");
+                // 縦横で同じように広がる
+                assert_eq!(value(x, y), value(y, x), "({x}, {y})");
+                // アルファも色と同じようにぼかす
+                assert_eq!(data[(y * SIZE + x) * 4..][..4], [value(x, y); 4]);
+            }
+        }
+        // クランプした分だけ角に残る（窓の左半分と中央の重み）
+        assert!(value(0, 0) > value(1, 0) && value(1, 0) > value(2, 0));
+
+        // 右下の角に置けば結果は鏡写しになる
+        let mut mirrored = vec![0; SIZE * SIZE * 4];
+        mirrored[(SIZE * SIZE - 1) * 4..].fill(255);
+        gaussian_blur_rgba(SIZE, SIZE, &mut mirrored, 1.0);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let flipped = ((SIZE - 1 - y) * SIZE + (SIZE - 1 - x)) * 4;
+                assert_eq!(mirrored[flipped], value(x, y), "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn gaussian_blur_blurs_alpha_like_the_colors() {
+        // アルファも他のチャンネルと同じ重みでぼかすので、透明な部分との境目で色とアルファが揃って変わる
+        let image: Vec<u8> = (0..6 * 2)
+            .flat_map(|i| if i % 6 < 3 { [0; 4] } else { [255; 4] })
+            .collect();
+        let mut data = image.clone();
+        gaussian_blur_rgba(6, 2, &mut data, 1.0);
+        for px in data.chunks_exact(4) {
+            assert!(px.iter().all(|&c| c == px[3]), "{px:?}");
+        }
+        let alpha: Vec<u8> = data[..6 * 4].chunks_exact(4).map(|px| px[3]).collect();
+        assert!(alpha.windows(2).all(|w| w[0] < w[1]), "{alpha:?}");
+        assert!(alpha[0] > 0 && alpha[5] < 255, "{alpha:?}");
+    }
+
+    #[test]
+    fn sharpen_leaves_a_uniform_image_unchanged() {
+        let uniform = [90, 120, 150, 77].repeat(8 * 4);
+        let mut data = uniform.clone();
+        UnsharpMask::default().apply(8, 4, &mut data);
+        assert_eq!(data, uniform);
+    }
+
+    #[test]
+    fn sharpen_enhances_an_edge() {
+        let mut data = step_edge();
+        UnsharpMask::default().apply(8, 4, &mut data);
+        for row in data.chunks_exact(8 * 4) {
+            // 段差の両側でアンダーシュート・オーバーシュートし、窓（半径2）の外の平らな所はそのまま
+            assert_eq!(red_values(row), [60, 60, 59, 36, 204, 181, 180, 180]);
+            assert!(row.chunks_exact(4).all(|px| px[3] == 200));
+        }
+    }
+
+    #[test]
+    fn sharpen_settings_apply_from_the_next_frame() {
+        let mut sharpen = UnsharpMask::default();
+        let settings = sharpen.settings();
+
+        // 半径を広げると、段差から離れた画素まで変わる
+        settings.store(SharpenSettings {
+            amount: 1.0,
+            radius: 6,
+        });
+        let mut data = step_edge();
+        sharpen.apply(8, 4, &mut data);
+        assert_eq!(
+            red_values(&data[..8 * 4]),
+            [55, 48, 33, 12, 228, 207, 192, 185]
+        );
+
+        // 戻せば既定の半径と同じ結果
+        settings.store(SharpenSettings::default());
+        let mut data = step_edge();
+        sharpen.apply(8, 4, &mut data);
+        assert_eq!(
+            red_values(&data[..8 * 4]),
+            [60, 60, 59, 36, 204, 181, 180, 180]
+        );
+
+        // 0にすれば何もしない
+        settings.store(SharpenSettings {
+            amount: 0.0,
+            radius: 6,
+        });
+        let mut data = step_edge();
+        sharpen.apply(8, 4, &mut data);
+        assert_eq!(data, step_edge());
+    }
+
+    #[test]
+    fn sharpen_clamps_huge_amounts() {
+        let settings = SharpenSettings {
+            amount: 1e9,
+            radius: 1000,
+        };
+        assert_eq!(
+            settings.clamped(),
+            SharpenSettings {
+                amount: UnsharpMask::MAX_AMOUNT,
+                radius: UnsharpMask::MAX_RADIUS
+            }
+        );
+        let small = SharpenSettings {
+            amount: f32::NAN,
+            radius: 0,
+        };
+        assert_eq!(
+            small.clamped(),
+            SharpenSettings {
+                amount: 0.0,
+                radius: UnsharpMask::MIN_RADIUS
+            }
+        );
+        // 固定小数点の掛け算があふれず、0か255に張り付くだけ
+        let mut data = step_edge();
+        UnsharpMask::new(settings).apply(8, 4, &mut data);
+        assert_eq!(data[3 * 4], 0);
+        assert_eq!(data[4 * 4], 255);
+    }
+
+    #[test]
+    fn color_correct_settings_apply_from_the_next_frame() {
+        let image: Vec<u8> = (0..=255u8).flat_map(|v| [v, v, v, 99]).collect();
+        let mut color_correct = ColorCorrect::default();
+        let settings = color_correct.settings();
+
+        // 既定値では元のまま
+        let mut data = image.clone();
+        color_correct.apply(256, 1, &mut data);
+        assert_eq!(data, image);
+
+        // 赤のコントラストを0にすると赤だけが中間の灰色になる。範囲外やNaNは丸める
+        let mut grades = ColorCorrectSettings::default();
+        grades.grades[0].contrast = -4.0;
+        grades.grades[2].gamma = f32::NAN;
+        settings.store(grades);
+        let mut data = image.clone();
+        color_correct.apply(256, 1, &mut data);
+        for (v, px) in data.chunks_exact(4).enumerate() {
+            assert_eq!(px, [128, v as u8, v as u8, 99]);
+        }
+
+        settings.store(ColorCorrectSettings::default());
+        let mut data = image.clone();
+        color_correct.apply(256, 1, &mut data);
+        assert_eq!(data, image);
+    }
+
+    fn assert_hsv([r, g, b]: [u8; 3], expected: (f32, f32, f32)) {
+        let (hue, saturation, value) = rgb_to_hsv([r, g, b]);
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+        assert!(
+            close(hue, expected.0) && close(saturation, expected.1) && close(value, expected.2),
+            "{:?} -> {:?}, expected {expected:?}",
+            [r, g, b],
+            (hue, saturation, value)
+        );
+    }
+
+    #[test]
+    fn hsv_of_primaries_and_grays() {
+        assert_hsv([255, 0, 0], (0.0, 1.0, 1.0));
+        assert_hsv([255, 255, 0], (60.0, 1.0, 1.0));
+        assert_hsv([0, 255, 0], (120.0, 1.0, 1.0));
+        assert_hsv([0, 255, 255], (180.0, 1.0, 1.0));
+        assert_hsv([0, 0, 255], (240.0, 1.0, 1.0));
+        // 赤の手前（青が多い）は360度の手前に回る
+        assert_hsv([255, 0, 255], (300.0, 1.0, 1.0));
+        assert_hsv([102, 51, 51], (0.0, 0.5, 0.4));
+        // 無彩色の色相は0
+        assert_hsv([128, 128, 128], (0.0, 0.0, 128.0 / 255.0));
+        assert_hsv([0, 0, 0], (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn chroma_key_alpha_by_hue_distance() {
+        const BLUE: [u8; 3] = [0, 0, 255];
+        let alpha = |key_hue| chroma_key_alpha(BLUE, key_hue, 30.0, 15.0);
+        // 許容幅の内側と境目は抜く
+        assert_eq!(alpha(240.0), 0);
+        assert_eq!(alpha(210.0), 0);
+        // ソフトネスの中ほどで半分、外側はそのまま
+        assert_eq!(alpha(240.0 - 37.5), 128);
+        assert_eq!(alpha(240.0 - 45.0), 255);
+        assert_eq!(alpha(60.0), 255);
+        // 色相の差は円周上で測る
+        assert_eq!(chroma_key_alpha([255, 0, 0], 350.0, 30.0, 15.0), 0);
+        // ソフトネス0は硬いエッジ
+        assert_eq!(chroma_key_alpha(BLUE, 209.0, 30.0, 0.0), 255);
+    }
+
+    #[test]
+    fn chroma_key_keeps_grays_and_dark_pixels() {
+        for pixel in [[128, 128, 128], [255, 255, 255], [0, 0, 0], [0, 20, 0]] {
+            assert_eq!(chroma_key_alpha(pixel, 120.0, 180.0, 0.0), 255, "{pixel:?}");
+        }
+        // 明度が下限から少し上では効きが弱まる
+        let dim = chroma_key_alpha([0, 51, 0], 120.0, 30.0, 15.0);
+        assert!((127..=128).contains(&dim), "{dim}");
+    }
+
+    #[test]
+    fn chroma_key_settings_apply_from_the_next_frame() {
+        let image = [[0, 255, 0, 200], [0, 0, 255, 200], [128, 128, 128, 200]].concat();
+        let mut chroma_key = ChromaKey::default();
+        let settings = chroma_key.settings();
+
+        // 既定はグリーンバック。元のアルファに掛ける
+        let mut data = image.clone();
+        chroma_key.apply(3, 1, &mut data);
+        let alphas = |data: &[u8]| data.chunks_exact(4).map(|px| px[3]).collect::<Vec<_>>();
+        assert_eq!(alphas(&data), [0, 200, 200]);
+
+        settings.store(ChromaKeySettings {
+            key_color: [0, 0, 255],
+            ..ChromaKeySettings::default()
+        });
+        let mut data = image.clone();
+        chroma_key.apply(3, 1, &mut data);
+        assert_eq!(alphas(&data), [200, 0, 200]);
+        // 色は変えない
+        assert!(data
+            .chunks_exact(4)
+            .zip(image.chunks_exact(4))
+            .all(|(a, b)| a[..3] == b[..3]));
+    }
+
+    #[test]
+    fn false_color_thresholds_must_be_four_ascending_values() {
+        assert_eq!(
+            FalseColorThresholds::parse(" 5, 100,130 ,250"),
+            Some(FalseColorThresholds::default())
+        );
+        // 中間調は1段だけでもよい
+        assert!(FalseColorThresholds::parse("0,128,128,255").is_some());
+        for invalid in [
+            "5,100,130",
+            "5,100,130,250,255",
+            "",
+            "5,100,x,250",
+            "5,100,130,256",
+            "100,100,130,250",
+            "5,131,130,250",
+            "5,100,250,250",
+            "250,130,100,5",
+        ] {
+            assert_eq!(FalseColorThresholds::parse(invalid), None, "{invalid}");
+        }
+        let thresholds = FalseColorThresholds::default();
+        assert_eq!(
+            FalseColorThresholds::parse(&thresholds.to_string()),
+            Some(thresholds)
+        );
+    }
+
+    #[test]
+    fn false_color_paints_each_band() {
+        // 2行に分けた灰色（灰色の輝度は値そのもの）。しきい値は既定の 5,100,130,250
+        let grays = [0, 5, 6, 99, 100, 115, 130, 131, 249, 250, 255, 64];
+        let image: Vec<u8> = grays.iter().flat_map(|&v| [v, v, v, v]).collect();
+        let mut data = image.clone();
+        FalseColor::default().apply(6, 2, &mut data);
+
+        let expected = [
+            FALSE_COLOR_CRUSHED,
+            FALSE_COLOR_CRUSHED,
+            [6; 3],
+            [99; 3],
+            FALSE_COLOR_MID,
+            FALSE_COLOR_MID,
+            FALSE_COLOR_MID,
+            [131; 3],
+            [249; 3],
+            FALSE_COLOR_CLIPPED,
+            FALSE_COLOR_CLIPPED,
+            [64; 3],
+        ];
+        for ((px, expected), gray) in data.chunks_exact(4).zip(expected).zip(grays) {
+            assert_eq!(px[..3], expected, "gray {gray}");
+            // アルファはそのまま
+            assert_eq!(px[3], gray, "gray {gray}");
+        }
+        // 色のある画素はBT.601の輝度で分ける（純赤は76、純緑は149、純青は28）
+        let thresholds = FalseColorThresholds::parse("29,76,76,149").unwrap();
+        let mut colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]].concat();
+        FalseColor::new(thresholds).apply(3, 1, &mut colors);
+        assert_eq!(colors[..3], FALSE_COLOR_MID);
+        assert_eq!(colors[4..7], FALSE_COLOR_CLIPPED);
+        assert_eq!(colors[8..11], FALSE_COLOR_CRUSHED);
+    }
+}
@@ -0,0 +1,159 @@
+//! フレームレートと帯域の統計（オーバーレイ表示用）
+//!
+//! run_loop がフレームごとに更新し、GUIスレッドが `update` ごとに読む。
+//! どちらもロックを取らないよう、値はすべてアトミックに入れる（f64はビット列として）。
+//! 書くのは受信スレッド1つだけなので、読む側では値どうしが1フレームずれることがある。
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// 受信フレームの平均FPS・到着間隔・ドロップ数・データレート
+#[derive(Debug, Default)]
+pub struct FrameStats {
+    // 受信したフレーム数（捨てたものも含む）
+    frames: AtomicU64,
+    // 到着間隔の指数移動平均（ナノ秒）
+    interval_nanos: AtomicU64,
+    // 1フレームのバイト数の指数移動平均（f64のビット列）
+    frame_bytes: AtomicU64,
+    // NDI SDKが数えたドロップ数
+    dropped: AtomicU64,
+}
+
+impl FrameStats {
+    /// 指数移動平均の重みを、直近この枚数ぶんの単純平均と同程度にする
+    pub const EMA_FRAMES: u32 = 30;
+
+    /// フレームが1枚届いたときに呼ぶ
+    ///
+    /// `interval` は前のフレームからの時間（最初のフレームなら `None`）、`bytes` は
+    /// 受信バッファのバイト数（変換前の受信フォーマットのまま、圧縮フレームなら圧縮後）。
+    pub fn record(&self, interval: Option<Duration>, bytes: usize) {
+        self.frames.fetch_add(1, Ordering::Relaxed);
+        let alpha = 2.0 / (Self::EMA_FRAMES as f64 + 1.0);
+        let ema = |previous: f64, sample: f64| {
+            // 最初の標本はそのまま使う（0から立ち上がらないように）
+            if previous == 0.0 {
+                sample
+            } else {
+                previous + alpha * (sample - previous)
+            }
+        };
+
+        let frame_bytes = f64::from_bits(self.frame_bytes.load(Ordering::Relaxed));
+        let frame_bytes = ema(frame_bytes, bytes as f64);
+        self.frame_bytes
+            .store(frame_bytes.to_bits(), Ordering::Relaxed);
+
+        if let Some(interval) = interval {
+            let previous = self.interval_nanos.load(Ordering::Relaxed) as f64;
+            let nanos = ema(previous, interval.as_nanos() as f64);
+            self.interval_nanos.store(nanos as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// NDI SDKの統計から読んだドロップ数を反映する
+    pub fn set_dropped(&self, dropped: u64) {
+        self.dropped.store(dropped, Ordering::Relaxed);
+    }
+
+    /// 受信したフレーム数
+    pub fn frames(&self) -> u64 {
+        self.frames.load(Ordering::Relaxed)
+    }
+
+    /// 平均の到着間隔（2枚目が届くまでは0）
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_nanos(self.interval_nanos.load(Ordering::Relaxed))
+    }
+
+    /// 平均の到着間隔から求めたFPS（2枚目が届くまでは0）
+    pub fn fps(&self) -> f64 {
+        let interval = self.frame_interval();
+        if interval.is_zero() {
+            0.0
+        } else {
+            1.0 / interval.as_secs_f64()
+        }
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// 受信データのバイト/秒（平均のフレームサイズ × FPS）
+    pub fn bytes_per_sec(&self) -> f64 {
+        f64::from_bits(self.frame_bytes.load(Ordering::Relaxed)) * self.fps()
+    }
+
+    /// オーバーレイ用の1行（例: "59.94 fps (16.7 ms), 497.2 MB/s, 3 dropped"）
+    pub fn overlay_text(&self) -> String {
+        if self.frames() == 0 {
+            return "No frames yet".to_string();
+        }
+        format!(
+            "{:.2} fps ({:.1} ms), {:.1} MB/s, {} dropped",
+            self.fps(),
+            self.frame_interval().as_secs_f64() * 1000.0,
+            self.bytes_per_sec() / 1_000_000.0,
+            self.dropped()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1920x1080 のUYVY
+    const FRAME_BYTES: usize = 1920 * 1080 * 2;
+
+    #[test]
+    fn nothing_is_averaged_before_the_second_frame() {
+        let stats = FrameStats::default();
+        assert_eq!(stats.overlay_text(), "No frames yet");
+
+        stats.record(None, FRAME_BYTES);
+        assert_eq!(stats.frames(), 1);
+        assert_eq!(stats.frame_interval(), Duration::ZERO);
+        assert_eq!(stats.fps(), 0.0);
+        assert_eq!(stats.bytes_per_sec(), 0.0);
+        assert_eq!(
+            stats.overlay_text(),
+            "0.00 fps (0.0 ms), 0.0 MB/s, 0 dropped"
+        );
+    }
+
+    #[test]
+    fn fixed_intervals_give_a_steady_rate() {
+        let stats = FrameStats::default();
+        stats.record(None, FRAME_BYTES);
+        for _ in 0..100 {
+            stats.record(Some(Duration::from_millis(20)), FRAME_BYTES);
+        }
+        stats.set_dropped(3);
+
+        assert_eq!(stats.frames(), 101);
+        assert_eq!(stats.frame_interval(), Duration::from_millis(20));
+        assert_eq!(stats.fps(), 50.0);
+        assert_eq!(stats.bytes_per_sec(), FRAME_BYTES as f64 * 50.0);
+        assert_eq!(
+            stats.overlay_text(),
+            "50.00 fps (20.0 ms), 207.4 MB/s, 3 dropped"
+        );
+    }
+
+    #[test]
+    fn a_late_frame_moves_the_average_by_the_ema_weight() {
+        let stats = FrameStats::default();
+        stats.record(Some(Duration::from_millis(20)), FRAME_BYTES);
+        // 重みは 2/(30+1) なので、31 ms 遅れたフレームで平均は 2 ms 延びる
+        stats.record(Some(Duration::from_millis(51)), FRAME_BYTES / 2);
+        let interval = stats.frame_interval().as_nanos() as i64;
+        assert!((interval - 22_000_000).abs() <= 1, "{interval}");
+        // フレームのサイズも同じ重みで動く
+        let bytes = stats.bytes_per_sec() / stats.fps();
+        let expected = FRAME_BYTES as f64 * (1.0 - 1.0 / 31.0);
+        assert!((bytes - expected).abs() < 1e-3, "{bytes} {expected}");
+    }
+}
@@ -0,0 +1,233 @@
+//! フリーズ（送信側が同じ画を送り続けている状態）の検出
+//!
+//! 4Kで毎フレーム全画素をハッシュするとメモリを1周読むことになるので、
+//! `stride` 画素おきの格子（既定で64画素に1つ）だけを前のフレームと比べる。
+//! 動いている映像は大抵最初の数個の標本で違いが見つかり、そこで比較をやめて残りは読まない。
+//! 読まなかった標本は古いままなので、次のフレームでは違いが見つかった所までを比べ、
+//! そこまで同じでも残りを写し直すだけで「変化あり」とする（止まった画はその次のフレームから同じと判定）。
+
+use std::time::{Duration, Instant};
+
+/// 間引いた標本を前のフレームと比べてフリーズを検出する
+pub struct FreezeDetector {
+    stride: usize,
+    // 前のフレームの標本（4バイトずつ）
+    samples: Vec<u32>,
+    // 前のフレームの値が入っている先頭の標本数（残りはそれより古い）
+    fresh: usize,
+    // 標本を取ったフレームの (width, height, line_stride)
+    layout: (usize, usize, usize),
+    // 最後に画が変わった時刻
+    last_change: Option<Instant>,
+}
+
+impl FreezeDetector {
+    /// 縦横8画素おき（64画素に1つ）
+    pub const DEFAULT_STRIDE: usize = 8;
+
+    /// `stride` は縦横の標本間隔（画素）。1なら全画素を比べる
+    pub fn new(stride: usize) -> Self {
+        Self {
+            stride: stride.max(1),
+            samples: Vec::new(),
+            fresh: 0,
+            layout: (0, 0, 0),
+            last_change: None,
+        }
+    }
+
+    /// 前のフレームから標本が1つも変わっていなければtrue
+    ///
+    /// `data` は `line_stride` バイトの行が `height` 行並んだもの。行の先頭から
+    /// `width*4` バイト（行がそれより短ければ行全体）を4バイト単位で見るので、
+    /// RGBA以外（UYVYなど）でも変化は検出できる。最初のフレームやサイズが変わったとき、
+    /// 変化したフレームの次のフレームはfalse。
+    pub fn is_same(
+        &mut self,
+        width: usize,
+        height: usize,
+        line_stride: usize,
+        data: &[u8],
+    ) -> bool {
+        let layout = (width, height, line_stride);
+        let row_bytes = (width * 4).min(line_stride);
+        let rows = height.min(data.len() / line_stride.max(1));
+        let per_row = (row_bytes / 4).div_ceil(self.stride);
+        let count = rows.div_ceil(self.stride) * per_row;
+        if layout != self.layout || self.samples.len() != count {
+            self.layout = layout;
+            self.samples.clear();
+            self.samples.resize(count, 0);
+            self.fresh = 0;
+        }
+        if count == 0 {
+            return false;
+        }
+
+        let stride = self.stride;
+        let sample = |i: usize| {
+            let offset = i / per_row * stride * line_stride + i % per_row * stride * 4;
+            u32::from_ne_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+        for i in 0..self.fresh {
+            let value = sample(i);
+            if self.samples[i] != value {
+                // ここより後ろは読まずに古いまま残す
+                self.samples[i] = value;
+                self.fresh = i + 1;
+                return false;
+            }
+        }
+        // 前のフレームの値がない標本は比べられないので写すだけ
+        let same = self.fresh == count;
+        for i in self.fresh..count {
+            self.samples[i] = sample(i);
+        }
+        self.fresh = count;
+        same
+    }
+
+    /// フレームを渡し、同じ画が続いている時間を返す（変化したフレームなら0）
+    pub fn update(
+        &mut self,
+        width: usize,
+        height: usize,
+        line_stride: usize,
+        data: &[u8],
+        now: Instant,
+    ) -> Duration {
+        if !self.is_same(width, height, line_stride, data) {
+            self.last_change = Some(now);
+        }
+        self.last_change
+            .map_or(Duration::ZERO, |last| now.duration_since(last))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 6;
+    const HEIGHT: usize = 5;
+    // 1行に2画素分の余白
+    const LINE_STRIDE: usize = (WIDTH + 2) * 4;
+
+    fn frame() -> Vec<u8> {
+        (0..LINE_STRIDE * HEIGHT).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn identical_frames_are_the_same() {
+        let mut detector = FreezeDetector::new(2);
+        let data = frame();
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data.clone()));
+    }
+
+    #[test]
+    fn a_change_in_the_last_sample_is_found() {
+        let mut detector = FreezeDetector::new(2);
+        let mut data = frame();
+        detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data);
+        // 格子の最後の標本は (4, 4)
+        data[4 * LINE_STRIDE + 4 * 4 + 3] ^= 1;
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+
+        // 標本の間の画素や行の余白は見ない
+        data[5 * 4] ^= 1;
+        data[WIDTH * 4] ^= 1;
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+    }
+
+    #[test]
+    fn a_still_picture_after_an_early_change_is_the_same_one_frame_later() {
+        let mut detector = FreezeDetector::new(1);
+        let first = frame();
+        let mut second = first.clone();
+        // 最初の標本が変われば残りは読まれない
+        second[0] ^= 1;
+        second[LINE_STRIDE * 4] ^= 1;
+        detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &first);
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &second));
+        // 残りの標本は1つ前のフレームのままなので、ここではまだ同じとは言えない
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &second));
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &second));
+    }
+
+    #[test]
+    fn a_new_layout_starts_over() {
+        let mut detector = FreezeDetector::new(2);
+        let data = frame();
+        detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data);
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        for (width, height, line_stride) in [
+            (WIDTH - 1, HEIGHT, LINE_STRIDE),
+            (WIDTH, HEIGHT - 1, LINE_STRIDE),
+            (WIDTH, HEIGHT, LINE_STRIDE - 4),
+        ] {
+            // 標本の数が同じでも、大きさが変われば前のフレームとは比べない
+            assert!(!detector.is_same(width, height, line_stride, &data));
+            assert!(detector.is_same(width, height, line_stride, &data));
+            assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+            assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        }
+    }
+
+    #[test]
+    fn stride_one_compares_every_pixel() {
+        let mut detector = FreezeDetector::new(0);
+        let mut data = frame();
+        detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data);
+        data[LINE_STRIDE + 5] ^= 1;
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        // 違いの後ろの標本を読み直すまで1フレームかかる
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+    }
+
+    #[test]
+    fn truncated_buffers_only_use_the_complete_rows() {
+        let mut detector = FreezeDetector::new(1);
+        let mut data = frame();
+        // 3行と少し
+        data.truncate(LINE_STRIDE * 3 + 5);
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        assert!(detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+        data[LINE_STRIDE * 2 + 8] ^= 1;
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data));
+
+        // 1行に満たなければ比べられない
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data[..4]));
+        assert!(!detector.is_same(WIDTH, HEIGHT, LINE_STRIDE, &data[..4]));
+    }
+
+    #[test]
+    fn update_reports_how_long_the_picture_has_been_still() {
+        let mut detector = FreezeDetector::new(2);
+        let data = frame();
+        let start = Instant::now();
+        let later = |ms| start + Duration::from_millis(ms);
+        assert_eq!(
+            detector.update(WIDTH, HEIGHT, LINE_STRIDE, &data, start),
+            Duration::ZERO
+        );
+        assert_eq!(
+            detector.update(WIDTH, HEIGHT, LINE_STRIDE, &data, later(40)),
+            Duration::from_millis(40)
+        );
+        let mut changed = data.clone();
+        changed[0] ^= 1;
+        assert_eq!(
+            detector.update(WIDTH, HEIGHT, LINE_STRIDE, &changed, later(80)),
+            Duration::ZERO
+        );
+    }
+}
@@ -0,0 +1,820 @@
+//! wgpuデバイスの初期化（バックエンドのフォールバック付き）、大きなフレームの分割処理と、
+//! eguiのwgpuレンダラーに直接描かせるテクスチャと、そこに書くコンピュートパイプライン
+
+use crate::convert::downscale_rgba;
+use crate::display::{paint_letterboxed, TextureFilter};
+use crate::{CliArgs, CubeLut, OwnedFrame};
+use anyhow::Result;
+use eframe::egui;
+use eframe::egui_wgpu::{RenderState, WgpuSetup, WgpuSetupCreateNew};
+use std::fmt;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use wgpu::util::DeviceExt;
+
+/// `--backend-order` 未指定時の順序（wgpuに任せる）
+pub const DEFAULT_BACKEND_ORDER: &[wgpu::Backends] = &[wgpu::Backends::all()];
+
+/// `--backend` の1つのバックエンド名（vulkan, metal, dx12, gl）をパースする
+pub fn parse_backend(s: &str) -> Option<wgpu::Backends> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "vulkan" => Some(wgpu::Backends::VULKAN),
+        "metal" => Some(wgpu::Backends::METAL),
+        "dx12" => Some(wgpu::Backends::DX12),
+        "gl" => Some(wgpu::Backends::GL),
+        _ => None,
+    }
+}
+
+/// "vulkan,gl" のようなカンマ区切りのバックエンド名をパースする
+pub fn parse_backend_order(s: &str) -> Option<Vec<wgpu::Backends>> {
+    s.split(',')
+        .map(|name| match name.trim().to_ascii_lowercase().as_str() {
+            "all" => Some(wgpu::Backends::all()),
+            "primary" => Some(wgpu::Backends::PRIMARY),
+            name => parse_backend(name),
+        })
+        .collect()
+}
+
+/// 試すバックエンドの順序（`--backend-order`、なければ `--backend` だけ、どちらもなければ既定）
+pub fn backend_order(args: &CliArgs) -> Vec<wgpu::Backends> {
+    match (&args.backend_order, args.backend) {
+        (Some(order), _) => order.clone(),
+        (None, Some(backend)) => vec![backend],
+        (None, None) => DEFAULT_BACKEND_ORDER.to_vec(),
+    }
+}
+
+/// ログ用のアダプタの説明（名前・種類・バックエンド・ドライバ）
+pub fn describe_adapter(info: &wgpu::AdapterInfo) -> String {
+    let driver = [info.driver.as_str(), info.driver_info.as_str()]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let driver = if driver.is_empty() {
+        "unknown"
+    } else {
+        &driver
+    };
+    format!(
+        "{} ({:?}, {:?}, driver: {driver})",
+        info.name, info.device_type, info.backend
+    )
+}
+
+/// `adapters` から `--adapter <index>` の番号のものを選ぶ（範囲外なら一覧を付けたエラー）
+fn select_adapter(adapters: &[wgpu::Adapter], index: usize) -> Result<wgpu::Adapter, String> {
+    for (i, adapter) in adapters.iter().enumerate() {
+        info!("Adapter #{i}: {}", describe_adapter(&adapter.get_info()));
+    }
+    adapters.get(index).cloned().ok_or_else(|| {
+        format!(
+            "Adapter index {index} out of range ({} adapter(s) available)",
+            adapters.len()
+        )
+    })
+}
+
+/// eguiのwgpuレンダラーで描画させる。`--backend` / `--adapter` があればeguiのデバイスもそれで作る
+///
+/// `--backend-order` はここでは使わない（eguiはバックエンドを1回しか試さないため）。
+pub fn use_wgpu_renderer(options: &mut eframe::NativeOptions, args: &CliArgs) {
+    options.renderer = eframe::Renderer::Wgpu;
+    if args.backend.is_none() && args.adapter.is_none() {
+        return;
+    }
+    let mut setup = WgpuSetupCreateNew::default();
+    if let Some(backend) = args.backend {
+        setup.instance_descriptor.backends = backend;
+    }
+    if let Some(index) = args.adapter {
+        setup.native_adapter_selector = Some(Arc::new(move |adapters, surface| {
+            let adapter = select_adapter(adapters, index)?;
+            if let Some(surface) = surface
+                && !adapter.is_surface_supported(surface)
+            {
+                return Err(format!(
+                    "Adapter #{index} ({}) can't present to the window",
+                    adapter.get_info().name
+                ));
+            }
+            Ok(adapter)
+        }));
+    }
+    options.wgpu_options.wgpu_setup = WgpuSetup::CreateNew(setup);
+}
+
+/// `order` のバックエンドを順に試し、最初に `setup` まで成功したものを返す
+///
+/// `setup` はパイプライン作成と試しのディスパッチまで行う想定。
+/// その間に発生したwgpuのエラーもキャプチャし、失敗扱いにして次のバックエンドへ進む。
+/// `adapter` があれば、各バックエンドで列挙したアダプタのうちその番号のものを使う（`--adapter`）。
+pub fn init_with_fallback<T, F>(
+    order: &[wgpu::Backends],
+    adapter: Option<usize>,
+    mut setup: F,
+) -> Result<T>
+where
+    F: FnMut(Arc<wgpu::Device>, Arc<wgpu::Queue>) -> Result<T>,
+{
+    for &backends in order {
+        info!("Trying wgpu backend(s): {backends:?}");
+        match try_backends(backends, adapter, &mut setup) {
+            Ok(value) => return Ok(value),
+            Err(e) => warn!("wgpu backend(s) {backends:?} failed: {e:#}"),
+        }
+    }
+    anyhow::bail!("No usable wgpu backend (tried {order:?})")
+}
+
+fn try_backends<T, F>(backends: wgpu::Backends, adapter: Option<usize>, setup: &mut F) -> Result<T>
+where
+    F: FnMut(Arc<wgpu::Device>, Arc<wgpu::Queue>) -> Result<T>,
+{
+    // wgpuインスタンスを手動で作成
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+
+    let adapter = match adapter {
+        Some(index) => select_adapter(&instance.enumerate_adapters(backends), index)
+            .map_err(|e| anyhow::anyhow!(e))?,
+        None => pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))
+        .map_err(|e| anyhow::anyhow!("Failed to find an appropriate adapter: {e}"))?,
+    };
+
+    let info = adapter.get_info();
+    info!("Adapter: {}", describe_adapter(&info));
+
+    // Raspi4 (Mobile/Downlevel向け) の制限設定
+    let mut limits = wgpu::Limits::downlevel_defaults();
+    limits.max_color_attachments = 4;
+
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        label: Some("Device"),
+        required_features: wgpu::Features::empty(),
+        required_limits: limits,
+        ..Default::default()
+    }))?;
+
+    let device = Arc::new(device);
+    let value = setup_on_device(&device, || setup(device.clone(), Arc::new(queue)))?;
+    info!("Using {:?} backend", info.backend);
+    let limits = device.limits();
+    debug!(
+        "Compute limits: {} MiB per storage binding, {} workgroups per dimension",
+        limits.max_storage_buffer_binding_size >> 20,
+        limits.max_compute_workgroups_per_dimension
+    );
+    Ok(value)
+}
+
+/// `device` を使う `setup` を実行し、その間に発生したwgpuのエラーも失敗として返す
+///
+/// eguiのレンダラーと共有するデバイスのように、自分で作っていないデバイスで
+/// パイプラインが使えるか確かめるときにも使う。
+pub fn setup_on_device<T>(device: &wgpu::Device, setup: impl FnOnce() -> Result<T>) -> Result<T> {
+    // setup中のエラーはパニックさせずに拾う
+    device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = setup();
+    let validation_error = pollster::block_on(device.pop_error_scope());
+    let oom_error = pollster::block_on(device.pop_error_scope());
+
+    if let Some(e) = validation_error.or(oom_error) {
+        anyhow::bail!("{e}");
+    }
+    result
+}
+
+/// フレームをGPUでどう処理したか（ログ表示用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// 1回のディスパッチでそのまま
+    Direct,
+    /// 横帯に分けて複数回ディスパッチ
+    Tiled { bands: u32 },
+    /// 1行すらデバイスの上限に収まらないので、CPUで縮小してから処理
+    Downscale { factor: u32, bands: u32 },
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Direct => write!(f, "direct"),
+            Self::Tiled { bands } => write!(f, "tiled ({bands} bands)"),
+            Self::Downscale { factor, bands } => {
+                write!(f, "CPU downscale 1/{factor}, then {bands} band(s)")
+            }
+        }
+    }
+}
+
+/// デバイスの上限を超えるフレームを横帯に分けてGPU処理する
+///
+/// 8Kなどではストレージバッファのバインドサイズやワークグループ数の上限を超えるため、
+/// 1回で処理できる行数ずつに分ける。近傍を読むフィルタ（ブラー）のために、
+/// 帯の上下に `halo` 行ののりしろを付けて処理し、のりしろ部分の出力は捨てる。
+pub struct TilePlanner {
+    halo: u32,
+    // 直近の方針（変わったときだけログに出す）
+    last: Option<Strategy>,
+}
+
+impl TilePlanner {
+    pub fn new(halo: u32) -> Self {
+        Self { halo, last: None }
+    }
+
+    /// `max_rows(width)` は幅 `width` のとき1回のディスパッチで処理できる最大行数。
+    /// `dispatch(width, rows, input)` が1帯分を処理して同じサイズのRGBAを返す。
+    ///
+    /// 戻り値は `(data, width, height)`（縮小した場合はその解像度）。
+    pub fn run<M, D>(
+        &mut self,
+        width: u32,
+        height: u32,
+        input: &[u8],
+        max_rows: M,
+        mut dispatch: D,
+    ) -> Result<(Vec<u8>, u32, u32)>
+    where
+        M: Fn(u32) -> u32,
+        D: FnMut(u32, u32, &[u8]) -> Result<Vec<u8>>,
+    {
+        // 1帯で最低1行は出力できるところまで縮小する
+        let mut factor = 1;
+        let (mut w, mut h, mut rows) = (width, height, max_rows(width));
+        while rows < h && rows <= 2 * self.halo && w > 1 {
+            factor += 1;
+            (w, h) = (width.div_ceil(factor), height.div_ceil(factor));
+            rows = max_rows(w);
+        }
+        if rows == 0 || (rows < h && rows <= 2 * self.halo) {
+            anyhow::bail!("Frame {width}x{height} cannot be processed within the device limits");
+        }
+
+        let downscaled;
+        let input = if factor > 1 {
+            downscaled = downscale_rgba(input, width as usize, height as usize, factor as usize).0;
+            &downscaled[..]
+        } else {
+            input
+        };
+
+        let row_bytes = w as usize * 4;
+        let step = if rows >= h { h } else { rows - 2 * self.halo };
+        let bands = h.div_ceil(step);
+        let strategy = match (factor, bands) {
+            (1, 1) => Strategy::Direct,
+            (1, bands) => Strategy::Tiled { bands },
+            (factor, bands) => Strategy::Downscale { factor, bands },
+        };
+        if self.last != Some(strategy) {
+            info!("GPU processing strategy for {width}x{height}: {strategy}");
+            self.last = Some(strategy);
+        }
+        if bands == 1 {
+            return Ok((dispatch(w, h, input)?, w, h));
+        }
+
+        let mut output = Vec::with_capacity(row_bytes * h as usize);
+        for output_y in (0..h).step_by(step as usize) {
+            let output_rows = step.min(h - output_y);
+            let input_y = output_y.saturating_sub(self.halo);
+            let input_end = (output_y + output_rows + self.halo).min(h);
+            let band = &input[input_y as usize * row_bytes..input_end as usize * row_bytes];
+
+            let processed = dispatch(w, input_end - input_y, band)?;
+            let skip = (output_y - input_y) as usize * row_bytes;
+            output.extend_from_slice(&processed[skip..skip + output_rows as usize * row_bytes]);
+        }
+        Ok((output, w, h))
+    }
+}
+
+/// eguiのwgpuレンダラーに登録したGPUテクスチャ（コンピュートシェーダーの出力先）
+///
+/// コンピュートシェーダーがストレージテクスチャとして直接書き込み、eguiはそれを
+/// そのまま描画する。CPUへの読み戻しと `ColorImage` での再アップロードが要らない。
+/// eguiと同じデバイス・キューを使うので、`submit` した処理はその後のeguiの描画より先に実行される。
+///
+/// eframeのレンダラーが `Renderer::Wgpu` のとき（`CreationContext::wgpu_render_state` があるとき）だけ使える。
+/// 中身はガンマ空間・乗算済みアルファのRGBA（不透明なら普通のRGBAと同じ）として表示される。
+pub struct EguiTexture {
+    render_state: RenderState,
+    // サイズが変わったときだけ作り直す
+    texture: Option<(wgpu::Texture, wgpu::TextureView)>,
+    id: Option<egui::TextureId>,
+    filter: TextureFilter,
+    // 今のテクスチャに設定してあるサンプラーのフィルタ
+    filter_mode: wgpu::FilterMode,
+}
+
+impl EguiTexture {
+    /// シェーダーからは `texture_storage_2d<rgba8unorm, write>` として書く
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn new(render_state: &RenderState) -> Self {
+        Self {
+            render_state: render_state.clone(),
+            texture: None,
+            id: None,
+            filter: TextureFilter::Auto,
+            filter_mode: wgpu::FilterMode::Linear,
+        }
+    }
+
+    pub fn with_filter(mut self, filter: TextureFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// eguiのレンダラーと共有しているデバイスとキュー
+    pub fn device(&self) -> &wgpu::Device {
+        &self.render_state.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.render_state.queue
+    }
+
+    /// `width`x`height` の書き込み先を返す（サイズが変わっていれば作り直して登録し直す）
+    pub fn target(&mut self, width: u32, height: u32) -> &wgpu::TextureView {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        if self
+            .texture
+            .as_ref()
+            .is_none_or(|(texture, _)| texture.size() != size)
+        {
+            let texture = self.device().create_texture(&wgpu::TextureDescriptor {
+                label: Some("egui output texture"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let mut renderer = self.render_state.renderer.write();
+            match self.id {
+                Some(id) => renderer.update_egui_texture_from_wgpu_texture(
+                    &self.render_state.device,
+                    &view,
+                    self.filter_mode,
+                    id,
+                ),
+                None => {
+                    self.id = Some(renderer.register_native_texture(
+                        &self.render_state.device,
+                        &view,
+                        self.filter_mode,
+                    ))
+                }
+            }
+            drop(renderer);
+            self.texture = Some((texture, view));
+        }
+        let (_, view) = self.texture.as_ref().expect("texture was just created");
+        view
+    }
+
+    /// 一度でも `target` を呼んでいれば、eguiで描けるテクスチャID
+    pub fn id(&self) -> Option<egui::TextureId> {
+        self.id
+    }
+
+    /// テクスチャのサイズ（ピクセル数）
+    pub fn size(&self) -> Option<[u32; 2]> {
+        self.texture
+            .as_ref()
+            .map(|(texture, _)| [texture.width(), texture.height()])
+    }
+
+    /// [`crate::FrameTexture::show`] と同じく、アスペクト比 `display_size` でレターボックス表示する
+    ///
+    /// 表示倍率に合ったフィルタが今のサンプラーと違えば、その場で登録し直す。
+    pub fn show(&mut self, ui: &mut egui::Ui, display_size: egui::Vec2) -> egui::Response {
+        let (Some(id), Some([width, height])) = (self.id, self.size()) else {
+            return ui
+                .allocate_exact_size(ui.available_size(), egui::Sense::hover())
+                .1;
+        };
+        let texels = egui::vec2(width as f32, height as f32);
+        let (response, scale) = paint_letterboxed(ui, id, texels, display_size);
+
+        let filter_mode = match self.filter.options_for(scale).magnification {
+            egui::TextureFilter::Nearest => wgpu::FilterMode::Nearest,
+            egui::TextureFilter::Linear => wgpu::FilterMode::Linear,
+        };
+        if filter_mode != self.filter_mode
+            && let Some((_, view)) = &self.texture
+        {
+            self.render_state
+                .renderer
+                .write()
+                .update_egui_texture_from_wgpu_texture(
+                    &self.render_state.device,
+                    view,
+                    filter_mode,
+                    id,
+                );
+            self.filter_mode = filter_mode;
+        }
+        response
+    }
+}
+
+impl Drop for EguiTexture {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            self.render_state.renderer.write().free_texture(&id);
+        }
+    }
+}
+
+/// 入力フレームをテクスチャで渡し、結果を [`EguiTexture`] に直接書くコンピュートパイプライン
+///
+/// シェーダーは binding 0 に入力（`texture_2d<u32>`、Rgba8Uint なので 0-255 の整数のまま読める）、
+/// binding 1 に出力（`texture_storage_2d<rgba8unorm, write>`）を取り、エントリポイント `main`、
+/// ワークグループ 16x16 で1スレッドが1画素を書く。入力がテクスチャなので、近傍の画素も `textureLoad` で読める。
+///
+/// [`TextureCompute::with_lut`] で作ると、binding 2 に3D LUT（`texture_3d<f32>`、Rgba32Float、
+/// 赤がx・緑がy・青がz）、binding 3 に入力の範囲（uniform、`domain_min` と `domain_max` の `vec4<f32>`）も渡す。
+///
+/// フレームごとのコピーは入力テクスチャへのアップロード1回だけで、CPUへの読み戻しはしない。
+/// テクスチャの上限を超えるフレームはCPUで縮小してからアップロードする。
+pub struct TextureCompute {
+    label: &'static str,
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    // 入力フレーム（サイズが変わったときだけ作り直す）
+    input: Option<wgpu::Texture>,
+    // `with_lut` で作ったときの3D LUTと入力の範囲（作ったら変えない）
+    lut: Option<(wgpu::TextureView, wgpu::Buffer)>,
+    // コンピュートシェーダーの出力先で、eguiがそのまま描画するテクスチャ
+    output: EguiTexture,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    // テクスチャの一辺の上限（デバイスの上限と --max-texture-size の小さい方）
+    max_side: u32,
+    // 直近の縮小率（変化したときだけ警告を出す）
+    downscale_factor: u32,
+    // 表示アスペクト比を反映したサイズ（最初のフレームが来るまではNone）
+    display_size: Option<egui::Vec2>,
+    // 直近のフレームの (幅, 高さ)（変わったら `resize` で作り直す）
+    last_dimensions: Option<(u32, u32)>,
+}
+
+impl TextureCompute {
+    /// シェーダーのワークグループの一辺（`@workgroup_size(16, 16)`）
+    pub const TILE_SIZE: u32 = 16;
+
+    /// `shader`（WGSL）を `output` のデバイスでコンパイルし、試しに1回ディスパッチする
+    ///
+    /// `label` はwgpuのオブジェクト名に使う。パイプラインが作れない・動かないデバイスでは `Err` を返すので、
+    /// 呼び出し側で別のバックエンドにフォールバックできる。
+    pub fn new(
+        output: EguiTexture,
+        label: &'static str,
+        shader: &str,
+        max_side_override: Option<usize>,
+    ) -> Result<Self> {
+        Self::build(output, label, shader, max_side_override, None)
+    }
+
+    /// [`TextureCompute::new`] と同じだが、`lut` を3Dテクスチャにしてシェーダーの binding 2 / 3 に渡す
+    pub fn with_lut(
+        output: EguiTexture,
+        label: &'static str,
+        shader: &str,
+        max_side_override: Option<usize>,
+        lut: &CubeLut,
+    ) -> Result<Self> {
+        Self::build(output, label, shader, max_side_override, Some(lut))
+    }
+
+    fn build(
+        output: EguiTexture,
+        label: &'static str,
+        shader: &str,
+        max_side_override: Option<usize>,
+        lut: Option<&CubeLut>,
+    ) -> Result<Self> {
+        let device = output.device().clone();
+        let queue = output.queue().clone();
+        setup_on_device(&device.clone(), move || {
+            // コンピュートシェーダーのコンパイル
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(shader.into()),
+            });
+
+            let mut entries = vec![
+                // Input texture (Rgba8Uint)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Uint,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                // Output texture (eguiが描画する)
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: EguiTexture::FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ];
+            if lut.is_some() {
+                entries.extend([
+                    // 3D LUT (Rgba32Float、補間はシェーダーで行うのでサンプラーは使わない)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D3,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // LUTの入力の範囲 (DOMAIN_MIN / DOMAIN_MAX)
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                        count: None,
+                    },
+                ]);
+            }
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some(label),
+                    entries: &entries,
+                });
+            let lut = lut.map(|lut| upload_lut(&device, &queue, label, lut));
+
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(label),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+            let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some("main"),
+                compilation_options: Default::default(),
+                cache: None,
+            });
+
+            let device_max = device.limits().max_texture_dimension_2d;
+            let max_side = match max_side_override {
+                Some(max) => device_max.min(max as u32),
+                None => device_max,
+            };
+            let mut compute = Self {
+                label,
+                pipeline,
+                bind_group_layout,
+                input: None,
+                lut,
+                output,
+                device,
+                queue,
+                max_side: max_side.max(1),
+                downscale_factor: 1,
+                display_size: None,
+                last_dimensions: None,
+            };
+            // 実行時に失敗するデバイスもあるので、試しに1回ディスパッチしておく
+            compute.dispatch(1, 1, &[0; 4]);
+            Ok(compute)
+        })
+    }
+
+    /// フレームを処理して出力テクスチャに書く（上限を超えるフレームはCPUで縮小してから）
+    pub fn process(&mut self, raw: &OwnedFrame) {
+        let (width, height) = (raw.width as usize, raw.height as usize);
+        if width == 0 || height == 0 {
+            return;
+        }
+        if self.last_dimensions != Some((width as u32, height as u32)) {
+            self.resize(width as u32, height as u32);
+        }
+        let factor = width.max(height).div_ceil(self.max_side as usize).max(1) as u32;
+        if factor != self.downscale_factor {
+            if factor > 1 {
+                warn!(
+                    "Frame {width}x{height} exceeds max texture size {}; \
+                     downscaling by 1/{factor} before upload.",
+                    self.max_side
+                );
+            }
+            self.downscale_factor = factor;
+        }
+
+        if factor > 1 {
+            let (data, w, h) = downscale_rgba(&raw.data, width, height, factor as usize);
+            self.dispatch(w as u32, h as u32, &data);
+        } else {
+            self.dispatch(width as u32, height as u32, &raw.data);
+        }
+        self.display_size = Some(raw.display_size());
+    }
+
+    /// フレームの解像度が変わったときに、前の解像度のテクスチャを解放する（`process` が呼ぶ）
+    ///
+    /// 新しいテクスチャは次の `dispatch` で作る。4Kから8Kへの切り替えなどで、新旧のテクスチャが
+    /// 同時にGPUメモリに載らないよう先に捨てる。
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if let Some(input) = self.input.take() {
+            input.destroy();
+        }
+        if let Some((last_width, last_height)) = self.last_dimensions {
+            debug!(
+                "{}: reallocating textures for {last_width}x{last_height} -> {width}x{height}",
+                self.label
+            );
+        }
+        self.last_dimensions = Some((width, height));
+    }
+
+    /// パックされたRGBA `input` を処理して出力テクスチャに書く（読み戻さずに送信するだけ）
+    pub fn dispatch(&mut self, width: u32, height: u32, input: &[u8]) {
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        // 入力テクスチャはサイズが変わったときだけ作り直す
+        if self
+            .input
+            .as_ref()
+            .is_none_or(|texture| texture.size() != size)
+        {
+            self.input = Some(self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(self.label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Uint,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            }));
+        }
+        let input_texture = self.input.as_ref().expect("input texture was just created");
+
+        // フレームをアップロード（CPU→GPUのコピーはこの1回だけ）
+        self.queue.write_texture(
+            input_texture.as_image_copy(),
+            input,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: None,
+            },
+            size,
+        );
+        let input_view = input_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // バインドグループを作成（出力はeguiに登録済みのテクスチャ）
+        let output_view = self.output.target(width, height);
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&input_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(output_view),
+            },
+        ];
+        if let Some((lut_view, domain)) = &self.lut {
+            entries.extend([
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: domain.as_entire_binding(),
+                },
+            ]);
+        }
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(self.label),
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some(self.label),
+            });
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some(self.label),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                width.div_ceil(Self::TILE_SIZE),
+                height.div_ceil(Self::TILE_SIZE),
+                1,
+            );
+        }
+
+        // 読み戻さずに送信するだけ。同じキューなので、この後のeguiの描画より先に実行される
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    /// 最初のフレームを処理するまでは `None`
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<egui::Response> {
+        let display_size = self.display_size?;
+        Some(self.output.show(ui, display_size))
+    }
+}
+
+// LUTを3Dテクスチャ（Rgba32Float、x = 赤）にし、入力の範囲をuniformバッファに入れる
+fn upload_lut(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: &'static str,
+    lut: &CubeLut,
+) -> (wgpu::TextureView, wgpu::Buffer) {
+    let n = lut.size() as u32;
+    let size = wgpu::Extent3d {
+        width: n,
+        height: n,
+        depth_or_array_layers: n,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    // .cube の並び（赤が一番速く変わる）はそのままテクスチャの x, y, z の順になる
+    let texels: Vec<[f32; 4]> = lut
+        .samples()
+        .iter()
+        .map(|&[r, g, b]| [r, g, b, 1.0])
+        .collect();
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(n * 16),
+            rows_per_image: Some(n),
+        },
+        size,
+    );
+
+    let (min, max) = lut.domain();
+    let domain = [min[0], min[1], min[2], 0.0, max[0], max[1], max[2], 0.0];
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents: bytemuck::cast_slice(&domain),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (view, buffer)
+}
@@ -0,0 +1,147 @@
+//! フレームのRGB・輝度ヒストグラム（露出の確認用、raw-viewer の H キー）
+
+use crate::ValidatedFrame;
+
+/// チャンネルごとの256段階の画素数
+///
+/// アルファは見ない（透明な画素も数える）。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    pub red: [u32; 256],
+    pub green: [u32; 256],
+    pub blue: [u32; 256],
+    /// BT.709の係数で求めた輝度（Y'）
+    pub luma: [u32; 256],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            red: [0; 256],
+            green: [0; 256],
+            blue: [0; 256],
+            luma: [0; 256],
+        }
+    }
+}
+
+impl Histogram {
+    /// 数えた画素数
+    pub fn total(&self) -> u64 {
+        self.luma.iter().map(|&count| count as u64).sum()
+    }
+
+    /// R・G・Bのうち最も多いビンの画素数（グラフの縦軸を揃えるのに使う）
+    pub fn max_rgb(&self) -> u32 {
+        [&self.red, &self.green, &self.blue]
+            .into_iter()
+            .flatten()
+            .copied()
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 輝度で最も多いビンの画素数
+    pub fn max_luma(&self) -> u32 {
+        self.luma.iter().copied().max().unwrap_or(0)
+    }
+}
+
+/// `frame` のRGBと輝度のヒストグラムを1回の走査で数える
+///
+/// 行ごとにパディングを飛ばすので、詰められていないフレームでもよい。
+/// 圧縮されたままのフレームは画素を読めないので空のヒストグラムを返す。
+pub fn compute_histogram(frame: &ValidatedFrame) -> Histogram {
+    let mut histogram = Histogram::default();
+    if frame.compressed {
+        return histogram;
+    }
+    for y in 0..frame.height.max(0) as usize {
+        for px in frame.row(y).chunks_exact(4) {
+            let (r, g, b) = (px[0], px[1], px[2]);
+            histogram.red[r as usize] += 1;
+            histogram.green[g as usize] += 1;
+            histogram.blue[b as usize] += 1;
+            // BT.709（0.2126, 0.7152, 0.0722）を256倍した整数係数、合計256なので255を超えない
+            let luma = (54 * r as u32 + 183 * g as u32 + 19 * b as u32 + 128) >> 8;
+            histogram.luma[luma as usize] += 1;
+        }
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ColorInfo, OwnedFrame, PixelFormat};
+
+    fn owned(width: i32, height: i32, data: Vec<u8>) -> OwnedFrame {
+        OwnedFrame {
+            width,
+            height,
+            data,
+            timecode: 0,
+            timestamp: 0,
+            format: PixelFormat::RGBA,
+            source_format: PixelFormat::RGBA,
+            color_info: ColorInfo::default(),
+            picture_aspect_ratio: 0.0,
+            compressed: false,
+        }
+    }
+
+    #[test]
+    fn counts_each_channel_and_skips_the_line_padding() {
+        // 3x2、各行の後ろに4バイトのパディング（値128はどの画素にもない）
+        let rows = [
+            [[255, 255, 255, 0], [0, 0, 0, 255], [255, 0, 0, 255]],
+            [[0, 255, 0, 255], [0, 0, 255, 255], [0, 0, 0, 255]],
+        ];
+        let data: Vec<u8> = rows
+            .iter()
+            .flat_map(|row| row.iter().flatten().copied().chain([128; 4]))
+            .collect();
+        let frame = owned(3, 2, Vec::new());
+        let validated = ValidatedFrame {
+            data: &data,
+            line_stride: 3 * 4 + 4,
+            packed: false,
+            ..frame.as_validated()
+        };
+        let histogram = compute_histogram(&validated);
+
+        assert_eq!(histogram.total(), 3 * 2);
+        assert_eq!((histogram.red[255], histogram.red[0]), (2, 4));
+        assert_eq!((histogram.green[255], histogram.green[0]), (2, 4));
+        assert_eq!((histogram.blue[255], histogram.blue[0]), (2, 4));
+        for bins in [
+            &histogram.red,
+            &histogram.green,
+            &histogram.blue,
+            &histogram.luma,
+        ] {
+            assert_eq!(bins[128], 0);
+            assert_eq!(bins.iter().sum::<u32>(), 6);
+        }
+        assert_eq!(histogram.max_rgb(), 4);
+
+        // 白は255、黒は0。原色はBT.709の係数どおり
+        assert_eq!(histogram.luma[255], 1);
+        assert_eq!(histogram.luma[0], 2);
+        assert_eq!(histogram.luma[54], 1);
+        assert_eq!(histogram.luma[182], 1);
+        assert_eq!(histogram.luma[19], 1);
+        assert_eq!(histogram.max_luma(), 2);
+    }
+
+    #[test]
+    fn compressed_frames_give_an_empty_histogram() {
+        let frame = OwnedFrame {
+            compressed: true,
+            ..owned(2, 2, vec![0, 0, 0, 1, 0x67, 0x42, 0x00, 0x1E])
+        };
+        let histogram = compute_histogram(&frame.as_validated());
+        assert_eq!(histogram, Histogram::default());
+        assert_eq!(histogram.total(), 0);
+    }
+}
@@ -1,186 +1,2985 @@
 use anyhow::Result;
+use arc_swap::{ArcSwap, ArcSwapOption};
+#[cfg(feature = "gui")]
+use eframe::egui;
 use grafton_ndi::{
-    Error, Finder, FinderOptions, LineStrideOrSize, PixelFormat, Receiver, ReceiverColorFormat,
-    ReceiverOptions, NDI,
+    Error, Finder, FinderOptions, FinderOptionsBuilder, LineStrideOrSize, Receiver,
+    ReceiverBandwidth, ReceiverOptions, Source, SourceAddress, VideoFrameRef, NDI,
 };
-use std::env;
-use std::time::Duration;
+use std::fmt;
+use std::ops::ControlFlow;
+#[cfg(feature = "gui")]
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, trace, warn};
+use tracing_subscriber::EnvFilter;
+
+use crate::audio::ChannelMix;
+use crate::color::ToneMapper;
+use crate::freeze::FreezeDetector;
+
+pub mod audio;
+pub mod cli;
+pub mod color;
+pub mod config;
+#[cfg(feature = "control")]
+pub mod control;
+pub mod convert;
+pub mod crop;
+pub mod deinterlace;
+pub mod delivery;
+#[cfg(feature = "gui")]
+pub mod display;
+pub mod filter;
+pub mod frame_stats;
+pub mod freeze;
+#[cfg(feature = "gui")]
+pub mod gpu;
+pub mod histogram;
+pub mod json;
+pub mod lut;
+pub mod params;
+pub mod pipeline;
+pub mod probe;
+pub mod ptz;
+pub mod record;
+pub mod snapshot;
+pub mod stream;
+pub mod sync;
+pub mod transform;
+#[cfg(feature = "gui")]
+pub mod verify;
+
+pub use cli::CliArgs;
+pub use color::ColorInfo;
+pub use config::ViewerConfig;
+pub use convert::downscale_rgba;
+#[cfg(feature = "gui")]
+pub use display::{
+    quality_indicator, status_label, store_latest, waiting_label, AlphaBackground, FramePacer,
+    FrameTexture, HelpOverlay, InfoOverlay, Playback, ScaleMode, SourceOverlay, TextureFilter,
+    Toast,
+};
+pub use frame_stats::FrameStats;
+/// 受信フォーマットの指定（[`CliArgs::color_format`]）と、届いたフレームのフォーマット
+pub use grafton_ndi::{PixelFormat, ReceiverColorFormat};
+pub use histogram::{compute_histogram, Histogram};
+pub use lut::CubeLut;
+pub use params::Params;
+pub use pipeline::Pipeline;
+pub use ptz::PtzControl;
+pub use record::FrameRecorder;
+pub use snapshot::save_frame_png;
+pub use stream::FrameStream;
+pub use transform::{flip_rgba, rotate_rgba, Rotation, Transform, TransformControl};
 
 // ここに探したいNDIソース名を入れてください
 // ※ 空文字 "" にすると、最初に見つかったソースに接続します
 pub const TARGET_SOURCE_NAME: &str = "";
 
+/// `--color-format` を指定しなかったときの受信フォーマット
+///
+/// RGBA/RGBXのまま届くので、変換もコピーもせずにそのままテクスチャへ渡せる。
+pub const DEFAULT_COLOR_FORMAT: ReceiverColorFormat = ReceiverColorFormat::RGBX_RGBA;
+
+/// キーボードショートカット
+///
+/// 新しいショートカットは [`SHORTCUTS`] にも追加し、ヘルプ表示と同期させること
+/// （1つのビューアだけのものは [`HelpOverlay::with_extra`] に渡す）。
+#[cfg(feature = "gui")]
+pub struct Shortcut {
+    /// どれか1つを押すと発動するキー
+    pub keys: &'static [egui::Key],
+    pub description: &'static str,
+}
+
+#[cfg(feature = "gui")]
+impl Shortcut {
+    /// このフレームで押されたか
+    pub fn pressed(&self, ctx: &egui::Context) -> bool {
+        ctx.input(|i| self.keys.iter().any(|key| i.key_pressed(*key)))
+    }
+
+    /// 押し続けているか（押している間だけ続く操作用）
+    pub fn down(&self, ctx: &egui::Context) -> bool {
+        ctx.input(|i| self.keys.iter().any(|key| i.key_down(*key)))
+    }
+
+    /// ヘルプ表示用のキー名（例: "? / F1"）
+    pub fn key_label(&self) -> String {
+        self.keys
+            .iter()
+            .map(|key| key.symbol_or_name())
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_HELP: Shortcut = Shortcut {
+    keys: &[egui::Key::Questionmark, egui::Key::F1],
+    description: "Show / hide this help",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_FULLSCREEN: Shortcut = Shortcut {
+    keys: &[egui::Key::F11, egui::Key::Enter],
+    description: "Toggle fullscreen",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_EXIT_FULLSCREEN: Shortcut = Shortcut {
+    keys: &[egui::Key::Escape],
+    description: "Leave fullscreen",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_INFO: Shortcut = Shortcut {
+    keys: &[egui::Key::I],
+    description: "Show / hide timecode, source and resolution",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_ASPECT: Shortcut = Shortcut {
+    keys: &[egui::Key::A],
+    description: "Toggle fit (keep aspect ratio) / fill (stretch to the window)",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_CHECKERBOARD: Shortcut = Shortcut {
+    keys: &[egui::Key::C],
+    description: "Show / hide a checkerboard behind transparent areas",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_PAUSE: Shortcut = Shortcut {
+    keys: &[egui::Key::Space],
+    description: "Freeze on the current frame / resume",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_ROTATE: Shortcut = Shortcut {
+    keys: &[egui::Key::T],
+    description: "Rotate the picture 90° clockwise",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_FLIP_HORIZONTAL: Shortcut = Shortcut {
+    keys: &[egui::Key::F],
+    description: "Flip the picture horizontally (mirror)",
+};
+
+#[cfg(feature = "gui")]
+pub const SHORTCUT_FLIP_VERTICAL: Shortcut = Shortcut {
+    keys: &[egui::Key::V],
+    description: "Flip the picture vertically",
+};
+
+/// 全ビューア共通のショートカット一覧
+#[cfg(feature = "gui")]
+pub const SHORTCUTS: &[Shortcut] = &[
+    SHORTCUT_HELP,
+    SHORTCUT_FULLSCREEN,
+    SHORTCUT_EXIT_FULLSCREEN,
+    SHORTCUT_INFO,
+    SHORTCUT_ASPECT,
+    SHORTCUT_CHECKERBOARD,
+    SHORTCUT_PAUSE,
+    SHORTCUT_ROTATE,
+    SHORTCUT_FLIP_HORIZONTAL,
+    SHORTCUT_FLIP_VERTICAL,
+];
+
+/// 全ビューア共通のキー操作（フルスクリーン、fit/fill、市松模様、一時停止、回転・反転の切り替え）と、ウィンドウの位置・大きさの記録。
+/// 各ビューアの `update` の最初に呼ぶ
+///
+/// ヘルプの開閉は [`HelpOverlay`] が受け持つ。テキスト入力中のキーは横取りしない。
+/// 設定ファイルの場所は `main` で読んだ `args` から取る。
+#[cfg(feature = "gui")]
+pub fn handle_common_input(ctx: &egui::Context, args: &CliArgs) {
+    // ウィンドウの位置・大きさは設定ファイルに覚えておく（設定ファイルの場所は最初の1回だけ調べる）
+    config::remember_window(ctx, || args.config_file().map(Path::to_path_buf));
+
+    if ctx.wants_keyboard_input() {
+        return;
+    }
+    let fullscreen = ctx.input(|i| i.viewport().fullscreen.unwrap_or(false));
+    if SHORTCUT_FULLSCREEN.pressed(ctx) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(!fullscreen));
+    } else if fullscreen && SHORTCUT_EXIT_FULLSCREEN.pressed(ctx) {
+        ctx.send_viewport_cmd(egui::ViewportCommand::Fullscreen(false));
+    }
+    if SHORTCUT_ASPECT.pressed(ctx) {
+        ScaleMode::toggle(ctx);
+    }
+    if SHORTCUT_CHECKERBOARD.pressed(ctx) {
+        AlphaBackground::toggle(ctx);
+    }
+    if SHORTCUT_PAUSE.pressed(ctx) {
+        Playback::toggle(ctx);
+    }
+    // 回転・反転は受信スレッドで次のフレームからかかる
+    if SHORTCUT_ROTATE.pressed(ctx) {
+        TransformControl::global().update(|t| Transform {
+            rotation: t.rotation.next(),
+            ..t
+        });
+    }
+    if SHORTCUT_FLIP_HORIZONTAL.pressed(ctx) {
+        TransformControl::global().update(|t| Transform {
+            flip_horizontal: !t.flip_horizontal,
+            ..t
+        });
+    }
+    if SHORTCUT_FLIP_VERTICAL.pressed(ctx) {
+        TransformControl::global().update(|t| Transform {
+            flip_vertical: !t.flip_vertical,
+            ..t
+        });
+    }
+}
+
+/// `img` のアスペクト比を保ったまま `available` に収まる最大サイズ（余りは上下か左右の黒帯になる）
+#[cfg(feature = "gui")]
+pub fn fit_preserve_aspect(available: egui::Vec2, img: egui::Vec2) -> egui::Vec2 {
+    // 0やNaNのサイズではアスペクト比を出せないので、利用可能な領域全体に広げる
+    if !(img.x > 0.0 && img.y > 0.0) {
+        return available;
+    }
+    let scale = (available.x / img.x).min(available.y / img.y).max(0.0);
+    img * scale
+}
+
+/// 受信状態の良し悪し（ビューア隅のインジケータ用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+    Good,
+    Degraded,
+    Bad,
+}
+
+impl Quality {
+    /// ドロップ率（0.0-1.0）とジッタから分類する
+    ///
+    /// 5%超のドロップか1フレーム間隔を超えるジッタで `Bad`、その半分で `Degraded`。
+    pub fn classify(drop_ratio: f64, jitter: Duration, frame_interval: Duration) -> Self {
+        if drop_ratio > 0.05 || jitter > frame_interval {
+            Self::Bad
+        } else if drop_ratio > 0.01 || jitter > frame_interval / 2 {
+            Self::Degraded
+        } else {
+            Self::Good
+        }
+    }
+
+    #[cfg(feature = "gui")]
+    pub fn color(self) -> egui::Color32 {
+        match self {
+            Self::Good => egui::Color32::from_rgb(0x2E, 0xCC, 0x40),
+            Self::Degraded => egui::Color32::from_rgb(0xFF, 0xDC, 0x00),
+            Self::Bad => egui::Color32::from_rgb(0xFF, 0x41, 0x36),
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Good => "Good",
+            Self::Degraded => "Degraded",
+            Self::Bad => "Bad",
+        }
+    }
+}
+
+/// 接続できているのに映像が来ないソースが、代わりに送ってきているもの
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoVideo {
+    /// 音声が届いている（メタデータもあってよい）
+    AudioOnly,
+    /// メタデータだけが届いている
+    MetadataOnly,
+}
+
+impl NoVideo {
+    /// 待機画面に出す説明
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::AudioOnly => "Connected \u{2014} audio only, no video",
+            Self::MetadataOnly => "Connected \u{2014} metadata only, no video",
+        }
+    }
+}
+
+/// 届いているのに表示できないフレームの理由（[`StreamStats::frame_error`]）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// 圧縮されたまま（H.264/HEVCなど）のフレーム。デコードする経路がないので表示できない
+    ///
+    /// [`NdiReceiver::with_compressed`] を有効にすればコールバックにはそのまま渡る。
+    CompressedUnsupported { format: PixelFormat },
+    /// 幅か高さが0以下のフレーム（フォーマットのネゴシエーション中に届くことがある）
+    ///
+    /// サイズの計算に使えないので、受信ループは何も計算せずに捨てる。
+    EmptySize { width: i32, height: i32 },
+}
+
+impl FrameError {
+    /// 幅か高さが0以下なら [`FrameError::EmptySize`]
+    pub fn empty_size(width: i32, height: i32) -> Option<Self> {
+        (width <= 0 || height <= 0).then_some(Self::EmptySize { width, height })
+    }
+
+    /// 圧縮フレームなら [`FrameError::CompressedUnsupported`]
+    pub fn compressed(line_stride_or_size: LineStrideOrSize, format: PixelFormat) -> Option<Self> {
+        match line_stride_or_size {
+            LineStrideOrSize::LineStrideBytes(_) => None,
+            LineStrideOrSize::DataSizeBytes(_) => Some(Self::CompressedUnsupported { format }),
+        }
+    }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CompressedUnsupported { format } => write!(
+                f,
+                "Connected \u{2014} compressed video ({format:?}) is not supported"
+            ),
+            Self::EmptySize { width, height } => write!(
+                f,
+                "Connected \u{2014} the source reports an empty frame size ({width}x{height})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// 受信統計（run_loopが更新し、GUIが読む）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamStats {
+    /// 接続してからの受信/ドロップ数（NDI SDKの統計）
+    pub received: u64,
+    pub dropped: u64,
+    /// 直近の集計区間でのドロップ率（0.0-1.0）
+    pub recent_drop_ratio: f64,
+    /// フレーム到着間隔のジッタ（平滑化済み）
+    pub jitter: Duration,
+    /// ソースのフレームレートから求めた本来の間隔（未受信なら0）
+    pub frame_interval: Duration,
+    /// 同じ画が届き続けている時間（フリーズ検出が無効なら常に0）
+    pub frozen_for: Duration,
+    /// 接続中で音声/メタデータは届くのに映像が `NO_VIDEO_AFTER` 以上来ていない（映像が届けば `None`）
+    pub no_video: Option<NoVideo>,
+    /// 映像は届いているが表示できない（圧縮ストリームなど）。表示できるフレームが届けば `None`
+    pub frame_error: Option<FrameError>,
+    /// 最後に届いたフレームのタイムコード（100ns単位、[`format_timecode`] で表示する）
+    pub timecode: i64,
+    /// 最後に届いたフレームの解像度（未受信なら `[0, 0]`）
+    pub frame_size: [i32; 2],
+}
+
+impl StreamStats {
+    pub fn quality(&self) -> Quality {
+        Quality::classify(self.recent_drop_ratio, self.jitter, self.frame_interval)
+    }
+}
+
+/// run_loopの1回の受信待ち時間の既定値で、上限（`--capture-timeout` で短くできる）
+///
+/// 接続断の判定（[`ReconnectPolicy::max_timeout_retries`]、`--max-connect-attempts`）は、
+/// 受信待ちを短くしてもこの長さを1回のタイムアウトとして数える。
+pub const CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// ドロップ率を集計し直す間隔
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+// 同じ画がこの時間続いたらフリーズとして警告する
+const FREEZE_WARN_AFTER: Duration = Duration::from_secs(2);
+
+// 接続中に映像がこの時間来ず、音声かメタデータが届いていれば映像なしのソースとみなす
+const NO_VIDEO_AFTER: Duration = Duration::from_secs(3);
+
+// ソースが1つも見つからないまま、この時間が経ったらファイアウォールを疑うヒントを出す
+const DISCOVERY_HINT_AFTER: Duration = Duration::from_secs(10);
+
+// 設定ファイルに覚えているソースは、ほかのソースが先に見つかってもこれだけ待つ
+// （mDNSではソースが1つずつ見つかっていくため）
+const SAVED_SOURCE_WAIT: Duration = Duration::from_secs(3);
+
+// ソース一覧を取り直す間隔（変化があればすぐに取り直す）
+#[cfg(feature = "gui")]
+const SOURCE_REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+
+// 復帰可能なキャプチャエラー後の待ち時間（失敗が続くたびに倍、成功で戻す）
+const RETRY_BACKOFF_MIN: Duration = Duration::from_millis(100);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(5);
+
+// 接続断の後、同じ名前のソースを探し直す時間（見つからなければ前のアドレスのまま作り直す）
+const REDISCOVER_TIMEOUT: Duration = Duration::from_secs(3);
+
+// run_loop_av（または with_metadata）で映像を待つ1回の時間（この間隔で音声・メタデータを取り出す）
+const AUDIO_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// 映像を待たせないよう、1回に取り出す音声フレームの上限
+const MAX_AUDIO_FRAMES_PER_POLL: usize = 16;
+
+// 同じく、1回に取り出すメタデータフレームの上限
+const MAX_METADATA_FRAMES_PER_POLL: usize = 16;
+
+/// 受信時の縮小（`--scale 1/n`）で指定できるnの上限
+pub const MAX_DOWNSCALE: usize = 8;
+
+/// 接続断の検出と再接続の方法（[`NdiReceiver::run_loop_with_policy`]）
+///
+/// 既定値は検出なしで、これまでどおりキャプチャエラーだけを [`CaptureErrorPolicy`] で扱う。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// ソースにつながっていないタイムアウト（1回 `CAPTURE_TIMEOUT`）がこの回数続いたら
+    /// 接続断とみなして受信機を作り直す（`None` なら検出しない）
+    pub max_timeout_retries: Option<u32>,
+    /// 再接続までの最初の待ち時間（失敗が続くたびに倍、最大5秒）
+    pub backoff: Duration,
+    /// trueなら受信機を作り直す前に同じ名前のソースを探し直す（再起動でアドレスが変わっても追える）
+    pub rediscover_on_loss: bool,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_timeout_retries: None,
+            backoff: RETRY_BACKOFF_MIN,
+            rediscover_on_loss: false,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// `--reconnect-after <secs>` があれば、その時間つながらなければ探し直して再接続する
+    pub fn from_args(args: &CliArgs) -> Self {
+        match args.reconnect_after {
+            Some(after) => Self {
+                max_timeout_retries: Some(
+                    (after.as_millis() / CAPTURE_TIMEOUT.as_millis()).max(1) as u32
+                ),
+                rediscover_on_loss: true,
+                ..Self::default()
+            },
+            None => Self::default(),
+        }
+    }
+}
+
+/// 再接続の経過（`run_loop_with_policy` の2つ目のコールバックに渡す）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconnectEvent {
+    /// 接続が切れた（理由）
+    Lost(String),
+    /// 受信機を作り直している（1から数える）
+    Reconnecting { attempt: u32 },
+    /// 作り直した後、最初のフレームが届いた
+    Restored,
+}
+
+/// 受信機の接続状態（[`NdiReceiver::connection_state`]、[`ReceiverHandle::connection_state`]）
+///
+/// 「テクスチャがまだない」ことから推測せずに、探索中・接続中・再接続中・失敗を出し分けるためのもの。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// ソースを探している（[`ReceiverHandle`] が接続を始めてから受信機ができるまで）
+    #[default]
+    Searching,
+    /// 受信機を作り、最初の映像フレームを待っている
+    Connecting,
+    /// 映像が届いている（"名前@アドレス"）
+    Connected { source: String },
+    /// 接続が切れ、受信機を作り直している
+    Reconnecting,
+    /// 接続か受信がエラーで終わった（理由）
+    Failed(String),
+}
+
+impl ConnectionState {
+    /// エラーで終わった状態（NDIのランタイムが無ければ、その説明だけを出す）
+    pub fn failed(error: &anyhow::Error) -> Self {
+        match NdiError::of(error) {
+            Some(ndi_error) => Self::Failed(ndi_error.to_string()),
+            None => Self::Failed(format!("{error:#}")),
+        }
+    }
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Searching => f.write_str("Searching for NDI sources..."),
+            Self::Connecting => f.write_str("Connecting \u{2014} waiting for video..."),
+            Self::Connected { source } => write!(f, "Connected to {source}"),
+            Self::Reconnecting => f.write_str("Connection lost \u{2014} reconnecting..."),
+            Self::Failed(reason) => write!(f, "Failed: {reason}"),
+        }
+    }
+}
+
+/// タイムアウト以外のキャプチャエラーが起きたときの動作（`--on-capture-error`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureErrorPolicy {
+    /// ログを出し、待ってから同じ受信機で続ける
+    Retry,
+    /// 待ってから同じソースに受信機を作り直す
+    #[default]
+    Reconnect,
+    /// run_loopをエラーで終了する（以前の動作）
+    Fail,
+}
+
+impl CaptureErrorPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "retry" => Some(Self::Retry),
+            "reconnect" => Some(Self::Reconnect),
+            "fail" => Some(Self::Fail),
+            _ => None,
+        }
+    }
+}
+
+/// 行ストライドやデータ長が合わないフレームの扱い（`--strict` / `--lenient`）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// 捨てて次のフレームを待つ
+    #[default]
+    Strict,
+    /// 行を詰め直し、足りない部分を黒で埋めてでも表示する（補った内容はログに出す）
+    Lenient,
+}
+
+/// 一時的なネットワーク障害などで、続ければ回復しうるエラーか
+///
+/// 初期化や設定の誤りは何度やっても失敗するので致命的とみなす。
+fn is_recoverable(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::CaptureFailed(_)
+            | Error::InvalidFrame(_)
+            | Error::NullPointer(_)
+            | Error::Io(_)
+            | Error::Disconnected { .. }
+            | Error::SourceUnavailable { .. }
+    )
+}
+
+/// 接続・受信をあきらめた理由（プロセスの終了コードに対応する）
+///
+/// systemd などの監視下で、どこで失敗したかを終了コードで区別できるようにする。
+/// 正常終了は0、ここに当てはまらないエラーは1。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GiveUp {
+    /// NDIを初期化できなかった（SDKのランタイムが無いなど）
+    NdiInit,
+    /// ソースが見つからなかった（`--discovery-timeout` 切れ、名前・番号・アドレスに一致なし）
+    Discovery,
+    /// 接続したソースが `--max-connect-attempts` 回続けて受信できなかった
+    SourceLost,
+}
+
+impl GiveUp {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::NdiInit => 2,
+            Self::Discovery => 3,
+            Self::SourceLost => 4,
+        }
+    }
+
+    /// エラーの原因をたどって分類する
+    pub fn of(error: &anyhow::Error) -> Option<Self> {
+        error
+            .chain()
+            .find_map(|cause| cause.downcast_ref().copied())
+    }
+
+    // 理由を原因として持つエラーを作る（表示は `message` のまま）
+    fn because(self, message: impl fmt::Display + Send + Sync + 'static) -> anyhow::Error {
+        anyhow::Error::new(self).context(message)
+    }
+}
+
+impl fmt::Display for GiveUp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::NdiInit => "NDI initialization failed",
+            Self::Discovery => "gave up discovering NDI sources",
+            Self::SourceLost => "NDI source vanished",
+        })
+    }
+}
+
+impl std::error::Error for GiveUp {}
+
+/// [`GiveUp`] で終わったエラーなら、対応する終了コードでプロセスを終了する
+///
+/// GUIのビューアは受信スレッドが諦めてもウィンドウが残るので、監視側が気付けるようにここで落とす。
+/// それ以外のエラーでは何もしない。
+pub fn exit_if_given_up(error: &anyhow::Error) {
+    if let Some(reason) = GiveUp::of(error) {
+        error!("Exiting: {error:#}");
+        std::process::exit(reason.exit_code());
+    }
+}
+
+// NDIのランタイムの入手先（NDI Tools にも含まれる）
+const NDI_RUNTIME_URL: &str = "https://ndi.video/tools/";
+
+/// NDIそのものを使えない理由（[`init_ndi`]）
+///
+/// [`GiveUp::NdiInit`] を原因に持つエラーとして返るので、終了コードは2のまま。
+/// GUIのビューアはこれだけは終了せず、ウィンドウに説明を出し続ける。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NdiError {
+    /// NDIのランタイム（Windowsなら Processing.NDI.Lib.x64.dll、Linuxなら libndi.so）を
+    /// 読み込めない・初期化できない（SDKの説明）
+    RuntimeMissing { detail: String },
+}
+
+impl NdiError {
+    /// エラーの文脈をたどって取り出す
+    pub fn of(error: &anyhow::Error) -> Option<&Self> {
+        error.downcast_ref()
+    }
+}
+
+impl fmt::Display for NdiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RuntimeMissing { detail } => write!(
+                f,
+                "NDI runtime not found ({detail}). Install the NDI SDK or NDI Tools \
+                 from {NDI_RUNTIME_URL} and restart the viewer."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NdiError {}
+
+/// NDIを初期化する
+///
+/// ランタイムが入っていないときは [`NdiError::RuntimeMissing`]（と [`GiveUp::NdiInit`]）のエラーを返す。
+pub fn init_ndi() -> Result<NDI> {
+    NDI::new().map_err(|e| match e {
+        Error::InitializationFailed(detail) => {
+            GiveUp::NdiInit.because(NdiError::RuntimeMissing { detail })
+        }
+        e => GiveUp::NdiInit.because(format!("Failed to initialize NDI: {e}")),
+    })
+}
+
+/// ログ（`tracing`）を標準エラーに出すようにする。各ビューアの `main` の最初で呼ぶ
+///
+/// 出す内容は `RUST_LOG`（例: `RUST_LOG=debug`、`RUST_LOG=rust_ndi_viewer=trace`）で絞れる。
+/// 未指定ならこのライブラリと `bin`（呼び出し側の `env!("CARGO_CRATE_NAME")`）は info 以上、
+/// wgpuやeframeなどほかのクレートは warn 以上。フレームごとのログは trace なので既定では出ない。
+/// 標準出力は `--probe` やsource-monitorの結果のために空けておく。
+pub fn init_tracing(bin: &str) {
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,rust_ndi_viewer=info,{bin}=info")));
+    // すでに初期化されていれば（ライブラリの利用者が先に設定していれば）そちらを使う
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .try_init();
+}
+
 /// バリデーション済みフレームデータ
+///
+/// `data` は常にRGBAかRGBX（`format`）。受信フォーマットによって扱いが変わる:
+///
+/// - RGBA / RGBX（既定の `RGBX_RGBA`）: 受信バッファをそのまま渡す（コピーなし）
+/// - BGRA / BGRX（`bgra`）、UYVY / UYVA（`uyvy`）、P216 / PA16、NV12、I420、YV12
+///   （`fastest` / `best` でソースから届くことがある）: [`convert::to_rgba`] でCPUで
+///   RGBAに変換してから渡す（フレームごとに1回の変換とコピー）。`source_format` に元の形式が残る
+/// - それ以外のフォーマット: 変換できないのでフレームを捨てる
 pub struct ValidatedFrame<'a> {
     pub width: i32,
     pub height: i32,
+    /// `packed` なら `width*4*height` バイト、そうでなければ行ごとに `line_stride` バイト
+    /// （`compressed` なら圧縮されたままのビットストリーム）
     pub data: &'a [u8],
     pub timecode: i64,
+    pub timestamp: i64,
+    /// RGBA か RGBX
+    pub format: PixelFormat,
+    /// 受信したままのフォーマット（UYVY、NV12 など。変換していなければ `format` と同じ）
+    pub source_format: PixelFormat,
+    /// フレームのメタデータから読んだ色空間・HDR情報
+    pub color_info: ColorInfo,
+    /// 送信側が指定した表示アスペクト比（幅/高さ、0なら正方ピクセル）
+    pub picture_aspect_ratio: f32,
+    /// 受信したままの1行あたりのバイト数
+    pub line_stride: i32,
+    /// `data` が `width*4` で詰められているか
+    pub packed: bool,
+    /// 受信側でデコードされていない圧縮フレームか（`line_stride` は0）
+    pub compressed: bool,
+}
+
+impl ValidatedFrame<'_> {
+    /// y行目の画素データ（`width*4` バイト、パディングを除く）。圧縮フレームには使えない
+    pub fn row(&self, y: usize) -> &[u8] {
+        let start = y * self.line_stride as usize;
+        &self.data[start..start + self.width as usize * 4]
+    }
+
+    /// 表示アスペクト比を反映した表示サイズ
+    #[cfg(feature = "gui")]
+    pub fn display_size(&self) -> egui::Vec2 {
+        display_size(self.width, self.height, self.picture_aspect_ratio)
+    }
+
+    /// 表示用の egui 画像にする（表示サイズ付き、RGBXは不透明として扱う）。圧縮フレームには使えない
+    #[cfg(feature = "gui")]
+    pub fn to_color_image(&self) -> egui::ColorImage {
+        let size = [self.width as usize, self.height as usize];
+        let mut image = if self.packed {
+            convert::rgba_to_color_image(size, self.data, self.format)
+        } else {
+            convert::rgba_to_color_image(size, &self.to_owned().data, self.format)
+        };
+        image.source_size = self.display_size();
+        image
+    }
+
+    /// `factor` 分の1にボックスフィルタで縮小したフレームを `out` に作る（`width*4` で詰めたRGBA）
+    ///
+    /// 端の半端なブロックはあるだけの画素で平均するので、幅・高さは切り上げになる。圧縮フレームには使えない。
+    pub fn downscaled<'b>(&self, factor: usize, out: &'b mut Vec<u8>) -> ValidatedFrame<'b> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (data, width, height) = if self.packed {
+            downscale_rgba(self.data, width, height, factor)
+        } else {
+            downscale_rgba(&self.to_owned().data, width, height, factor)
+        };
+        *out = data;
+        ValidatedFrame {
+            width: width as i32,
+            height: height as i32,
+            data: out,
+            timecode: self.timecode,
+            timestamp: self.timestamp,
+            format: self.format,
+            source_format: self.source_format,
+            color_info: self.color_info,
+            picture_aspect_ratio: self.picture_aspect_ratio,
+            line_stride: width as i32 * 4,
+            packed: true,
+            compressed: false,
+        }
+    }
+
+    /// 受信バッファから切り離した所有フレームにコピーする（パディングは除去される）
+    pub fn to_owned(&self) -> OwnedFrame {
+        let data = if self.packed || self.compressed {
+            self.data.to_vec()
+        } else {
+            let mut data = Vec::with_capacity(self.width as usize * 4 * self.height as usize);
+            for y in 0..self.height as usize {
+                data.extend_from_slice(self.row(y));
+            }
+            data
+        };
+        OwnedFrame {
+            width: self.width,
+            height: self.height,
+            data,
+            timecode: self.timecode,
+            timestamp: self.timestamp,
+            format: self.format,
+            source_format: self.source_format,
+            color_info: self.color_info,
+            picture_aspect_ratio: self.picture_aspect_ratio,
+            compressed: self.compressed,
+        }
+    }
+}
+
+/// スレッド間で受け渡せる所有フレーム（`width*4` で詰めたRGBA/RGBX）
+#[derive(Clone)]
+pub struct OwnedFrame {
+    pub width: i32,
+    pub height: i32,
+    pub data: Vec<u8>,
+    pub timecode: i64,
+    pub timestamp: i64,
+    pub format: PixelFormat,
+    /// 受信したままのフォーマット（[`ValidatedFrame::source_format`]）
+    pub source_format: PixelFormat,
+    pub color_info: ColorInfo,
+    pub picture_aspect_ratio: f32,
+    pub compressed: bool,
+}
+
+impl OwnedFrame {
+    /// 表示アスペクト比を反映した表示サイズ
+    #[cfg(feature = "gui")]
+    pub fn display_size(&self) -> egui::Vec2 {
+        display_size(self.width, self.height, self.picture_aspect_ratio)
+    }
+
+    /// [`ValidatedFrame`] を受け取る関数（[`snapshot::save_frame_png`] など）に渡すための借用
+    pub fn as_validated(&self) -> ValidatedFrame<'_> {
+        ValidatedFrame {
+            width: self.width,
+            height: self.height,
+            data: &self.data,
+            timecode: self.timecode,
+            timestamp: self.timestamp,
+            format: self.format,
+            source_format: self.source_format,
+            color_info: self.color_info,
+            picture_aspect_ratio: self.picture_aspect_ratio,
+            line_stride: if self.compressed { 0 } else { self.width * 4 },
+            packed: true,
+            compressed: self.compressed,
+        }
+    }
+}
+
+/// [`NdiReceiver::run_loop_av`] が音声コールバックに渡す音声フレーム
+///
+/// `samples` はプレーナ形式で、チャンネルごとに同じ数のサンプルを詰めて並べる
+/// （ch0の全サンプル、ch1の全サンプル、…）。SDKのチャンネルストライドは取り除き、
+/// `--audio-channels` / `--downmix`（[`audio::ChannelMix`]）も適用済み。
+/// インターリーブ（L R L R …）が必要なら [`audio::interleave`] で並べ替える。
+pub struct ValidatedAudioFrame<'a> {
+    /// サンプリング周波数（Hz）
+    pub sample_rate: i32,
+    pub channels: usize,
+    /// 32bit浮動小数点（-1.0〜1.0）、`channels * samples_per_channel()` 個
+    pub samples: &'a [f32],
+    pub timecode: i64,
+    pub timestamp: i64,
+}
+
+impl ValidatedAudioFrame<'_> {
+    /// 1チャンネルあたりのサンプル数
+    pub fn samples_per_channel(&self) -> usize {
+        self.samples.len() / self.channels.max(1)
+    }
+
+    /// c番目のチャンネルのサンプル
+    pub fn channel(&self, c: usize) -> &[f32] {
+        let samples = self.samples_per_channel();
+        &self.samples[c * samples..(c + 1) * samples]
+    }
+}
+
+/// [`NdiReceiver::with_metadata`] のコールバックに渡すメタデータフレーム
+///
+/// `data` は送信側が送ったXML（PTZの状態や独自のタグなど）をそのまま渡したもので、
+/// パースも整形もしない。UTF-8として不正なバイトだけは U+FFFD に置き換える。
+pub struct ValidatedMetadataFrame<'a> {
+    pub data: &'a str,
+    pub timecode: i64,
+}
+
+type MetadataCallback = Box<dyn FnMut(ValidatedMetadataFrame) + Send>;
+
+/// ソース探索を `groups`（`--groups`、カンマ区切り）のNDIグループに絞る
+///
+/// `None` か空白だけならそのまま返すので、グループを問わずすべてのソースを探す。
+pub fn with_discovery_groups(
+    builder: FinderOptionsBuilder,
+    groups: Option<&str>,
+) -> FinderOptionsBuilder {
+    match groups.map(str::trim).filter(|groups| !groups.is_empty()) {
+        Some(groups) => builder.groups(groups),
+        None => builder,
+    }
+}
+
+/// `frame` の (x, y) の画素のRGBA（範囲外と圧縮フレームは `None`）
+///
+/// RGBXフレームのアルファは未定義なので255にする。
+pub fn sample_pixel(frame: &ValidatedFrame, x: usize, y: usize) -> Option<[u8; 4]> {
+    if frame.compressed || x >= frame.width.max(0) as usize || y >= frame.height.max(0) as usize {
+        return None;
+    }
+    let px = frame.row(y).get(x * 4..x * 4 + 4)?;
+    let alpha = if frame.format == PixelFormat::RGBX {
+        255
+    } else {
+        px[3]
+    };
+    Some([px[0], px[1], px[2], alpha])
+}
+
+/// NDIのタイムコード（100ns単位）を `HH:MM:SS.mmm` にする
+///
+/// タイムコードは1日で折り返すものとして24時間で割った余りを出す（負の値も 23:59:59.999 側に折り返す）。
+pub fn format_timecode(ts: i64) -> String {
+    const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+    let ms = ts.div_euclid(10_000).rem_euclid(MS_PER_DAY);
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        ms % 1000
+    )
+}
+
+/// 表示アスペクト比を反映した表示サイズ（高さはそのままで幅を合わせる）
+///
+/// 比率が無い・不正な値のときは正方ピクセルとみなす。
+#[cfg(feature = "gui")]
+pub fn display_size(width: i32, height: i32, picture_aspect_ratio: f32) -> egui::Vec2 {
+    let height = height as f32;
+    if picture_aspect_ratio.is_finite() && picture_aspect_ratio > 0.0 {
+        egui::vec2(height * picture_aspect_ratio, height)
+    } else {
+        egui::vec2(width as f32, height)
+    }
+}
+
+/// 映像を送ってこないソースの検出
+///
+/// 映像待ちのタイムアウト中に `STATS_INTERVAL` ごとにSDKの統計を見て、受信機がソースに
+/// つながっていて、前回から音声かメタデータのフレーム数が増えていて、最後の映像から
+/// `NO_VIDEO_AFTER` 以上経っていれば映像なしと判定する。音声・メタデータは取り出さないが、
+/// SDKはキューからあふれた分も含めて受信数を数えるので増え方で分かる。
+struct NoVideoDetector {
+    // 最後に映像が届いた（または受信機を作った）時刻
+    last_video: Instant,
+    last_check: Instant,
+    // 前回見たときの音声・メタデータの受信数
+    audio_frames: u64,
+    metadata_frames: u64,
+}
+
+impl NoVideoDetector {
+    fn new(now: Instant) -> Self {
+        Self {
+            last_video: now,
+            last_check: now,
+            audio_frames: 0,
+            metadata_frames: 0,
+        }
+    }
+
+    /// 映像待ちがタイムアウトしたときに呼ぶ。判定し直したら結果を返す
+    fn check(&mut self, receiver: &Receiver, now: Instant) -> Option<Option<NoVideo>> {
+        if now.duration_since(self.last_check) < STATS_INTERVAL {
+            return None;
+        }
+        self.last_check = now;
+        let connection = receiver.connection_stats();
+        let audio = connection.audio_frames_received > self.audio_frames;
+        let metadata = connection.metadata_frames_received > self.metadata_frames;
+        self.audio_frames = connection.audio_frames_received;
+        self.metadata_frames = connection.metadata_frames_received;
+
+        if !receiver.is_connected() || now.duration_since(self.last_video) < NO_VIDEO_AFTER {
+            return Some(None);
+        }
+        Some(if audio {
+            Some(NoVideo::AudioOnly)
+        } else if metadata {
+            Some(NoVideo::MetadataOnly)
+        } else {
+            None
+        })
+    }
+}
+
+/// 探索で見つかったソース（[`NdiReceiver::list_sources`]、[`spawn_source_finder`]）
+///
+/// 別々のエンコーダーが同じ名前（`CAMERA (NDI Output)` など）で送っていても、
+/// `machine_name` と `url_address` で見分けられる。
+#[derive(Debug, Clone)]
+pub struct SourceInfo {
+    /// NDIのソース名（"MACHINE (Source Name)"）
+    pub name: String,
+    /// IPアドレス（ポート付き）か、NDI HXなどのURL（分からなければ `None`）
+    pub url_address: Option<String>,
+    /// 送信しているマシン名（ソース名の括弧の前。名前がその形でなければ `None`）
+    pub machine_name: Option<String>,
+    source: Source,
+}
+
+impl SourceInfo {
+    /// 接続に使うソース（[`NdiReceiver::connect_source`] に渡す）
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    /// ポートやスキームを除いたホスト名かIPアドレス
+    pub fn host(&self) -> Option<&str> {
+        self.source.host()
+    }
+}
+
+impl From<Source> for SourceInfo {
+    fn from(source: Source) -> Self {
+        let url_address = match &source.address {
+            SourceAddress::Url(address) | SourceAddress::Ip(address) => Some(address.clone()),
+            SourceAddress::None => None,
+        };
+        let machine_name = source
+            .name
+            .split_once(" (")
+            .filter(|(machine, rest)| !machine.is_empty() && rest.ends_with(')'))
+            .map(|(machine, _)| machine.to_string());
+        Self {
+            name: source.name.clone(),
+            url_address,
+            machine_name,
+            source,
+        }
+    }
+}
+
+impl fmt::Display for SourceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
 }
 
 /// NDI受信機の初期化と接続を管理
 pub struct NdiReceiver {
-    receiver: Receiver,
+    // PtzControl と共有する（再接続で差し替える）
+    receiver: Arc<Receiver>,
+    // 再接続用に受信機の作成情報を保持する
+    ndi: NDI,
+    recv_opts: ReceiverOptions,
+    // 探し直すときの追加のディスカバリIP/サブネットとNDIグループ
+    extra_ips: Vec<String>,
+    groups: Option<String>,
+    error_policy: CaptureErrorPolicy,
+    validation: Validation,
+    // 続けて失敗してよい接続・受信の回数（Noneなら無制限）
+    max_connect_attempts: Option<u32>,
+    // run_loopの1回の受信待ち時間（停止フラグもこの間隔で確認する）
+    capture_timeout: Duration,
+    // trueならパディング付きの行もそのまま（packed: false で）コールバックに渡す
+    raw_stride: bool,
+    // trueなら圧縮フレームも（compressed: true で）コールバックに渡す
+    allow_compressed: bool,
+    // trueならHDR（PQ/HLG）のフレームをSDRにトーンマッピングしてから渡す
+    tone_map: bool,
+    // run_loop_av で音声に適用するチャンネル選択・ダウンミックス
+    audio_mix: ChannelMix,
+    // フリーズ検出（Noneなら無効）
+    freeze: Option<FreezeDetector>,
+    // trueになるとrun_loopが抜ける
+    stop: Arc<AtomicBool>,
+    // コールバックに渡す前にかける回転・反転（既定は全ビューア共通のもの）
+    transform: Arc<TransformControl>,
+    // 受信したメタデータを渡す先（Noneならメタデータは取り出さない）
+    on_metadata: Option<MetadataCallback>,
+    // コールバックに渡す前に何分の1に縮小するか（1なら縮小しない）
+    downscale: usize,
+    // 受信統計の共有先
+    stats: Arc<ArcSwap<StreamStats>>,
+    // 接続状態の共有先
+    connection_state: Arc<ArcSwap<ConnectionState>>,
+    // FPS・帯域の共有先
+    frame_stats: Arc<FrameStats>,
+    // 今の受信機を指すPTZ操作のハンドル
+    ptz: PtzControl,
+    // 最後に渡した非圧縮フレームの (幅, 高さ)（解像度の変化の検出用）
+    last_dimensions: Option<(u32, u32)>,
 }
 
 impl NdiReceiver {
-    /// NDIを初期化し、ソースを探索して接続する
-    pub fn connect() -> Result<Self> {
-        // Parse command line: allow picking extra discovery IPs if provided
-        let args: Vec<String> = env::args().collect();
-        let mut extra_ips = Vec::new();
-        let mut i = 1;
-        while i < args.len() {
-            if !args[i].starts_with("--") {
-                extra_ips.push(args[i].as_str());
+    /// `timeout` の間ソースを探索し、見つかったものを返す（1つもなければ空）
+    ///
+    /// 接続はしない。ソースが見つかるまで待ち続ける `connect` と違って必ず `timeout` で戻るので、
+    /// 「探索中…」を出しながら一覧を作るUIに使える。追加の探索IPとグループは `args.extra_ips` と
+    /// `args.groups` を使う。
+    pub fn list_sources(args: &CliArgs, timeout: Duration) -> Result<Vec<SourceInfo>> {
+        let ndi = init_ndi()?;
+        let mut builder = with_discovery_groups(
+            FinderOptions::builder().show_local_sources(true),
+            args.groups.as_deref(),
+        );
+        for ip in &args.extra_ips {
+            builder = builder.extra_ips(ip.as_str());
+        }
+        let finder = Finder::new(&ndi, &builder.build())?;
+
+        // mDNSではソースが1つずつ見つかっていくので、最初の1つで戻らず時間いっぱい集める
+        let start = Instant::now();
+        loop {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                break;
             }
-            i += 1;
+            finder.wait_for_sources(remaining)?;
         }
+        let sources = finder.sources(Duration::ZERO)?;
+        Ok(sources.into_iter().map(SourceInfo::from).collect())
+    }
+
+    /// NDIを初期化し、[`TARGET_SOURCE_NAME`] のソースを探索して接続する
+    ///
+    /// 探索IPや `--source-index` / `--address` は `main` で読んだ `args` を使う。
+    pub fn connect(args: &CliArgs) -> Result<Self> {
+        let source_name = (!TARGET_SOURCE_NAME.is_empty()).then_some(TARGET_SOURCE_NAME);
+        Self::connect_to(source_name, args)
+    }
+
+    /// `connect` と同じだが、`stop` がtrueになるとソース探索を打ち切る
+    ///
+    /// `stop` はそのままrun_loopの停止フラグになる。
+    pub fn connect_with_stop(args: &CliArgs, stop: Arc<AtomicBool>) -> Result<Self> {
+        let source_name = (!TARGET_SOURCE_NAME.is_empty()).then_some(TARGET_SOURCE_NAME);
+        Self::connect_to_with_stop(source_name, args, stop)
+    }
+
+    /// 指定した名前のソースに接続する（`None` なら最初に見つかったソース）
+    ///
+    /// `args.source_index` / `args.address` があれば同名ソースの中からさらに絞り込む。
+    /// コマンドラインを読まないので、`CliArgs { extra_ips, ..Default::default() }` のように
+    /// 組み立てればGUIのソース選択やテストからも使える。見つからなければ、見つかった
+    /// ソースを並べたエラー（[`GiveUp::Discovery`]）を返す。
+    ///
+    /// 受信フォーマットは `args.color_format`（`None` なら [`DEFAULT_COLOR_FORMAT`]）で選ぶ。
+    /// `CliArgs { color_format: Some(ReceiverColorFormat::Fastest), ..Default::default() }`
+    /// のようにすれば、コマンドラインなしでもソースのネイティブ形式で受け取れる。
+    pub fn connect_to(source_name: Option<&str>, args: &CliArgs) -> Result<Self> {
+        Self::connect_to_with_stop(source_name, args, Arc::new(AtomicBool::new(false)))
+    }
 
-        println!("NDI Video Receiver - GUI Frame Injector Example");
-        println!("==============================================\n");
+    /// `connect_to` と同じだが、`stop` がtrueになるとソース探索を打ち切る
+    pub fn connect_to_with_stop(
+        source_name: Option<&str>,
+        args: &CliArgs,
+        stop: Arc<AtomicBool>,
+    ) -> Result<Self> {
+        // 設定ファイルの探索IPも足し、ソースを名前・番号・アドレスのどれでも指定していなければ
+        // 前回のソースを優先する
+        let saved = args.config_file().and_then(config::load_or_warn);
+        let mut extra_ips = args.extra_ips.clone();
+        for ip in saved.iter().flat_map(|saved| &saved.extra_ips) {
+            if !extra_ips.contains(ip) {
+                extra_ips.push(ip.clone());
+            }
+        }
+        let extra_ips = &extra_ips;
+        let explicit =
+            source_name.is_some() || args.source_index.is_some() || args.address.is_some();
+        let saved_source = saved
+            .as_ref()
+            .map(|saved| saved.source_name.as_str())
+            .filter(|name| !explicit && !name.is_empty());
 
         // Initialize NDI
-        let ndi = NDI::new()?;
-        println!("NDI initialized successfully\n");
+        let ndi = init_ndi()?;
+        debug!("NDI initialized successfully");
 
         // Discover sources
-        let mut builder = FinderOptions::builder().show_local_sources(true);
+        let mut builder = with_discovery_groups(
+            FinderOptions::builder().show_local_sources(true),
+            args.groups.as_deref(),
+        );
+        if let Some(groups) = &args.groups {
+            info!("Searching NDI groups: {groups}");
+        }
 
         if !extra_ips.is_empty() {
-            println!("Searching additional IPs/subnets:");
-            for ip in &extra_ips {
-                println!("  - {ip}");
-                builder = builder.extra_ips(*ip);
+            info!("Searching additional IPs/subnets:");
+            for ip in extra_ips {
+                info!("  - {ip}");
+                builder = builder.extra_ips(ip.as_str());
             }
-            println!();
         }
 
         let finder = Finder::new(&ndi, &builder.build())?;
 
-        println!("Looking for sources ...");
-        let sources = loop {
-            finder.wait_for_sources(Duration::from_secs(1))?;
-            let sources = finder.sources(Duration::ZERO)?;
-            if !sources.is_empty() {
-                let count = sources.len();
-                println!("Found {count} source(s):");
-                for (i, source) in sources.iter().enumerate() {
-                    let num = i + 1;
-                    println!("  {num}. {source}");
-                }
-                break sources;
+        info!("Looking for sources ...");
+        let discovery_start = Instant::now();
+        let mut hinted = false;
+        let sources = loop {
+            if stop.load(Ordering::Relaxed) {
+                anyhow::bail!("Stopped before any NDI source was found");
+            }
+            let elapsed = discovery_start.elapsed();
+            if let Some(timeout) = args.discovery_timeout
+                && elapsed >= timeout
+            {
+                return Err(GiveUp::Discovery.because(format!(
+                    "No NDI source found within {}s. {}",
+                    timeout.as_secs_f32(),
+                    discovery_hint(extra_ips)
+                )));
+            }
+            if !hinted && elapsed >= DISCOVERY_HINT_AFTER {
+                warn!(
+                    "Still no NDI source after {}s. {}",
+                    elapsed.as_secs(),
+                    discovery_hint(extra_ips)
+                );
+                hinted = true;
+            }
+            finder.wait_for_sources(Duration::from_secs(1))?;
+            let sources = finder.sources(Duration::ZERO)?;
+            let waiting_for_saved = saved_source
+                .is_some_and(|saved| !sources.iter().any(|s| s.name == saved))
+                && elapsed < SAVED_SOURCE_WAIT;
+            if !sources.is_empty() && !waiting_for_saved {
+                let count = sources.len();
+                info!("Found {count} source(s):");
+                for (i, source) in sources.iter().enumerate() {
+                    let num = i + 1;
+                    info!("  {num}. {source}");
+                }
+                break sources;
+            }
+        };
+
+        warn_duplicate_names(&sources);
+
+        let source_name = match saved_source {
+            Some(saved) if sources.iter().any(|s| s.name == saved) => {
+                info!("Using the last connected source \"{saved}\" from the config file");
+                Some(saved)
+            }
+            Some(saved) => {
+                warn!("Last connected source \"{saved}\" not found; choosing as usual.");
+                source_name
+            }
+            None => source_name,
+        };
+
+        // Pick source by index, or by name (None = first available) and address
+        let source = select_source(
+            &sources,
+            source_name,
+            args.source_index,
+            args.address.as_deref(),
+        )
+        .map_err(|e| GiveUp::Discovery.because(e))?;
+
+        let mut receiver = Self::connect_source(&ndi, source.clone(), args)?;
+        // 探し直すときは設定ファイルの探索IPも使う
+        receiver.extra_ips = extra_ips.clone();
+
+        if let Some(path) = args.config_file() {
+            config::update_or_warn(path, |config| {
+                config.source_name = source.name.clone();
+                config.extra_ips = extra_ips.clone();
+            });
+        }
+
+        Ok(receiver.with_stop_flag(stop))
+    }
+
+    /// 探索済みの `source` に直接つなぐ
+    ///
+    /// コマンドラインのソース選択（`--source-index` / `--address`）も設定ファイルも見ず、
+    /// 接続先を設定ファイルに書きもしない。`args` からは受信の設定（カラーフォーマット・帯域・
+    /// エラー時の動作など）だけを使うので、1つの `NDI` から複数の受信機を同時に作れる（grid-viewer）。
+    /// 再接続で探し直すときは `args.extra_ips` と `args.groups` を使う。
+    pub fn connect_source(ndi: &NDI, source: Source, args: &CliArgs) -> Result<Self> {
+        info!("Creating receiver for: {}", source);
+        let recv_opts = ReceiverOptions::builder(source)
+            .color(args.color_format.unwrap_or(DEFAULT_COLOR_FORMAT))
+            .bandwidth(args.bandwidth)
+            .build();
+
+        let receiver = Arc::new(Receiver::new(ndi, &recv_opts)?);
+        let ptz = PtzControl::default();
+        ptz.attach(receiver.clone());
+        if args.bandwidth == ReceiverBandwidth::Lowest {
+            info!("Receiving the low-bandwidth stream (--bandwidth lowest)");
+        }
+        info!("Receiver created. Waiting for video frame...");
+
+        Ok(Self {
+            receiver,
+            ndi: ndi.clone(),
+            recv_opts,
+            extra_ips: args.extra_ips.clone(),
+            groups: args.groups.clone(),
+            error_policy: args.capture_error_policy,
+            validation: args.validation,
+            max_connect_attempts: args.max_connect_attempts,
+            capture_timeout: args.capture_timeout.unwrap_or(CAPTURE_TIMEOUT),
+            raw_stride: false,
+            allow_compressed: false,
+            tone_map: !args.no_tone_map,
+            audio_mix: args.audio_mix.clone(),
+            freeze: match args.freeze_stride {
+                Some(0) => None,
+                stride => Some(FreezeDetector::new(
+                    stride.unwrap_or(FreezeDetector::DEFAULT_STRIDE),
+                )),
+            },
+            stop: Arc::new(AtomicBool::new(false)),
+            transform: TransformControl::global(),
+            on_metadata: None,
+            downscale: args.downscale.unwrap_or(1),
+            stats: Arc::new(ArcSwap::from_pointee(StreamStats::default())),
+            connection_state: Arc::new(ArcSwap::from_pointee(ConnectionState::Connecting)),
+            frame_stats: Arc::new(FrameStats::default()),
+            ptz,
+            last_dimensions: None,
+        })
+    }
+
+    /// 実際に接続しているソース名
+    ///
+    /// 部分一致や `--source-index` / `--address` での選び分けの結果なので、
+    /// 指定した名前と違うことがある。再接続しても同じソースのまま。
+    pub fn current_source(&self) -> &str {
+        &self.recv_opts.source_to_connect_to.name
+    }
+
+    /// 接続しているソースのアドレス（IP/ホスト名、分からなければ `None`）
+    pub fn current_address(&self) -> Option<&str> {
+        self.recv_opts.source_to_connect_to.host()
+    }
+
+    /// 表示用の "名前@アドレス"
+    pub fn source_label(&self) -> String {
+        self.recv_opts.source_to_connect_to.to_string()
+    }
+
+    /// run_loopを止めるフラグを外部から共有する
+    pub fn with_stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// trueにするとrun_loopが次の受信待ちの区切りで `Ok(())` を返す
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    /// フレームにかける回転・反転を外部から共有する（既定は [`TransformControl::global`]）
+    pub fn with_transform(mut self, transform: Arc<TransformControl>) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// コールバックに渡す前にフレームを `factor` 分の1に縮小する（既定は `--scale`、1なら縮小しない）
+    pub fn with_downscale(mut self, factor: usize) -> Self {
+        self.downscale = factor.clamp(1, MAX_DOWNSCALE);
+        self
+    }
+
+    /// タイムアウト以外のキャプチャエラー時の動作を指定する
+    pub fn with_error_policy(mut self, error_policy: CaptureErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// 続けて何回失敗したら [`GiveUp::SourceLost`] で諦めるか（`None` なら無制限）
+    ///
+    /// 復帰可能なキャプチャエラーに加え、ソースとの接続が無いままのタイムアウトも1回と数える
+    /// （ソースが消えてもNDIはエラーではなくタイムアウトを返し続けるため）。
+    /// 試行の間はバックオフで待つので、回数が同じでも諦めるまでの時間は徐々に延びる。
+    pub fn with_max_connect_attempts(mut self, max_connect_attempts: Option<u32>) -> Self {
+        self.max_connect_attempts = max_connect_attempts;
+        self
+    }
+
+    /// run_loopの1回の受信待ち時間（1ms..=[`CAPTURE_TIMEOUT`] に丸める、既定は `--capture-timeout`）
+    ///
+    /// 短くするほど受信が途切れたときにループが早く戻り、停止フラグや映像なしの判定を
+    /// こまめに確認できる（ソースの切り替えや終了が速くなる）。タイムアウトはエラーではなく、
+    /// ループはそのまま待ち続ける。接続断の判定と再接続（[`ReconnectPolicy`]）はタイムアウトの
+    /// 回数ではなく `CAPTURE_TIMEOUT` 単位の経過時間で数えるので、`--reconnect-after` の秒数は変わらない。
+    pub fn with_capture_timeout(mut self, capture_timeout: Duration) -> Self {
+        self.capture_timeout = capture_timeout.clamp(Duration::from_millis(1), CAPTURE_TIMEOUT);
+        self
+    }
+
+    /// フリーズ検出の標本間隔（縦横の画素数、`None` なら検出しない）
+    pub fn with_freeze_stride(mut self, stride: Option<usize>) -> Self {
+        self.freeze = stride.map(FreezeDetector::new);
+        self
+    }
+
+    /// 受信統計の共有先を外部から指定する
+    pub fn with_stats(mut self, stats: Arc<ArcSwap<StreamStats>>) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    /// run_loopが更新する受信統計
+    pub fn stats(&self) -> Arc<ArcSwap<StreamStats>> {
+        self.stats.clone()
+    }
+
+    /// 接続状態の共有先を外部から指定する（今の状態を書き込んでから差し替える）
+    pub fn with_connection_state(mut self, state: Arc<ArcSwap<ConnectionState>>) -> Self {
+        state.store(self.connection_state.load_full());
+        self.connection_state = state;
+        self
+    }
+
+    /// run_loopが更新する接続状態（受信機ができた時点で [`ConnectionState::Connecting`]）
+    pub fn connection_state(&self) -> Arc<ArcSwap<ConnectionState>> {
+        self.connection_state.clone()
+    }
+
+    /// FPS・帯域の統計の共有先を外部から指定する
+    pub fn with_frame_stats(mut self, frame_stats: Arc<FrameStats>) -> Self {
+        self.frame_stats = frame_stats;
+        self
+    }
+
+    /// run_loopがフレームごとに更新するFPS・帯域の統計（GUIスレッドからロックなしで読める）
+    pub fn frame_stats(&self) -> Arc<FrameStats> {
+        self.frame_stats.clone()
+    }
+
+    /// 送信側から届くメタデータ（XML）を受け取るコールバックを指定する
+    ///
+    /// 受信ループが映像の合間に取り出して渡す（映像は `AUDIO_POLL_INTERVAL`（10ms）ずつ待ち、
+    /// その合間に届いているものを `MAX_METADATA_FRAMES_PER_POLL` 個まで）。映像と同じスレッドで
+    /// 呼ばれるので、重い処理はせずに別スレッドへ渡すこと。メタデータの受信エラーはログに出すだけで映像は止めない。
+    pub fn with_metadata(
+        mut self,
+        on_metadata: impl FnMut(ValidatedMetadataFrame) + Send + 'static,
+    ) -> Self {
+        self.on_metadata = Some(Box::new(on_metadata));
+        self
+    }
+
+    /// PTZ操作のハンドルを外部から指定する（受信スレッドの外からコマンドを送る用）
+    ///
+    /// 再接続で受信機が作り直されても `ptz` は新しい受信機を指す。
+    pub fn with_ptz(mut self, ptz: PtzControl) -> Self {
+        self.ptz.detach(&self.receiver);
+        ptz.attach(self.receiver.clone());
+        self.ptz = ptz;
+        self
+    }
+
+    /// 最後に受信した非圧縮フレームの (幅, 高さ)（まだ受信していなければ `None`）
+    ///
+    /// 送信側が途中で解像度を変えると、次のフレームから新しい値になる（変わったときはログに出る）。
+    pub fn last_dimensions(&self) -> Option<(u32, u32)> {
+        self.last_dimensions
+    }
+
+    /// 今の受信機を指すPTZ操作のハンドル
+    pub fn ptz(&self) -> PtzControl {
+        self.ptz.clone()
+    }
+
+    /// 接続中のソースがPTZカメラか（[`PtzControl::is_supported`]）
+    pub fn ptz_is_supported(&self) -> bool {
+        self.ptz.is_supported()
+    }
+
+    /// 絶対位置へパン・チルトする（-1.0..=1.0）。PTZ非対応のソースならエラー
+    pub fn ptz_pan_tilt(&self, pan: f32, tilt: f32) -> Result<()> {
+        self.ptz.pan_tilt(pan, tilt)
+    }
+
+    /// 絶対位置へズームする（0.0 が広角端、1.0 が望遠端）。PTZ非対応のソースならエラー
+    pub fn ptz_zoom(&self, level: f32) -> Result<()> {
+        self.ptz.zoom(level)
+    }
+
+    /// プリセット（0..=99）を呼び出す。PTZ非対応のソースならエラー
+    pub fn ptz_recall_preset(&self, index: u32) -> Result<()> {
+        self.ptz.recall_preset(index)
+    }
+
+    /// パディング付きの行（`line_stride > width*4`）を詰め直さずにそのまま渡すようにする
+    ///
+    /// 既定ではコピーして `width*4` に詰めてから渡す。有効にした場合はコピーしない代わりに、
+    /// コールバック側で `ValidatedFrame::packed` と `line_stride` を見て行を処理する必要がある。
+    pub fn with_raw_stride(mut self, raw_stride: bool) -> Self {
+        self.raw_stride = raw_stride;
+        self
+    }
+
+    /// 行ストライドやデータ長が合わないフレームを捨てるか、補って渡すか（`--strict` / `--lenient`）
+    pub fn with_validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// 圧縮されたままのフレームを破棄せずそのまま渡すようにする
+    ///
+    /// 有効にした場合、コールバック側で `ValidatedFrame::compressed` を見て処理を分けること。
+    pub fn with_compressed(mut self, allow_compressed: bool) -> Self {
+        self.allow_compressed = allow_compressed;
+        self
+    }
+
+    /// HDRフレームをSDRにトーンマッピングするか（デフォルトで有効）
+    ///
+    /// 無効にした場合、HDRの信号値がそのまま渡るので `ValidatedFrame::color_info` を見て
+    /// 処理すること。
+    pub fn with_tone_mapping(mut self, tone_map: bool) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    /// run_loop_av で渡す音声のチャンネル選択・ダウンミックス（既定は `--audio-channels` / `--downmix`）
+    pub fn with_audio_mix(mut self, audio_mix: ChannelMix) -> Self {
+        self.audio_mix = audio_mix;
+        self
+    }
+
+    /// フレーム受信ループ。バリデーション済みのRGBAフレームをコールバックに渡す
+    ///
+    /// 復帰可能なエラーは [`CaptureErrorPolicy`] に従って処理する。
+    /// コールバックから止めたい場合は [`NdiReceiver::run_loop_until`] を使う。
+    pub fn run_loop<F>(&mut self, mut callback: F) -> Result<()>
+    where
+        F: FnMut(ValidatedFrame),
+    {
+        self.run_loop_until(|frame| {
+            callback(frame);
+            ControlFlow::Continue(())
+        })
+    }
+
+    /// 受信ループを専用のスレッドで回し、フレームを非同期の [`FrameStream`] で受け取る
+    ///
+    /// フレームは [`OwnedFrame`] にコピーされて [`stream::DEFAULT_STREAM_CAPACITY`] 枚までのキューに入り、
+    /// 満杯なら一番古いものから捨てる（詳しくは [`stream`]）。
+    pub fn into_stream(self) -> FrameStream {
+        self.into_stream_with_capacity(stream::DEFAULT_STREAM_CAPACITY)
+    }
+
+    /// `into_stream` と同じだが、キューの容量（1以上）を指定する
+    pub fn into_stream_with_capacity(self, capacity: usize) -> FrameStream {
+        FrameStream::spawn(self, capacity)
+    }
+
+    /// `run_loop` と同じだが、コールバックが `ControlFlow::Break` を返すとループを抜けて `Ok` を返す
+    ///
+    /// 1枚だけ保存する、Nフレームで計測を終える、といった用途向け。
+    pub fn run_loop_until<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut(ValidatedFrame) -> ControlFlow<()>,
+    {
+        self.run_loop_with_policy(ReconnectPolicy::default(), callback, |_| {})
+    }
+
+    /// `run_loop_until` に接続断の検出と再接続の方法を指定したもの
+    ///
+    /// 接続断（`policy.max_timeout_retries` 回続いた未接続のタイムアウト、または
+    /// [`CaptureErrorPolicy::Reconnect`] でのキャプチャエラー）が起きると受信機を作り直し、
+    /// その経過を `on_reconnect` に渡す（「再接続中」の表示用）。
+    pub fn run_loop_with_policy<F, R>(
+        &mut self,
+        policy: ReconnectPolicy,
+        callback: F,
+        on_reconnect: R,
+    ) -> Result<()>
+    where
+        F: FnMut(ValidatedFrame) -> ControlFlow<()>,
+        R: FnMut(ReconnectEvent),
+    {
+        self.run_loop_inner(
+            policy,
+            callback,
+            on_reconnect,
+            None::<fn(ValidatedAudioFrame) -> ControlFlow<()>>,
+        )
+    }
+
+    /// `run_loop_until` と同じだが、映像に加えて音声も受信して `on_audio` に渡す
+    ///
+    /// 音声は [`ValidatedAudioFrame`] のプレーナ形式で渡る。どちらかのコールバックが
+    /// `ControlFlow::Break` を返すとループを抜けて `Ok` を返す。
+    ///
+    /// 映像と音声は同じスレッドで交互に受信する。映像を `CAPTURE_TIMEOUT` まで待つと
+    /// その間音声が滞るので、映像は `AUDIO_POLL_INTERVAL`（10ms）ずつ待ち、その合間に
+    /// 届いている音声を取り出す。1回に取り出す音声は `MAX_AUDIO_FRAMES_PER_POLL` 個までなので、
+    /// 音声だけが大量に届いても映像のコールバックは待たされない。映像を送ってこない
+    /// ソースでも音声は渡り続け、映像なしの検出（[`StreamStats::no_video`]）や接続断の
+    /// 判定は `run_loop_until` と同じ間隔で行う。音声の受信エラーはログに出すだけで映像は止めない。
+    pub fn run_loop_av<F, A>(&mut self, callback: F, on_audio: A) -> Result<()>
+    where
+        F: FnMut(ValidatedFrame) -> ControlFlow<()>,
+        A: FnMut(ValidatedAudioFrame) -> ControlFlow<()>,
+    {
+        self.run_loop_inner(ReconnectPolicy::default(), callback, |_| {}, Some(on_audio))
+    }
+
+    // 受信ループがエラーで終わったら接続状態を Failed にする
+    fn run_loop_inner<F, R, A>(
+        &mut self,
+        policy: ReconnectPolicy,
+        callback: F,
+        on_reconnect: R,
+        on_audio: Option<A>,
+    ) -> Result<()>
+    where
+        F: FnMut(ValidatedFrame) -> ControlFlow<()>,
+        R: FnMut(ReconnectEvent),
+        A: FnMut(ValidatedAudioFrame) -> ControlFlow<()>,
+    {
+        let result = self.receive_loop(policy, callback, on_reconnect, on_audio);
+        if let Err(e) = &result {
+            self.connection_state
+                .store(Arc::new(ConnectionState::failed(e)));
+        }
+        result
+    }
+
+    fn receive_loop<F, R, A>(
+        &mut self,
+        policy: ReconnectPolicy,
+        mut callback: F,
+        mut on_reconnect: R,
+        mut on_audio: Option<A>,
+    ) -> Result<()>
+    where
+        F: FnMut(ValidatedFrame) -> ControlFlow<()>,
+        R: FnMut(ReconnectEvent),
+        A: FnMut(ValidatedAudioFrame) -> ControlFlow<()>,
+    {
+        // 回転・反転はどの経路で作ったフレームにも、コールバックに渡す直前にかける
+        let transform = self.transform.clone();
+        let mut transformed = Vec::new();
+        let mut callback = move |frame: ValidatedFrame| {
+            let current = transform.get();
+            if current.is_identity() || frame.compressed {
+                callback(frame)
+            } else {
+                callback(current.apply(&frame, &mut transformed))
             }
         };
-
-        // Pick source according to const (empty = first available)
-        let source = if TARGET_SOURCE_NAME.is_empty() {
-            &sources[0]
-        } else {
-            match sources.iter().find(|s| s.name == TARGET_SOURCE_NAME) {
-                Some(src) => src,
-                None => {
-                    anyhow::bail!("No NDI source named \"{TARGET_SOURCE_NAME}\" available");
-                }
+        // 縮小（--scale）は回転より先にかけ、後の処理が扱う画素を減らす
+        let downscale = self.downscale;
+        let mut downscaled = Vec::new();
+        let mut callback = move |frame: ValidatedFrame| {
+            if downscale <= 1 || frame.compressed {
+                callback(frame)
+            } else {
+                callback(frame.downscaled(downscale, &mut downscaled))
             }
         };
 
-        println!("\nCreating receiver for: {}", source);
-        let recv_opts = ReceiverOptions::builder(source.clone())
-            .color(ReceiverColorFormat::RGBX_RGBA)
-            .build();
+        let mut stats = StreamStats::default();
+        let mut last_arrival: Option<Instant> = None;
+        let mut window_start = Instant::now();
+        let (mut window_received, mut window_dropped) = (0, 0);
+        // RGBA以外で届いたフレームの変換先（使い回す）
+        let mut converted = Vec::new();
+        // `--lenient` で詰め直したフレームと、最後にログに出した補い方
+        let mut repaired = Vec::new();
+        let mut last_repair = String::new();
+        // 直近のHDRフレーム用のLUT（伝達関数が変わったら作り直す）
+        let mut cached_tone_mapper: Option<ToneMapper> = None;
+        let mut backoff = policy.backoff;
+        let mut reconnect_pending = false;
+        // 続けて失敗した回数（フレームが届いたら0に戻す）
+        let mut failed_attempts = 0;
+        // ソースにつながっていないタイムアウトが続いた回数
+        let mut disconnected_timeouts = 0;
+        // 接続断から最初のフレームまでの間は、作り直した回数を数える
+        let mut lost = false;
+        let mut reconnect_attempts = 0;
+        // 接続状態を Connected にしたか（最初のフレームと、再接続後の最初のフレームで書き込む）
+        let mut connected = false;
+        let mut freeze_warned = false;
+        let mut no_video = NoVideoDetector::new(Instant::now());
+        // 受信待ちが CAPTURE_TIMEOUT より短いとき（音声と交互に受信するとき、`--capture-timeout`）の、
+        // 最後に映像が届いた（またはタイムアウトと数えた）時刻
+        let mut waiting_since = Instant::now();
+        // チャンネル選択後の音声（使い回す）と、音声の受信エラーをログに出したか
+        let mut mixed_audio = Vec::new();
+        let mut audio_error_logged = false;
+        let mut metadata_error_logged = false;
 
-        let receiver = Receiver::new(&ndi, &recv_opts)?;
-        println!("Receiver created. Waiting for video frame...\n");
+        loop {
+            if self.stop.load(Ordering::Relaxed) {
+                return Ok(());
+            }
 
-        Ok(Self { receiver })
-    }
+            if reconnect_pending {
+                reconnect_pending = false;
+                reconnect_attempts += 1;
+                on_reconnect(ReconnectEvent::Reconnecting {
+                    attempt: reconnect_attempts,
+                });
+                if policy.rediscover_on_loss {
+                    self.rediscover();
+                }
+                match Receiver::new(&self.ndi, &self.recv_opts) {
+                    Ok(receiver) => {
+                        let receiver = Arc::new(receiver);
+                        self.ptz.detach(&self.receiver);
+                        self.ptz.attach(receiver.clone());
+                        self.receiver = receiver;
+                        // 新しい受信機の統計は0から数え直す
+                        (window_received, window_dropped) = (0, 0);
+                        no_video = NoVideoDetector::new(Instant::now());
+                        info!("Reconnected to {}", self.recv_opts.source_to_connect_to);
+                    }
+                    Err(e) => warn!("Reconnect failed: {e}"),
+                }
+            }
+
+            if self.on_metadata.is_some() {
+                self.drain_metadata(&mut metadata_error_logged);
+            }
+            let capture_timeout = match on_audio.as_mut() {
+                Some(on_audio) => {
+                    if self
+                        .drain_audio(on_audio, &mut mixed_audio, &mut audio_error_logged)
+                        .is_break()
+                    {
+                        return Ok(());
+                    }
+                    AUDIO_POLL_INTERVAL.min(self.capture_timeout)
+                }
+                // メタデータだけでも、映像を長く待つと届いてから渡すまでが遅れる
+                None if self.on_metadata.is_some() => AUDIO_POLL_INTERVAL.min(self.capture_timeout),
+                None => self.capture_timeout,
+            };
 
-    /// フレーム受信ループ。バリデーション済みのRGBAフレームをコールバックに渡す
-    pub fn run_loop<F>(&self, mut callback: F) -> Result<()>
-    where
-        F: FnMut(ValidatedFrame),
-    {
-        loop {
             // Use capture_video_ref for zero-copy
-            let video_frame_ref_opt = match self.receiver.capture_video_ref(Duration::from_secs(2)) {
-                Ok(frame_opt) => frame_opt,
-                Err(e) if matches!(e, Error::Timeout { .. }) => {
+            // （タイムアウトは Ok(None) で返ってくる）
+            let video_frame_ref = match self.receiver.capture_video_ref(capture_timeout) {
+                Ok(Some(frame)) => frame,
+                Ok(None) | Err(Error::Timeout(_) | Error::FrameTimeout { .. }) => {
+                    self.check_no_video(&mut no_video, &mut stats);
+                    // 短く待っている間は、CAPTURE_TIMEOUT ぶん待って1回のタイムアウトと数える
+                    if capture_timeout < CAPTURE_TIMEOUT {
+                        if waiting_since.elapsed() < CAPTURE_TIMEOUT {
+                            continue;
+                        }
+                        waiting_since = Instant::now();
+                    }
+                    if self.receiver.is_connected() {
+                        disconnected_timeouts = 0;
+                    } else {
+                        disconnected_timeouts += 1;
+                    }
+                    if let Some(max) = policy.max_timeout_retries
+                        && disconnected_timeouts >= max
+                    {
+                        disconnected_timeouts = 0;
+                        let reason = format!(
+                            "no connection for {:.1}s",
+                            (CAPTURE_TIMEOUT * max).as_secs_f32()
+                        );
+                        warn!(
+                            "Lost {} ({reason}); reconnecting",
+                            self.recv_opts.source_to_connect_to
+                        );
+                        if !lost {
+                            lost = true;
+                            connected = false;
+                            self.connection_state
+                                .store(Arc::new(ConnectionState::Reconnecting));
+                            on_reconnect(ReconnectEvent::Lost(reason));
+                        }
+                        reconnect_pending = true;
+                        continue;
+                    }
                     // No frame received in time, keep waiting
+                    if self.max_connect_attempts.is_none() || self.receiver.is_connected() {
+                        continue;
+                    }
+                    // 上限があるときは、ソースにつながっていないタイムアウトも失敗として数える
+                    failed_attempts += 1;
+                    self.check_connect_attempts(failed_attempts, "no connection to the source")?;
+                    warn!("No connection to the source; waiting {backoff:?}");
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
                     continue;
                 }
-                Err(e) => {
+                Err(e) if self.error_policy == CaptureErrorPolicy::Fail || !is_recoverable(&e) => {
                     anyhow::bail!("Receiver error: {e}");
                 }
+                Err(e) => {
+                    failed_attempts += 1;
+                    self.check_connect_attempts(failed_attempts, &e)?;
+                    warn!(
+                        "Capture error: {e}; {} in {backoff:?}",
+                        match self.error_policy {
+                            CaptureErrorPolicy::Reconnect => "reconnecting",
+                            _ => "retrying",
+                        }
+                    );
+                    reconnect_pending = self.error_policy == CaptureErrorPolicy::Reconnect;
+                    if reconnect_pending && !lost {
+                        lost = true;
+                        connected = false;
+                        self.connection_state
+                            .store(Arc::new(ConnectionState::Reconnecting));
+                        on_reconnect(ReconnectEvent::Lost(e.to_string()));
+                    }
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(RETRY_BACKOFF_MAX);
+                    continue;
+                }
             };
+            backoff = policy.backoff;
+            failed_attempts = 0;
+            disconnected_timeouts = 0;
+            waiting_since = Instant::now();
+            if lost {
+                lost = false;
+                reconnect_attempts = 0;
+                on_reconnect(ReconnectEvent::Restored);
+            }
+            if !connected {
+                connected = true;
+                self.connection_state
+                    .store(Arc::new(ConnectionState::Connected {
+                        source: self.source_label(),
+                    }));
+            }
 
-            // VideoFrameRefがNoneの場合はスキップ
-            let video_frame_ref = match video_frame_ref_opt {
-                Some(frame) => frame,
-                None => continue,
-            };
+            // 到着間隔のずれからジッタを求める（RFC 3550 と同じく1/16で平滑化）
+            let now = Instant::now();
+            no_video.last_video = now;
+            stats.no_video = None;
+            let rate_n = video_frame_ref.frame_rate_n();
+            let rate_d = video_frame_ref.frame_rate_d();
+            if rate_n > 0 && rate_d > 0 {
+                stats.frame_interval = Duration::from_secs_f64(rate_d as f64 / rate_n as f64);
+            }
+            if let Some(last) = last_arrival {
+                let deviation = now.duration_since(last).abs_diff(stats.frame_interval);
+                stats.jitter = if deviation > stats.jitter {
+                    stats.jitter + (deviation - stats.jitter) / 16
+                } else {
+                    stats.jitter - (stats.jitter - deviation) / 16
+                };
+            }
+            self.frame_stats.record(
+                last_arrival.map(|last| now.duration_since(last)),
+                video_frame_ref.data().len(),
+            );
+            last_arrival = Some(now);
 
+            // ドロップ率は累計ではなく直近の区間で見る
+            if now.duration_since(window_start) >= STATS_INTERVAL {
+                let connection = self.receiver.connection_stats();
+                stats.received = connection.video_frames_received;
+                stats.dropped = connection.video_frames_dropped;
+                self.frame_stats.set_dropped(stats.dropped);
+                let received = stats.received.saturating_sub(window_received);
+                let dropped = stats.dropped.saturating_sub(window_dropped);
+                stats.recent_drop_ratio = if received + dropped > 0 {
+                    dropped as f64 / (received + dropped) as f64
+                } else {
+                    0.0
+                };
+                (window_received, window_dropped) = (stats.received, stats.dropped);
+                window_start = now;
+            }
+            stats.timecode = video_frame_ref.timecode();
+            stats.frame_size = [video_frame_ref.width(), video_frame_ref.height()];
+            // サイズが空のフレームと、圧縮フレーム（with_compressed でなければ）は捨てるので、
+            // 待機画面で分かるように統計に載せる
+            let compressed = FrameError::compressed(
+                video_frame_ref.line_stride_or_size(),
+                video_frame_ref.pixel_format(),
+            )
+            .filter(|_| !self.allow_compressed);
+            let frame_error =
+                FrameError::empty_size(video_frame_ref.width(), video_frame_ref.height())
+                    .or(compressed);
+            if let Some(e) = frame_error
+                && stats.frame_error.is_none()
+            {
+                warn!(
+                    "{}: {e}; skipping its frames.",
+                    self.recv_opts.source_to_connect_to
+                );
+            }
+            stats.frame_error = frame_error;
+            self.stats.store(Arc::new(stats));
+
+            // 幅・高さを使う計算（ストライドやサイズの検証、変換）より前に捨てる
             let width = video_frame_ref.width();
             let height = video_frame_ref.height();
+            if let Some(FrameError::EmptySize { .. }) = frame_error {
+                trace!("Empty video frame ({width}x{height}); skipping frame.");
+                continue;
+            }
+            // 詰めたRGBAの1行（i32）と全体（usize）のバイト数が表せないフレームも捨てる。
+            // ここを通れば以降の width*4 や width*height*4 は桁あふれしない
+            let Some(expected_stride) = width.checked_mul(4) else {
+                warn!("Video frame width {width} is too large for a line stride; skipping frame.");
+                continue;
+            };
+            if let Err(e) = convert::rgba_size(width as usize, height as usize) {
+                warn!("{e:#}; skipping frame.");
+                continue;
+            }
             let fourcc = video_frame_ref.pixel_format();
             let timecode = video_frame_ref.timecode();
+            let timestamp = video_frame_ref.timestamp();
+            let picture_aspect_ratio = video_frame_ref.picture_aspect_ratio();
+            let mut color_info = video_frame_ref
+                .metadata()
+                .and_then(|metadata| metadata.to_str().ok())
+                .map(ColorInfo::parse)
+                .unwrap_or_default();
+            // 圧縮かどうかはFourCCで決まる（grafton-ndiがFourCCを見て行ストライドと
+            // データサイズのどちらが入っているかを判定する。データ長からの推測はしない）
             let line_stride = match video_frame_ref.line_stride_or_size() {
                 LineStrideOrSize::LineStrideBytes(stride) => stride,
-                LineStrideOrSize::DataSizeBytes(_) => {
-                    eprintln!("Unexpected data size instead of stride -- skipping frame.");
+                LineStrideOrSize::DataSizeBytes(size) => {
+                    // 理由は上で stats.frame_error に載せて、最初の1回だけログに出している
+                    if !self.allow_compressed {
+                        trace!(
+                            "Compressed video frame ({fourcc:?}, {size} bytes); skipping frame."
+                        );
+                        continue;
+                    }
+                    if callback(ValidatedFrame {
+                        width,
+                        height,
+                        data: video_frame_ref.data(),
+                        timecode,
+                        timestamp,
+                        format: fourcc,
+                        source_format: fourcc,
+                        color_info,
+                        picture_aspect_ratio,
+                        line_stride: 0,
+                        packed: false,
+                        compressed: true,
+                    })
+                    .is_break()
+                    {
+                        return Ok(());
+                    }
                     continue;
                 }
             };
 
-            // Only accept RGBA, RGBX
-            match fourcc {
-                PixelFormat::RGBA | PixelFormat::RGBX => {}
-                other => {
-                    eprintln!("Warning: Got unexpected format {other:?}, skipping frame.");
-                    continue;
-                }
+            // カメラのモード切り替えなどで解像度が変わったらログに出す（再接続をまたいでも比べる）。
+            // コールバック側のバッファやテクスチャは、フレームのサイズを見て作り直すこと
+            let dimensions = (width as u32, height as u32);
+            if let Some((last_width, last_height)) = self.last_dimensions
+                && (last_width, last_height) != dimensions
+            {
+                info!(
+                    "{} changed resolution from {last_width}x{last_height} to {width}x{height}",
+                    self.recv_opts.source_to_connect_to
+                );
             }
+            self.last_dimensions = Some(dimensions);
 
-            let expected_stride = width * 4;
-            if line_stride != expected_stride {
-                eprintln!(
-                    "Line stride ({actual_stride}) doesn't match width*4 ({expected_stride}); skipping frame.",
-                    actual_stride = line_stride
+            // 間引いた標本で前のフレームと比べ、同じ画が続いていればフリーズとみなす
+            if let Some(freeze) = self.freeze.as_mut() {
+                let frozen_for = freeze.update(
+                    width as usize,
+                    height as usize,
+                    line_stride as usize,
+                    video_frame_ref.data(),
+                    now,
                 );
+                if frozen_for >= FREEZE_WARN_AFTER && !freeze_warned {
+                    warn!(
+                        "{} has sent the same picture for {:.1}s (frozen?)",
+                        self.recv_opts.source_to_connect_to,
+                        frozen_for.as_secs_f32()
+                    );
+                    freeze_warned = true;
+                } else if frozen_for.is_zero() && freeze_warned {
+                    info!("{} is moving again", self.recv_opts.source_to_connect_to);
+                    freeze_warned = false;
+                }
+                if frozen_for != stats.frozen_for {
+                    stats.frozen_for = frozen_for;
+                    self.stats.store(Arc::new(stats));
+                }
+            }
+
+            // HDRソースはSDRにトーンマッピングする（SDRモニタで白っぽく見えないように）
+            let tone_mapper = if self.tone_map && color_info.transfer.is_hdr() {
+                if cached_tone_mapper
+                    .as_ref()
+                    .is_none_or(|mapper| mapper.transfer() != color_info.transfer)
+                {
+                    cached_tone_mapper = Some(ToneMapper::new(color_info.transfer));
+                }
+                color_info.tone_mapped = true;
+                cached_tone_mapper.as_ref()
+            } else {
+                None
+            };
+
+            // RGBA/RGBX以外（Fastest/Best で受信した場合など）はRGBAに変換してから渡す
+            if !matches!(fourcc, PixelFormat::RGBA | PixelFormat::RGBX) {
+                if let Err(e) = convert::to_rgba(
+                    fourcc,
+                    width as usize,
+                    height as usize,
+                    line_stride as usize,
+                    video_frame_ref.data(),
+                    &mut converted,
+                ) {
+                    if !self.repair(
+                        e,
+                        &video_frame_ref,
+                        line_stride as usize,
+                        &mut repaired,
+                        &mut last_repair,
+                    ) {
+                        continue;
+                    }
+                    // 詰め直した後は行ストライド = 1行の最低バイト数
+                    let min_stride = repaired.len() / height as usize;
+                    if let Err(e) = convert::to_rgba(
+                        fourcc,
+                        width as usize,
+                        height as usize,
+                        min_stride,
+                        &repaired,
+                        &mut converted,
+                    ) {
+                        warn!("{e}; skipping frame.");
+                        continue;
+                    }
+                }
+                if let Some(tone_mapper) = tone_mapper {
+                    tone_mapper.apply(&mut converted);
+                }
+                if callback(ValidatedFrame {
+                    width,
+                    height,
+                    data: &converted,
+                    timecode,
+                    timestamp,
+                    format: if fourcc == PixelFormat::BGRX {
+                        PixelFormat::RGBX
+                    } else {
+                        PixelFormat::RGBA
+                    },
+                    source_format: fourcc,
+                    color_info,
+                    picture_aspect_ratio,
+                    line_stride: expected_stride,
+                    packed: true,
+                    compressed: false,
+                })
+                .is_break()
+                {
+                    return Ok(());
+                }
                 continue;
             }
 
-            // Validate length for uncompressed
-            let expected_uncompressed_size = (width * height * 4) as usize;
-            if video_frame_ref.data().len() < expected_uncompressed_size / 2 {
-                eprintln!(
-                    "Warning: Compressed video frame, data too small: {} bytes (expected {})",
-                    video_frame_ref.data().len(),
-                    expected_uncompressed_size
-                );
+            // 行末のパディング（line_stride > width*4）は正常。足りない場合だけ壊れている
+            let packed = line_stride == expected_stride;
+            let problem = if line_stride < expected_stride {
+                Some(format!(
+                    "Line stride ({line_stride}) is smaller than width*4 ({expected_stride})"
+                ))
+            } else {
+                // 最終行まで読めることを保証する（最終行の後ろのパディングは無くてもよい）。
+                // 大きな行ストライドで桁あふれするなら、読めないフレームとして扱う
+                match convert::required_sizes(
+                    fourcc,
+                    width as usize,
+                    height as usize,
+                    line_stride as usize,
+                ) {
+                    Ok((_, required_size)) => {
+                        (video_frame_ref.data().len() < required_size).then(|| {
+                            format!(
+                                "Video frame is truncated: {} bytes (expected {required_size})",
+                                video_frame_ref.data().len()
+                            )
+                        })
+                    }
+                    Err(e) => Some(e.to_string()),
+                }
+            };
+            if let Some(problem) = problem {
+                if !self.repair(
+                    problem,
+                    &video_frame_ref,
+                    line_stride as usize,
+                    &mut converted,
+                    &mut last_repair,
+                ) {
+                    continue;
+                }
+                if let Some(tone_mapper) = tone_mapper {
+                    tone_mapper.apply(&mut converted);
+                }
+                if callback(ValidatedFrame {
+                    width,
+                    height,
+                    data: &converted,
+                    timecode,
+                    timestamp,
+                    format: fourcc,
+                    source_format: fourcc,
+                    color_info,
+                    picture_aspect_ratio,
+                    line_stride: expected_stride,
+                    packed: true,
+                    compressed: false,
+                })
+                .is_break()
+                {
+                    return Ok(());
+                }
                 continue;
             }
 
             // Clone data only once for the callback (zero-copy until this point)
-            let data = video_frame_ref.data();
-
-            // Call callback with validated frame
-            callback(ValidatedFrame {
+            let mut frame = ValidatedFrame {
                 width,
                 height,
-                data,
+                data: video_frame_ref.data(),
                 timecode,
+                timestamp,
+                format: fourcc,
+                source_format: fourcc,
+                color_info,
+                picture_aspect_ratio,
+                line_stride,
+                packed,
+                compressed: false,
+            };
+
+            // パディング付きの行は（with_raw_stride でなければ）詰め直したコピーを渡す。
+            // トーンマッピングする場合も、詰め直したコピーに対して行う
+            if (!packed && !self.raw_stride) || tone_mapper.is_some() {
+                converted.clear();
+                for y in 0..height as usize {
+                    converted.extend_from_slice(frame.row(y));
+                }
+                if let Some(tone_mapper) = tone_mapper {
+                    tone_mapper.apply(&mut converted);
+                }
+                frame.data = &converted;
+                frame.line_stride = expected_stride;
+                frame.packed = true;
+            }
+
+            // Call callback with validated frame
+            if callback(frame).is_break() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// 合わないフレームの扱いを `--strict` / `--lenient` で分ける
+    ///
+    /// strictなら `problem` をログに出してfalse（捨てる）。lenientなら `out` に詰め直して
+    /// trueを返し、補い方が前回と変わったときだけログに出す（毎フレーム出さないように）。
+    fn repair(
+        &self,
+        problem: impl fmt::Display,
+        frame: &VideoFrameRef<'_>,
+        line_stride: usize,
+        out: &mut Vec<u8>,
+        last_repair: &mut String,
+    ) -> bool {
+        if self.validation == Validation::Strict {
+            warn!("{problem}; skipping frame.");
+            return false;
+        }
+        match convert::repack(
+            frame.pixel_format(),
+            frame.width() as usize,
+            frame.height() as usize,
+            line_stride,
+            frame.data(),
+            out,
+        ) {
+            Ok(repair) => {
+                if repair != *last_repair {
+                    warn!("Lenient: {problem}; {repair}");
+                    *last_repair = repair;
+                }
+                true
+            }
+            Err(e) => {
+                warn!("{problem} ({e}); skipping frame.");
+                false
+            }
+        }
+    }
+
+    // 届いている音声を（映像を待たせないよう MAX_AUDIO_FRAMES_PER_POLL 個まで）取り出して渡す
+    fn drain_audio<A>(
+        &self,
+        on_audio: &mut A,
+        mixed: &mut Vec<f32>,
+        error_logged: &mut bool,
+    ) -> ControlFlow<()>
+    where
+        A: FnMut(ValidatedAudioFrame) -> ControlFlow<()>,
+    {
+        for _ in 0..MAX_AUDIO_FRAMES_PER_POLL {
+            let frame = match self.receiver.capture_audio_ref(Duration::ZERO) {
+                Ok(Some(frame)) => frame,
+                Ok(None) | Err(Error::Timeout(_) | Error::FrameTimeout { .. }) => break,
+                Err(e) => {
+                    // 続いている間は最初の1回だけ出す
+                    if !*error_logged {
+                        warn!("Audio capture error: {e}");
+                        *error_logged = true;
+                    }
+                    break;
+                }
+            };
+            *error_logged = false;
+
+            // FLTP: 各チャンネルが channel_stride_in_bytes ずつ離れて並ぶ
+            // （data() は channels*samples 個までなので、ストライドが広いと後ろのチャンネルは無音になる）
+            let channels = frame.num_channels() as usize;
+            let samples = frame.num_samples() as usize;
+            let stride = frame.channel_stride_in_bytes().max(0) as usize / size_of::<f32>();
+            if stride < samples {
+                warn!(
+                    "Audio channel stride ({stride} samples) is shorter than {samples} samples; skipping audio frame."
+                );
+                continue;
+            }
+            let channels = self
+                .audio_mix
+                .apply(frame.data(), channels, samples, stride, mixed);
+            if on_audio(ValidatedAudioFrame {
+                sample_rate: frame.sample_rate(),
+                channels,
+                samples: mixed,
+                timecode: frame.timecode(),
+                timestamp: frame.timestamp(),
+            })
+            .is_break()
+            {
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    // 届いているメタデータを（映像を待たせないよう MAX_METADATA_FRAMES_PER_POLL 個まで）取り出して渡す
+    fn drain_metadata(&mut self, error_logged: &mut bool) {
+        let Some(on_metadata) = self.on_metadata.as_mut() else {
+            return;
+        };
+        for _ in 0..MAX_METADATA_FRAMES_PER_POLL {
+            let frame = match self.receiver.capture_metadata_ref(Duration::ZERO) {
+                Ok(Some(frame)) => frame,
+                Ok(None) | Err(Error::Timeout(_) | Error::FrameTimeout { .. }) => break,
+                Err(e) => {
+                    // 続いている間は最初の1回だけ出す
+                    if !*error_logged {
+                        warn!("Metadata capture error: {e}");
+                        *error_logged = true;
+                    }
+                    break;
+                }
+            };
+            *error_logged = false;
+            on_metadata(ValidatedMetadataFrame {
+                data: &frame.data().to_string_lossy(),
+                timecode: frame.timecode(),
             });
         }
     }
+
+    // 同じ名前のソースを探し直し、見つかれば接続先を差し替える（再起動でアドレスが変わった場合など）
+    fn rediscover(&mut self) {
+        let name = self.recv_opts.source_to_connect_to.name.clone();
+        let mut builder = with_discovery_groups(
+            FinderOptions::builder().show_local_sources(true),
+            self.groups.as_deref(),
+        );
+        for ip in &self.extra_ips {
+            builder = builder.extra_ips(ip.as_str());
+        }
+        let finder = match Finder::new(&self.ndi, &builder.build()) {
+            Ok(finder) => finder,
+            Err(e) => {
+                warn!("Rediscovery of {name} failed: {e}");
+                return;
+            }
+        };
+
+        let start = Instant::now();
+        while start.elapsed() < REDISCOVER_TIMEOUT && !self.stop.load(Ordering::Relaxed) {
+            // タイムアウトは「変化なし」なので、その後の一覧を見ればよい
+            let _ = finder.wait_for_sources(Duration::from_millis(500));
+            let Ok(sources) = finder.sources(Duration::ZERO) else {
+                continue;
+            };
+            if let Some(found) = sources.into_iter().find(|s| s.name == name) {
+                if found.to_string() != self.recv_opts.source_to_connect_to.to_string() {
+                    info!("{name} is now at {found}");
+                    self.recv_opts.source_to_connect_to = found;
+                }
+                return;
+            }
+        }
+        warn!("{name} not found again yet; retrying its last address");
+    }
+
+    // 音声/メタデータしか送ってこないソースなら、待機画面で分かるように統計に載せる
+    fn check_no_video(&self, detector: &mut NoVideoDetector, stats: &mut StreamStats) {
+        if let Some(kind) = detector.check(&self.receiver, Instant::now())
+            && kind != stats.no_video
+        {
+            if let Some(kind) = kind {
+                warn!("{}: {}", self.recv_opts.source_to_connect_to, kind.label());
+            }
+            stats.no_video = kind;
+            self.stats.store(Arc::new(*stats));
+        }
+    }
+
+    /// `--max-connect-attempts` に達していれば [`GiveUp::SourceLost`] を返す
+    fn check_connect_attempts(
+        &self,
+        failed_attempts: u32,
+        reason: impl fmt::Display,
+    ) -> Result<()> {
+        match self.max_connect_attempts {
+            Some(max) if failed_attempts >= max => Err(GiveUp::SourceLost.because(format!(
+                "Gave up on {} after {failed_attempts} failed attempt(s): {reason}",
+                self.recv_opts.source_to_connect_to
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for NdiReceiver {
+    fn drop(&mut self) {
+        // 捨てた受信機にPTZコマンドを送らないよう、ハンドルから外す
+        self.ptz.detach(&self.receiver);
+    }
+}
+
+/// 同じ名前を名乗るソースが複数あれば、それぞれのアドレスを添えて警告する
+fn warn_duplicate_names(sources: &[Source]) {
+    for (i, source) in sources.iter().enumerate() {
+        // 各名前につき最初の1回だけ報告する
+        if sources[..i].iter().any(|s| s.name == source.name) {
+            continue;
+        }
+        let duplicates: Vec<(usize, &Source)> = sources
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.name == source.name)
+            .collect();
+        if duplicates.len() < 2 {
+            continue;
+        }
+        warn!(
+            "{} sources share the name \"{}\":",
+            duplicates.len(),
+            source.name
+        );
+        for (j, dup) in duplicates {
+            let num = j + 1;
+            let address = dup.host().unwrap_or("unknown address");
+            warn!("  {num}. {address}");
+        }
+        warn!("  Use --source-index <n> or --address <host> to pick one.");
+    }
+}
+
+/// 探索結果から接続先を選ぶ
+///
+/// `index`（1始まり）が指定されていれば名前より優先する。
+fn select_source<'a>(
+    sources: &'a [Source],
+    name: Option<&str>,
+    index: Option<usize>,
+    address: Option<&str>,
+) -> Result<&'a Source> {
+    if let Some(index) = index {
+        return match sources.get(index - 1) {
+            Some(source) => Ok(source),
+            None => anyhow::bail!(
+                "--source-index {index} is out of range ({} source(s) found)",
+                sources.len()
+            ),
+        };
+    }
+
+    let candidates: Vec<&Source> = sources
+        .iter()
+        .filter(|s| name.is_none_or(|name| s.name == name))
+        .filter(|s| address.is_none_or(|addr| s.address.contains_host(addr)))
+        .collect();
+
+    let Some(&first) = candidates.first() else {
+        // 選び直せるよう、見つかったソースを並べる
+        let available = sources
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        match (name, address) {
+            (Some(name), Some(addr)) => anyhow::bail!(
+                "No NDI source named \"{name}\" at address \"{addr}\" available (found: {available})"
+            ),
+            (Some(name), None) => {
+                anyhow::bail!("No NDI source named \"{name}\" available (found: {available})")
+            }
+            (None, Some(addr)) => {
+                anyhow::bail!("No NDI source at address \"{addr}\" available (found: {available})")
+            }
+            (None, None) => anyhow::bail!("No NDI sources available"),
+        }
+    };
+
+    // 名前で選んだのに同名が複数残った場合は最初のものを使う
+    if name.is_some() && candidates.len() > 1 {
+        warn!(
+            "\"{}\" matches {} sources; using {first}.",
+            first.name,
+            candidates.len()
+        );
+    }
+    Ok(first)
+}
+
+/// ソースが見つからないときの案内（多くはmDNSがファイアウォールで塞がれている）
+fn discovery_hint(extra_ips: &[String]) -> String {
+    let firewall = "check that the firewall allows mDNS (UDP 5353) and NDI (TCP 5959-5969) \
+                    on this network";
+    if extra_ips.is_empty() {
+        format!(
+            "If the sender is on another subnet or mDNS is blocked, pass its IP or subnet \
+             as an argument (e.g. `raw-viewer 192.168.1.0/24`), and {firewall}."
+        )
+    } else {
+        format!(
+            "Check that {} is reachable, and {firewall}.",
+            extra_ips.join(", ")
+        )
+    }
+}
+
+/// GUIと受信スレッドで共有するエフェクトのパラメータ
+///
+/// フィルタが受信スレッド（[`Pipeline`] や [`NdiReceiver::run_loop`]）で動くとき、スライダーの値は
+/// これで渡す。GUIが [`store`](Self::store) で丸ごと差し替え、受信スレッドは毎フレーム
+/// [`load`](Self::load) で読む（ArcSwapなのでロックを取らない）。クローンは同じ値を指す。
+pub struct ParamHandle<T>(Arc<ArcSwap<T>>);
+
+impl<T> ParamHandle<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(ArcSwap::from_pointee(value)))
+    }
+
+    /// 今の値（次の `store` までの間に読んだものはどれも同じ値）
+    pub fn load(&self) -> arc_swap::Guard<Arc<T>> {
+        self.0.load()
+    }
+
+    pub fn store(&self, value: T) {
+        self.0.store(Arc::new(value));
+    }
+}
+
+impl<T: Copy> ParamHandle<T> {
+    /// 今の値のコピー（数値などの小さな値用）
+    pub fn get(&self) -> T {
+        **self.0.load()
+    }
+}
+
+impl<T> Clone for ParamHandle<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Default> Default for ParamHandle<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ParamHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ParamHandle")
+            .field(&**self.0.load())
+            .finish()
+    }
+}
+
+/// 受信スレッドの所有ハンドル
+///
+/// Dropすると停止フラグを立ててスレッドの終了を待つ。パニックや早期returnでも
+/// 受信スレッドが取り残されない。待ち時間は最大で受信待ち1回分（[`CAPTURE_TIMEOUT`]）か、
+/// 探索中ならソース探索1回分（約1秒）。
+///
+/// 接続か受信が [`GiveUp`] で終わった場合は、[`exit_if_given_up`] でプロセスごと終了する。
+/// ただしNDIのランタイムが無いとき（[`NdiError`]）は、説明を [`connection_state`](Self::connection_state)
+/// に残してウィンドウを開いたままにする。
+pub struct ReceiverHandle {
+    stop: Arc<AtomicBool>,
+    // 接続できたら受信スレッドが [`NdiReceiver::source_label`] を入れる
+    source: Arc<ArcSwapOption<String>>,
+    // 探索中から受信ループの終わりまでの接続状態
+    connection_state: Arc<ArcSwap<ConnectionState>>,
+    thread: Option<thread::JoinHandle<Result<()>>>,
+}
+
+impl ReceiverHandle {
+    /// 別スレッドで `connect` してから受信ループを回す
+    ///
+    /// `connect` には停止フラグが渡されるので、`NdiReceiver::connect_with_stop` などに渡すこと。
+    pub fn spawn<C, F>(connect: C, callback: F) -> Self
+    where
+        C: FnOnce(Arc<AtomicBool>) -> Result<NdiReceiver> + Send + 'static,
+        F: FnMut(ValidatedFrame) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let source = Arc::new(ArcSwapOption::empty());
+        let thread_source = source.clone();
+        let connection_state = Arc::new(ArcSwap::from_pointee(ConnectionState::Searching));
+        let thread_state = connection_state.clone();
+        let thread = thread::spawn(move || {
+            let result = connect(thread_stop)
+                .inspect_err(|e| {
+                    error!("Failed to connect to NDI: {e}");
+                    thread_state.store(Arc::new(ConnectionState::failed(e)));
+                })
+                .and_then(|receiver| {
+                    let mut receiver = receiver.with_connection_state(thread_state);
+                    thread_source.store(Some(Arc::new(receiver.source_label())));
+                    receiver.run_loop(callback)
+                });
+            if let Err(e) = &result
+                && NdiError::of(e).is_none()
+            {
+                exit_if_given_up(e);
+            }
+            result
+        });
+        Self {
+            stop,
+            source,
+            connection_state,
+            thread: Some(thread),
+        }
+    }
+
+    /// 接続しているソース（"名前@アドレス"、接続前は `None`）
+    pub fn current_source(&self) -> Option<Arc<String>> {
+        self.source.load_full()
+    }
+
+    /// 今の接続状態（接続するまでは [`ConnectionState::Searching`]）
+    pub fn connection_state(&self) -> Arc<ConnectionState> {
+        self.connection_state.load_full()
+    }
+
+    /// 停止フラグだけ立てて、終了は待たない
+    ///
+    /// 受信スレッドを複数止めるときに、先に全部に立ててからDropで待てば待ち時間が重ならない。
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    /// 受信を止めてスレッドの終了を待ち、受信ループの結果を返す
+    pub fn join(mut self) -> Result<()> {
+        self.stop_and_join()
+    }
+
+    fn stop_and_join(&mut self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| anyhow::anyhow!("Receiver thread panicked"))?,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ReceiverHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop_and_join() {
+            error!("Receiver thread ended with error: {e}");
+        }
+    }
+}
+
+/// ソース一覧を探し続け、変わったら `sources` を置き換えて再描画を頼む
+///
+/// 受信機の接続とは別のFinderで、アプリの終了まで動く（raw-viewerのソース一覧、grid-viewer）。
+/// `groups` は `--groups` と同じく探索するNDIグループ（`None` ならすべて）。
+#[cfg(feature = "gui")]
+pub fn spawn_source_finder(
+    extra_ips: Vec<String>,
+    groups: Option<String>,
+    sources: Arc<ArcSwap<Vec<SourceInfo>>>,
+    ctx: egui::Context,
+) {
+    thread::spawn(move || {
+        let find = || -> Result<()> {
+            let ndi = init_ndi()?;
+            let mut builder = with_discovery_groups(
+                FinderOptions::builder().show_local_sources(true),
+                groups.as_deref(),
+            );
+            for ip in &extra_ips {
+                builder = builder.extra_ips(ip.as_str());
+            }
+            let finder = Finder::new(&ndi, &builder.build())?;
+            loop {
+                // 変化がなければタイムアウトまで待ってから取り直す
+                finder.wait_for_sources(SOURCE_REFRESH_INTERVAL)?;
+                let found = finder.sources(Duration::ZERO)?;
+                let changed = found.len() != sources.load().len()
+                    || found
+                        .iter()
+                        .zip(sources.load().iter())
+                        .any(|(a, b)| a.to_string() != b.to_string());
+                if changed {
+                    sources.store(Arc::new(found.into_iter().map(SourceInfo::from).collect()));
+                    ctx.request_repaint();
+                }
+            }
+        };
+        if let Err(e) = find() {
+            warn!("Source list unavailable: {e:#}");
+        }
+    });
 }
 
 /// eframeウィンドウ作成の共通オプション（1920x1080、非フルスクリーン）
-pub fn create_native_options() -> eframe::NativeOptions {
+///
+/// 設定ファイル（[`ViewerConfig`]）があれば、前回のウィンドウの大きさ・位置・フルスクリーン状態で開く。
+///
+/// `--msaa` が指定されていればMSAAを有効にする。対応はレンダラ（glow/wgpu）と
+/// ドライバ次第で、使えないサンプル数だとウィンドウ作成に失敗することがある。
+/// サンプル数に比例してフィルレートを食うので、Raspberry Piなどでは既定（なし）のままにすること。
+#[cfg(feature = "gui")]
+pub fn create_native_options(args: &CliArgs) -> eframe::NativeOptions {
+    let config = args
+        .config_file()
+        .and_then(config::load_or_warn)
+        .unwrap_or_default();
+    let mut viewport = egui::ViewportBuilder::default()
+        .with_fullscreen(config.fullscreen)
+        .with_inner_size(config.window_size);
+    if let Some(position) = config.window_position {
+        viewport = viewport.with_position(position);
+    }
+
     eframe::NativeOptions {
-        viewport: eframe::egui::ViewportBuilder::default()
-            .with_fullscreen(false)
-            .with_inner_size([1920.0, 1080.0]),
+        // wgpuレンダラーも組み込んでいるが、既定は従来どおりglow（GPUテクスチャを直接表示する
+        // ビューアだけ `Renderer::Wgpu` に切り替える）
+        renderer: eframe::Renderer::Glow,
+        viewport,
+        // 1x はMSAAなし（eframeでは0）
+        multisampling: match args.msaa_samples {
+            Some(samples) if samples > 1 => samples,
+            _ => 0,
+        },
         ..Default::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNCOMPRESSED: &[PixelFormat] = &[
+        PixelFormat::RGBA,
+        PixelFormat::RGBX,
+        PixelFormat::BGRA,
+        PixelFormat::BGRX,
+        PixelFormat::UYVY,
+        PixelFormat::UYVA,
+        PixelFormat::P216,
+        PixelFormat::PA16,
+        PixelFormat::NV12,
+        PixelFormat::I420,
+        PixelFormat::YV12,
+    ];
+
+    fn frame(width: i32, height: i32, data: Vec<u8>) -> OwnedFrame {
+        OwnedFrame {
+            width,
+            height,
+            data,
+            timecode: 0,
+            timestamp: 0,
+            format: PixelFormat::RGBA,
+            source_format: PixelFormat::RGBA,
+            color_info: ColorInfo::default(),
+            picture_aspect_ratio: 0.0,
+            compressed: false,
+        }
+    }
+
+    #[test]
+    fn frames_with_a_line_stride_are_not_compressed() {
+        for &format in UNCOMPRESSED {
+            for stride in [1920 * 4, 1920 * 2, 0] {
+                let stride = LineStrideOrSize::LineStrideBytes(stride);
+                assert_eq!(FrameError::compressed(stride, format), None, "{format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn frames_with_a_data_size_are_compressed() {
+        // SDKはH.264/HEVCのフレームに行のストライドではなくビットストリームの長さを付けてくる
+        for &format in UNCOMPRESSED {
+            for size in [0, 1, 48_213] {
+                let size = LineStrideOrSize::DataSizeBytes(size);
+                assert_eq!(
+                    FrameError::compressed(size, format),
+                    Some(FrameError::CompressedUnsupported { format })
+                );
+            }
+        }
+        let error = FrameError::CompressedUnsupported {
+            format: PixelFormat::UYVY,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Connected \u{2014} compressed video (UYVY) is not supported"
+        );
+    }
+
+    #[test]
+    fn zero_and_negative_sizes_are_empty() {
+        for (width, height) in [(0, 1080), (1920, 0), (0, 0), (-1, 1080), (1920, i32::MIN)] {
+            assert_eq!(
+                FrameError::empty_size(width, height),
+                Some(FrameError::EmptySize { width, height })
+            );
+        }
+        for (width, height) in [(1, 1), (1920, 1080), (i32::MAX, 1)] {
+            assert_eq!(FrameError::empty_size(width, height), None);
+        }
+        assert_eq!(
+            FrameError::EmptySize {
+                width: 0,
+                height: 1080
+            }
+            .to_string(),
+            "Connected \u{2014} the source reports an empty frame size (0x1080)"
+        );
+    }
+
+    #[test]
+    fn empty_frames_do_not_panic_in_size_math() {
+        // 受信ループはこれらより前に捨てるが、直接呼ばれても桁あふれやパニックにはならない
+        let mut out = vec![1, 2, 3];
+        for (width, height) in [(0, 1080), (1920, 0), (0, 0)] {
+            assert_eq!(convert::rgba_size(width, height).unwrap(), 0);
+            assert!(convert::to_rgba(PixelFormat::UYVY, width, height, 0, &[], &mut out).is_err());
+            assert!(convert::repack(PixelFormat::RGBA, width, height, 0, &[], &mut out).is_err());
+            assert_eq!(
+                convert::downscale_rgba(&[], width, height, 2),
+                (Vec::new(), width.div_ceil(2), height.div_ceil(2))
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_frames_keep_their_bitstream() {
+        let bitstream = vec![0, 0, 0, 1, 0x67, 0x42, 0x00];
+        let frame = OwnedFrame {
+            compressed: true,
+            ..frame(1920, 1080, bitstream.clone())
+        };
+        let validated = frame.as_validated();
+        assert!(validated.compressed);
+        assert_eq!(validated.line_stride, 0);
+        // 行単位のコピーはせず、そのまま渡す
+        assert_eq!(validated.to_owned().data, bitstream);
+    }
+
+    #[test]
+    fn rows_skip_the_line_padding() {
+        // 3x2のRGBA、各行の後ろに8バイトのパディング
+        let (width, stride) = (3, 3 * 4 + 8);
+        let data: Vec<u8> = (0..2u8)
+            .flat_map(|y| {
+                let pixels = (0..12).map(move |i| y * 100 + i);
+                pixels.chain([0xEE; 8])
+            })
+            .collect();
+        let owned = frame(width, 2, Vec::new());
+        let validated = ValidatedFrame {
+            data: &data,
+            line_stride: stride,
+            packed: false,
+            ..owned.as_validated()
+        };
+        for y in 0..2 {
+            let expected: Vec<u8> = (0..12).map(|i| y as u8 * 100 + i).collect();
+            assert_eq!(validated.row(y), expected, "row {y}");
+        }
+        // 詰め直したコピーにはパディングが残らない
+        let packed = validated.to_owned();
+        assert_eq!(packed.data.len(), 3 * 4 * 2);
+        assert!(!packed.data.contains(&0xEE));
+        assert_eq!(packed.as_validated().row(1), validated.row(1));
+    }
+
+    #[test]
+    fn sample_pixel_reads_through_the_line_padding() {
+        // 2x2、各行の後ろに4バイトのパディング。画素の値は (y*2+x)*10 から
+        let (width, stride) = (2, 2 * 4 + 4);
+        let data: Vec<u8> = (0..2u8)
+            .flat_map(|y| {
+                let pixels = (0..8).map(move |i| (y * 2 + i / 4) * 10 + i % 4);
+                pixels.chain([0xEE; 4])
+            })
+            .collect();
+        let owned = frame(width, 2, Vec::new());
+        let validated = ValidatedFrame {
+            data: &data,
+            line_stride: stride,
+            packed: false,
+            ..owned.as_validated()
+        };
+        assert_eq!(sample_pixel(&validated, 0, 0), Some([0, 1, 2, 3]));
+        assert_eq!(sample_pixel(&validated, 1, 0), Some([10, 11, 12, 13]));
+        assert_eq!(sample_pixel(&validated, 0, 1), Some([20, 21, 22, 23]));
+        assert_eq!(sample_pixel(&validated, 1, 1), Some([30, 31, 32, 33]));
+        // 右端・下端の外（パディングやその先）は読まない
+        assert_eq!(sample_pixel(&validated, 2, 0), None);
+        assert_eq!(sample_pixel(&validated, 0, 2), None);
+        assert_eq!(sample_pixel(&validated, usize::MAX, usize::MAX), None);
+    }
+
+    #[test]
+    fn sample_pixel_of_rgbx_is_opaque() {
+        let owned = OwnedFrame {
+            format: PixelFormat::RGBX,
+            ..frame(1, 1, vec![1, 2, 3, 0])
+        };
+        assert_eq!(
+            sample_pixel(&owned.as_validated(), 0, 0),
+            Some([1, 2, 3, 255])
+        );
+        let rgba = frame(1, 1, vec![1, 2, 3, 0]);
+        assert_eq!(sample_pixel(&rgba.as_validated(), 0, 0), Some([1, 2, 3, 0]));
+    }
+
+    #[test]
+    fn sample_pixel_of_compressed_frames_is_none() {
+        let owned = OwnedFrame {
+            compressed: true,
+            ..frame(2, 2, vec![0, 0, 0, 1, 0x67, 0x42, 0x00, 0x1E])
+        };
+        assert_eq!(sample_pixel(&owned.as_validated(), 0, 0), None);
+    }
+
+    #[cfg(feature = "gui")]
+    #[test]
+    fn fit_keeps_the_aspect_of_portrait_and_ultrawide_images() {
+        let available = egui::vec2(1280.0, 720.0);
+        // 縦長は高さいっぱいで左右に黒帯
+        assert_eq!(
+            fit_preserve_aspect(available, egui::vec2(1080.0, 1920.0)),
+            egui::vec2(405.0, 720.0)
+        );
+        // 21:9は幅いっぱいで上下に黒帯
+        assert_eq!(
+            fit_preserve_aspect(available, egui::vec2(2560.0, 1080.0)),
+            egui::vec2(1280.0, 540.0)
+        );
+        // 小さい画は広げる
+        assert_eq!(
+            fit_preserve_aspect(available, egui::vec2(640.0, 360.0)),
+            available
+        );
+        // 領域が0なら0
+        assert_eq!(
+            fit_preserve_aspect(egui::Vec2::ZERO, egui::vec2(1920.0, 1080.0)),
+            egui::Vec2::ZERO
+        );
+    }
+
+    // タイムコードは100ns単位
+    const TICKS_PER_MS: i64 = 10_000;
+    const TICKS_PER_DAY: i64 = 24 * 60 * 60 * 1000 * TICKS_PER_MS;
+
+    #[test]
+    fn timecode_zero_and_within_a_day() {
+        assert_eq!(format_timecode(0), "00:00:00.000");
+        // 1ms未満は切り捨て
+        assert_eq!(format_timecode(TICKS_PER_MS - 1), "00:00:00.000");
+        let ms = 3_723_456; // 1時間2分3.456秒
+        assert_eq!(format_timecode(ms * TICKS_PER_MS), "01:02:03.456");
+        assert_eq!(format_timecode(TICKS_PER_DAY - 1), "23:59:59.999");
+    }
+
+    #[test]
+    fn timecode_wraps_around_each_day() {
+        assert_eq!(format_timecode(TICKS_PER_DAY), "00:00:00.000");
+        assert_eq!(
+            format_timecode(3 * TICKS_PER_DAY + 1500 * TICKS_PER_MS),
+            "00:00:01.500"
+        );
+        // 負の値は前日の終わりに折り返す
+        assert_eq!(format_timecode(-1), "23:59:59.999");
+        assert_eq!(format_timecode(-TICKS_PER_DAY), "00:00:00.000");
+        // 両端でもあふれない
+        assert_eq!(format_timecode(i64::MAX), "02:48:05.477");
+        assert_eq!(format_timecode(i64::MIN), "21:11:54.522");
+    }
+
+    const FRAME_INTERVAL: Duration = Duration::from_micros(33_367);
+
+    #[test]
+    fn quality_by_drop_ratio() {
+        let classify = |ratio| Quality::classify(ratio, Duration::ZERO, FRAME_INTERVAL);
+        assert_eq!(classify(0.0), Quality::Good);
+        // 1%ちょうどまでは Good、5%ちょうどまでは Degraded
+        assert_eq!(classify(0.01), Quality::Good);
+        assert_eq!(classify(0.010_001), Quality::Degraded);
+        assert_eq!(classify(0.05), Quality::Degraded);
+        assert_eq!(classify(0.050_001), Quality::Bad);
+        assert_eq!(classify(1.0), Quality::Bad);
+    }
+
+    #[test]
+    fn quality_by_jitter() {
+        let classify = |jitter| Quality::classify(0.0, jitter, FRAME_INTERVAL);
+        let nanos = Duration::from_nanos(1);
+        assert_eq!(classify(FRAME_INTERVAL / 2), Quality::Good);
+        assert_eq!(classify(FRAME_INTERVAL / 2 + nanos), Quality::Degraded);
+        assert_eq!(classify(FRAME_INTERVAL), Quality::Degraded);
+        assert_eq!(classify(FRAME_INTERVAL + nanos), Quality::Bad);
+        // ドロップ率とジッタは悪い方で決まる
+        assert_eq!(
+            Quality::classify(0.02, FRAME_INTERVAL + nanos, FRAME_INTERVAL),
+            Quality::Bad
+        );
+    }
+
+    #[test]
+    fn quality_before_any_stats() {
+        // まだフレーム間隔が分からない（StreamStats::default()）うちは Good
+        assert_eq!(StreamStats::default().quality(), Quality::Good);
+        assert_eq!(
+            Quality::classify(0.0, Duration::ZERO, Duration::ZERO),
+            Quality::Good
+        );
+        // ドロップ率だけは間隔が分からなくても効く
+        assert_eq!(
+            Quality::classify(0.02, Duration::ZERO, Duration::ZERO),
+            Quality::Degraded
+        );
+    }
+}
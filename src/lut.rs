@@ -0,0 +1,357 @@
+//! カラーグレーディング用の3D LUT（`.cube` 形式、`--lut`）
+//!
+//! DaVinci Resolve などが書き出す Adobe/IRIDAS の `.cube` を読み、RGBを格子点の間で三線形補間して置き換える。
+//! GPUでは `lut-wgpu` が3Dテクスチャにして同じ補間をシェーダーで行う。1D LUTには対応しない。
+
+use crate::filter::{for_each_row, Filter};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+/// 3D LUTの格子
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    title: Option<String>,
+    size: usize,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+    // size^3 個。赤が一番速く変わる（r + g*size + b*size^2 の順）
+    samples: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    /// `LUT_3D_SIZE` の範囲（仕様の上限は256）
+    pub const SIZE_RANGE: std::ops::RangeInclusive<usize> = 2..=256;
+
+    /// `.cube` ファイルを読む
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid LUT {}", path.display()))
+    }
+
+    /// `.cube` の中身を解析する（エラーには行番号が付く）
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut title = None;
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut samples = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_no = i + 1;
+            let line = strip_comment(line).trim();
+            let Some(keyword) = line.split_whitespace().next() else {
+                continue;
+            };
+            let rest = line[keyword.len()..].trim();
+
+            // 数値で始まる行は格子点
+            if keyword.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.')) {
+                let Some(size) = size else {
+                    bail!("line {line_no}: data before LUT_3D_SIZE");
+                };
+                if samples.len() == size * size * size {
+                    bail!("line {line_no}: more than {size}^3 data lines");
+                }
+                samples.push(parse_triplet(line).with_context(|| format!("line {line_no}"))?);
+                continue;
+            }
+
+            match keyword {
+                "TITLE" => title = Some(rest.trim_matches('"').to_string()),
+                "LUT_3D_SIZE" => {
+                    let n = rest
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|n| Self::SIZE_RANGE.contains(n))
+                        .with_context(|| {
+                            format!(
+                                "line {line_no}: invalid LUT_3D_SIZE \"{rest}\" (expected {}-{})",
+                                Self::SIZE_RANGE.start(),
+                                Self::SIZE_RANGE.end()
+                            )
+                        })?;
+                    if size.is_some() {
+                        bail!("line {line_no}: LUT_3D_SIZE given twice");
+                    }
+                    size = Some(n);
+                    samples.reserve_exact(n * n * n);
+                }
+                "DOMAIN_MIN" => {
+                    domain_min = parse_triplet(rest).with_context(|| format!("line {line_no}"))?
+                }
+                "DOMAIN_MAX" => {
+                    domain_max = parse_triplet(rest).with_context(|| format!("line {line_no}"))?
+                }
+                // Resolve が書く形式。3チャンネル共通の範囲
+                "LUT_3D_INPUT_RANGE" => {
+                    let range = parse_floats(rest)
+                        .ok()
+                        .and_then(|v| <[f32; 2]>::try_from(v).ok())
+                        .with_context(|| {
+                            format!("line {line_no}: expected two numbers after {keyword}")
+                        })?;
+                    domain_min = [range[0]; 3];
+                    domain_max = [range[1]; 3];
+                }
+                "LUT_1D_SIZE" | "LUT_1D_INPUT_RANGE" => {
+                    bail!("line {line_no}: 1D LUTs are not supported (only LUT_3D_SIZE)")
+                }
+                // 仕様どおり、知らないキーワードは読み飛ばす
+                _ => debug!("Ignoring unknown .cube keyword {keyword} on line {line_no}"),
+            }
+        }
+
+        let Some(size) = size else {
+            bail!("missing LUT_3D_SIZE");
+        };
+        let expected = size * size * size;
+        if samples.len() != expected {
+            bail!(
+                "expected {expected} data lines for LUT_3D_SIZE {size}, found {}",
+                samples.len()
+            );
+        }
+        if (0..3).any(|c| domain_min[c] >= domain_max[c]) {
+            bail!("DOMAIN_MIN {domain_min:?} must be below DOMAIN_MAX {domain_max:?}");
+        }
+
+        Ok(Self {
+            title,
+            size,
+            domain_min,
+            domain_max,
+            samples,
+        })
+    }
+
+    /// `TITLE` に書かれた名前
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// 1辺の格子点の数（`LUT_3D_SIZE`）
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// 入力の範囲 (`DOMAIN_MIN`, `DOMAIN_MAX`)
+    pub fn domain(&self) -> ([f32; 3], [f32; 3]) {
+        (self.domain_min, self.domain_max)
+    }
+
+    /// 格子点のRGB（`size^3` 個、赤が一番速く変わる順）
+    pub fn samples(&self) -> &[[f32; 3]] {
+        &self.samples
+    }
+
+    /// RGB（0-1）を三線形補間で変換する
+    pub fn lookup(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        let mut base = [0; 3];
+        let mut frac = [0.0; 3];
+        for c in 0..3 {
+            let t = (rgb[c] - self.domain_min[c]) / (self.domain_max[c] - self.domain_min[c]);
+            let pos = t.clamp(0.0, 1.0) * last;
+            // 上端は1つ手前の格子から frac = 1 で補間する
+            let i = (pos as usize).min(self.size - 2);
+            base[c] = i;
+            frac[c] = pos - i as f32;
+        }
+
+        let n = self.size;
+        let at = |r: usize, g: usize, b: usize| {
+            self.samples[(base[0] + r) + (base[1] + g) * n + (base[2] + b) * n * n]
+        };
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| {
+            [
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+            ]
+        };
+        let c00 = lerp(at(0, 0, 0), at(1, 0, 0), frac[0]);
+        let c10 = lerp(at(0, 1, 0), at(1, 1, 0), frac[0]);
+        let c01 = lerp(at(0, 0, 1), at(1, 0, 1), frac[0]);
+        let c11 = lerp(at(0, 1, 1), at(1, 1, 1), frac[0]);
+        let c0 = lerp(c00, c10, frac[1]);
+        let c1 = lerp(c01, c11, frac[1]);
+        lerp(c0, c1, frac[2])
+    }
+}
+
+/// RGBAの各画素のRGBをLUTで置き換える（アルファはそのまま）
+///
+/// GPUが使えないときの `lut-wgpu` のフォールバック。シェーダーと同じ三線形補間。
+impl Filter for CubeLut {
+    fn apply(&mut self, width: usize, _height: usize, data: &mut [u8]) {
+        let lut = &*self;
+        for_each_row(data, width * 4, |_, row| {
+            for px in row.chunks_exact_mut(4) {
+                let rgb = [px[0], px[1], px[2]].map(|v| v as f32 / 255.0);
+                let out = lut.lookup(rgb);
+                for c in 0..3 {
+                    px[c] = (out[c].clamp(0.0, 1.0) * 255.0).round() as u8;
+                }
+            }
+        });
+    }
+}
+
+// `#` から行末までを取り除く（`TITLE "Grade #3"` のように引用符の中の `#` は残す）
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+// 空白区切りの3つの数値
+fn parse_triplet(s: &str) -> Result<[f32; 3]> {
+    let values = parse_floats(s)?;
+    <[f32; 3]>::try_from(values.as_slice())
+        .ok()
+        .with_context(|| format!("expected three numbers, found \"{s}\""))
+}
+
+fn parse_floats(s: &str) -> Result<Vec<f32>> {
+    s.split_whitespace()
+        .map(|v| match v.parse::<f32>() {
+            Ok(v) if v.is_finite() => Ok(v),
+            _ => bail!("invalid number \"{v}\""),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2^3 の恒等LUT（赤が一番速く変わる順）
+    const IDENTITY: &str = "\
+# Created by hand
+TITLE \"Grade #3\"
+LUT_3D_SIZE 2
+
+0 0 0
+1 0 0
+0 1 0
+1 1 0
+0 0 1
+1 0 1
+0 1 1
+1 1 1  # white
+";
+
+    fn error_of(text: &str) -> String {
+        format!("{:#}", CubeLut::parse(text).unwrap_err())
+    }
+
+    #[test]
+    fn identity_lut_returns_its_input() {
+        let lut = CubeLut::parse(IDENTITY).unwrap();
+        assert_eq!(lut.title(), Some("Grade #3"));
+        assert_eq!(lut.size(), 2);
+        assert_eq!(lut.domain(), ([0.0; 3], [1.0; 3]));
+        assert_eq!(lut.samples().len(), 8);
+
+        // 角は補間せずそのまま
+        for b in [0.0, 1.0] {
+            for g in [0.0, 1.0] {
+                for r in [0.0, 1.0] {
+                    assert_eq!(lut.lookup([r, g, b]), [r, g, b]);
+                }
+            }
+        }
+        for rgb in [[0.25, 0.5, 0.75], [0.1, 0.9, 0.3]] {
+            let out = lut.lookup(rgb);
+            for c in 0..3 {
+                assert!((out[c] - rgb[c]).abs() < 1e-6, "{rgb:?} -> {out:?}");
+            }
+        }
+        // 範囲外は端に寄せる
+        assert_eq!(lut.lookup([-0.5, 2.0, 0.0]), [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn data_before_the_size_is_an_error() {
+        let error = error_of("0 0 0\nLUT_3D_SIZE 2\n");
+        assert!(error.contains("line 1: data before LUT_3D_SIZE"), "{error}");
+    }
+
+    #[test]
+    fn the_number_of_data_lines_must_match_the_size() {
+        let short = IDENTITY.replace("1 1 1  # white\n", "");
+        let error = error_of(&short);
+        assert!(
+            error.contains("expected 8 data lines for LUT_3D_SIZE 2, found 7"),
+            "{error}"
+        );
+
+        let long = format!("{IDENTITY}0.5 0.5 0.5\n");
+        let error = error_of(&long);
+        assert!(
+            error.contains("line 13: more than 2^3 data lines"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn the_size_can_only_be_given_once() {
+        let twice = IDENTITY.replace("LUT_3D_SIZE 2\n", "LUT_3D_SIZE 2\nLUT_3D_SIZE 2\n");
+        let error = error_of(&twice);
+        assert!(error.contains("line 4: LUT_3D_SIZE given twice"), "{error}");
+        assert!(error_of("LUT_3D_SIZE 1\n").contains("invalid LUT_3D_SIZE \"1\""));
+    }
+
+    #[test]
+    fn one_dimensional_luts_are_rejected() {
+        let error = error_of("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n");
+        assert!(
+            error.contains("line 1: 1D LUTs are not supported"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn the_domain_must_be_ascending() {
+        let equal = format!("DOMAIN_MIN 0 0.5 0\nDOMAIN_MAX 1 0.5 1\n{IDENTITY}");
+        assert!(error_of(&equal).contains("must be below DOMAIN_MAX"));
+        let reversed = format!("DOMAIN_MIN 1 1 1\nDOMAIN_MAX 0 0 0\n{IDENTITY}");
+        assert!(error_of(&reversed).contains("must be below DOMAIN_MAX"));
+    }
+
+    #[test]
+    fn non_finite_numbers_are_rejected() {
+        for value in ["NaN", "inf", "1e999"] {
+            let text = IDENTITY.replace("1 0 0\n", &format!("1 {value} 0\n"));
+            let error = error_of(&text);
+            assert!(
+                error.contains(&format!("invalid number \"{value}\"")),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn input_range_sets_the_domain_of_all_channels() {
+        let text = format!("LUT_3D_INPUT_RANGE 0 2\n{IDENTITY}");
+        let lut = CubeLut::parse(&text).unwrap();
+        assert_eq!(lut.domain(), ([0.0; 3], [2.0; 3]));
+        // 入力の1.0は格子の真ん中
+        assert_eq!(lut.lookup([1.0, 2.0, 0.0]), [0.5, 1.0, 0.0]);
+
+        let error = error_of(&format!("LUT_3D_INPUT_RANGE 0\n{IDENTITY}"));
+        assert!(
+            error.contains("expected two numbers after LUT_3D_INPUT_RANGE"),
+            "{error}"
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! 実行中に変更できるビューア設定
+
+use crate::cli::CliArgs;
+use crate::filter::{
+    FalseColor, FalseColorThresholds, Filter, FilterKind, LumaKey, LumaKeySettings,
+    SharpenSettings, UnsharpMask,
+};
+use crate::TARGET_SOURCE_NAME;
+
+/// GUIやリモート操作から切り替えられる設定
+///
+/// スレッド間では `Arc<ArcSwap<Params>>` で共有し、変更時は丸ごと差し替える。
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params {
+    /// 接続するソース名（`None` なら最初に見つかったソース）
+    pub source: Option<String>,
+    /// 同名のソースから選ぶアドレス（GUIの一覧で選んだとき。`None` なら `--address` の指定どおり）
+    pub source_address: Option<String>,
+    pub filter: Option<FilterKind>,
+    pub false_color_thresholds: FalseColorThresholds,
+    pub luma_key: LumaKeySettings,
+    pub sharpen: SharpenSettings,
+}
+
+impl Params {
+    pub fn from_args(args: &CliArgs) -> Self {
+        Self {
+            source: (!TARGET_SOURCE_NAME.is_empty()).then(|| TARGET_SOURCE_NAME.to_string()),
+            source_address: None,
+            filter: args.filter,
+            false_color_thresholds: args.false_color_thresholds,
+            luma_key: args.luma_key,
+            sharpen: args.sharpen,
+        }
+    }
+
+    /// 現在の設定でフィルタを生成する（フィルタなしなら `None`）
+    pub fn build_filter(&self) -> Option<Box<dyn Filter>> {
+        match self.filter? {
+            FilterKind::FalseColor => Some(Box::new(FalseColor::new(self.false_color_thresholds))),
+            FilterKind::LumaKey => Some(Box::new(LumaKey::new(self.luma_key))),
+            FilterKind::Sharpen => Some(Box::new(UnsharpMask::new(self.sharpen))),
+        }
+    }
+}
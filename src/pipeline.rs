@@ -0,0 +1,149 @@
+//! 受信→フィルタ→受け渡しをひとまとめにしたライブラリ向けAPI
+//!
+//! バイナリと同じ接続・受信ループ・[`Filter`] を、他のクレートからも定型コードなしで使えるようにする。
+//! `Pipeline::builder().source("CAMERA 1").filter(Grayscale).on_frame(|frame| ...).run()`
+//! のように組み立て、`run` なら呼び出しスレッドで、`spawn` なら別スレッドで受信する。
+
+use crate::filter::Filter;
+use crate::{CliArgs, FrameStats, NdiReceiver, ReceiverHandle, StreamStats, ValidatedFrame, TARGET_SOURCE_NAME};
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+type FrameCallback = Box<dyn FnMut(ValidatedFrame) + Send>;
+
+/// [`PipelineBuilder`] の入口
+pub struct Pipeline;
+
+impl Pipeline {
+    pub fn builder() -> PipelineBuilder {
+        PipelineBuilder::default()
+    }
+}
+
+/// 受信パイプラインの設定
+///
+/// 指定しなかった項目は既定値（[`CliArgs::default`] と [`TARGET_SOURCE_NAME`]）になる。
+/// コマンドラインは読まないので、バイナリは `main` で読んだ引数を [`PipelineBuilder::args`] で渡す。
+#[derive(Default)]
+pub struct PipelineBuilder {
+    source: Option<String>,
+    args: Option<CliArgs>,
+    filters: Vec<Box<dyn Filter>>,
+    stats: Option<Arc<ArcSwap<StreamStats>>>,
+    frame_stats: Option<Arc<FrameStats>>,
+    on_frame: Option<FrameCallback>,
+}
+
+impl PipelineBuilder {
+    /// 接続するソース名（未指定なら [`TARGET_SOURCE_NAME`]、それも空なら最初に見つかったソース）
+    pub fn source(mut self, name: impl Into<String>) -> Self {
+        self.source = Some(name.into());
+        self
+    }
+
+    /// 探索IPやカラーフォーマットなどの設定（未指定なら `CliArgs::default()`）
+    pub fn args(mut self, args: CliArgs) -> Self {
+        self.args = Some(args);
+        self
+    }
+
+    /// フィルタを追加する（追加した順に適用する）
+    pub fn filter(mut self, filter: impl Filter + 'static) -> Self {
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// 受信統計の書き込み先を共有する
+    pub fn stats(mut self, stats: Arc<ArcSwap<StreamStats>>) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// FPS・帯域の統計の書き込み先を共有する
+    pub fn frame_stats(mut self, frame_stats: Arc<FrameStats>) -> Self {
+        self.frame_stats = Some(frame_stats);
+        self
+    }
+
+    /// フィルタ適用後のフレームを受け取るコールバック
+    ///
+    /// フィルタがあればフレームは詰め直したコピー（`packed`）になる。圧縮フレームには適用しない。
+    pub fn on_frame(mut self, on_frame: impl FnMut(ValidatedFrame) + Send + 'static) -> Self {
+        self.on_frame = Some(Box::new(on_frame));
+        self
+    }
+
+    /// 呼び出しスレッドで接続して受信ループを回す（エラーか停止まで戻らない）
+    pub fn run(self) -> Result<()> {
+        let (connect, deliver) = self.into_parts();
+        connect(Arc::new(AtomicBool::new(false)))?.run_loop(deliver)
+    }
+
+    /// 別スレッドで受信する（ハンドルをDropすると停止して終了を待つ）
+    pub fn spawn(self) -> ReceiverHandle {
+        let (connect, deliver) = self.into_parts();
+        ReceiverHandle::spawn(connect, deliver)
+    }
+
+    /// 接続処理と、フィルタを適用してコールバックに渡す処理に分ける
+    fn into_parts(
+        self,
+    ) -> (
+        impl FnOnce(Arc<AtomicBool>) -> Result<NdiReceiver> + Send + 'static,
+        impl FnMut(ValidatedFrame) + Send + 'static,
+    ) {
+        let Self {
+            source,
+            args,
+            mut filters,
+            stats,
+            frame_stats,
+            mut on_frame,
+        } = self;
+
+        let connect = move |stop| {
+            let args = args.unwrap_or_default();
+            let source = source
+                .or_else(|| (!TARGET_SOURCE_NAME.is_empty()).then(|| TARGET_SOURCE_NAME.into()));
+            let receiver = NdiReceiver::connect_to_with_stop(source.as_deref(), &args, stop)?;
+            let receiver = match stats {
+                Some(stats) => receiver.with_stats(stats),
+                None => receiver,
+            };
+            Ok(match frame_stats {
+                Some(frame_stats) => receiver.with_frame_stats(frame_stats),
+                None => receiver,
+            })
+        };
+
+        let mut filtered = Vec::new();
+        let deliver = move |frame: ValidatedFrame| {
+            let Some(on_frame) = on_frame.as_mut() else {
+                return;
+            };
+            if filters.is_empty() || frame.compressed {
+                on_frame(frame);
+                return;
+            }
+
+            let (width, height) = (frame.width as usize, frame.height as usize);
+            filtered.clear();
+            for y in 0..height {
+                filtered.extend_from_slice(frame.row(y));
+            }
+            for filter in &mut filters {
+                filter.apply(width, height, &mut filtered);
+            }
+            on_frame(ValidatedFrame {
+                data: &filtered,
+                line_stride: frame.width * 4,
+                packed: true,
+                ..frame
+            });
+        };
+
+        (connect, deliver)
+    }
+}
@@ -0,0 +1,155 @@
+//! `--probe`: 最初の1フレームの情報と検証結果を表示して終了する
+//!
+//! 「画面が真っ黒のまま」の原因を調べるためのもの。受信ループではフレームを捨てた理由が
+//! 個別の警告として流れるだけなので、ここでは同じ検証を1フレームに対してすべて行い、
+//! 見えている情報と一緒に1回だけ表示する。
+
+use crate::convert;
+use crate::{CliArgs, NdiReceiver, CAPTURE_TIMEOUT, TARGET_SOURCE_NAME};
+use anyhow::Result;
+use grafton_ndi::{Error, LineStrideOrSize, VideoFrameRef};
+use std::time::{Duration, Instant};
+
+// 接続してから最初のフレームを待つ時間
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 接続して最初のフレームを調べ、結果を表示する
+///
+/// 検証に1つでも失敗したか、フレームが来なければエラーを返す（終了コード1）。
+pub fn run(args: &CliArgs) -> Result<()> {
+    let source_name = (!TARGET_SOURCE_NAME.is_empty()).then_some(TARGET_SOURCE_NAME);
+    let receiver = NdiReceiver::connect_to(source_name, args)?;
+    println!("Probing {} ...", receiver.source_label());
+
+    let started = Instant::now();
+    loop {
+        match receiver.receiver.capture_video_ref(CAPTURE_TIMEOUT) {
+            Ok(Some(frame)) => {
+                println!(
+                    "First video frame after {:.2}s:",
+                    started.elapsed().as_secs_f32()
+                );
+                return report(&frame);
+            }
+            Ok(None) | Err(Error::Timeout(_) | Error::FrameTimeout { .. }) => {
+                if started.elapsed() >= PROBE_TIMEOUT {
+                    anyhow::bail!(
+                        "No video frame within {}s (receiver {} connected to the source)",
+                        PROBE_TIMEOUT.as_secs(),
+                        if receiver.receiver.is_connected() {
+                            "is"
+                        } else {
+                            "is not"
+                        }
+                    );
+                }
+            }
+            Err(e) => anyhow::bail!("Capture error: {e}"),
+        }
+    }
+}
+
+fn report(frame: &VideoFrameRef<'_>) -> Result<()> {
+    let width = frame.width();
+    let height = frame.height();
+    let format = frame.pixel_format();
+    let stride_or_size = frame.line_stride_or_size();
+    let data_len = frame.data().len();
+    let (rate_n, rate_d) = (frame.frame_rate_n(), frame.frame_rate_d());
+
+    println!("  width:                {width}");
+    println!("  height:               {height}");
+    println!("  pixel_format:         {format:?}");
+    match stride_or_size {
+        LineStrideOrSize::LineStrideBytes(stride) => {
+            println!("  line_stride_or_size:  LineStrideBytes({stride})")
+        }
+        LineStrideOrSize::DataSizeBytes(size) => {
+            println!("  line_stride_or_size:  DataSizeBytes({size}) (compressed)")
+        }
+    }
+    println!("  data length:          {data_len} bytes");
+    if rate_n > 0 && rate_d > 0 {
+        println!(
+            "  frame rate:           {rate_n}/{rate_d} ({:.3} fps)",
+            rate_n as f64 / rate_d as f64
+        );
+    } else {
+        println!("  frame rate:           {rate_n}/{rate_d} (unknown)");
+    }
+    println!("  scan type:            {:?}", frame.scan_type());
+    println!("  picture aspect ratio: {}", frame.picture_aspect_ratio());
+    println!("  timecode:             {}", frame.timecode());
+    println!("  timestamp:            {}", frame.timestamp());
+    let metadata = frame.metadata().map(|m| m.to_string_lossy());
+    println!(
+        "  metadata:             {}",
+        metadata.as_deref().unwrap_or("(none)")
+    );
+
+    // run_loop と同じ順で検証する（最初に失敗したところでフレームは捨てられる）
+    let mut checks: Vec<(String, Result<(), String>)> = Vec::new();
+    checks.push((
+        "non-empty size".to_string(),
+        (width > 0 && height > 0)
+            .then_some(())
+            .ok_or_else(|| format!("{width}x{height}")),
+    ));
+    let line_stride = match stride_or_size {
+        LineStrideOrSize::LineStrideBytes(stride) => {
+            checks.push(("uncompressed".to_string(), Ok(())));
+            Some(stride)
+        }
+        LineStrideOrSize::DataSizeBytes(size) => {
+            checks.push((
+                "uncompressed".to_string(),
+                Err(format!(
+                    "compressed {size}-byte frame; the viewers skip these"
+                )),
+            ));
+            None
+        }
+    };
+
+    if let Some(line_stride) = line_stride
+        && width > 0
+        && height > 0
+    {
+        let (width, height, stride) = (width as usize, height as usize, line_stride as usize);
+        match convert::required_sizes(format, width, height, stride) {
+            Ok((min_stride, required)) => {
+                checks.push(("supported pixel format".to_string(), Ok(())));
+                checks.push((
+                    format!("line stride >= {min_stride}"),
+                    (stride >= min_stride)
+                        .then_some(())
+                        .ok_or_else(|| format!("line stride is {stride}")),
+                ));
+                checks.push((
+                    format!("data length >= {required} bytes"),
+                    (data_len >= required)
+                        .then_some(())
+                        .ok_or_else(|| format!("truncated: {data_len} bytes")),
+                ));
+            }
+            Err(e) => checks.push(("supported pixel format".to_string(), Err(e.to_string()))),
+        }
+    }
+
+    println!("Checks:");
+    let mut failed = 0;
+    for (name, result) in &checks {
+        match result {
+            Ok(()) => println!("  [ok]   {name}"),
+            Err(reason) => {
+                println!("  [FAIL] {name}: {reason}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} check(s) failed; the viewers would skip this frame");
+    }
+    println!("All checks passed; the viewers would display this frame.");
+    Ok(())
+}
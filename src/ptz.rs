@@ -0,0 +1,112 @@
+//! PTZカメラの操作（パン・チルト・ズーム・プリセット）
+//!
+//! コマンドは受信機を通して送るが、受信機は受信スレッドが持っていて再接続で作り直される。
+//! [`PtzControl`] は [`crate::NdiReceiver::with_ptz`] で受信機とつなぎ、今の受信機を
+//! 指し続けるので、GUIスレッドからも受信を止めずにコマンドを送れる。
+
+use anyhow::{bail, Context, Result};
+use arc_swap::ArcSwapOption;
+use grafton_ndi::Receiver;
+use std::fmt;
+use std::sync::Arc;
+
+/// 接続中のソースへPTZコマンドを送るハンドル（クローンは同じ受信機を指す）
+///
+/// ソースがPTZに対応していなければ、どのコマンドも何もせずにエラーを返す。
+/// 値の範囲はNDIのもの（パン・チルトは -1.0..=1.0、ズームは 0.0（広角）..=1.0（望遠））。
+#[derive(Clone, Default)]
+pub struct PtzControl {
+    // 今の受信機（接続前と受信機を捨てた後は None）
+    receiver: Arc<ArcSwapOption<Receiver>>,
+}
+
+impl PtzControl {
+    /// 接続中のソースがPTZに対応しているか（未接続なら `false`）
+    ///
+    /// 対応しているかは接続後にソースから届くので、つないだ直後は `false` のことがある。
+    pub fn is_supported(&self) -> bool {
+        self.receiver
+            .load()
+            .as_ref()
+            .is_some_and(|receiver| receiver.ptz_is_supported())
+    }
+
+    /// 絶対位置へパン・チルトする（-1.0..=1.0、0.0 が中央）
+    pub fn pan_tilt(&self, pan: f32, tilt: f32) -> Result<()> {
+        let receiver = self.receiver()?;
+        receiver.ptz_pan_tilt(pan.clamp(-1.0, 1.0), tilt.clamp(-1.0, 1.0))?;
+        Ok(())
+    }
+
+    /// 止めるまでその速さでパン・チルトし続ける（-1.0..=1.0、正は右・上、0.0 で止める）
+    pub fn pan_tilt_speed(&self, pan_speed: f32, tilt_speed: f32) -> Result<()> {
+        let receiver = self.receiver()?;
+        receiver.ptz_pan_tilt_speed(pan_speed.clamp(-1.0, 1.0), tilt_speed.clamp(-1.0, 1.0))?;
+        Ok(())
+    }
+
+    /// 絶対位置へズームする（0.0 が広角端、1.0 が望遠端）
+    pub fn zoom(&self, level: f32) -> Result<()> {
+        let receiver = self.receiver()?;
+        receiver.ptz_zoom(level.clamp(0.0, 1.0))?;
+        Ok(())
+    }
+
+    /// 止めるまでその速さでズームし続ける（-1.0..=1.0、正は望遠側、0.0 で止める）
+    pub fn zoom_speed(&self, speed: f32) -> Result<()> {
+        let receiver = self.receiver()?;
+        receiver.ptz_zoom_speed(speed.clamp(-1.0, 1.0))?;
+        Ok(())
+    }
+
+    /// カメラに保存されたプリセット（0..=99）を呼び出す（最高速で移動）
+    pub fn recall_preset(&self, index: u32) -> Result<()> {
+        if index > 99 {
+            bail!("PTZ preset {index} is out of range (0-99)");
+        }
+        let receiver = self.receiver()?;
+        receiver.ptz_recall_preset(index, 1.0)?;
+        Ok(())
+    }
+
+    /// 受信機をつなぐ（受信機の作成時と再接続時）
+    pub(crate) fn attach(&self, receiver: Arc<Receiver>) {
+        self.receiver.store(Some(receiver));
+    }
+
+    /// `receiver` がまだつながっていれば外す（別の受信機に差し替わっていればそのまま）
+    pub(crate) fn detach(&self, receiver: &Arc<Receiver>) {
+        let current = self.receiver.load();
+        if current.as_ref().is_some_and(|r| Arc::ptr_eq(r, receiver)) {
+            self.receiver.store(None);
+        }
+    }
+
+    // コマンドを送れる受信機。未接続かPTZ非対応ならその旨のエラー
+    fn receiver(&self) -> Result<Arc<Receiver>> {
+        let receiver = self
+            .receiver
+            .load_full()
+            .context("Not connected to a source; PTZ commands need a connected camera")?;
+        if !receiver.ptz_is_supported() {
+            bail!(
+                "{} does not support PTZ (or hasn't reported PTZ support yet)",
+                receiver.source()
+            );
+        }
+        Ok(receiver)
+    }
+}
+
+impl fmt::Debug for PtzControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = self
+            .receiver
+            .load()
+            .as_ref()
+            .map(|r| r.source().to_string());
+        f.debug_struct("PtzControl")
+            .field("source", &source)
+            .finish()
+    }
+}
@@ -0,0 +1,232 @@
+//! 受信したフレームを動画ファイルに書く録画（record-viewer）
+//!
+//! エンコーダは同梱せず、`ffmpeg` を子プロセスで起動して標準入力に生のRGBAを流す
+//! （H.264、yuv420p）。パイプへの書き込みは専用のスレッドで行い、エンコードが追いつかなければ
+//! 受信を止めずにそのフレームを捨てる（捨てた数は [`FrameRecorder::dropped`]）。
+//! 出力は一定のフレームレートなので、捨てたり受信できなかったりしたフレームの分だけ短くなる。
+
+use crate::ValidatedFrame;
+use anyhow::{bail, Context, Result};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use tracing::{info, warn};
+
+// エンコード待ちにためておけるフレーム数（4Kで約260MB）
+const QUEUE_FRAMES: usize = 8;
+
+// フレームレートが分からないとき（受信統計がまだないとき）
+const DEFAULT_FPS: f64 = 30.0;
+
+/// 録画ファイルのコンテナ
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// 途中で落ちても書けたところまで再生できる
+    #[default]
+    Mkv,
+    /// 最後に `finish` まで行かないと再生できない
+    Mp4,
+}
+
+impl RecordFormat {
+    pub const ALL: &[Self] = &[Self::Mkv, Self::Mp4];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|f| f.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mkv => "mkv",
+            Self::Mp4 => "mp4",
+        }
+    }
+
+    /// ファイルの拡張子（`.` なし）
+    pub fn extension(self) -> &'static str {
+        self.name()
+    }
+}
+
+/// 1つの録画ファイル（`ffmpeg` の子プロセスと、そこへ書き込むスレッド）
+///
+/// 解像度は `start` で決まり、途中で変えられない。解像度が変わったら `finish` して
+/// 新しいファイルで `start` し直すこと。`finish` せずにDropしてもファイルは閉じる。
+pub struct FrameRecorder {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    // 書き込みスレッドへ渡すフレーム（Noneにするとパイプが閉じてffmpegが書き終える）
+    sender: Option<SyncSender<Vec<u8>>>,
+    writer: Option<JoinHandle<Result<()>>>,
+    child: Option<Child>,
+    frames: u64,
+    dropped: u64,
+}
+
+impl FrameRecorder {
+    /// `path` に `width`x`height`、`fps` フレーム/秒で書き始める（コンテナは拡張子で決まる）
+    ///
+    /// `ffmpeg` がPATHになければエラー。`fps` が0以下なら30として書く。
+    pub fn start(path: &Path, width: u32, height: u32, fps: f64) -> Result<Self> {
+        if width == 0 || height == 0 {
+            bail!("Cannot record a {width}x{height} frame");
+        }
+        let fps = if fps.is_finite() && fps > 0.0 {
+            fps
+        } else {
+            DEFAULT_FPS
+        };
+        let spawned = Command::new("ffmpeg")
+            .args(["-hide_banner", "-loglevel", "error", "-y"])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgba"])
+            .args(["-s", &format!("{width}x{height}"), "-r", &format!("{fps}")])
+            .args(["-i", "-"])
+            // yuv420p は幅・高さが偶数でないと作れないので、奇数なら1画素足す
+            .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+            .args(["-c:v", "libx264", "-preset", "veryfast"])
+            .args(["-pix_fmt", "yuv420p"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                bail!("ffmpeg was not found in PATH; install it to record")
+            }
+            Err(e) => return Err(e).context("Failed to start ffmpeg"),
+        };
+        let mut stdin = child.stdin.take().context("ffmpeg has no stdin")?;
+
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(QUEUE_FRAMES);
+        let writer = thread::spawn(move || -> Result<()> {
+            for data in receiver {
+                stdin
+                    .write_all(&data)
+                    .context("Failed to write a frame to ffmpeg")?;
+            }
+            // ここでstdinが閉じ、ffmpegは残りをエンコードしてファイルを閉じる
+            Ok(())
+        });
+
+        info!(
+            "Recording {width}x{height} at {fps:.2} fps to {}",
+            path.display()
+        );
+        Ok(Self {
+            path: path.to_path_buf(),
+            width,
+            height,
+            sender: Some(sender),
+            writer: Some(writer),
+            child: Some(child),
+            frames: 0,
+            dropped: 0,
+        })
+    }
+
+    /// 1フレーム書く（エンコード待ちがいっぱいなら捨てて `Ok`）
+    ///
+    /// 解像度が `start` と違うフレームと、圧縮されたままのフレームはエラー。
+    /// ffmpegが止まっていてもエラー（そのあとは `finish` でffmpegのエラーを読める）。
+    pub fn push(&mut self, frame: &ValidatedFrame) -> Result<()> {
+        if frame.compressed {
+            bail!("Cannot record a compressed frame");
+        }
+        let size = (frame.width as u32, frame.height as u32);
+        if size != self.size() {
+            bail!(
+                "Frame size changed from {}x{} to {}x{} while recording",
+                self.width,
+                self.height,
+                size.0,
+                size.1
+            );
+        }
+        let Some(sender) = &self.sender else {
+            bail!("Recording already finished");
+        };
+        match sender.try_send(frame.to_owned().data) {
+            Ok(()) => self.frames += 1,
+            Err(TrySendError::Full(_)) => self.dropped += 1,
+            Err(TrySendError::Disconnected(_)) => {
+                bail!("ffmpeg stopped while recording {}", self.path.display())
+            }
+        }
+        Ok(())
+    }
+
+    /// ファイルを閉じ、ffmpegが書き終えるのを待つ
+    ///
+    /// エンコード待ちのフレームを書き切るまで戻らないので、受信スレッドを止めたくなければ
+    /// 別のスレッドで呼ぶ。ffmpegが失敗していれば、その出力をエラーに入れて返す。
+    pub fn finish(mut self) -> Result<()> {
+        self.close()
+    }
+
+    /// 書き込み先のファイル
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `start` で決めた (幅, 高さ)
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// ffmpegに渡したフレーム数
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// エンコードが追いつかずに捨てたフレーム数
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn close(&mut self) -> Result<()> {
+        // 送り側を捨てると書き込みスレッドが終わり、パイプが閉じる
+        self.sender = None;
+        let written = match self.writer.take() {
+            Some(writer) => writer
+                .join()
+                .unwrap_or_else(|_| Err(anyhow::anyhow!("Recording thread panicked"))),
+            None => Ok(()),
+        };
+        let Some(child) = self.child.take() else {
+            return Ok(());
+        };
+        let output = child
+            .wait_with_output()
+            .context("Failed to wait for ffmpeg")?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!(
+                "ffmpeg failed ({}) writing {}: {}",
+                output.status,
+                self.path.display(),
+                stderr.trim()
+            );
+        }
+        written?;
+        info!(
+            "Recorded {} frames to {} ({} dropped)",
+            self.frames,
+            self.path.display(),
+            self.dropped
+        );
+        Ok(())
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!("Failed to finish recording: {e:#}");
+        }
+    }
+}
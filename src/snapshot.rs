@@ -0,0 +1,328 @@
+//! スナップショット（PNG）の保存と、保存したフレームを追跡するためのメタデータ
+//!
+//! QCでスナップショットが何十枚も溜まっても後から出どころを追えるよう、ソース名・タイムコード・
+//! タイムスタンプ・解像度・ピクセルフォーマットをPNGのiTXtチャンク（UTF-8）に埋め込む。
+//! `--snapshot-sidecar` を付けると、同じ内容をPNGの隣の `<name>.json` にも書き出す。
+//! 受信したフレームをそのまま保存するには [`save_frame_png`]（raw-viewer の S キー）。
+
+use crate::json;
+use crate::ValidatedFrame;
+use anyhow::{bail, Context, Result};
+use grafton_ndi::PixelFormat;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// スナップショットに添えるフレームの情報
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotMeta {
+    /// 接続中のソース（`NdiReceiver::source_label()` の "名前@アドレス"）
+    pub source: String,
+    /// NDIのタイムコード（100ns単位）
+    pub timecode: i64,
+    /// NDIのタイムスタンプ（100ns単位、送信側の時刻）
+    pub timestamp: i64,
+    pub width: u32,
+    pub height: u32,
+    /// 受信したピクセルフォーマット（"RGBA"、"UYVY" など）
+    pub pixel_format: String,
+}
+
+// PNGのキーワードとJSONのキー（順序はJSONの出力順）
+const KEYS: [&str; 6] = [
+    "source",
+    "timecode",
+    "timestamp",
+    "width",
+    "height",
+    "pixel_format",
+];
+
+impl SnapshotMeta {
+    pub fn new(source: &str, frame: &ValidatedFrame) -> Self {
+        Self {
+            source: source.to_string(),
+            timecode: frame.timecode,
+            timestamp: frame.timestamp,
+            width: frame.width as u32,
+            height: frame.height as u32,
+            pixel_format: format!("{:?}", frame.source_format),
+        }
+    }
+
+    /// `KEYS` の順に値を文字列にしたもの
+    fn values(&self) -> [String; 6] {
+        [
+            self.source.clone(),
+            self.timecode.to_string(),
+            self.timestamp.to_string(),
+            self.width.to_string(),
+            self.height.to_string(),
+            self.pixel_format.clone(),
+        ]
+    }
+
+    /// キーと値の組から組み立てる（知らないキーは無視、足りないキーはエラー）
+    fn from_pairs(pairs: impl IntoIterator<Item = (String, String)>) -> Result<Self> {
+        let mut values: [Option<String>; 6] = Default::default();
+        for (key, value) in pairs {
+            if let Some(i) = KEYS.iter().position(|k| *k == key) {
+                values[i] = Some(value);
+            }
+        }
+        let [source, timecode, timestamp, width, height, pixel_format] =
+            values.map(|value| value.unwrap_or_default());
+        Ok(Self {
+            timecode: parse_number("timecode", &timecode)?,
+            timestamp: parse_number("timestamp", &timestamp)?,
+            width: parse_number("width", &width)?,
+            height: parse_number("height", &height)?,
+            source,
+            pixel_format,
+        })
+    }
+
+    /// サイドカー用のJSON（1行に1キー）
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> = KEYS
+            .iter()
+            .zip(self.values())
+            .map(|(key, value)| match *key {
+                "source" | "pixel_format" => format!("  \"{key}\": \"{}\"", json::escape(&value)),
+                _ => format!("  \"{key}\": {value}"),
+            })
+            .collect();
+        format!("{{\n{}\n}}\n", fields.join(",\n"))
+    }
+
+    /// `to_json` で書いたJSONを読む（入れ子のない1行1キーの形式だけ）
+    pub fn from_json(json: &str) -> Result<Self> {
+        let pairs = json.lines().filter_map(|line| {
+            let (key, value) = line.trim().trim_end_matches(',').split_once(':')?;
+            let value = value.trim();
+            let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+                Some(quoted) => json::unescape(quoted)?,
+                None => value.to_string(),
+            };
+            Some((key.trim().trim_matches('"').to_string(), value))
+        });
+        Self::from_pairs(pairs)
+    }
+
+    /// PNGに埋め込んだメタデータを読む
+    pub fn read_png(path: &Path) -> Result<Self> {
+        let file =
+            File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let reader = png::Decoder::new(BufReader::new(file)).read_info()?;
+        let pairs = reader
+            .info()
+            .utf8_text
+            .iter()
+            .map(|chunk| Ok((chunk.keyword.clone(), chunk.get_text()?)))
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_pairs(pairs)
+            .with_context(|| format!("{} has no snapshot metadata", path.display()))
+    }
+}
+
+/// サイドカーのパス（`shot.png` → `shot.json`）
+pub fn sidecar_path(png_path: &Path) -> PathBuf {
+    png_path.with_extension("json")
+}
+
+/// `width*4` で詰めたRGBAをPNGに保存し、`meta` をiTXtチャンクとして埋め込む
+///
+/// `sidecar` なら同じ内容のJSONを [`sidecar_path`] にも書く。
+pub fn save_png(
+    path: &Path,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    meta: &SnapshotMeta,
+    sidecar: bool,
+) -> Result<()> {
+    let file =
+        File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    for (key, value) in KEYS.iter().zip(meta.values()) {
+        encoder.add_itxt_chunk(key.to_string(), value)?;
+    }
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+
+    if sidecar {
+        let json_path = sidecar_path(path);
+        fs::write(&json_path, meta.to_json())
+            .with_context(|| format!("Failed to write {}", json_path.display()))?;
+    }
+    Ok(())
+}
+
+/// 受信したフレームをPNGに保存する（`source` はメタデータに入れるソース名）
+///
+/// 行のパディングは取り除き、RGBXはアルファが未定義なので255にして、
+/// PNGがうっかり透明にならないようにする。圧縮されたままのフレームは保存できない。
+pub fn save_frame_png(
+    frame: &ValidatedFrame,
+    path: &Path,
+    source: &str,
+    sidecar: bool,
+) -> Result<()> {
+    if frame.compressed {
+        bail!("Cannot save a compressed frame as PNG");
+    }
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let mut rgba = Vec::with_capacity(width * 4 * height);
+    for y in 0..height {
+        rgba.extend_from_slice(frame.row(y));
+    }
+    if frame.format == PixelFormat::RGBX {
+        for pixel in rgba.chunks_exact_mut(4) {
+            pixel[3] = 255;
+        }
+    }
+    let meta = SnapshotMeta::new(source, frame);
+    save_png(path, &rgba, meta.width, meta.height, &meta, sidecar)
+}
+
+/// 今の時刻（UTC）から付けたスナップショットのファイル名（`snapshot-20240131-235959-123.png`）
+///
+/// ミリ秒まで入れるので、続けて押しても上書きしない。
+pub fn snapshot_file_name(now: SystemTime) -> String {
+    timestamped_file_name("snapshot", "png", now)
+}
+
+/// `<prefix>-YYYYMMDD-HHMMSS-mmm.<extension>`（UTC）のファイル名（録画のファイル名にも使う）
+pub fn timestamped_file_name(prefix: &str, extension: &str, now: SystemTime) -> String {
+    let elapsed = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (days, time) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{prefix}-{year:04}{month:02}{day:02}-{:02}{:02}{:02}-{:03}.{extension}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+// 1970-01-01 からの日数を年月日にする（Howard Hinnant の days_from_civil の逆）
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn parse_number<T: FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .ok()
+        .with_context(|| format!("Invalid snapshot {key} \"{value}\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ColorInfo;
+    use std::time::Duration;
+
+    // テストごとの一時ディレクトリ（終わったら消す）
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("rust-ndi-viewer-{name}-{}", std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn metadata_round_trips_through_png_and_sidecar() {
+        let dir = TempDir::new("snapshot");
+        let path = dir.0.join("shot.png");
+
+        // 2x2のRGBX、1行に4バイトのパディング（アルファは未定義なので0）
+        let data = [
+            [1, 2, 3, 0, 4, 5, 6, 0, 0xEE, 0xEE, 0xEE, 0xEE],
+            [7, 8, 9, 0, 10, 11, 12, 0, 0xEE, 0xEE, 0xEE, 0xEE],
+        ]
+        .concat();
+        let frame = ValidatedFrame {
+            width: 2,
+            height: 2,
+            data: &data,
+            timecode: 1_234_567_890,
+            timestamp: -42,
+            format: PixelFormat::RGBX,
+            source_format: PixelFormat::UYVY,
+            color_info: ColorInfo::default(),
+            picture_aspect_ratio: 0.0,
+            line_stride: 12,
+            packed: false,
+            compressed: false,
+        };
+        let source = "CAMERA \"1\" (Studio B)@192.168.1.20:5961";
+        save_frame_png(&frame, &path, source, true).unwrap();
+
+        let expected = SnapshotMeta {
+            source: source.to_string(),
+            timecode: 1_234_567_890,
+            timestamp: -42,
+            width: 2,
+            height: 2,
+            pixel_format: "UYVY".to_string(),
+        };
+        assert_eq!(SnapshotMeta::read_png(&path).unwrap(), expected);
+        let json = fs::read_to_string(sidecar_path(&path)).unwrap();
+        assert_eq!(SnapshotMeta::from_json(&json).unwrap(), expected);
+
+        // 画素はパディングを除き、アルファを255にして保存される
+        let mut reader = png::Decoder::new(BufReader::new(File::open(&path).unwrap()))
+            .read_info()
+            .unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(
+            pixels,
+            [1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255, 10, 11, 12, 255]
+        );
+    }
+
+    #[test]
+    fn missing_metadata_is_an_error() {
+        assert!(SnapshotMeta::from_json("{\n  \"source\": \"CAM\"\n}\n").is_err());
+        assert!(SnapshotMeta::from_json("").is_err());
+    }
+
+    #[test]
+    fn file_names_use_utc_date_and_milliseconds() {
+        let now = UNIX_EPOCH + Duration::from_millis(1_706_745_599_123);
+        assert_eq!(snapshot_file_name(now), "snapshot-20240131-235959-123.png");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(
+            timestamped_file_name("recording", "mkv", leap_day),
+            "recording-20000229-000000-000.mkv"
+        );
+    }
+}
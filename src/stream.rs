@@ -0,0 +1,158 @@
+//! 非同期のコードからフレームを受け取るための [`FrameStream`]（[`NdiReceiver::into_stream`]）
+//!
+//! 受信ループは同期的なので、専用のスレッドで [`NdiReceiver::run_loop`] を回し、届いたフレームを
+//! [`OwnedFrame`] にコピーして容量付きのキューに入れる。非同期側は `Stream` として、または
+//! `while let Some(frame) = frames.next().await` のように取り出す。
+//!
+//! キューが満杯のとき（取り出す側が追いつかないとき）は、受信を待たせずに一番古いフレームを捨てる。
+//! ライブ映像では遅れて届く古いフレームより新しいフレームの方が役に立つため。
+//! 捨てた数は [`FrameStream::dropped`] で分かる。
+
+use crate::{NdiReceiver, OwnedFrame};
+use anyhow::Result;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use tracing::{debug, warn};
+
+/// [`NdiReceiver::into_stream`] のキューの容量（フレーム数）
+pub const DEFAULT_STREAM_CAPACITY: usize = 4;
+
+/// 受信スレッドと非同期側で共有するキュー
+struct Queue {
+    frames: VecDeque<OwnedFrame>,
+    capacity: usize,
+    // 満杯で捨てた古いフレームの数
+    dropped: u64,
+    // 次のフレームを待っている非同期タスク
+    waker: Option<Waker>,
+    // 受信ループが終わった（以降フレームは来ない）
+    finished: bool,
+    // 受信ループがエラーで終わった場合のエラー（取り出すまで持つ）
+    error: Option<anyhow::Error>,
+}
+
+type Shared = Arc<Mutex<Queue>>;
+
+fn lock(shared: &Shared) -> MutexGuard<'_, Queue> {
+    // 中身はフレームとフラグだけなので、パニックしたスレッドがあってもそのまま使う
+    shared.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 受信したフレームを非同期に受け取る `Stream<Item = OwnedFrame>`
+///
+/// フレームは受信スレッドで受信バッファからコピーされる（[`crate::ValidatedFrame::to_owned`]）ので、
+/// 取り出したフレームはいつまで持っていてもよい。借用の形が要る関数には
+/// [`OwnedFrame::as_validated`] で渡す。Dropすると受信スレッドを止める（終了は待たない）。
+pub struct FrameStream {
+    shared: Shared,
+    stop: Arc<AtomicBool>,
+}
+
+impl FrameStream {
+    /// `receiver` の受信ループを新しいスレッドで始め、フレームを `capacity` 枚までキューに溜める
+    pub(crate) fn spawn(receiver: NdiReceiver, capacity: usize) -> Self {
+        let shared = Arc::new(Mutex::new(Queue {
+            frames: VecDeque::with_capacity(capacity.max(1)),
+            capacity: capacity.max(1),
+            dropped: 0,
+            waker: None,
+            finished: false,
+            error: None,
+        }));
+        let stop = receiver.stop_flag();
+
+        let thread_shared = shared.clone();
+        let spawned = thread::Builder::new()
+            .name("ndi-stream".into())
+            .spawn(move || capture(receiver, &thread_shared));
+        if let Err(e) = spawned {
+            let mut queue = lock(&shared);
+            queue.finished = true;
+            queue.error = Some(anyhow::anyhow!("Failed to spawn the capture thread: {e}"));
+        }
+
+        Self { shared, stop }
+    }
+
+    /// 次のフレームを待つ（受信ループが終わると `None`）
+    ///
+    /// `StreamExt` を入れなくても `while let Some(frame) = stream.next().await` と書けるようにするもの。
+    pub async fn next(&mut self) -> Option<OwnedFrame> {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// キューが満杯で捨てた古いフレームの数
+    pub fn dropped(&self) -> u64 {
+        lock(&self.shared).dropped
+    }
+
+    /// 受信ループがエラーで終わっていれば、そのエラーを取り出す（ストリームが `None` を返した後に見る）
+    pub fn take_error(&mut self) -> Option<anyhow::Error> {
+        lock(&self.shared).error.take()
+    }
+}
+
+impl Stream for FrameStream {
+    type Item = OwnedFrame;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<OwnedFrame>> {
+        let mut queue = lock(&self.shared);
+        if let Some(frame) = queue.frames.pop_front() {
+            return Poll::Ready(Some(frame));
+        }
+        if queue.finished {
+            return Poll::Ready(None);
+        }
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (lock(&self.shared).frames.len(), None)
+    }
+}
+
+impl Drop for FrameStream {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// 受信スレッド: フレームをコピーしてキューに入れ、終わったら待っているタスクを起こす
+fn capture(mut receiver: NdiReceiver, shared: &Shared) {
+    let result: Result<()> = receiver.run_loop(|frame| {
+        let frame = frame.to_owned();
+        let mut queue = lock(shared);
+        if queue.frames.len() >= queue.capacity {
+            queue.frames.pop_front();
+            queue.dropped += 1;
+            debug!(
+                dropped = queue.dropped,
+                "Stream queue full, dropped the oldest frame"
+            );
+        }
+        queue.frames.push_back(frame);
+        let waker = queue.waker.take();
+        drop(queue);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    });
+
+    let mut queue = lock(shared);
+    queue.finished = true;
+    if let Err(e) = result {
+        warn!("Frame stream ended: {e:#}");
+        queue.error = Some(e);
+    }
+    let waker = queue.waker.take();
+    drop(queue);
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
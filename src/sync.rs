@@ -0,0 +1,102 @@
+//! 複数ソースのtimecode合わせ
+//!
+//! ソースごとに [`TimecodeBuffer`] へフレームを溜め、[`SyncTimeline`] が決める共通の
+//! プレイヘッドまで進めて取り出すことで、合成表示するフレームの時刻をそろえる。
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+// NDIのtimecodeは100ns単位
+const TICKS_PER_SECOND: i64 = 10_000_000;
+
+/// timecode順にフレームを溜めるソース1つ分のバッファ
+pub struct TimecodeBuffer<T> {
+    frames: VecDeque<(i64, T)>,
+    // これを超えたら古いものから捨てる（オーバーフロー防止）
+    max_len: usize,
+}
+
+impl<T> TimecodeBuffer<T> {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            max_len,
+        }
+    }
+
+    /// フレームを追加する。上限を超えて古いフレームを捨てた数を返す
+    pub fn push(&mut self, timecode: i64, frame: T) -> usize {
+        // 送信側の巻き戻り（ソース再起動など）では溜めた分を捨ててやり直す
+        if self.latest_timecode().is_some_and(|latest| timecode < latest) {
+            self.frames.clear();
+        }
+        self.frames.push_back((timecode, frame));
+
+        let mut dropped = 0;
+        while self.frames.len() > self.max_len {
+            self.frames.pop_front();
+            dropped += 1;
+        }
+        dropped
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn latest_timecode(&self) -> Option<i64> {
+        self.frames.back().map(|(timecode, _)| *timecode)
+    }
+
+    /// `playhead` 以前のフレームのうち最新のものを取り出す（それより古いものは捨てる）
+    pub fn pop_until(&mut self, playhead: i64) -> Option<(i64, T)> {
+        let mut result = None;
+        while self
+            .frames
+            .front()
+            .is_some_and(|(timecode, _)| *timecode <= playhead)
+        {
+            result = self.frames.pop_front();
+        }
+        result
+    }
+}
+
+/// 複数ソースに共通するプレイヘッドを決める
+///
+/// 全ソースが揃っているtimecode（各ソースの最新のうち最小のもの）から、
+/// さらに `delay` だけ遅らせた位置を表示する。
+#[derive(Debug, Clone, Copy)]
+pub struct SyncTimeline {
+    delay_ticks: i64,
+}
+
+impl SyncTimeline {
+    pub fn new(delay: Duration) -> Self {
+        Self {
+            delay_ticks: (delay.as_secs_f64() * TICKS_PER_SECOND as f64) as i64,
+        }
+    }
+
+    /// 共通のプレイヘッド（まだフレームが無いソースがあれば `None`）
+    pub fn playhead(&self, latest_timecodes: &[Option<i64>]) -> Option<i64> {
+        let mut common: Option<i64> = None;
+        for latest in latest_timecodes {
+            let latest = (*latest)?;
+            common = Some(common.map_or(latest, |c| c.min(latest)));
+        }
+        common.map(|c| c - self.delay_ticks)
+    }
+
+    /// 同期によって加わる遅延（最も進んでいるソースからプレイヘッドまで）
+    pub fn added_latency(&self, latest_timecodes: &[Option<i64>]) -> Option<Duration> {
+        let playhead = self.playhead(latest_timecodes)?;
+        let newest = latest_timecodes.iter().flatten().max()?;
+        let ticks = (newest - playhead).max(0);
+        Some(Duration::from_nanos(ticks as u64 * 100))
+    }
+}
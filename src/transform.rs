@@ -0,0 +1,345 @@
+//! 天吊りのカメラなどのための回転・反転（`--rotate` / `--flip`、T / F / V キー）
+//!
+//! 受信ループがコールバックに渡す直前に画素を並べ替えるので、どのビューアにも（録画・スナップショット・
+//! フィルタにも）回した後のフレームが届く。90°/270°では幅と高さが入れ替わる。
+//! キー操作は全ビューアで共有する [`TransformControl::global`] を変え、次のフレームから効く。
+
+use crate::{CliArgs, ValidatedFrame};
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, OnceLock};
+use tracing::info;
+
+/// 時計回りの回転
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Rotation {
+    const ALL: [Self; 4] = [Self::None, Self::Cw90, Self::Cw180, Self::Cw270];
+
+    /// "0" / "90" / "180" / "270"（度、時計回り）
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim() {
+            "0" => Some(Self::None),
+            "90" => Some(Self::Cw90),
+            "180" => Some(Self::Cw180),
+            "270" => Some(Self::Cw270),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(self) -> u32 {
+        self as u32 * 90
+    }
+
+    /// さらに90°時計回りに回したもの
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % 4]
+    }
+
+    /// 幅と高さが入れ替わるか
+    pub fn swaps_dimensions(self) -> bool {
+        matches!(self, Self::Cw90 | Self::Cw270)
+    }
+}
+
+/// フレームにかける回転と反転（回転してから反転する）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Transform {
+    pub rotation: Rotation,
+    /// 左右反転
+    pub flip_horizontal: bool,
+    /// 上下反転
+    pub flip_vertical: bool,
+}
+
+impl Transform {
+    /// `--flip <none|h|v|hv>` の値を反映したもの
+    pub fn with_flip(self, s: &str) -> Option<Self> {
+        let (flip_horizontal, flip_vertical) = match s.trim().to_ascii_lowercase().as_str() {
+            "none" => (false, false),
+            "h" => (true, false),
+            "v" => (false, true),
+            "hv" | "vh" => (true, true),
+            _ => return None,
+        };
+        Some(Self {
+            flip_horizontal,
+            flip_vertical,
+            ..self
+        })
+    }
+
+    /// 何もしない（フレームをそのまま渡す）か
+    pub fn is_identity(self) -> bool {
+        self == Self::default()
+    }
+
+    /// `frame` を回転・反転したフレームを `out` に作る（`width*4` で詰めたRGBA）
+    ///
+    /// 90°/270°では表示アスペクト比も逆数にする。圧縮フレームには使えない。
+    pub fn apply<'a>(self, frame: &ValidatedFrame<'_>, out: &'a mut Vec<u8>) -> ValidatedFrame<'a> {
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let (width, height) = if self.rotation == Rotation::None {
+            out.clear();
+            for y in 0..height {
+                out.extend_from_slice(frame.row(y));
+            }
+            (width, height)
+        } else {
+            rotate_rgba(
+                frame.data,
+                width,
+                height,
+                frame.line_stride as usize,
+                self.rotation,
+                out,
+            )
+        };
+        flip_rgba(out, width, height, self.flip_horizontal, self.flip_vertical);
+
+        let picture_aspect_ratio =
+            if self.rotation.swaps_dimensions() && frame.picture_aspect_ratio > 0.0 {
+                1.0 / frame.picture_aspect_ratio
+            } else {
+                frame.picture_aspect_ratio
+            };
+        ValidatedFrame {
+            width: width as i32,
+            height: height as i32,
+            data: out,
+            timecode: frame.timecode,
+            timestamp: frame.timestamp,
+            format: frame.format,
+            source_format: frame.source_format,
+            color_info: frame.color_info,
+            picture_aspect_ratio,
+            line_stride: width as i32 * 4,
+            packed: true,
+            compressed: false,
+        }
+    }
+
+    fn to_bits(self) -> u8 {
+        self.rotation as u8 | (self.flip_horizontal as u8) << 2 | (self.flip_vertical as u8) << 3
+    }
+
+    fn from_bits(bits: u8) -> Self {
+        Self {
+            rotation: Rotation::ALL[(bits & 0b11) as usize],
+            flip_horizontal: bits & 0b100 != 0,
+            flip_vertical: bits & 0b1000 != 0,
+        }
+    }
+}
+
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rotate {}°", self.rotation.degrees())?;
+        match (self.flip_horizontal, self.flip_vertical) {
+            (false, false) => Ok(()),
+            (true, false) => write!(f, ", flip horizontal"),
+            (false, true) => write!(f, ", flip vertical"),
+            (true, true) => write!(f, ", flip horizontal + vertical"),
+        }
+    }
+}
+
+/// 受信スレッドとGUIで共有する [`Transform`]（次のフレームから効く）
+#[derive(Debug, Default)]
+pub struct TransformControl {
+    bits: AtomicU8,
+}
+
+// 全ビューア共通の [`TransformControl`]
+static GLOBAL: OnceLock<Arc<TransformControl>> = OnceLock::new();
+
+impl TransformControl {
+    pub fn new(transform: Transform) -> Self {
+        Self {
+            bits: AtomicU8::new(transform.to_bits()),
+        }
+    }
+
+    /// 全ビューア共通のものを `--rotate` / `--flip` の値にする（`main` で引数を読んだらすぐ呼ぶ）
+    ///
+    /// 先に [`global`](Self::global) が使われていても、値を差し替えるだけなので同じものを指し続ける。
+    pub fn init(args: &CliArgs) {
+        if GLOBAL.set(Arc::new(Self::new(args.transform))).is_err() {
+            Self::global().set(args.transform);
+        }
+    }
+
+    /// 全ビューア共通のもの（[`init`](Self::init) していなければ回転・反転なし）
+    ///
+    /// `NdiReceiver::connect` などで作った受信機と、[`crate::handle_common_input`] のキー操作が使う。
+    pub fn global() -> Arc<Self> {
+        GLOBAL.get_or_init(Arc::default).clone()
+    }
+
+    pub fn get(&self) -> Transform {
+        Transform::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, transform: Transform) {
+        self.bits.store(transform.to_bits(), Ordering::Relaxed);
+        info!("Frame transform: {transform}");
+    }
+
+    /// 今の値を `change` で変える（キー操作用）
+    pub fn update(&self, change: impl FnOnce(Transform) -> Transform) {
+        self.set(change(self.get()));
+    }
+}
+
+/// RGBAバッファを時計回りに `rotation` だけ回して `out` に詰めて書く
+///
+/// `stride` は入力の1行あたりのバイト数（`width*4` 以上）。90°/270°では幅と高さが入れ替わる。
+/// 戻り値は回した後の `(width, height)`。
+pub fn rotate_rgba(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    stride: usize,
+    rotation: Rotation,
+    out: &mut Vec<u8>,
+) -> (usize, usize) {
+    let (out_width, out_height) = if rotation.swaps_dimensions() {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    out.clear();
+    out.resize(width * height * 4, 0);
+
+    for y in 0..height {
+        let row = &data[y * stride..y * stride + width * 4];
+        for (x, px) in row.chunks_exact(4).enumerate() {
+            // 入力の (x, y) が出力でどこに来るか
+            let (ox, oy) = match rotation {
+                Rotation::None => (x, y),
+                Rotation::Cw90 => (height - 1 - y, x),
+                Rotation::Cw180 => (width - 1 - x, height - 1 - y),
+                Rotation::Cw270 => (y, width - 1 - x),
+            };
+            let i = (oy * out_width + ox) * 4;
+            out[i..i + 4].copy_from_slice(px);
+        }
+    }
+
+    (out_width, out_height)
+}
+
+/// 詰めたRGBAバッファ（`width*4` バイト/行）をその場で左右・上下に反転する
+pub fn flip_rgba(data: &mut [u8], width: usize, height: usize, horizontal: bool, vertical: bool) {
+    let row_len = width * 4;
+    if row_len == 0 {
+        return;
+    }
+    if horizontal {
+        for row in data.chunks_exact_mut(row_len).take(height) {
+            for x in 0..width / 2 {
+                let (left, right) = row.split_at_mut((width - 1 - x) * 4);
+                left[x * 4..x * 4 + 4].swap_with_slice(&mut right[..4]);
+            }
+        }
+    }
+    if vertical {
+        for y in 0..height / 2 {
+            let (top, bottom) = data.split_at_mut((height - 1 - y) * row_len);
+            top[y * row_len..(y + 1) * row_len].swap_with_slice(&mut bottom[..row_len]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 3x2の画像。各画素のRは番号（0から左上→右下）、それ以外は 0, 0, 255
+    const WIDTH: usize = 3;
+    const HEIGHT: usize = 2;
+
+    fn numbered() -> Vec<u8> {
+        (0..(WIDTH * HEIGHT) as u8)
+            .flat_map(|i| [i, 0, 0, 255])
+            .collect()
+    }
+
+    // 四隅の画素の番号（左上、右上、左下、右下）
+    fn corners(data: &[u8], width: usize, height: usize) -> [u8; 4] {
+        let at = |x: usize, y: usize| data[(y * width + x) * 4];
+        [
+            at(0, 0),
+            at(width - 1, 0),
+            at(0, height - 1),
+            at(width - 1, height - 1),
+        ]
+    }
+
+    #[test]
+    fn rotate_moves_the_corners_clockwise() {
+        // 元の四隅は 0, 2, 3, 5
+        for (rotation, size, expected) in [
+            (Rotation::None, (3, 2), [0, 2, 3, 5]),
+            (Rotation::Cw90, (2, 3), [3, 0, 5, 2]),
+            (Rotation::Cw180, (3, 2), [5, 3, 2, 0]),
+            (Rotation::Cw270, (2, 3), [2, 5, 0, 3]),
+        ] {
+            let mut out = Vec::new();
+            let (width, height) =
+                rotate_rgba(&numbered(), WIDTH, HEIGHT, WIDTH * 4, rotation, &mut out);
+            assert_eq!((width, height), size, "{rotation:?}");
+            assert_eq!(out.len(), WIDTH * HEIGHT * 4);
+            assert_eq!(corners(&out, width, height), expected, "{rotation:?}");
+            assert!(out.chunks_exact(4).all(|px| px[1..] == [0, 0, 255]));
+        }
+    }
+
+    #[test]
+    fn rotate_skips_the_line_padding() {
+        let padded: Vec<u8> = numbered()
+            .chunks_exact(WIDTH * 4)
+            .flat_map(|row| [row, &[0xEE; 8]].concat())
+            .collect();
+        let mut out = Vec::new();
+        let size = rotate_rgba(
+            &padded,
+            WIDTH,
+            HEIGHT,
+            WIDTH * 4 + 8,
+            Rotation::Cw90,
+            &mut out,
+        );
+        assert_eq!(size, (2, 3));
+        assert_eq!(corners(&out, 2, 3), [3, 0, 5, 2]);
+        assert!(!out.contains(&0xEE));
+    }
+
+    #[test]
+    fn flip_swaps_the_corners() {
+        for (horizontal, vertical, expected) in [
+            (false, false, [0, 2, 3, 5]),
+            (true, false, [2, 0, 5, 3]),
+            (false, true, [3, 5, 0, 2]),
+            (true, true, [5, 3, 2, 0]),
+        ] {
+            let mut data = numbered();
+            flip_rgba(&mut data, WIDTH, HEIGHT, horizontal, vertical);
+            assert_eq!(
+                corners(&data, WIDTH, HEIGHT),
+                expected,
+                "horizontal {horizontal}, vertical {vertical}"
+            );
+            // 奇数幅の真ん中の列は左右反転で動かない
+            let middle = [data[4], data[(WIDTH + 1) * 4]];
+            assert_eq!(middle, if vertical { [4, 1] } else { [1, 4] });
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! `--verify`: 受信スレッドからGUIスレッドに渡したフレームが途中で壊れていないかの確認
+//!
+//! 受信スレッドで渡す直前の画素にチェックサムを付け（[`Checked::new`]）、GUIスレッドで
+//! テクスチャに載せる直前にもう一度計算して比べる（[`FrameVerifier::verify`]）。合わなければ警告を出す。
+//! ArcSwapでの受け渡しや、`--smooth` / `--target-fps` で持ち回す間に画が壊れていないかを確かめるためのもの。
+//!
+//! チェックサムは64bitずつの乗算と回転による簡単なもので、暗号学的な強さはない。
+//! それでも1080pで1フレームあたり数msかかるので、ふだんは使わない。
+
+use eframe::egui;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// 1語ごとに掛ける奇数（64bitの黄金比）
+const CHECKSUM_MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// 照合した数の報告間隔
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// `data` の64bitチェックサム（長さも含める）
+pub fn checksum(data: &[u8]) -> u64 {
+    let mut hash = data.len() as u64;
+    let mut words = data.chunks_exact(8);
+    for word in &mut words {
+        let word = u64::from_le_bytes(word.try_into().unwrap_or_default());
+        hash = (hash ^ word)
+            .wrapping_mul(CHECKSUM_MULTIPLIER)
+            .rotate_left(29);
+    }
+    for &byte in words.remainder() {
+        hash = (hash ^ byte as u64).wrapping_mul(CHECKSUM_MULTIPLIER);
+    }
+    hash
+}
+
+/// 受信スレッドで計算したチェックサムを付けた画像（`--verify` でなければ付けない）
+#[derive(Clone)]
+pub struct Checked {
+    image: egui::ColorImage,
+    checksum: Option<u64>,
+}
+
+impl Checked {
+    /// `verify` なら今の画素のチェックサムを付ける（受信スレッドで、渡す直前に呼ぶ）
+    pub fn new(image: egui::ColorImage, verify: bool) -> Self {
+        let checksum = verify.then(|| checksum(image.as_raw()));
+        Self { image, checksum }
+    }
+}
+
+/// GUIスレッドでの照合と、その集計
+#[derive(Debug, Default)]
+pub struct FrameVerifier {
+    checked: u64,
+    mismatches: u64,
+    last_report: Option<Instant>,
+}
+
+impl FrameVerifier {
+    /// チェックサムが付いていれば計算し直して比べ、画像を取り出す（テクスチャに載せる直前に呼ぶ）
+    pub fn verify(&mut self, checked: Checked) -> egui::ColorImage {
+        let Checked { image, checksum } = checked;
+        let Some(expected) = checksum else {
+            return image;
+        };
+        self.checked += 1;
+        let actual = self::checksum(image.as_raw());
+        if actual != expected {
+            self.mismatches += 1;
+            warn!(
+                expected = format_args!("{expected:016x}"),
+                actual = format_args!("{actual:016x}"),
+                width = image.size[0],
+                height = image.size[1],
+                "Frame changed between the receiver thread and upload ({} of {} frames so far)",
+                self.mismatches,
+                self.checked
+            );
+        }
+
+        let now = Instant::now();
+        let last_report = *self.last_report.get_or_insert(now);
+        if now.duration_since(last_report) >= REPORT_INTERVAL {
+            info!(
+                "Frame verification: {} frames checked, {} mismatches",
+                self.checked, self.mismatches
+            );
+            self.last_report = Some(now);
+        }
+        image
+    }
+}
@@ -0,0 +1,128 @@
+//! 幅か高さが1のフレーム（1x1、1x1080、1920x1）を変換・フィルタ・表示サイズの計算に通す
+
+use rust_ndi_viewer::convert::{downscale_rgba, to_rgba};
+use rust_ndi_viewer::filter::{
+    BoxBlur, ChromaKey, ColorCorrect, FalseColor, Filter, GaussianBlur, Grayscale, LumaKey, Sobel,
+    UnsharpMask,
+};
+use rust_ndi_viewer::PixelFormat;
+
+const SIZES: [(usize, usize); 3] = [(1, 1), (1, 1080), (1920, 1)];
+
+// リミテッドレンジの Y=126 は RGB の 128（灰色）
+const GRAY_Y: u8 = 126;
+
+#[test]
+fn yuv_frames_convert_to_gray() {
+    for (width, height) in SIZES {
+        // 4:2:2は奇数幅でも最後の組（U Y V Y）まで届く
+        let stride = width.div_ceil(2) * 4;
+        let uyvy = [128, GRAY_Y, 128, GRAY_Y].repeat(stride / 4 * height);
+        // 4:2:0は色差の行・列が切り上げ
+        let chroma = width.div_ceil(2) * height.div_ceil(2);
+        let nv12_stride = width.div_ceil(2) * 2;
+        let nv12 = [
+            vec![GRAY_Y; nv12_stride * height],
+            vec![128; nv12_stride * height.div_ceil(2)],
+        ]
+        .concat();
+        let i420 = [vec![GRAY_Y; nv12_stride * height], vec![128; chroma * 2]].concat();
+
+        for (format, stride, data) in [
+            (PixelFormat::UYVY, stride, &uyvy),
+            (PixelFormat::NV12, nv12_stride, &nv12),
+            (PixelFormat::I420, nv12_stride, &i420),
+        ] {
+            let mut out = Vec::new();
+            to_rgba(format, width, height, stride, data, &mut out)
+                .unwrap_or_else(|e| panic!("{format:?} {width}x{height}: {e:#}"));
+            assert_eq!(out.len(), width * height * 4, "{format:?} {width}x{height}");
+            assert!(
+                out.chunks_exact(4).all(|px| px == [128, 128, 128, 255]),
+                "{format:?} {width}x{height}"
+            );
+        }
+    }
+}
+
+#[test]
+fn empty_frames_are_rejected() {
+    let mut out = Vec::new();
+    assert!(to_rgba(PixelFormat::RGBA, 0, 1080, 0, &[], &mut out).is_err());
+    assert!(to_rgba(PixelFormat::UYVY, 1920, 0, 3840, &[], &mut out).is_err());
+}
+
+#[test]
+fn downscale_rounds_up_to_one_pixel() {
+    for (width, height) in SIZES {
+        let data = vec![200; width * height * 4];
+        let (out, out_width, out_height) = downscale_rgba(&data, width, height, 4);
+        assert_eq!(
+            (out_width, out_height),
+            (width.div_ceil(4), height.div_ceil(4))
+        );
+        assert_eq!(out.len(), out_width * out_height * 4);
+        assert!(out.iter().all(|&v| v == 200));
+    }
+}
+
+#[test]
+fn filters_handle_single_row_and_column_frames() {
+    let filters: Vec<(&str, Box<dyn Filter>)> = vec![
+        ("grayscale", Box::new(Grayscale)),
+        ("gaussian blur", Box::new(GaussianBlur::new(3.0))),
+        ("box blur", Box::new(BoxBlur::new(4))),
+        ("false color", Box::new(FalseColor::default())),
+        ("luma key", Box::new(LumaKey::default())),
+        ("sharpen", Box::new(UnsharpMask::default())),
+        ("sobel", Box::new(Sobel::default())),
+        ("color correct", Box::new(ColorCorrect::default())),
+        ("chroma key", Box::new(ChromaKey::default())),
+    ];
+    for (name, mut filter) in filters {
+        for (width, height) in SIZES {
+            let mut data = [90, 120, 150, 255].repeat(width * height);
+            filter.apply(width, height, &mut data);
+            assert_eq!(data.len(), width * height * 4, "{name} {width}x{height}");
+        }
+    }
+
+    // ぼかし・シャープは端の画素を繰り返すので、一様な画は変わらない
+    let blurs: Vec<(&str, Box<dyn Filter>)> = vec![
+        ("gaussian blur", Box::new(GaussianBlur::new(3.0))),
+        ("box blur", Box::new(BoxBlur::new(4))),
+        ("sharpen", Box::new(UnsharpMask::default())),
+    ];
+    for (name, mut filter) in blurs {
+        for (width, height) in SIZES {
+            let uniform = [90, 120, 150, 255].repeat(width * height);
+            let mut data = uniform.clone();
+            filter.apply(width, height, &mut data);
+            assert!(data == uniform, "{name} {width}x{height}");
+        }
+    }
+}
+
+#[cfg(feature = "gui")]
+#[test]
+fn display_sizes_stay_finite() {
+    use rust_ndi_viewer::{display_size, fit_preserve_aspect};
+
+    let available = eframe::egui::vec2(1280.0, 720.0);
+    for (width, height) in SIZES {
+        let size = display_size(width as i32, height as i32, 0.0);
+        let fitted = fit_preserve_aspect(available, size);
+        assert!(
+            fitted.x.is_finite() && fitted.y.is_finite(),
+            "{width}x{height}"
+        );
+        assert!(fitted.x <= available.x && fitted.y <= available.y);
+    }
+    // サイズが0やNaNなら領域全体
+    for size in [
+        eframe::egui::vec2(0.0, 1080.0),
+        eframe::egui::vec2(f32::NAN, 1.0),
+    ] {
+        assert_eq!(fit_preserve_aspect(available, size), available);
+    }
+}
@@ -0,0 +1,113 @@
+//! `Fastest` / `Best` で届きうる形式を、同じ絵（左2画素が赤、右2画素が白）にして
+//! `convert::to_rgba` に通し、どれも同じRGBAになることを確かめる
+
+use rust_ndi_viewer::convert::to_rgba;
+use rust_ndi_viewer::PixelFormat;
+
+const WIDTH: usize = 4;
+const HEIGHT: usize = 2;
+
+// BT.601（720p未満）リミテッドレンジの赤と白。色差は2画素で1組
+const RED: [u8; 3] = [81, 90, 240];
+const WHITE: [u8; 3] = [235, 128, 128];
+const YUV: [[u8; 3]; 2] = [RED, WHITE];
+
+const EXPECTED_ROW: [u8; WIDTH * 4] = [
+    255, 0, 0, 255, 255, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 255,
+];
+
+// 変換して、赤・赤・白・白の2行（アルファは `alpha`）になっていること
+fn check(format: PixelFormat, line_stride: usize, data: &[u8], alpha: [u8; WIDTH]) {
+    let mut rgba = Vec::new();
+    to_rgba(format, WIDTH, HEIGHT, line_stride, data, &mut rgba)
+        .unwrap_or_else(|e| panic!("{format:?}: {e:#}"));
+    assert_eq!(rgba.len(), WIDTH * HEIGHT * 4, "{format:?}");
+    for row in rgba.chunks_exact(WIDTH * 4) {
+        for (x, (px, expected)) in row
+            .chunks_exact(4)
+            .zip(EXPECTED_ROW.chunks_exact(4))
+            .enumerate()
+        {
+            assert_eq!(px[..3], expected[..3], "{format:?} x={x}");
+            assert_eq!(px[3], alpha[x], "{format:?} alpha x={x}");
+        }
+    }
+}
+
+// 1行ぶんの Y
+fn luma_row() -> Vec<u8> {
+    (0..WIDTH).map(|x| YUV[x / 2][0]).collect()
+}
+
+#[test]
+fn rgb_formats() {
+    let rgba = EXPECTED_ROW.repeat(HEIGHT);
+    check(PixelFormat::RGBA, WIDTH * 4, &rgba, [255; 4]);
+
+    let bgra: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|p| [p[2], p[1], p[0], p[3]])
+        .collect();
+    check(PixelFormat::BGRA, WIDTH * 4, &bgra, [255; 4]);
+}
+
+#[test]
+fn packed_422_with_padded_stride() {
+    // 行の後ろに4バイトのパディング
+    let stride = WIDTH * 2 + 4;
+    let mut row: Vec<u8> = YUV.iter().flat_map(|&[y, u, v]| [u, y, v, y]).collect();
+    row.extend([0xEE; 4]);
+    let uyvy = row.repeat(HEIGHT);
+    check(PixelFormat::UYVY, stride, &uyvy, [255; 4]);
+
+    // UYVYの後ろに width バイト/行 のアルファプレーン
+    let alpha = [255, 128, 0, 255];
+    let mut uyva = uyvy.clone();
+    for _ in 0..HEIGHT {
+        uyva.extend(alpha);
+    }
+    check(PixelFormat::UYVA, stride, &uyva, alpha);
+}
+
+#[test]
+fn planar_16_bit_422() {
+    // リトルエンディアンの16bit。上位バイトだけが使われる
+    let stride = WIDTH * 2;
+    let wide = |values: &[u8]| -> Vec<u8> { values.iter().flat_map(|&v| [0x7F, v]).collect() };
+    let luma = wide(&luma_row()).repeat(HEIGHT);
+    let chroma = wide(&[RED[1], RED[2], WHITE[1], WHITE[2]]).repeat(HEIGHT);
+    let p216 = [luma.clone(), chroma.clone()].concat();
+    check(PixelFormat::P216, stride, &p216, [255; 4]);
+
+    let alpha = [10, 20, 30, 40];
+    let pa16 = [luma, chroma, wide(&alpha).repeat(HEIGHT)].concat();
+    check(PixelFormat::PA16, stride, &pa16, alpha);
+}
+
+#[test]
+fn planar_420() {
+    let luma = luma_row().repeat(HEIGHT);
+    let u = [RED[1], WHITE[1]];
+    let v = [RED[2], WHITE[2]];
+
+    let nv12 = [luma.clone(), vec![u[0], v[0], u[1], v[1]]].concat();
+    check(PixelFormat::NV12, WIDTH, &nv12, [255; 4]);
+
+    let i420 = [luma.clone(), u.to_vec(), v.to_vec()].concat();
+    check(PixelFormat::I420, WIDTH, &i420, [255; 4]);
+
+    // YV12はVが先
+    let yv12 = [luma, v.to_vec(), u.to_vec()].concat();
+    check(PixelFormat::YV12, WIDTH, &yv12, [255; 4]);
+}
+
+#[test]
+fn truncated_and_narrow_frames_are_errors() {
+    let fails = |format, line_stride, data: &[u8]| {
+        to_rgba(format, WIDTH, HEIGHT, line_stride, data, &mut Vec::new()).is_err()
+    };
+    let uyvy = vec![0; WIDTH * 2 * HEIGHT];
+    assert!(fails(PixelFormat::UYVY, WIDTH * 2, &uyvy[1..]));
+    assert!(fails(PixelFormat::UYVY, WIDTH, &uyvy));
+    assert!(fails(PixelFormat::NV12, WIDTH, &[0; WIDTH * 2]));
+}